@@ -0,0 +1,139 @@
+//! Transforming the coordinates of all points while retiling (`--translate` and `--affine`), e.g.
+//! to correct a datum shift or a known survey offset in the same pass
+
+use anyhow::Result;
+
+use crate::input;
+
+/// An affine transformation of the coordinates, the first three rows of a 4×4 matrix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    rows: [[f64; 4]; 3],
+}
+
+impl Affine {
+    /// The transformation given by `--affine` (a 3×4 or 4×4 matrix in row-major order) followed
+    /// by the shift of `--translate`, if any is given
+    pub fn new(matrix: Option<&[f64]>, translate: Option<&[f64]>) -> Result<Option<Self>> {
+        if matrix.is_none() && translate.is_none() {
+            return Ok(None);
+        }
+        let mut rows = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+        ];
+        if let Some(matrix) = matrix {
+            match matrix.len() {
+                12 => {}
+                16 => anyhow::ensure!(
+                    matrix[12..] == [0.0, 0.0, 0.0, 1.0],
+                    "the last row of --affine must be 0,0,0,1"
+                ),
+                n => anyhow::bail!("--affine needs 12 or 16 values, got {n}"),
+            }
+            for (row, values) in rows.iter_mut().zip(matrix.chunks_exact(4)) {
+                row.copy_from_slice(values);
+            }
+        }
+        if let Some(translate) = translate {
+            anyhow::ensure!(
+                translate.len() == 3,
+                "--translate needs 3 values DX,DY,DZ, got {}",
+                translate.len()
+            );
+            for (row, shift) in rows.iter_mut().zip(translate) {
+                row[3] += shift;
+            }
+        }
+        Ok(Some(Self { rows }))
+    }
+
+    /// Transform a single position
+    pub fn apply(&self, [x, y, z]: [f64; 3]) -> [f64; 3] {
+        self.rows.map(|[a, b, c, d]| a * x + b * y + c * z + d)
+    }
+
+    /// Transform the coordinates of the points in place
+    pub fn apply_points(&self, points: &mut [las::Point]) {
+        for p in points {
+            [p.x, p.y, p.z] = self.apply([p.x, p.y, p.z]);
+        }
+    }
+
+    /// The bounds of the transformed corners of the bounds
+    pub fn bounds(&self, bounds: &las::Bounds) -> las::Bounds {
+        let (lo, hi) = (bounds.min, bounds.max);
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for corner in 0..8 {
+            let x = if corner & 1 == 0 { lo.x } else { hi.x };
+            let y = if corner & 2 == 0 { lo.y } else { hi.y };
+            let z = if corner & 4 == 0 { lo.z } else { hi.z };
+            for (axis, v) in self.apply([x, y, z]).into_iter().enumerate() {
+                min[axis] = min[axis].min(v);
+                max[axis] = max[axis].max(v);
+            }
+        }
+        las::Bounds {
+            min: las::Vector {
+                x: min[0],
+                y: min[1],
+                z: min[2],
+            },
+            max: las::Vector {
+                x: max[0],
+                y: max[1],
+                z: max[2],
+            },
+        }
+    }
+
+    /// The bounds of a transformed rectangle `[min x, min y, max x, max y]`, or `None` if X or Y
+    /// depend on Z, so they are not known without it
+    pub fn rect(&self, [x0, y0, x1, y1]: [f64; 4]) -> Option<[f64; 4]> {
+        if self.rows[0][2] != 0.0 || self.rows[1][2] != 0.0 {
+            return None;
+        }
+        let min = las::Vector {
+            x: x0,
+            y: y0,
+            z: 0.0,
+        };
+        let max = las::Vector {
+            x: x1,
+            y: y1,
+            z: 0.0,
+        };
+        let bounds = self.bounds(&las::Bounds { min, max });
+        Some([bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y])
+    }
+
+    /// The input header as if its points were transformed: with the transformed bounds, and the
+    /// offsets of the coordinates moved along so the quantized coordinates stay in range
+    pub fn header(&self, header: &las::Header) -> Result<las::Header> {
+        let bounds = self.bounds(&header.bounds());
+        let transforms = header.transforms();
+        let [x_offset, y_offset, z_offset] = self.apply([
+            transforms.x.offset,
+            transforms.y.offset,
+            transforms.z.offset,
+        ]);
+
+        input::modify_header(header, |raw| {
+            (raw.min_x, raw.min_y, raw.min_z) = (bounds.min.x, bounds.min.y, bounds.min.z);
+            (raw.max_x, raw.max_y, raw.max_z) = (bounds.max.x, bounds.max.y, bounds.max.z);
+            (raw.x_offset, raw.y_offset, raw.z_offset) = (x_offset, y_offset, z_offset);
+        })
+    }
+
+    /// The 4×4 matrix in row-major order, as PDAL takes it
+    pub fn matrix(&self) -> [f64; 16] {
+        let mut matrix = [0.0; 16];
+        for (i, row) in self.rows.iter().enumerate() {
+            matrix[i * 4..i * 4 + 4].copy_from_slice(row);
+        }
+        matrix[15] = 1.0;
+        matrix
+    }
+}
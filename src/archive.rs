@@ -0,0 +1,422 @@
+//! LAS/LAZ files inside ZIP and TAR archives, as many agencies distribute them and HPC systems
+//! export them. Archives given as inputs or found in input folders are read like folders: each
+//! LAS/LAZ member is an input of its own. Members stored uncompressed are read in place, others
+//! are extracted to a temporary folder (LAS readers need to seek) when they are first read, and
+//! removed at the end of the run.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::{Context, Result};
+
+use crate::input::{ReadSeek, SkipReason, SkipReport, is_pointcloud_extension};
+
+/// Where the data of the members listed or extracted so far is
+static LOCATED: Mutex<BTreeMap<ArchiveMember, Location>> = Mutex::new(BTreeMap::new());
+
+/// The version of each archive when its members were located, so that listing an archive again
+/// (when watching folders or updating tiles) keeps the members already extracted, unless the
+/// archive changed
+static LISTED: Mutex<BTreeMap<PathBuf, String>> = Mutex::new(BTreeMap::new());
+
+/// Number of members extracted so far, which numbers the extracted files
+static EXTRACTED: AtomicUsize = AtomicUsize::new(0);
+
+/// The kinds of archives that are read as inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    /// A gzip compressed TAR archive, which can only be read from start to end
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where the data of a member is
+#[derive(Debug, Clone)]
+enum Location {
+    /// Stored uncompressed in the archive, starting at this offset
+    Stored { start: u64, len: u64 },
+    /// Compressed, and extracted when it is first read, needing `len` bytes
+    Compressed { len: u64 },
+    /// Extracted to this file
+    Extracted(PathBuf),
+}
+
+/// A LAS/LAZ file inside an archive
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArchiveMember {
+    pub archive: PathBuf,
+    /// The path of the member inside the archive
+    pub name: String,
+}
+
+/// Returns true for files that are read as archives of inputs: `.zip`, `.tar`, `.tar.gz` and
+/// `.tgz` files
+pub fn is_archive(path: &Path) -> bool {
+    ArchiveKind::of(path).is_some()
+}
+
+/// The LAS/LAZ members of an archive, in the order of their names. Other members are recorded in
+/// `skipped`. Nothing is extracted while listing, and members extracted before are kept unless the
+/// archive changed since it was last listed.
+pub fn members(archive: &Path, skipped: &mut SkipReport) -> Result<Vec<ArchiveMember>> {
+    let version = crate::input::InputFile::Local(archive.to_owned()).version()?;
+    let previous = LISTED
+        .lock()
+        .expect("not poisoned")
+        .insert(archive.to_owned(), version.clone());
+    if previous.is_some_and(|previous| previous != version) {
+        forget(archive);
+    }
+
+    let mut members = match ArchiveKind::of(archive) {
+        Some(ArchiveKind::Zip) => zip_members(archive, skipped)?,
+        Some(ArchiveKind::Tar) => tar_members(archive, skipped)?,
+        Some(ArchiveKind::TarGz) => tar_gz_members(archive, skipped)?,
+        None => anyhow::bail!("not an archive: {}", archive.display()),
+    };
+    members.sort();
+    Ok(members)
+}
+
+/// The member called `name`, if it is a LAS/LAZ file. Other files are recorded in `skipped`,
+/// folders are part of the names of their members.
+fn member(
+    archive: &Path,
+    name: &str,
+    is_file: bool,
+    skipped: &mut SkipReport,
+) -> Option<ArchiveMember> {
+    if !is_file {
+        return None;
+    }
+    let member = ArchiveMember {
+        archive: archive.to_owned(),
+        name: name.to_owned(),
+    };
+    if !is_pointcloud_extension(Path::new(name).extension().and_then(|e| e.to_str())) {
+        skipped.add(SkipReason::WrongExtension, &member);
+        return None;
+    }
+    Some(member)
+}
+
+/// Forget where the members of `archive` are, removing the ones extracted
+fn forget(archive: &Path) {
+    let mut located = LOCATED.lock().expect("not poisoned");
+    located.retain(|member, location| {
+        if member.archive != archive {
+            return true;
+        }
+        if let Location::Extracted(path) = location
+            && let Err(e) = std::fs::remove_file(&*path)
+        {
+            tracing::warn!("could not remove {}: {e}", path.display());
+        }
+        false
+    });
+}
+
+/// Record where `member` is, unless it is known already
+fn locate(member: &ArchiveMember, location: Location) {
+    LOCATED
+        .lock()
+        .expect("not poisoned")
+        .entry(member.clone())
+        .or_insert(location);
+}
+
+fn open_file(path: &Path) -> Result<File> {
+    File::open(path).with_context(|| format!("open file: {}", path.display()))
+}
+
+fn open_zip(archive: &Path) -> Result<zip::ZipArchive<File>> {
+    zip::ZipArchive::new(open_file(archive)?)
+        .with_context(|| format!("read ZIP archive: {}", archive.display()))
+}
+
+fn zip_members(archive: &Path, skipped: &mut SkipReport) -> Result<Vec<ArchiveMember>> {
+    let mut zip = open_zip(archive)?;
+    let mut members = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip
+            .by_index(i)
+            .with_context(|| format!("read entry {i} of {}", archive.display()))?;
+        let Some(member) = member(archive, file.name(), file.is_file(), skipped) else {
+            continue;
+        };
+        locate(
+            &member,
+            match file.compression() {
+                zip::CompressionMethod::Stored => Location::Stored {
+                    start: file.data_start(),
+                    len: file.size(),
+                },
+                _ => Location::Compressed { len: file.size() },
+            },
+        );
+        members.push(member);
+    }
+    Ok(members)
+}
+
+/// The member of a TAR entry, see [`member`]
+fn tar_member<R: Read>(
+    archive: &Path,
+    entry: &tar::Entry<'_, R>,
+    skipped: &mut SkipReport,
+) -> Result<Option<ArchiveMember>> {
+    let name = entry
+        .path()
+        .with_context(|| format!("read entry of {}", archive.display()))?
+        .to_string_lossy()
+        .into_owned();
+    Ok(member(
+        archive,
+        &name,
+        entry.header().entry_type().is_file(),
+        skipped,
+    ))
+}
+
+fn tar_members(archive: &Path, skipped: &mut SkipReport) -> Result<Vec<ArchiveMember>> {
+    let mut tar = tar::Archive::new(open_file(archive)?);
+    let mut members = Vec::new();
+    // only the headers are read, the data of the entries is skipped over
+    for entry in tar
+        .entries_with_seek()
+        .with_context(|| format!("read TAR archive: {}", archive.display()))?
+    {
+        let entry = entry.with_context(|| format!("read TAR archive: {}", archive.display()))?;
+        let Some(member) = tar_member(archive, &entry, skipped)? else {
+            continue;
+        };
+        locate(
+            &member,
+            Location::Stored {
+                start: entry.raw_file_position(),
+                len: entry.size(),
+            },
+        );
+        members.push(member);
+    }
+    Ok(members)
+}
+
+fn open_tar_gz(archive: &Path) -> Result<tar::Archive<impl Read>> {
+    let decoder = flate2::read::GzDecoder::new(BufReader::new(open_file(archive)?));
+    Ok(tar::Archive::new(decoder))
+}
+
+fn tar_gz_members(archive: &Path, skipped: &mut SkipReport) -> Result<Vec<ArchiveMember>> {
+    let mut tar = open_tar_gz(archive)?;
+    let mut members = Vec::new();
+    // the stream is decompressed to find the entries, but nothing is written
+    for entry in tar
+        .entries()
+        .with_context(|| format!("read TAR archive: {}", archive.display()))?
+    {
+        let entry = entry.with_context(|| format!("read TAR archive: {}", archive.display()))?;
+        let Some(member) = tar_member(archive, &entry, skipped)? else {
+            continue;
+        };
+        locate(&member, Location::Compressed { len: entry.size() });
+        members.push(member);
+    }
+    Ok(members)
+}
+
+/// Extract all members of a gzip compressed TAR archive that are not extracted yet, in a single
+/// pass, as there is no going back in the stream to extract them one by one
+fn extract_tar_gz(archive: &Path, located: &mut BTreeMap<ArchiveMember, Location>) -> Result<()> {
+    let mut tar = open_tar_gz(archive)?;
+    for entry in tar
+        .entries()
+        .with_context(|| format!("read TAR archive: {}", archive.display()))?
+    {
+        let mut entry =
+            entry.with_context(|| format!("read TAR archive: {}", archive.display()))?;
+        let Some(member) = tar_member(archive, &entry, &mut SkipReport::default())? else {
+            continue;
+        };
+        if matches!(located.get(&member), Some(Location::Compressed { .. })) {
+            extract(&member, &mut entry, located)?;
+        }
+    }
+    Ok(())
+}
+
+/// The number of bytes the members listed so far need when they are extracted, which they are in
+/// the system temporary directory
+pub fn to_extract() -> u64 {
+    LOCATED
+        .lock()
+        .expect("not poisoned")
+        .values()
+        .map(|location| match location {
+            Location::Compressed { len } => *len,
+            Location::Stored { .. } | Location::Extracted(_) => 0,
+        })
+        .sum()
+}
+
+/// The folder members are extracted to
+fn extract_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("lasretile-{}-archives", std::process::id()))
+}
+
+/// Extract the data of `member` from `data` to the extraction folder
+fn extract(
+    member: &ArchiveMember,
+    data: &mut impl Read,
+    located: &mut BTreeMap<ArchiveMember, Location>,
+) -> Result<PathBuf> {
+    let dir = extract_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("create folder: {}", dir.display()))?;
+    let file_name = Path::new(&member.name)
+        .file_name()
+        .context("member without a file name")?;
+    // numbered, as members of different archives or folders may have the same name
+    let number = EXTRACTED.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("{number}-{}", file_name.to_string_lossy()));
+    tracing::debug!("Extracting {member} to {}", path.display());
+    let mut output = File::create(&path).with_context(|| format!("create {}", path.display()))?;
+    std::io::copy(data, &mut output).with_context(|| format!("extract {member}"))?;
+    located.insert(member.clone(), Location::Extracted(path.clone()));
+    Ok(path)
+}
+
+/// Remove the members extracted during the run
+pub fn cleanup() {
+    LOCATED.lock().expect("not poisoned").clear();
+    LISTED.lock().expect("not poisoned").clear();
+    let dir = extract_dir();
+    if !dir.exists() {
+        return;
+    }
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        tracing::warn!("could not remove {}: {e}", dir.display());
+    }
+}
+
+impl ArchiveMember {
+    /// Open the member for reading
+    pub fn open(&self) -> Result<Box<dyn ReadSeek>> {
+        // held while extracting, so that each member is only extracted once
+        let mut located = LOCATED.lock().expect("not poisoned");
+        match located.get(self) {
+            Some(Location::Stored { start, len }) => return self.window(*start, *len),
+            Some(Location::Extracted(path)) => return Ok(Box::new(open_file(path)?)),
+            Some(Location::Compressed { .. }) => {}
+            None => anyhow::bail!("{self} was not found when listing the archive"),
+        }
+
+        if ArchiveKind::of(&self.archive) == Some(ArchiveKind::TarGz) {
+            extract_tar_gz(&self.archive, &mut located)?;
+            return match located.get(self) {
+                Some(Location::Extracted(path)) => Ok(Box::new(open_file(path)?)),
+                _ => anyhow::bail!("{self} was not found when extracting the archive"),
+            };
+        }
+        let mut zip = open_zip(&self.archive)?;
+        let mut file = zip
+            .by_name(&self.name)
+            .with_context(|| format!("find {self}"))?;
+        let path = extract(self, &mut file, &mut located)?;
+        Ok(Box::new(open_file(&path)?))
+    }
+
+    /// The member stored uncompressed at `start` in the archive
+    fn window(&self, start: u64, len: u64) -> Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(Window {
+            file: open_file(&self.archive)?,
+            start,
+            len,
+            position: 0,
+        }))
+    }
+
+    /// What tells whether the member changed between runs: the size and modification time of
+    /// the archive, and for ZIP archives the checksum of the member
+    pub fn version(&self) -> Result<String> {
+        let archive = crate::input::InputFile::Local(self.archive.clone()).version()?;
+        if ArchiveKind::of(&self.archive) != Some(ArchiveKind::Zip) {
+            return Ok(archive);
+        }
+        let mut zip = open_zip(&self.archive)?;
+        let file = zip
+            .by_name(&self.name)
+            .with_context(|| format!("find {self}"))?;
+        Ok(format!("{archive}-{:08x}", file.crc32()))
+    }
+}
+
+impl std::fmt::Display for ArchiveMember {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.archive.display(), self.name)
+    }
+}
+
+/// A stored member, read from its part of the archive as if it was a file of its own
+struct Window {
+    file: File,
+    /// Offset of the member in the archive
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl Read for Window {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let n = (buf.len() as u64).min(remaining) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.file
+            .seek(SeekFrom::Start(self.start + self.position))?;
+        let n = self.file.read(&mut buf[..n])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Window {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = new_position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
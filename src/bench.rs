@@ -0,0 +1,128 @@
+//! Throughput reporting with `--bench`, to compare buffer sizes, thread counts and storage
+//! backends without an external profiler.
+//!
+//! The time spent in each stage of the pipeline is summed over all threads working on it, so the
+//! throughput of a stage is per thread: with 8 writer threads busy all the time, the encode
+//! throughput is an eighth of the overall rate.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::input::InputFile;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Nanoseconds spent and points handled per stage, indexed by [`Stage`]
+static NANOS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+static POINTS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading and decompressing the input files
+    Decode = 0,
+    /// Filtering the points and assigning them to tiles
+    Route = 1,
+    /// Compressing and writing the output tiles
+    Encode = 2,
+}
+
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::Decode, Stage::Route, Stage::Encode];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Route => "route",
+            Stage::Encode => "encode",
+        }
+    }
+}
+
+/// Start recording the stage timings
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// The start of a measurement, `None` unless `--bench` is given so that the clock is not read
+/// for every buffer otherwise
+pub fn start() -> Option<Instant> {
+    ENABLED.load(Ordering::Relaxed).then(Instant::now)
+}
+
+/// Record that `points` points went through a stage since `started`
+pub fn record(stage: Stage, started: Option<Instant>, points: usize) {
+    if let Some(started) = started {
+        let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        NANOS[stage as usize].fetch_add(nanos, Ordering::Relaxed);
+        POINTS[stage as usize].fetch_add(points as u64, Ordering::Relaxed);
+    }
+}
+
+/// Wall-clock time and points read of each input file
+#[derive(Debug, Default)]
+pub struct FileTimings {
+    files: Vec<(InputFile, Duration, u64)>,
+}
+
+impl FileTimings {
+    pub fn add(&mut self, path: &InputFile, elapsed: Duration, points: u64) {
+        self.files.push((path.clone(), elapsed, points));
+    }
+}
+
+/// Log the throughput of each stage, the timings of each file and the peak memory usage, so the
+/// report goes wherever the other messages go (e.g. `--log-file`)
+pub fn print_report(files: &FileTimings, total: Duration) {
+    let points_read = POINTS[Stage::Decode as usize].load(Ordering::Relaxed);
+    tracing::info!("Benchmark:");
+    for stage in Stage::ALL {
+        let seconds =
+            Duration::from_nanos(NANOS[stage as usize].load(Ordering::Relaxed)).as_secs_f64();
+        let points = POINTS[stage as usize].load(Ordering::Relaxed);
+        tracing::info!(
+            "  {:<8} {:>10.2} MP/s  ({points} points in {seconds:.2} thread-seconds)",
+            stage.name(),
+            mega_points_per_second(points, seconds),
+        );
+    }
+    tracing::info!(
+        "  {:<8} {:>10.2} MP/s  ({points_read} points in {:.2} s)",
+        "overall",
+        mega_points_per_second(points_read, total.as_secs_f64()),
+        total.as_secs_f64()
+    );
+
+    tracing::info!("Input files:");
+    for (path, elapsed, points) in &files.files {
+        tracing::info!(
+            "  {:>8.2} s {:>10.2} MP/s  {path}",
+            elapsed.as_secs_f64(),
+            mega_points_per_second(*points, elapsed.as_secs_f64())
+        );
+    }
+
+    match peak_rss() {
+        Some(bytes) => tracing::info!("Peak RSS: {:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        None => tracing::info!("Peak RSS: unknown on this platform"),
+    }
+}
+
+fn mega_points_per_second(points: u64, seconds: f64) -> f64 {
+    points as f64 / 1e6 / seconds.max(f64::EPSILON)
+}
+
+/// The peak resident set size of this process in bytes, read from `/proc` (Linux only)
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
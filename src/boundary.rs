@@ -0,0 +1,206 @@
+//! Footprint of the actual point coverage, traced from the occupied cells of a density grid
+
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::density::DensityGrid;
+
+/// A vertex of the cell grid, in multiples of the cell size
+type Vertex = (i64, i64);
+
+/// Directions in counter-clockwise order: east, north, west, south
+const DIRECTIONS: [Vertex; 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// Write a GeoJSON file with the footprint of all points as the first feature, followed by the
+/// footprint of each tile. The footprint of a tile consists of the occupied cells whose center
+/// lies within the tile bounds `[min x, min y, max x, max y]`.
+pub fn write(path: &Path, grid: &DensityGrid, tiles: &[(String, [f64; 4])]) -> Result<()> {
+    let cell_size = grid.cell_size();
+    let mut features = vec![json!({
+        "type": "Feature",
+        "geometry": multi_polygon(&footprint(grid, grid.extent(), |_, _| true), cell_size),
+        "properties": { "name": null },
+    })];
+    for (name, [x0, y0, x1, y1]) in tiles {
+        let inside = |col: i64, row: i64| {
+            let (x, y) = (
+                (col as f64 + 0.5) * cell_size,
+                (row as f64 + 0.5) * cell_size,
+            );
+            x >= *x0 && x < *x1 && y >= *y0 && y < *y1
+        };
+        let extent = [
+            (x0 / cell_size).floor() as i64,
+            (y0 / cell_size).floor() as i64,
+            (x1 / cell_size).floor() as i64,
+            (y1 / cell_size).floor() as i64,
+        ];
+        features.push(json!({
+            "type": "Feature",
+            "geometry": multi_polygon(&footprint(grid, extent, inside), cell_size),
+            "properties": { "name": name },
+        }));
+    }
+
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create boundary: {}", path.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &collection)
+        .with_context(|| format!("write boundary: {}", path.display()))
+}
+
+fn multi_polygon(polygons: &[Vec<Vec<Vertex>>], cell_size: f64) -> serde_json::Value {
+    let coordinates: Vec<Vec<Vec<[f64; 2]>>> = polygons
+        .iter()
+        .map(|rings| {
+            rings
+                .iter()
+                .map(|ring| {
+                    // GeoJSON rings are closed by repeating the first position
+                    ring.iter()
+                        .chain(&ring[..1])
+                        .map(|&(x, y)| [x as f64 * cell_size, y as f64 * cell_size])
+                        .collect()
+                })
+                .collect()
+        })
+        .collect();
+    json!({ "type": "MultiPolygon", "coordinates": coordinates })
+}
+
+/// The polygons (outer ring followed by its holes) covering the occupied cells within the
+/// `[min column, min row, max column, max row]` extent for which `include` returns true. Outer
+/// rings are counter-clockwise and holes clockwise, as GeoJSON recommends.
+fn footprint(
+    grid: &DensityGrid,
+    [c0, r0, c1, r1]: [i64; 4],
+    include: impl Fn(i64, i64) -> bool,
+) -> Vec<Vec<Vec<Vertex>>> {
+    let occupied = |col: i64, row: i64| {
+        (c0..=c1).contains(&col)
+            && (r0..=r1).contains(&row)
+            && include(col, row)
+            && grid.count(col, row) > 0
+    };
+
+    // the cell edges between occupied and empty cells, directed so that the occupied cell is on
+    // the left
+    let mut edges = HashSet::new();
+    for col in c0..=c1 {
+        for row in r0..=r1 {
+            if !occupied(col, row) {
+                continue;
+            }
+            let corners = [
+                (col, row),
+                (col + 1, row),
+                (col + 1, row + 1),
+                (col, row + 1),
+            ];
+            for (i, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+                // the neighbour across the edge that runs in this direction, e.g. the cell to
+                // the south for the edge running east
+                let (nx, ny) = DIRECTIONS[(i + 3) % 4];
+                if !occupied(col + nx, row + ny) {
+                    let start = corners[i];
+                    edges.insert((start, (start.0 + dx, start.1 + dy)));
+                }
+            }
+        }
+    }
+
+    let mut rings = Vec::new();
+    let mut used = HashSet::new();
+    let mut ordered: Vec<_> = edges.iter().copied().collect();
+    ordered.sort();
+    for first in ordered {
+        if used.contains(&first) {
+            continue;
+        }
+        let mut ring = Vec::new();
+        let mut edge = first;
+        loop {
+            used.insert(edge);
+            ring.push(edge.0);
+            let (from, to) = edge;
+            let heading = DIRECTIONS
+                .iter()
+                .position(|&d| d == (to.0 - from.0, to.1 - from.1))
+                .expect("edges are axis-aligned and one cell long");
+            // turn left where possible so that cells touching only at a corner end up in
+            // separate rings
+            let next = [1, 0, 3]
+                .into_iter()
+                .map(|turn| {
+                    let (dx, dy) = DIRECTIONS[(heading + turn) % 4];
+                    (to, (to.0 + dx, to.1 + dy))
+                })
+                .find(|next| edges.contains(next))
+                .expect("boundary rings are closed");
+            if next == first {
+                break;
+            }
+            edge = next;
+        }
+        rings.push(simplify(ring));
+    }
+
+    // every hole lies within the smallest outer ring that contains it
+    let (outer, holes): (Vec<_>, Vec<_>) = rings.into_iter().partition(|r| signed_area(r) > 0);
+    let mut polygons: Vec<Vec<Vec<Vertex>>> = outer.into_iter().map(|r| vec![r]).collect();
+    for hole in holes {
+        // a point inside the occupied cell to the left of the first edge of the hole
+        let (a, b) = (hole[0], hole[1]);
+        let (dx, dy) = ((b.0 - a.0).signum(), (b.1 - a.1).signum());
+        let point = (
+            a.0 as f64 + 0.5 * dx as f64 - 0.5 * dy as f64,
+            a.1 as f64 + 0.5 * dy as f64 + 0.5 * dx as f64,
+        );
+        let container = polygons
+            .iter_mut()
+            .filter(|p| contains(&p[0], point))
+            .min_by_key(|p| signed_area(&p[0]));
+        if let Some(polygon) = container {
+            polygon.push(hole);
+        }
+    }
+    polygons
+}
+
+/// Remove the vertices in the middle of straight lines
+fn simplify(ring: Vec<Vertex>) -> Vec<Vertex> {
+    let n = ring.len();
+    let direction = |a: Vertex, b: Vertex| ((b.0 - a.0).signum(), (b.1 - a.1).signum());
+    (0..n)
+        .filter(|&i| {
+            let (prev, v, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+            direction(prev, v) != direction(v, next)
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Twice the signed area of a ring, positive for counter-clockwise rings
+fn signed_area(ring: &[Vertex]) -> i64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+        .sum()
+}
+
+/// Whether the point lies inside the ring (even-odd rule)
+fn contains(ring: &[Vertex], (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+    for (&(ax, ay), &(bx, by)) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        let (ax, ay, bx, by) = (ax as f64, ay as f64, bx as f64, by as f64);
+        if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * (bx - ax) {
+            inside = !inside;
+        }
+    }
+    inside
+}
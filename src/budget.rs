@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use crate::interrupt;
+
+/// Limits on how much work a single run is allowed to do before stopping early
+#[derive(Debug)]
+pub struct Budget {
+    max_points: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+/// Why processing was stopped before all input files were read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxPoints,
+    MaxRuntime,
+    /// Ctrl-C or SIGTERM
+    Interrupted,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StopReason::MaxPoints => "max-points",
+            StopReason::MaxRuntime => "max-runtime",
+            StopReason::Interrupted => "interrupted",
+        })
+    }
+}
+
+impl Budget {
+    /// Create a new budget, the runtime is measured from the moment this is called
+    pub fn new(max_points: Option<u64>, max_runtime: Option<Duration>) -> Self {
+        Self {
+            max_points,
+            deadline: max_runtime.map(|d| Instant::now() + d),
+        }
+    }
+
+    /// Returns the reason to stop if the budget has been used up
+    pub fn exhausted(&self, processed_points: u64) -> Option<StopReason> {
+        if interrupt::requested() {
+            return Some(StopReason::Interrupted);
+        }
+        if self.max_points.is_some_and(|max| processed_points >= max) {
+            return Some(StopReason::MaxPoints);
+        }
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Some(StopReason::MaxRuntime);
+        }
+        None
+    }
+
+    /// Do not count time spent paused towards the runtime
+    pub fn extend(&mut self, paused: Duration) {
+        if let Some(deadline) = &mut self.deadline {
+            *deadline += paused;
+        }
+    }
+
+    /// Limit the number of points to read next so that the point budget is not exceeded
+    pub fn limit_points(&self, processed_points: u64, wanted: u64) -> u64 {
+        match self.max_points {
+            Some(max) => wanted.min(max.saturating_sub(processed_points)),
+            None => wanted,
+        }
+    }
+}
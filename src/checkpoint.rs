@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    input::InputFile,
+    output::Output,
+    schema::{Document, Versioned},
+};
+
+/// Name of the checkpoint file written to the output folder when a run stops early
+pub const CHECKPOINT_FILE_NAME: &str = "checkpoint.json";
+
+/// Records how far a run got before it was stopped early
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Checkpoint {
+    /// Why the run was stopped
+    pub stop_reason: String,
+
+    /// The tile size used for this run, unless the tiles were read from a tile index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tile_size: Option<f64>,
+
+    /// The tile size along X used for this run, for rectangular tiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tile_size_x: Option<f64>,
+
+    /// The tile size along Y used for this run, for rectangular tiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tile_size_y: Option<f64>,
+
+    /// The Z tile size used for this run, if tiles were also partitioned along Z
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tile_size_z: Option<f64>,
+
+    /// The tile index the tiles were read from, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tiles_from: Option<PathBuf>,
+
+    /// Total number of points written to output tiles
+    pub points_processed: u64,
+
+    /// Input files that were read completely
+    pub completed_inputs: Vec<InputFile>,
+
+    /// The input file that was being read when the run stopped, if any
+    pub partial_input: Option<PartialInput>,
+
+    /// Input files that were not read at all
+    pub remaining_inputs: Vec<InputFile>,
+
+    /// Output tiles that received all their points
+    pub complete_tiles: Vec<String>,
+
+    /// Output tiles that were written to but are missing points from unread inputs
+    pub incomplete_tiles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PartialInput {
+    pub path: InputFile,
+    pub points_processed: u64,
+}
+
+impl Document for Checkpoint {
+    const SCHEMA_VERSION: &'static str = "1.3";
+}
+
+impl Checkpoint {
+    pub fn write(&self, output: &Output) -> Result<()> {
+        let path = output.path(CHECKPOINT_FILE_NAME);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("create checkpoint: {}", path.display()))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &Versioned::new(self))
+            .with_context(|| format!("write checkpoint: {}", path.display()))?;
+        output.publish(CHECKPOINT_FILE_NAME)
+    }
+
+    /// The incomplete tiles recorded by the checkpoint of a previous run that stopped early, if
+    /// there is one
+    pub fn read_incomplete_tiles(output: &Output) -> Result<Vec<String>> {
+        let Some(data) = output.read(CHECKPOINT_FILE_NAME)? else {
+            return Ok(Vec::new());
+        };
+        let checkpoint: serde_json::Value =
+            serde_json::from_slice(&data).context("read checkpoint of the previous run")?;
+        Ok(checkpoint["incomplete_tiles"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name.as_str().map(str::to_owned))
+            .collect())
+    }
+
+    /// Remove a checkpoint left behind by a previous run that stopped early
+    pub fn remove_stale(output: &Output) -> Result<()> {
+        output
+            .remove(CHECKPOINT_FILE_NAME)
+            .context("remove stale checkpoint")
+    }
+}
@@ -0,0 +1,1057 @@
+use std::{
+    ffi::OsString,
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    compression::ChunkSize,
+    crs::Crs,
+    grid_scheme::GridScheme,
+    memory,
+    schema::SchemaName,
+    shard::ShardSpec,
+    tiling::GridDivision,
+    units::{LinearUnit, UnitSystem, Units},
+};
+
+/// Re-tile LAS/LAZ point cloud files into a new tile size
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // retiling is the default when no command is given
+    #[command(flatten)]
+    pub args: Args,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+
+    /// Limit all parallel work (LAZ decompression and compression, tile writer threads, header
+    /// reads) to this many threads, e.g. on shared servers (defaults to the number of available
+    /// cores)
+    #[arg(long, value_name = "N", global = true)]
+    pub threads: Option<NonZeroUsize>,
+}
+
+/// The command line of the first versions, `lasretile <INPUT> <OUTPUT> <TILE_SIZE>`, with the
+/// options that replaced the positional output folder and tile size. `None` for any other
+/// command line.
+pub fn legacy_args(args: &[OsString]) -> Option<Vec<OsString>> {
+    let [program, input, output, tile_size] = args else {
+        return None;
+    };
+    let is_option_or_command = |arg: &OsString| {
+        arg.to_str().is_some_and(|arg| {
+            arg.starts_with('-') || Cli::command().find_subcommand(arg).is_some()
+        })
+    };
+    if is_option_or_command(input) || is_option_or_command(output) {
+        return None;
+    }
+    tile_size.to_str()?.parse::<f64>().ok()?;
+    Some(vec![
+        program.clone(),
+        input.clone(),
+        "--output".into(),
+        output.clone(),
+        "--tile-size".into(),
+        tile_size.clone(),
+    ])
+}
+
+/// How much is logged, and where
+#[derive(Debug, clap::Args)]
+pub struct LogArgs {
+    /// Log more details, repeat for even more (-vv)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only log warnings, repeat to only log errors (-qq)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub quiet: u8,
+
+    /// Also write the log, including details, to this file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Concatenate all inputs into a single LAS/LAZ file
+    Merge(MergeArgs),
+    /// Split each input into files with at most a fixed number of points, ignoring geometry
+    Split(SplitArgs),
+    /// Merge a folder of small adjacent tiles into a coarser grid
+    Compact(CompactArgs),
+    /// Print the point counts, bounds, formats and CRS of the inputs from their headers, and the
+    /// number of tiles for a few tile sizes
+    Info(InfoArgs),
+    /// Read all points of the inputs to check them against their headers and each other, and
+    /// print a JSON report of the problems found
+    Validate(ValidateArgs),
+    /// Read all tiles in an output folder to detect incomplete or corrupt files, e.g. left by a
+    /// crash or a full disk, and print a JSON report of the problems found
+    Verify(VerifyArgs),
+    /// Compare the inputs against the manifest.json of an earlier run with --manifest, and only
+    /// write the tiles of added, modified or removed inputs again, with the same options as the
+    /// earlier run
+    Update(Box<Args>),
+}
+
+/// The input files to process
+#[derive(Debug, clap::Args)]
+pub struct InputArgs {
+    /// Input folders and/or LAS/LAZ files, `-` for a LAS/LAZ stream on stdin (copied to the
+    /// system temporary directory before it is read)
+    pub inputs: Vec<PathBuf>,
+
+    /// Read additional input paths from a file with one path per line (`-` for stdin)
+    #[arg(long, value_name = "FILE")]
+    pub input_list: Option<PathBuf>,
+
+    /// Also scan the sub folders (and sub prefixes) of input folders for LAS/LAZ files
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// File to cache the headers of local input files in, so that later runs over the same
+    /// inputs only open the files that changed
+    #[arg(long, value_name = "FILE")]
+    pub header_cache: Option<PathBuf>,
+}
+
+/// User-supplied filters that every point passes through before it is written
+#[derive(Debug, clap::Args)]
+pub struct FilterArgs {
+    /// WASM module to filter/transform the points with before they are written, see the
+    /// README for the interface it must implement
+    #[arg(long, value_name = "FILE")]
+    pub wasm_filter: Option<PathBuf>,
+
+    /// Rhai script evaluated for every point, which can modify the `point` variable and drop it
+    /// by evaluating to `false` (much slower than the built-in processing)
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+
+    /// Only keep the ground points (class 2), e.g. for a bare-earth tile set
+    #[arg(long)]
+    pub ground_only: bool,
+
+    /// Other points to keep with --ground-only
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POINTS",
+        value_delimiter = ',',
+        requires = "ground_only"
+    )]
+    pub ground_with: Vec<GroundWith>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroundWith {
+    /// Water (class 9)
+    Water,
+    /// Model key points (class 8, or flagged as key points)
+    KeyPoints,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MergeArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// The LAS/LAZ file to write, compressed if it has a `.laz` extension, or `-` to write the
+    /// merged stream to stdout (assembled in the system temporary directory first)
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SplitArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Directory where the split files will be written, or a `s3://`, `gs://` or `az://` URL to
+    /// upload them to
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Maximum number of points in each output file
+    #[arg(long, value_name = "N")]
+    pub max_points: NonZeroU64,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompactArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Directory where the compacted tiles will be written, or a `s3://`, `gs://` or `az://` URL
+    /// to upload them to
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Size of the coarser tiles, each input tile must lie within a single one of them (e.g. a
+    /// multiple of the input tile size)
+    #[arg(short, long)]
+    pub tile_size: f64,
+
+    /// Write a GeoJSON tile index with the outline and statistics of each compacted tile
+    #[arg(long, value_name = "FILE")]
+    pub tile_index: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub units: UnitArgs,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Tile sizes to count the tiles for, defaults to a few sizes around the size of the inputs
+    #[arg(short, long, value_name = "SIZE", value_delimiter = ',')]
+    pub tile_sizes: Option<Vec<f64>>,
+
+    #[command(flatten)]
+    pub units: UnitArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(group(
+    clap::ArgGroup::new("grid")
+        .multiple(true)
+        .args(["tile_size", "tile_size_x", "tile_size_y"])
+))]
+pub struct VerifyArgs {
+    /// The output folder (or tiles) of a run
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// The tile size the tiles were written with, to check that each tile only holds points
+    /// within the grid cell it is named after
+    #[arg(short, long)]
+    pub tile_size: Option<f64>,
+
+    /// The width along X of rectangular tiles, instead of `--tile-size`
+    #[arg(
+        long,
+        value_name = "SIZE",
+        requires = "tile_size_y",
+        conflicts_with = "tile_size"
+    )]
+    pub tile_size_x: Option<f64>,
+
+    /// The height along Y of rectangular tiles, instead of `--tile-size`
+    #[arg(
+        long,
+        value_name = "SIZE",
+        requires = "tile_size_x",
+        conflicts_with = "tile_size"
+    )]
+    pub tile_size_y: Option<f64>,
+
+    /// The Z tile size the tiles were written with, if any
+    #[arg(long, requires = "grid")]
+    pub tile_size_z: Option<f64>,
+
+    /// How the tiles were named
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        requires = "grid"
+    )]
+    pub tile_names: TileNames,
+}
+
+impl VerifyArgs {
+    /// The X and Y size of the grid tiles, if given
+    pub fn grid_size(&self) -> Option<[f64; 2]> {
+        self.tile_size
+            .map(|size| [size, size])
+            .or(self.tile_size_x.zip(self.tile_size_y).map(|(x, y)| [x, y]))
+    }
+}
+
+/// Options for retiling
+#[derive(Debug, clap::Args)]
+#[command(group(
+    clap::ArgGroup::new("grid")
+        .multiple(true)
+        .args(["tile_size", "tile_size_x", "tile_size_y", "target_points", "grid_scheme", "grid_cells"])
+))]
+pub struct Args {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Read options from a TOML file, with the long option names as keys (e.g.
+    /// `tile-size = 1000`) and `inputs` for the inputs. Options on the command line take
+    /// precedence.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Directory where new tiles will be written, a `s3://`, `gs://` or `az://` URL to upload
+    /// the tiles to, or `-` to write them to stdout as a tar archive
+    #[arg(short, long, required_unless_present = "schema")]
+    pub output: Option<PathBuf>,
+
+    /// Local directory where tiles are written before being uploaded to a remote output or
+    /// written to stdout (defaults to a folder in the system temporary directory)
+    #[arg(long, value_name = "DIR")]
+    pub staging_dir: Option<PathBuf>,
+
+    /// Tile size in the same units as the LAS/LAZ files (e.g., meters)
+    #[arg(
+        short,
+        long,
+        required_unless_present_any = [
+            "schema",
+            "tiles_from",
+            "split_by",
+            "tile_size_x",
+            "target_points",
+            "grid_scheme",
+            "grid_cells"
+        ]
+    )]
+    pub tile_size: Option<f64>,
+
+    /// Width of the tiles along X, for rectangular tiles together with `--tile-size-y`
+    #[arg(
+        long,
+        value_name = "SIZE",
+        requires = "tile_size_y",
+        conflicts_with = "tile_size"
+    )]
+    pub tile_size_x: Option<f64>,
+
+    /// Height of the tiles along Y, for rectangular tiles together with `--tile-size-x`
+    #[arg(
+        long,
+        value_name = "SIZE",
+        requires = "tile_size_x",
+        conflicts_with = "tile_size"
+    )]
+    pub tile_size_y: Option<f64>,
+
+    /// Pick the tile size that gives about this many points per tile, estimated from the point
+    /// count and area of the inputs
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["tile_size", "tile_size_x", "tile_size_y"]
+    )]
+    pub target_points: Option<NonZeroU64>,
+
+    /// Use the tile size and tile names of a standard grid, the inputs have to be in the CRS of
+    /// the grid
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SCHEME",
+        conflicts_with_all = ["tile_size", "tile_size_x", "tile_size_y", "target_points", "tile_names"]
+    )]
+    pub grid_scheme: Option<GridScheme>,
+
+    /// Divide the extent of the inputs into this many columns and rows of tiles (e.g. `4x4`),
+    /// instead of tiles of a fixed size
+    #[arg(
+        long = "grid",
+        value_name = "COLUMNSxROWS",
+        conflicts_with_all = ["tile_size", "tile_size_x", "tile_size_y", "target_points", "grid_scheme"]
+    )]
+    pub grid_cells: Option<GridDivision>,
+
+    /// Use the (multi)polygon features of this GeoJSON tile index as output tiles instead of a
+    /// regular grid, named after their `name` property. Only `.geojson`/`.json` files are read,
+    /// convert GeoPackage and other formats with `ogr2ogr -f GeoJSON`.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_geojson_path,
+        conflicts_with_all = ["grid", "tile_size_z"]
+    )]
+    pub tiles_from: Option<PathBuf>,
+
+    /// Only keep the points inside the polygons of this GeoJSON file, which must use the same
+    /// CRS as the input files. Tiles completely outside the polygons are not created. Only
+    /// `.geojson`/`.json` files are read, convert GeoPackage and other formats with
+    /// `ogr2ogr -f GeoJSON`.
+    #[arg(long, value_name = "FILE", value_parser = parse_geojson_path)]
+    pub clip: Option<PathBuf>,
+
+    /// Shift all points by `DX,DY,DZ` before they are assigned to tiles, e.g. to correct a known
+    /// survey offset. Applied after --affine.
+    #[arg(
+        long,
+        value_name = "DX,DY,DZ",
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    pub translate: Option<Vec<f64>>,
+
+    /// Transform all points with an affine transformation before they are assigned to tiles,
+    /// given as a 3×4 or 4×4 matrix in row-major order (12 or 16 comma-separated values)
+    #[arg(
+        long,
+        value_name = "MATRIX",
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    pub affine: Option<Vec<f64>>,
+
+    /// Add the value of this single band GeoTIFF at the XY of each point to its Z, e.g. geoid
+    /// heights to convert ellipsoidal heights to orthometric ones
+    #[arg(long, value_name = "FILE")]
+    pub z_adjust: Option<PathBuf>,
+
+    /// Set the color of each point to the one of the pixel of this RGB(A) GeoTIFF orthophoto at
+    /// its XY, moving to a point format with colors if needed
+    #[arg(long, value_name = "FILE")]
+    pub colorize: Option<PathBuf>,
+
+    /// Only keep the points within `MINX,MINY,MAXX,MAXY`, in the CRS of the input files. Input
+    /// files completely outside are not read at all.
+    #[arg(
+        long,
+        value_name = "MINX,MINY,MAXX,MAXY",
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    pub bounds: Option<Vec<f64>>,
+
+    /// Also partition the tiles along Z into layers of this size, giving octree-style
+    /// `tile_<x>_<y>_<z>.laz` tiles
+    #[arg(long, value_name = "SIZE", requires = "grid")]
+    pub tile_size_z: Option<f64>,
+
+    /// Write each tile to the path given by this template, relative to the output folder, to
+    /// spread the tiles over subfolders. `{name}` is replaced by the file name of the tile,
+    /// `{x}`, `{y}` and `{z}` by its index and `{x_div_N}` and `{y_div_N}` by its index divided
+    /// by `N`, e.g. `{x_div_10}/{y_div_10}/{name}`
+    #[arg(long, value_name = "TEMPLATE")]
+    pub output_layout: Option<String>,
+
+    /// How the grid tiles are named: by their index, or by the coordinates of their lower left
+    /// corner like most national lidar products
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        requires = "grid"
+    )]
+    pub tile_names: TileNames,
+
+    /// Further partition each tile into one file per group of points. Without a tile size or
+    /// tile index, all points are partitioned as a single tile.
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub split_by: Option<SplitBy>,
+
+    /// LAS version of the output tiles, defaults to the version of the inputs (or the one the
+    /// point format requires)
+    #[arg(long, value_enum, value_name = "VERSION")]
+    pub las_version: Option<LasVersion>,
+
+    /// Assign this CRS to all inputs, replacing any CRS in their headers: `EPSG:<code>` (with
+    /// an optional vertical CRS, e.g. `EPSG:2056+5728`), WKT or a file with WKT (e.g. a `.prj`)
+    #[arg(long, value_name = "CRS")]
+    pub a_srs: Option<Crs>,
+
+    /// Only warn instead of stopping when the inputs declare different coordinate systems
+    #[arg(long)]
+    pub ignore_crs_mismatch: bool,
+
+    /// Number of points per LAZ chunk in the output tiles, or `variable`. Smaller chunks make
+    /// spatial subsets of the tiles faster to read later. Defaults to the LASzip default of
+    /// 50000 points.
+    #[arg(long, value_name = "POINTS")]
+    pub laz_chunk_size: Option<ChunkSize>,
+
+    /// Copy the compressed LAZ chunks of COPC inputs that lie within a single tile into the
+    /// tile as they are, instead of decompressing and compressing their points again. Only for
+    /// inputs with the scale, offset and point format of the tiles, and not with options that
+    /// change or inspect the points. The tiles get chunks of varying size.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "laz_chunk_size", "wasm_filter", "script", "ground_only", "translate", "affine",
+            "z_adjust", "colorize", "tile_size_z", "tiles_from", "split_by", "renumber_psid",
+            "normalize_intensity", "stats", "tile_index", "density_raster", "boundary",
+            "max_points"
+        ]
+    )]
+    pub chunk_passthrough: bool,
+
+    /// Renumber the point source IDs of each input file into a compact range starting at 1, so
+    /// IDs of different deliveries no longer collide, and write the mapping to
+    /// `psid_mapping.json` in the output folder
+    #[arg(long)]
+    pub renumber_psid: bool,
+
+    /// Equalize the intensity of the point source IDs (flight lines), so the tiles show no
+    /// stripes between them: a first pass over all inputs collects the mean and standard
+    /// deviation of each, which are then mapped onto those of all points
+    #[arg(long)]
+    pub normalize_intensity: bool,
+
+    /// Set the File Source ID of each output tile from a template, where `{n}` is replaced by
+    /// the sequential number of the tile, `{x}`, `{y}` and `{z}` by its index and `{part}` by
+    /// its part with `--split-by` (e.g. `{n}` or `1{x}{y}`)
+    #[arg(long, value_name = "TEMPLATE")]
+    pub file_source_id: Option<String>,
+
+    /// Minimum gap in GPS time between two flight lines with the same point source ID, for
+    /// `--split-by flightline`
+    #[arg(long, value_name = "SECONDS", default_value_t = 10.0)]
+    pub flightline_gap: f64,
+
+    /// Scale factors of the coordinates in the output tiles, one value for all axes or `X,Y,Z`.
+    /// Defaults to the scale of the inputs, or the finest one if they differ.
+    #[arg(
+        long,
+        visible_alias = "rescale",
+        value_name = "SCALE",
+        value_delimiter = ','
+    )]
+    pub scale: Option<Vec<f64>>,
+
+    /// Offsets of the coordinates in the output tiles, one value for all axes or `X,Y,Z`.
+    /// Defaults to the offset of the inputs, or one near the minimum of the bounds if they
+    /// differ.
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    pub offset: Option<Vec<f64>>,
+
+    /// Point data record format of the output tiles, points of other formats are converted.
+    /// Defaults to the format of the inputs, or the richest one if they differ.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=10))]
+    pub point_format: Option<u8>,
+
+    /// Write the points of inputs with waveforms (point formats 4, 5, 9 and 10) without them,
+    /// in the format without waveforms (1, 3, 6 and 8), dropping the wave packet descriptors.
+    /// Their waveform packets can not be copied to the tiles.
+    #[arg(long)]
+    pub strip_waveforms: bool,
+
+    /// Recursively split tiles into four quadrants (quadtree) until each holds fewer than
+    /// --max-points-per-tile points. The quadrants taken are appended to the tile name, e.g.
+    /// `tile_3_4_02.laz`.
+    #[arg(long, requires = "max_points_per_tile")]
+    pub adaptive: bool,
+
+    /// Maximum number of points in each output file with --adaptive
+    #[arg(long, value_name = "N", requires = "adaptive")]
+    pub max_points_per_tile: Option<NonZeroU64>,
+
+    /// Also write N levels of overview tiles, `overview<level>_<x>_<y>.laz`, where each level
+    /// has tiles of twice the size and a quarter of the points of the level below it
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "grid",
+        conflicts_with_all = ["tiles_from", "tile_size_z", "split_by", "adaptive", "shard"],
+        value_parser = clap::value_parser!(u32).range(1..=16)
+    )]
+    pub overviews: Option<u32>,
+
+    /// Read the inputs twice: first only counting the points of each tile, then writing the
+    /// tiles, which are closed as soon as the last input with points in them is read
+    #[arg(long, conflicts_with_all = ["wasm_filter", "script"])]
+    pub two_pass: bool,
+
+    /// Do not create tiles with fewer points than this, dropping their points (needs
+    /// --two-pass)
+    #[arg(long, value_name = "N", requires = "two_pass")]
+    pub min_tile_points: Option<NonZeroU64>,
+
+    /// Maximum number of output tiles that are kept open for writing at the same time. Writers
+    /// are closed in least-recently-used order and reopened transparently when needed.
+    #[arg(long, value_name = "N")]
+    pub max_open_files: Option<NonZeroUsize>,
+
+    /// Number of threads compressing and writing output tiles concurrently (defaults to
+    /// --threads, or the number of available cores)
+    #[arg(long, value_name = "N")]
+    pub writer_threads: Option<NonZeroUsize>,
+
+    /// Read local inputs and write the tiles with this many positioned reads or writes in flight
+    /// per file (default 8), for network file systems and NVMe arrays with deep queues
+    #[cfg(all(feature = "async-io", unix))]
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        num_args = 0..=1,
+        default_missing_value = "8"
+    )]
+    pub async_io: Option<NonZeroUsize>,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+
+    /// Approximate memory budget for the point read buffer, the tile write buffers, sorting
+    /// tiles by GPS time and finding duplicates, e.g. `512M` or `8G` (defaults to --memory-percent of the available
+    /// memory)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub memory: Option<u64>,
+
+    /// Share of the available memory used as the memory budget without --memory, in percent
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = memory::DEFAULT_MEMORY_PERCENT,
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    pub memory_percent: u8,
+
+    /// Profile for machines with little memory: small read buffers, sequential decompression, a
+    /// single writer thread and few open writers (evicted tiles spill to part files that are
+    /// merged when the tile is finished). Explicit --memory, --max-open-files and
+    /// --writer-threads take precedence.
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// Pause while this file exists, e.g. to temporarily yield I/O to higher-priority work. On
+    /// unix, sending SIGUSR1 to the process also pauses it and a second SIGUSR1 resumes it.
+    #[arg(long, value_name = "FILE")]
+    pub pause_file: Option<PathBuf>,
+
+    /// After retiling, keep watching the input folders and retile new LAS/LAZ files as they
+    /// arrive, rebuilding the tiles they touch from all of their inputs
+    #[arg(long, conflicts_with_all = ["max_points", "max_runtime", "shard"])]
+    pub watch: bool,
+
+    /// With --watch, wait until no file has changed for this many seconds before retiling the
+    /// new files, so that files still being written are not read
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 10.0,
+        requires = "watch"
+    )]
+    pub watch_settle: f64,
+
+    /// Stop cleanly after this many points have been written
+    #[arg(long, value_name = "N")]
+    pub max_points: Option<u64>,
+
+    /// Stop cleanly after running for this long (e.g. `90s`, `45m`, `2h`)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub max_runtime: Option<Duration>,
+
+    /// Write a JSON report with statistics (point count, bounds, Z histogram and percentiles)
+    /// for each tile
+    #[arg(long, value_name = "FILE")]
+    pub stats: Option<PathBuf>,
+
+    /// Write a GeoJSON tile index with the outline and statistics of each tile
+    #[arg(long, value_name = "FILE")]
+    pub tile_index: Option<PathBuf>,
+
+    /// Only write the tiles of shard `i` of `n` (e.g. `2/8`), reading only the inputs that touch
+    /// them, to split a job across machines
+    #[arg(long, value_name = "I/N")]
+    pub shard: Option<ShardSpec>,
+
+    /// Write a PDAL pipeline equivalent to the retiling plan (readers, crop filters and a writer
+    /// per tile), to audit the plan or run it with PDAL instead
+    #[arg(long, value_name = "FILE")]
+    pub export_pdal: Option<PathBuf>,
+
+    /// Write a `manifest.json` with the point count, bounds and SHA-256 checksum of every tile to
+    /// the output folder
+    #[arg(long)]
+    pub manifest: bool,
+
+    /// Write a `<tile>.json` next to each tile with its bounds, point count, classes, density,
+    /// source files and the processing parameters
+    #[arg(long)]
+    pub sidecar: bool,
+
+    /// Write a `summary.json` with the inputs read, the point count and bounds of every tile,
+    /// the dropped points by reason and timings to the output folder
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Shell command to run on each tile once it has been written, e.g. `lasindex -i {path}`.
+    /// `{path}` is replaced by the path of the tile and `{name}` by its name, both quoted
+    #[arg(long, value_name = "COMMAND")]
+    pub on_tile_complete: Option<String>,
+
+    /// Number of --on-tile-complete commands that run at the same time
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "2",
+        requires = "on_tile_complete"
+    )]
+    pub on_tile_complete_jobs: NonZeroUsize,
+
+    /// Log the throughput of decoding, routing and encoding the points, the time spent on
+    /// each input file and the peak memory usage at the end of the run
+    #[arg(long)]
+    pub bench: bool,
+
+    /// After the run, compare the written tiles against the `manifest.json` of an earlier run
+    /// and fail on any difference
+    #[arg(long, value_name = "FILE")]
+    pub compare_manifest: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub units: UnitArgs,
+
+    /// Write a GeoTIFF raster with the density (points per unit area) of the written points, in
+    /// the CRS of the first input if it has GeoTIFF keys
+    #[arg(long, value_name = "FILE")]
+    pub density_raster: Option<PathBuf>,
+
+    /// Size of the cells of --density-raster
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0)]
+    pub density_cell_size: f64,
+
+    /// Write a GeoJSON file with the footprint of the actual point coverage, overall and per tile,
+    /// traced from the cells of --boundary-cell-size that contain points
+    #[arg(long, value_name = "FILE")]
+    pub boundary: Option<PathBuf>,
+
+    /// Size of the cells of --boundary, larger cells give simpler polygons that bridge small gaps
+    #[arg(long, value_name = "SIZE", default_value_t = 10.0)]
+    pub boundary_cell_size: f64,
+
+    /// Bin size of the per-tile Z histograms
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0)]
+    pub z_bin_size: f64,
+
+    /// Proceed even if input files have overlapping bounds, keeping all points
+    #[arg(long)]
+    pub allow_overlap: bool,
+
+    /// How to thin regions where flightlines (point source IDs) overlap. Allows overlapping
+    /// input files.
+    #[arg(long, value_enum, value_name = "PREFERENCE")]
+    pub overlap_prefer: Option<OverlapPreference>,
+
+    /// Remove duplicate points from overlapping inputs, e.g. re-delivered swaths. This also
+    /// allows input files with overlapping bounds.
+    #[arg(long, value_enum, value_name = "POLICY")]
+    pub dedupe: Option<Dedupe>,
+
+    /// Also require the same GPS time for points to be duplicates with `--dedupe exact`
+    #[arg(long)]
+    pub dedupe_gps_time: bool,
+
+    /// Size of the cells in which flightlines are compared with `--dedupe keep-latest`
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0)]
+    pub dedupe_tolerance: f64,
+
+    /// Size of the cells in which overlapping flightlines are detected and thinned
+    #[arg(long, value_name = "SIZE", default_value_t = 1.0)]
+    pub overlap_cell_size: f64,
+
+    /// What to do with tiles that cross the antimeridian or lie outside the valid area of the
+    /// CRS of the input files
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ExtentPolicy::Warn)]
+    pub invalid_extent: ExtentPolicy,
+
+    /// What to do with output tiles that already exist in the output folder, e.g. when rerunning
+    /// an interrupted job
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ExistingPolicy::Overwrite)]
+    pub if_exists: ExistingPolicy,
+
+    /// Start even if the tiles are estimated not to fit into the free space of the output folder
+    #[arg(long)]
+    pub force: bool,
+
+    /// How to report progress: a progress bar, or newline-delimited JSON events (file started,
+    /// points processed, tile closed, ...) on stdout for other programs
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = ProgressMode::Bar)]
+    pub progress: ProgressMode,
+
+    /// Write the JSON progress events to this file descriptor instead of stdout (unix only)
+    #[arg(long, value_name = "FD")]
+    pub progress_fd: Option<i32>,
+
+    /// What to do with points that lie outside the bounds in the header of their input file
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OutOfBoundsPolicy::Warn)]
+    pub out_of_bounds: OutOfBoundsPolicy,
+
+    /// The order in which the input files are read
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = InputOrder::Hilbert)]
+    pub input_order: InputOrder,
+
+    /// The order of the points within each output tile
+    #[arg(long, value_enum, default_value_t = TileOrder::Input)]
+    pub tile_order: TileOrder,
+
+    /// Print the JSON schema of a machine-readable output and exit
+    #[arg(long, value_enum, value_name = "NAME")]
+    pub schema: Option<SchemaName>,
+}
+
+impl Args {
+    /// The X and Y size of the grid tiles, unless the tiles are not a grid
+    pub fn grid_size(&self) -> Option<[f64; 2]> {
+        self.tile_size
+            .map(|size| [size, size])
+            .or(self.tile_size_x.zip(self.tile_size_y).map(|(x, y)| [x, y]))
+            .or(self.grid_scheme.map(GridScheme::size))
+    }
+
+    /// The units to report lengths, areas and densities in
+    pub fn units(&self) -> Units {
+        self.units.units()
+    }
+}
+
+/// The units of the input coordinates and of the reported values
+#[derive(Debug, clap::Args)]
+pub struct UnitArgs {
+    /// Linear unit of the coordinates of the input files
+    #[arg(long, value_enum, value_name = "UNIT", default_value_t = LinearUnit::Meter)]
+    pub coordinate_units: LinearUnit,
+
+    /// Unit system for lengths, areas and point densities in the console output and reports
+    #[arg(long, value_enum, value_name = "SYSTEM", default_value_t = UnitSystem::Metric)]
+    pub report_units: UnitSystem,
+}
+
+impl UnitArgs {
+    /// The units to report lengths, areas and densities in
+    pub fn units(&self) -> Units {
+        Units {
+            coordinates: self.coordinate_units,
+            system: self.report_units,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LasVersion {
+    /// For legacy software, only point formats 0 to 3
+    #[value(name = "1.2")]
+    V1_2,
+    #[value(name = "1.4")]
+    V1_4,
+}
+
+impl LasVersion {
+    pub fn version(self) -> las::Version {
+        match self {
+            LasVersion::V1_2 => las::Version::new(1, 2),
+            LasVersion::V1_4 => las::Version::new(1, 4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SplitBy {
+    /// One file per flight line (`_l<n>`), detected from the point source IDs and gaps in GPS
+    /// time
+    Flightline,
+    /// One file per point source ID (`_p<id>`)
+    Psid,
+    /// One file per classification (`_c<class>`, e.g. `_c02` for ground)
+    Class,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputOrder {
+    /// Along a Hilbert curve through the centers of the inputs, so that neighbouring files are
+    /// read one after another and fewer tiles are open at the same time
+    Hilbert,
+    /// In the order the inputs were given, with the files of folders sorted by name
+    Given,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TileNames {
+    /// By the grid index, e.g. `tile_534_6723.laz`
+    #[default]
+    Index,
+    /// By the coordinates of the lower left corner, e.g. `534000_6723000.laz`
+    Coords,
+    /// By the coordinates of the lower left corner in thousands (kilometers for metric CRSs),
+    /// e.g. `534_6723.laz`
+    Km,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TileOrder {
+    /// Keep the order in which the points were read from the input files
+    Input,
+    /// Sort the points by GPS time, in blocks sized from --memory that are then merged
+    GpsTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OverlapPreference {
+    /// Keep the flightline with the smallest mean absolute scan angle (closest to nadir)
+    Nadir,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dedupe {
+    /// Drop points with exactly the same coordinates as a point already in the tile
+    Exact,
+    /// Where flightlines overlap within `--dedupe-tolerance`, keep only the one with the most
+    /// recent GPS time
+    KeepLatest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExtentPolicy {
+    /// Print a warning (as a JSON line on stderr) and write the tile anyway
+    Warn,
+    /// Print a warning and drop the points of the tile
+    Skip,
+    /// Abort before writing anything
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// An interactive progress bar
+    Bar,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutOfBoundsPolicy {
+    /// Keep the points and report how many there were per file. Tiles that only receive such
+    /// points are created as needed, but points for tiles that were already written are dropped.
+    Warn,
+    /// Drop the points and report how many there were per file
+    Clip,
+    /// Abort at the first such point
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExistingPolicy {
+    /// Abort before writing anything
+    Error,
+    /// Keep the existing tile and drop its points, so finished tiles are not written again
+    Skip,
+    /// Replace the existing tile
+    Overwrite,
+    /// Rewrite the existing tile with its points and the new ones
+    Append,
+}
+
+/// Parse a size in bytes with an optional binary unit suffix, e.g. `200M`, `8G` or `1.5GiB`
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: f64 = number.parse().map_err(|_| format!("invalid size: {s:?}"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("unknown size unit in {s:?}")),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parse the path of a GeoJSON file, rejecting other vector formats before anything is read
+pub fn parse_geojson_path(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    let is_geojson = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("geojson") || ext.eq_ignore_ascii_case("json"));
+    if !is_geojson {
+        return Err(format!(
+            "{s} is not a GeoJSON file (.geojson or .json), convert GeoPackage and other formats with e.g. `ogr2ogr -f GeoJSON polygons.geojson {s}`"
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(Into::into).collect()
+    }
+
+    #[test]
+    fn legacy_positional_arguments_become_options() {
+        assert_eq!(
+            legacy_args(&args(&["lasretile", "in", "out", "100"])),
+            Some(args(&[
+                "lasretile",
+                "in",
+                "--output",
+                "out",
+                "--tile-size",
+                "100"
+            ]))
+        );
+    }
+
+    #[test]
+    fn other_command_lines_are_kept() {
+        for other in [
+            &["lasretile", "a.laz", "b.laz", "c.laz"][..],
+            &["lasretile", "in", "-o", "out"],
+            &["lasretile", "-r", "in", "100"],
+            &["lasretile", "info", "in", "100"],
+            &["lasretile", "in", "out", "100", "-r"],
+        ] {
+            assert_eq!(legacy_args(&args(other)), None, "{other:?}");
+        }
+    }
+
+    #[test]
+    fn only_geojson_polygons_are_read() {
+        assert_eq!(
+            parse_geojson_path("tiles.GeoJSON"),
+            Ok(PathBuf::from("tiles.GeoJSON"))
+        );
+        assert!(parse_geojson_path("boundary.json").is_ok());
+
+        let err = parse_geojson_path("index.gpkg").unwrap_err();
+        assert!(err.contains("ogr2ogr -f GeoJSON"), "{err}");
+        assert!(parse_geojson_path("index").is_err());
+    }
+}
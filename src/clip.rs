@@ -0,0 +1,317 @@
+//! Clipping the output to polygons read from a GeoJSON file (`--clip`) and/or a rectangle
+//! (`--bounds`)
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// How a tile relates to the clip area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coverage {
+    /// The tile lies completely outside all polygons, it does not need to be created
+    Outside,
+    /// The tile lies completely inside a polygon, all of its points are kept
+    Inside,
+    /// The tile crosses the boundary of a polygon, each point needs to be checked
+    Partial,
+}
+
+/// A polygon with optional holes, all rings are treated the same using the even-odd rule
+pub struct Polygon {
+    rings: Vec<Vec<[f64; 2]>>,
+    /// `[min x, min y, max x, max y]`
+    bbox: [f64; 4],
+}
+
+/// The area that points are clipped to: the polygons of a `--clip` file, the `--bounds`
+/// rectangle or both
+pub struct Clip {
+    /// Points must lie inside one of the polygons, unless there are none
+    polygons: Vec<Polygon>,
+    /// `[min x, min y, max x, max y]` that points must lie within, borders included
+    bounds: Option<[f64; 4]>,
+}
+
+impl Clip {
+    /// Clip to the polygons of a GeoJSON file and/or to the `[min x, min y, max x, max y]`
+    /// bounds, if any of them are given
+    pub fn new(path: Option<&Path>, bounds: Option<&[f64]>) -> Result<Option<Self>> {
+        let bounds = bounds
+            .map(|values| -> Result<[f64; 4]> {
+                let bounds: [f64; 4] = values
+                    .try_into()
+                    .ok()
+                    .context("--bounds takes four values, MINX,MINY,MAXX,MAXY")?;
+                let [x0, y0, x1, y1] = bounds;
+                anyhow::ensure!(
+                    x0 < x1 && y0 < y1,
+                    "the minimum of --bounds must be less than the maximum"
+                );
+                Ok(bounds)
+            })
+            .transpose()?;
+        let polygons = match path {
+            Some(path) => Self::load(path)?,
+            None if bounds.is_some() => Vec::new(),
+            None => return Ok(None),
+        };
+        Ok(Some(Self { polygons, bounds }))
+    }
+
+    /// Read all (multi)polygons from a GeoJSON file. Their coordinates must be in the same CRS as
+    /// the point clouds.
+    fn load(path: &Path) -> Result<Vec<Polygon>> {
+        crate::cli::parse_geojson_path(&path.to_string_lossy()).map_err(anyhow::Error::msg)?;
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("open clip file: {}", path.display()))?;
+        let json: Value = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("read clip file: {}", path.display()))?;
+
+        let mut polygons = Vec::new();
+        collect_polygons(&json, &mut polygons)
+            .with_context(|| format!("read polygons from {}", path.display()))?;
+        anyhow::ensure!(
+            !polygons.is_empty(),
+            "no polygons found in {}",
+            path.display()
+        );
+        Ok(polygons)
+    }
+
+    /// Whether the point lies inside the clip area
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.bounds.is_none_or(|bounds| inside_rect([x, y], bounds))
+            && (self.polygons.is_empty() || self.polygons.iter().any(|p| p.contains(x, y)))
+    }
+
+    /// The polygons as a WKT multipolygon, if there are any
+    pub fn wkt(&self) -> Option<String> {
+        (!self.polygons.is_empty()).then(|| multipolygon_wkt(&self.polygons))
+    }
+
+    /// The `--bounds` rectangle, if any
+    pub fn bounds(&self) -> Option<[f64; 4]> {
+        self.bounds
+    }
+
+    /// How the rectangle `[min x, min y, max x, max y]` relates to the clip area
+    pub fn coverage(&self, rect: [f64; 4]) -> Coverage {
+        let mut coverage = if self.polygons.is_empty() {
+            Coverage::Inside
+        } else {
+            Coverage::Outside
+        };
+        for polygon in &self.polygons {
+            match polygon.coverage(rect) {
+                Coverage::Inside => {
+                    coverage = Coverage::Inside;
+                    break;
+                }
+                Coverage::Partial => coverage = Coverage::Partial,
+                Coverage::Outside => {}
+            }
+        }
+        let Some([bx0, by0, bx1, by1]) = self.bounds else {
+            return coverage;
+        };
+        let [x0, y0, x1, y1] = rect;
+        if x1 < bx0 || x0 > bx1 || y1 < by0 || y0 > by1 {
+            Coverage::Outside
+        } else if x0 >= bx0 && x1 <= bx1 && y0 >= by0 && y1 <= by1 {
+            coverage
+        } else if coverage == Coverage::Outside {
+            Coverage::Outside
+        } else {
+            Coverage::Partial
+        }
+    }
+}
+
+impl Polygon {
+    pub fn new(rings: Vec<Vec<[f64; 2]>>) -> Self {
+        let mut bbox = [
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        for &[x, y] in rings.iter().flatten() {
+            bbox = [
+                bbox[0].min(x),
+                bbox[1].min(y),
+                bbox[2].max(x),
+                bbox[3].max(y),
+            ];
+        }
+        Self { rings, bbox }
+    }
+
+    /// `[min x, min y, max x, max y]`
+    pub fn bbox(&self) -> [f64; 4] {
+        self.bbox
+    }
+
+    /// The polygon as `[min x, min y, max x, max y]` if it is an axis-aligned rectangle
+    pub fn as_rectangle(&self) -> Option<[f64; 4]> {
+        let [ring] = self.rings.as_slice() else {
+            return None;
+        };
+        // four corners, possibly closed by repeating the first one
+        let corners = match ring.len() {
+            4 => &ring[..],
+            5 if ring[0] == ring[4] => &ring[..4],
+            _ => return None,
+        };
+        let [x0, y0, x1, y1] = self.bbox;
+        let is_rectangle = (0..4).all(|i| {
+            let ([ax, ay], [bx, by]) = (corners[i], corners[(i + 1) % 4]);
+            // every corner is a distinct corner of the bounding box, and every edge is
+            // axis-aligned
+            (ax == x0 || ax == x1)
+                && (ay == y0 || ay == y1)
+                && (ax == bx) != (ay == by)
+                && corners[i] != corners[(i + 2) % 4]
+        });
+        (is_rectangle && x0 < x1 && y0 < y1).then_some(self.bbox)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+        self.rings
+            .iter()
+            .flat_map(|ring| ring.iter().zip(ring.iter().cycle().skip(1)))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let [x0, y0, x1, y1] = self.bbox;
+        if x < x0 || x > x1 || y < y0 || y > y1 {
+            return false;
+        }
+        // count the edges crossed by a ray towards +x
+        let mut inside = false;
+        for ([ax, ay], [bx, by]) in self.edges() {
+            if (ay > y) != (by > y) && x < ax + (y - ay) / (by - ay) * (bx - ax) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    fn coverage(&self, rect: [f64; 4]) -> Coverage {
+        let [x0, y0, x1, y1] = rect;
+        let [bx0, by0, bx1, by1] = self.bbox;
+        if bx1 < x0 || bx0 > x1 || by1 < y0 || by0 > y1 {
+            return Coverage::Outside;
+        }
+
+        let corners = [[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+        let crosses_boundary = self.edges().any(|(a, b)| {
+            inside_rect(a, rect)
+                || (0..4).any(|i| segments_intersect(a, b, corners[i], corners[(i + 1) % 4]))
+        });
+        if crosses_boundary {
+            Coverage::Partial
+        } else if self.contains((x0 + x1) / 2.0, (y0 + y1) / 2.0) {
+            Coverage::Inside
+        } else {
+            Coverage::Outside
+        }
+    }
+}
+
+fn inside_rect([x, y]: [f64; 2], [x0, y0, x1, y1]: [f64; 4]) -> bool {
+    x >= x0 && x <= x1 && y >= y0 && y <= y1
+}
+
+/// Whether the segments `a`-`b` and `c`-`d` intersect (including touching)
+fn segments_intersect(a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> bool {
+    let orientation = |p: [f64; 2], q: [f64; 2], r: [f64; 2]| {
+        ((q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])).signum()
+    };
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+    // collinear overlaps are caught by the vertex-in-rectangle check of the caller
+    o1 != o2 && o3 != o4
+}
+
+/// Describe polygons as a WKT multipolygon, e.g. `MULTIPOLYGON(((0 0,1 0,1 1,0 0)))`
+pub fn multipolygon_wkt(polygons: &[Polygon]) -> String {
+    let polygons: Vec<String> = polygons
+        .iter()
+        .map(|polygon| {
+            let rings: Vec<String> = polygon
+                .rings
+                .iter()
+                .map(|ring| {
+                    let mut positions: Vec<String> =
+                        ring.iter().map(|[x, y]| format!("{x} {y}")).collect();
+                    // WKT rings are closed explicitly
+                    if ring.first() != ring.last() {
+                        positions.push(positions[0].clone());
+                    }
+                    format!("({})", positions.join(","))
+                })
+                .collect();
+            format!("({})", rings.join(","))
+        })
+        .collect();
+    format!("MULTIPOLYGON({})", polygons.join(","))
+}
+
+/// Collect the polygons of a GeoJSON object: a feature collection, a feature or a geometry
+pub fn collect_polygons(json: &Value, polygons: &mut Vec<Polygon>) -> Result<()> {
+    match json["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in json["features"].as_array().context("missing features")? {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        Some("Feature") => {
+            if !json["geometry"].is_null() {
+                collect_polygons(&json["geometry"], polygons)?;
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in json["geometries"]
+                .as_array()
+                .context("missing geometries")?
+            {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        Some("Polygon") => polygons.push(Polygon::new(rings(&json["coordinates"])?)),
+        Some("MultiPolygon") => {
+            for polygon in json["coordinates"]
+                .as_array()
+                .context("missing coordinates")?
+            {
+                polygons.push(Polygon::new(rings(polygon)?));
+            }
+        }
+        // points and lines do not cover any area
+        Some(_) => {}
+        None => anyhow::bail!("not a GeoJSON object"),
+    }
+    Ok(())
+}
+
+/// Parse the coordinates of a polygon, a list of rings of `[x, y]` positions
+fn rings(coordinates: &Value) -> Result<Vec<Vec<[f64; 2]>>> {
+    coordinates
+        .as_array()
+        .context("polygon coordinates must be an array")?
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .context("polygon ring must be an array")?
+                .iter()
+                .map(|position| {
+                    let x = position[0].as_f64().context("invalid position")?;
+                    let y = position[1].as_f64().context("invalid position")?;
+                    Ok([x, y])
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+//! Colorizing the points from an orthophoto while retiling (`--colorize`), saving a separate pass
+//! over the whole dataset. The RGB of the pixel containing each point is written to the point.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tiff::{
+    ColorType,
+    decoder::{Decoder, DecodingResult},
+};
+
+use crate::geotiff::{self, Georeference};
+
+/// The samples of the image, interleaved by pixel
+#[derive(Debug)]
+enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+}
+
+#[derive(Debug)]
+pub struct Colorize {
+    georeference: Georeference,
+    /// 3 for RGB, 4 for RGBA where an alpha of 0 is no data
+    bands: usize,
+    samples: Samples,
+    /// The color of no data pixels, scaled to 16 bits
+    no_data: Option<[u16; 3]>,
+}
+
+impl Colorize {
+    /// Load the orthophoto from an RGB or RGBA GeoTIFF with 8 or 16 bits per sample
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let load = || -> Result<Self> {
+            let mut decoder = Decoder::new(std::io::BufReader::new(file))?;
+            let bands = match decoder.colortype()? {
+                ColorType::RGB(8 | 16) => 3,
+                ColorType::RGBA(8 | 16) => 4,
+                other => {
+                    anyhow::bail!("only 8 or 16 bit RGB(A) images are supported, not {other:?}")
+                }
+            };
+            let georeference = Georeference::read(&mut decoder)?;
+            let no_data = geotiff::no_data(&mut decoder)?;
+            let samples = match decoder.read_image()? {
+                DecodingResult::U8(v) => Samples::U8(v),
+                DecodingResult::U16(v) => Samples::U16(v),
+                _ => anyhow::bail!("unsupported sample format"),
+            };
+            let len = match &samples {
+                Samples::U8(v) => v.len(),
+                Samples::U16(v) => v.len(),
+            };
+            anyhow::ensure!(
+                len == georeference.width * georeference.height * bands,
+                "unexpected number of samples"
+            );
+            let scale = match samples {
+                Samples::U8(_) => 257.0,
+                Samples::U16(_) => 1.0,
+            };
+            Ok(Self {
+                georeference,
+                bands,
+                samples,
+                no_data: no_data.map(|value| [(value * scale) as u16; 3]),
+            })
+        };
+        load().with_context(|| format!("read --colorize image {}", path.display()))
+    }
+
+    /// The color of the pixel containing a position, scaled to 16 bits. `None` outside the image,
+    /// on transparent pixels or on no data.
+    pub fn color(&self, x: f64, y: f64) -> Option<las::Color> {
+        let start = self.georeference.nearest(x, y)? * self.bands;
+        let rgb = [
+            self.sample(start),
+            self.sample(start + 1),
+            self.sample(start + 2),
+        ];
+        if (self.bands == 4 && self.sample(start + 3) == 0) || self.no_data == Some(rgb) {
+            return None;
+        }
+        let [red, green, blue] = rgb;
+        Some(las::Color { red, green, blue })
+    }
+
+    /// A sample scaled to 16 bits, so that 8 bit white is 65535
+    fn sample(&self, i: usize) -> u16 {
+        match &self.samples {
+            Samples::U8(v) => u16::from(v[i]) * 257,
+            Samples::U16(v) => v[i],
+        }
+    }
+
+    /// Set the color of the points within the image, the others keep theirs
+    pub fn apply(&self, points: &mut [las::Point]) {
+        for p in points {
+            if let Some(color) = self.color(p.x, p.y) {
+                p.color = Some(color);
+            }
+        }
+    }
+}
+
+/// Make sure the output point format has colors, upgrading it if it was not given with
+/// `--point-format`
+pub fn with_color(format: &mut las::point::Format, requested: Option<u8>) -> Result<()> {
+    if format.has_color {
+        return Ok(());
+    }
+    anyhow::ensure!(
+        requested.is_none(),
+        "--colorize needs a point format with colors, {format} has none"
+    );
+    format.has_color = true;
+    tracing::info!("Writing all tiles with {format} to hold the colors of --colorize");
+    Ok(())
+}
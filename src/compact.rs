@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    cli::CompactArgs,
+    extent,
+    filter::PointFilters,
+    input,
+    memory::MemoryPlan,
+    merge,
+    output::Output,
+    point_format,
+    postprocess::create_writer,
+    progress_bar,
+    reader::InputReader,
+    stats::{TileStats, TileSummary},
+    tile::{self, TileKey},
+    tile_index,
+};
+
+/// Bin size of the Z histograms collected for the tile index, which only reports percentiles
+const Z_BIN_SIZE: f64 = 1.0;
+
+/// Merge input tiles into a coarser grid. Every input must lie within a single coarse tile, so
+/// that whole files can be concatenated without looking at the location of each point.
+pub fn run(args: &CompactArgs) -> Result<()> {
+    let mut skipped = input::SkipReport::default();
+    let input_files = input::collect_inputs(&args.input, &mut skipped)?;
+    let headers = input::scan_headers(
+        input_files,
+        args.input.header_cache.as_deref(),
+        &mut skipped,
+    );
+    skipped.print_summary();
+    anyhow::ensure!(!headers.is_empty(), "no input files to compact");
+
+    // group the inputs by the coarse tile they lie in
+    let mut groups: BTreeMap<TileKey, Vec<(input::InputFile, las::Header)>> = BTreeMap::new();
+    for (path, header) in headers {
+        let bounds = header.bounds();
+        anyhow::ensure!(
+            extent::check_bounds(&bounds, [args.tile_size; 2], None),
+            "bounds of {path} are too large for tile size {}",
+            args.tile_size
+        );
+        let key = |x: f64, y: f64| TileKey {
+            x: (x / args.tile_size).floor() as i32,
+            y: (y / args.tile_size).floor() as i32,
+            z: None,
+            part: None,
+        };
+        let min = key(bounds.min.x, bounds.min.y);
+        anyhow::ensure!(
+            min == key(bounds.max.x, bounds.max.y),
+            "{path} does not lie within a single tile of size {}, retile it instead",
+            args.tile_size
+        );
+        groups.entry(min).or_default().push((path, header));
+    }
+    for group in groups.values() {
+        merge::common_header(group)?;
+    }
+
+    let total_points: u64 = groups
+        .values()
+        .flatten()
+        .map(|(_, h)| h.number_of_points())
+        .sum();
+    tracing::info!(
+        "Compacting {} input tiles with a total {}M points into {} tiles.",
+        groups.values().map(Vec::len).sum::<usize>(),
+        total_points / 1_000_000,
+        groups.len()
+    );
+
+    let output = Output::new(&args.output, None)?;
+    let mut filters = PointFilters::load(&args.filters)?;
+    let options = las::ReaderOptions::default().with_laz_parallelism(las::LazParallelism::Yes);
+    let reader = InputReader::spawn(
+        groups
+            .values()
+            .flatten()
+            .map(|(path, _)| path.clone())
+            .collect(),
+        options,
+        MemoryPlan::new(None, 1, 1).read_buffer_points,
+    );
+
+    let pb = progress_bar(total_points);
+    let mut summaries = Vec::new();
+    for (i_tile, (&key, group)) in groups.iter().enumerate() {
+        pb.set_message(format!("{}/{}", i_tile + 1, groups.len()));
+        let name = tile::file_name(key);
+        let points = group.iter().map(|(_, h)| h.number_of_points()).sum();
+        let header = point_format::for_points(&group[0].1, points, &name)?;
+        let mut writer = create_writer(&output.path(&name), &header)?;
+        let mut stats = args
+            .tile_index
+            .is_some()
+            .then(|| TileStats::new(Z_BIN_SIZE));
+        merge::append(
+            &reader,
+            group,
+            &mut writer,
+            &mut filters,
+            &pb,
+            stats.as_mut(),
+        )?;
+        writer.close().with_context(|| format!("close {name}"))?;
+        output.publish(&name)?;
+
+        if let Some(stats) = stats {
+            let (x, y) = (key.x as f64 * args.tile_size, key.y as f64 * args.tile_size);
+            summaries.push(TileSummary {
+                name,
+                tile_index: key,
+                quadtree_path: String::new(),
+                bounds: [x, y, x + args.tile_size, y + args.tile_size],
+                stats,
+            });
+        }
+    }
+    pb.finish_with_message("Done");
+
+    if let Some(path) = &args.tile_index {
+        tile_index::write(path, &args.units.units(), &summaries)?;
+    }
+    output.finish()?;
+
+    tracing::info!("Wrote {} tiles to {}", groups.len(), args.output.display());
+    Ok(())
+}
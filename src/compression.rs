@@ -0,0 +1,80 @@
+//! Chunking of the LAZ files written (`--laz-chunk-size`). LAZ data is compressed in chunks that
+//! can be decompressed independently, smaller chunks make spatial subsets of a tile faster to
+//! read at the cost of a slightly larger file.
+
+use std::{str::FromStr, sync::OnceLock};
+
+use anyhow::{Context, Result};
+
+/// The chunking used for all LAZ files written by this process, the LASzip default if not set
+static CHUNK_SIZE: OnceLock<ChunkSize> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSize {
+    /// Chunks of this many points
+    Fixed(u32),
+    /// Chunks of varying size, each holding the points written between two flushes
+    Variable,
+}
+
+impl FromStr for ChunkSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "variable" => Ok(ChunkSize::Variable),
+            _ => match s.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "expected a positive number of points or `variable`, got {s:?}"
+                )),
+                Ok(n) => Ok(ChunkSize::Fixed(n)),
+            },
+        }
+    }
+}
+
+/// Use the given chunking for all LAZ files written from now on
+pub fn configure(chunk_size: ChunkSize) {
+    // set once at startup, later calls keep the first value
+    let _ = CHUNK_SIZE.set(chunk_size);
+}
+
+/// Describe the configured chunking in the LASzip VLR of a header, which the compressor of the
+/// LAZ writer is set up from. Does nothing for uncompressed files or without a configured
+/// chunking.
+pub fn apply(builder: &mut las::Builder) -> Result<()> {
+    let Some(&chunk_size) = CHUNK_SIZE.get() else {
+        return Ok(());
+    };
+    if !builder.point_format.is_compressed {
+        return Ok(());
+    }
+
+    let data = laz_vlr_data(&builder.point_format, chunk_size)?;
+    builder.vlrs.retain(|vlr| {
+        !(vlr.user_id.trim_end_matches('\0') == laz::LazVlr::USER_ID
+            && vlr.record_id == laz::LazVlr::RECORD_ID)
+    });
+    builder.vlrs.push(las::Vlr {
+        user_id: laz::LazVlr::USER_ID.to_owned(),
+        record_id: laz::LazVlr::RECORD_ID,
+        description: laz::LazVlr::DESCRIPTION.to_owned(),
+        data,
+    });
+    Ok(())
+}
+
+/// The data of the LASzip VLR for compressing points of a format with the given chunking
+pub fn laz_vlr_data(format: &las::point::Format, chunk_size: ChunkSize) -> Result<Vec<u8>> {
+    let laz_builder = laz::LazVlrBuilder::default()
+        .with_point_format(format.to_u8()?, format.extra_bytes)
+        .context("describe the point format for LAZ compression")?;
+    let laz_vlr = match chunk_size {
+        ChunkSize::Fixed(n) => laz_builder.with_fixed_chunk_size(n),
+        ChunkSize::Variable => laz_builder.with_variable_chunk_size(),
+    }
+    .build();
+    let mut data = Vec::new();
+    laz_vlr.write_to(&mut data)?;
+    Ok(data)
+}
@@ -0,0 +1,199 @@
+//! Job configuration files (`--config job.toml`), so that large production jobs can be
+//! version-controlled instead of living in shell history.
+//!
+//! Each key of the file is the long name of a command line option, with `_` or `-`, e.g.
+//! `tile-size = 1000` or `max_open_files = 256`. Flags take `true` or `false`, options that can be
+//! given multiple times take an array, and `inputs` lists the input folders and files. Options
+//! given on the command line replace the ones in the file, inputs are added to them.
+
+use std::{collections::HashSet, ffi::OsString, path::Path};
+
+use anyhow::{Context, Result};
+
+/// Key of the inputs, which are positional arguments on the command line
+const INPUTS_KEY: &str = "inputs";
+
+/// The command line arguments with the options of the `--config` file inserted, if one is given.
+/// `command` is used to find the options given on the command line.
+pub fn expand_args(args: Vec<OsString>, command: &clap::Command) -> Result<Vec<OsString>> {
+    let Some(path) = config_path(&args) else {
+        return Ok(args);
+    };
+    let config = read(&path, &given_options(&args, command))?;
+    let mut expanded = Vec::with_capacity(args.len() + config.len());
+    let mut args = args.into_iter();
+    expanded.extend(args.next());
+    expanded.extend(config);
+    expanded.extend(args);
+    Ok(expanded)
+}
+
+/// The value of `--config`, ignoring anything after `--`
+fn config_path(args: &[OsString]) -> Option<std::path::PathBuf> {
+    let mut args = args.iter().skip(1).take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str()?;
+        if arg == "--config" {
+            return args.next().map(Into::into);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+/// The long names of the options on the command line, also when given by their short name
+fn given_options(args: &[OsString], command: &clap::Command) -> HashSet<String> {
+    let mut given = HashSet::new();
+    for arg in args.iter().skip(1).take_while(|arg| *arg != "--") {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        if let Some(long) = arg.strip_prefix("--") {
+            given.insert(long.split('=').next().unwrap_or(long).to_owned());
+        } else if let Some(short) = arg.strip_prefix('-').and_then(|s| s.chars().next()) {
+            given.extend(
+                command
+                    .get_arguments()
+                    .filter(|a| a.get_short() == Some(short))
+                    .filter_map(|a| a.get_long().map(str::to_owned)),
+            );
+        }
+    }
+    given
+}
+
+/// Read a configuration file as command line arguments, leaving out the `given` options
+fn read(path: &Path, given: &HashSet<String>) -> Result<Vec<OsString>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("read config file: {}", path.display()))?;
+    let table: toml::Table = text
+        .parse()
+        .with_context(|| format!("parse config file: {}", path.display()))?;
+
+    let mut options = Vec::new();
+    let mut inputs = Vec::new();
+    for (key, value) in &table {
+        let name = key.replace('_', "-");
+        anyhow::ensure!(
+            name != "config",
+            "{}: config files can not include other config files",
+            path.display()
+        );
+        let result = if name == INPUTS_KEY {
+            push_values(&mut inputs, None, value)
+        } else if given.contains(&name) {
+            // replaced by the command line
+            Ok(())
+        } else {
+            push_values(&mut options, Some(&name), value)
+        };
+        result.with_context(|| format!("{}: invalid value for `{key}`", path.display()))?;
+    }
+    // the inputs go last, after `--` they could not be told apart from options
+    options.extend(inputs);
+    Ok(options)
+}
+
+/// Add a value as arguments, preceded by `--<name>` for options. Arrays give the option once per
+/// element.
+fn push_values(args: &mut Vec<OsString>, name: Option<&str>, value: &toml::Value) -> Result<()> {
+    let flag = || name.map(|name| OsString::from(format!("--{name}")));
+    let text = match value {
+        toml::Value::Boolean(true) => {
+            args.push(flag().context("expected a path, not a boolean")?);
+            return Ok(());
+        }
+        toml::Value::Boolean(false) => return Ok(()),
+        toml::Value::Array(values) => {
+            for value in values {
+                anyhow::ensure!(
+                    !matches!(value, toml::Value::Array(_) | toml::Value::Table(_)),
+                    "nested arrays and tables are not supported"
+                );
+                push_values(args, name, value)?;
+            }
+            return Ok(());
+        }
+        toml::Value::Table(_) => anyhow::bail!("tables are not supported"),
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Integer(n) => n.to_string(),
+        toml::Value::Float(n) => n.to_string(),
+        toml::Value::Datetime(datetime) => datetime.to_string(),
+    };
+    args.extend(flag());
+    args.push(text.into());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(Into::into).collect()
+    }
+
+    /// Expand the command line with a config file `name` holding `config`, without the program
+    fn expand(name: &str, config: &str, command_line: &[&str]) -> Result<Vec<OsString>> {
+        let path =
+            std::env::temp_dir().join(format!("lasretile-test-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, config).unwrap();
+        let mut full = vec!["lasretile", "--config", path.to_str().unwrap()];
+        full.extend(command_line);
+        let expanded = expand_args(args(&full), &Cli::command());
+        std::fs::remove_file(&path).unwrap();
+        Ok(expanded?.into_iter().skip(1).collect())
+    }
+
+    #[test]
+    fn without_config_the_arguments_are_kept() {
+        let command_line = args(&["lasretile", "in", "-o", "out", "-t", "100"]);
+        assert_eq!(
+            expand_args(command_line.clone(), &Cli::command()).unwrap(),
+            command_line
+        );
+    }
+
+    #[test]
+    fn config_options_go_before_the_command_line() {
+        let config = r#"
+            tile-size = 1000
+            max_open_files = 256
+            recursive = true
+            ground-only = false
+            output = "out"
+            inputs = ["a", "b"]
+        "#;
+        let expanded = expand("options", config, &["-t", "50", "c"]).unwrap();
+        let config_path = expanded[expanded.len() - 4].clone();
+        assert_eq!(
+            expanded,
+            [
+                // the tile size is given on the command line, and the inputs go last
+                &args(&[
+                    "--max-open-files",
+                    "256",
+                    "--output",
+                    "out",
+                    "--recursive",
+                    "a",
+                    "b"
+                ])[..],
+                &[OsString::from("--config"), config_path],
+                &args(&["-t", "50", "c"]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn invalid_configs_are_refused() {
+        assert!(expand("nested", r#"config = "other.toml""#, &[]).is_err());
+        assert!(expand("boolean-inputs", "inputs = true", &["x"]).is_err());
+    }
+}
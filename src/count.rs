@@ -0,0 +1,82 @@
+//! The counting pass of `--two-pass`: read all inputs once without writing anything, to know how
+//! many points each tile will receive and from which inputs before the first tile is created
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    clip::Clip, input, input::InputFile, reader::InputReader, tile::TileKey, tiling::Tiling,
+};
+
+/// What the counting pass found out about a tile
+#[derive(Debug, Default)]
+pub struct TileCount {
+    pub points: u64,
+    /// The inputs with points in the tile
+    pub input_files: HashSet<usize>,
+}
+
+/// Count the points of the inputs marked in `reading` per tile, as read by `reader`, only
+/// counting the points inside the `clip` polygons and, with `drop_out_of_bounds`, the header
+/// bounds of their input. Points removed by filters, `--dedupe` or `--overlap-prefer` are still
+/// counted.
+pub fn count_points(
+    headers: &[(InputFile, las::Header)],
+    reading: &[bool],
+    reader: InputReader,
+    tiling: &Tiling,
+    clip: Option<&Clip>,
+    drop_out_of_bounds: bool,
+) -> Result<HashMap<TileKey, TileCount>> {
+    let mut counts: HashMap<TileKey, TileCount> = HashMap::new();
+    for (i_file, (path, header)) in headers.iter().enumerate() {
+        if !reading[i_file] {
+            continue;
+        }
+        tracing::debug!("Counting the points of {path}");
+        let bounds = header.bounds();
+        let tolerance = input::bounds_tolerance(header);
+        let inside_bounds = |p: &las::Point| {
+            p.x >= bounds.min.x - tolerance[0]
+                && p.x <= bounds.max.x + tolerance[0]
+                && p.y >= bounds.min.y - tolerance[1]
+                && p.y <= bounds.max.y + tolerance[1]
+                && p.z >= bounds.min.z - tolerance[2]
+                && p.z <= bounds.max.z + tolerance[2]
+        };
+
+        // consecutive points mostly fall into the same tile, only look up the tile once per run
+        let mut add = |key: TileKey, points: u64| {
+            let count = counts.entry(key).or_default();
+            count.points += points;
+            count.input_files.insert(i_file);
+        };
+        let mut run: Option<(TileKey, u64)> = None;
+        while let Some(points) = reader.next()? {
+            for p in &points {
+                if (drop_out_of_bounds && !inside_bounds(p))
+                    || clip.is_some_and(|clip| !clip.contains(p.x, p.y))
+                {
+                    continue;
+                }
+                let Some(key) = tiling.key_of(p, run.map(|(key, _)| key)) else {
+                    continue;
+                };
+                match &mut run {
+                    Some((current, n)) if *current == key => *n += 1,
+                    _ => {
+                        if let Some((key, n)) = run.replace((key, 1)) {
+                            add(key, n);
+                        }
+                    }
+                }
+            }
+            reader.recycle(points);
+        }
+        if let Some((key, n)) = run {
+            add(key, n);
+        }
+    }
+    Ok(counts)
+}
@@ -0,0 +1,393 @@
+//! Assigning a CRS to the inputs (`--a-srs`), for inputs without georeferencing or with a wrong
+//! one. The CRS VLRs of the input headers are replaced before planning, so the tiles and every
+//! report derived from the headers use the assigned CRS.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::Result;
+
+use crate::{extent, input::InputFile};
+
+/// User ID of the VLRs describing the CRS
+const PROJECTION_USER_ID: &str = "LASF_Projection";
+const GEO_KEY_DIRECTORY_RECORD_ID: u16 = 34735;
+const WKT_RECORD_ID: u16 = 2112;
+
+/// GeoTIFF keys, see the GeoTIFF specification
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const VERTICAL_CS_TYPE_GEO_KEY: u16 = 4096;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+/// GeoTIFF key value of a CRS defined by other keys instead of a code
+const USER_DEFINED: u16 = 32767;
+
+/// What identifies the CRS of an input when comparing it with the others
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Identity {
+    /// The EPSG code of the CRS, however it is declared
+    Epsg(u32),
+    /// A CRS without an EPSG code, e.g. user-defined GeoTIFF keys or WKT without `AUTHORITY`,
+    /// described for messages
+    Unresolved(String),
+    /// No CRS declared
+    None,
+}
+
+/// The EPSG code of the (horizontal) CRS declared in a header, from the GeoTIFF keys or the
+/// `AUTHORITY`/`ID` of the WKT
+pub fn epsg(header: &las::Header) -> Option<u32> {
+    match identity(header) {
+        Identity::Epsg(code) => Some(code),
+        Identity::Unresolved(_) | Identity::None => None,
+    }
+}
+
+/// What identifies the CRS declared in a header: the EPSG code from the GeoTIFF keys or the
+/// `AUTHORITY`/`ID` of the WKT, if there is one
+fn identity(header: &las::Header) -> Identity {
+    let mut declared = false;
+    for vlr in header.vlrs().iter().chain(header.evlrs()) {
+        if vlr.user_id.trim_end_matches('\0') != PROJECTION_USER_ID {
+            continue;
+        }
+        let code = match vlr.record_id {
+            GEO_KEY_DIRECTORY_RECORD_ID => geo_keys_epsg(&vlr.data),
+            WKT_RECORD_ID => wkt_epsg(&String::from_utf8_lossy(&vlr.data)),
+            _ => continue,
+        };
+        match code {
+            Some(code) => return Identity::Epsg(code),
+            None => declared = true,
+        }
+    }
+    match declared {
+        true => Identity::Unresolved(
+            extent::crs_description(header).unwrap_or_else(|| "user-defined CRS".to_owned()),
+        ),
+        false => Identity::None,
+    }
+}
+
+/// The EPSG code of the projected or geographic CRS of a GeoKeyDirectoryTag
+fn geo_keys_epsg(data: &[u8]) -> Option<u32> {
+    let code = extent::geo_key(data, PROJECTED_CS_TYPE_GEO_KEY)
+        .or_else(|| extent::geo_key(data, GEOGRAPHIC_TYPE_GEO_KEY))?;
+    (code != 0 && code != USER_DEFINED).then_some(u32::from(code))
+}
+
+/// The EPSG code of a WKT CRS: the `AUTHORITY` (WKT1) or `ID` (WKT2) of the root node, or of the
+/// horizontal part of a compound CRS
+fn wkt_epsg(wkt: &str) -> Option<u32> {
+    // the nodes of the WKT as (depth, keyword, arguments), skipping quoted names
+    let mut nodes = Vec::new();
+    let mut open: Vec<(usize, String, usize)> = Vec::new();
+    let mut keyword_start = 0;
+    let mut quoted = false;
+    for (i, c) in wkt.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '[' | '(' => {
+                let keyword = wkt[keyword_start..i].trim().to_ascii_uppercase();
+                open.push((open.len(), keyword, i + 1));
+            }
+            ']' | ')' => {
+                let (depth, keyword, start) = open.pop()?;
+                nodes.push((depth, keyword, &wkt[start..i]));
+            }
+            ',' => {}
+            _ => continue,
+        }
+        keyword_start = i + 1;
+    }
+    let authority = |depth: usize| {
+        nodes
+            .iter()
+            .filter(|(d, keyword, _)| *d == depth && (keyword == "AUTHORITY" || keyword == "ID"))
+            .find_map(|(_, _, arguments)| {
+                let mut arguments = arguments.split(',').map(|a| a.trim().trim_matches('"'));
+                let name = arguments.next()?;
+                let code = arguments.next()?;
+                match name.eq_ignore_ascii_case("EPSG") {
+                    true => code.parse().ok(),
+                    false => None,
+                }
+            })
+    };
+    let (_, root, _) = nodes.iter().find(|(depth, ..)| *depth == 0)?;
+    if matches!(root.as_str(), "COMPD_CS" | "COMPOUNDCRS") {
+        // the first part of a compound CRS is the horizontal one
+        return authority(1).or_else(|| authority(2));
+    }
+    authority(1)
+}
+
+/// Check that all inputs that declare a CRS declare the same one, as tiles mixing coordinate
+/// systems are garbage. CRSs are compared by their EPSG code, whether it is declared in GeoTIFF
+/// keys or WKT. Fails unless `ignore_mismatch` is given, which only warns. Inputs without a CRS,
+/// or with one without an EPSG code, are assumed to be in the CRS of the others, with a warning.
+pub fn check_consistent(headers: &[(InputFile, las::Header)], ignore_mismatch: bool) -> Result<()> {
+    let mut by_crs: BTreeMap<Identity, Vec<&InputFile>> = BTreeMap::new();
+    for (path, header) in headers {
+        by_crs.entry(identity(header)).or_default().push(path);
+    }
+    let mut by_code: Vec<(u32, &Vec<&InputFile>)> = Vec::new();
+    let mut unknown = 0;
+    for (crs, paths) in &by_crs {
+        match crs {
+            Identity::Epsg(code) => by_code.push((*code, paths)),
+            Identity::Unresolved(description) => {
+                unknown += paths.len();
+                tracing::warn!(
+                    "The CRS {description} of {} inputs (e.g. {}) has no EPSG code, it can not be compared with the others",
+                    paths.len(),
+                    paths[0]
+                );
+            }
+            Identity::None => {
+                unknown += paths.len();
+                tracing::warn!("{} inputs declare no CRS (e.g. {})", paths.len(), paths[0]);
+            }
+        }
+    }
+    if unknown > 0 && by_crs.len() > 1 {
+        tracing::warn!("Assuming the {unknown} inputs are in the CRS of the others");
+    }
+    if by_code.len() <= 1 {
+        return Ok(());
+    }
+
+    let crss: Vec<String> = by_code
+        .iter()
+        .map(|(code, paths)| format!("EPSG:{code} ({} inputs, e.g. {})", paths.len(), paths[0]))
+        .collect();
+    let message = format!(
+        "the inputs reference different coordinate systems: {}",
+        crss.join(", ")
+    );
+    anyhow::ensure!(
+        ignore_mismatch,
+        "{message}. Reproject them first, assign one CRS to all with --a-srs or retile anyway with --ignore-crs-mismatch"
+    );
+    tracing::warn!("{message}, each tile declares the CRS of the first input written to it");
+    Ok(())
+}
+
+/// A CRS as given on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Crs {
+    /// An EPSG code, with an optional vertical CRS (`EPSG:2056+5728`), written as GeoTIFF keys
+    Epsg {
+        horizontal: u16,
+        vertical: Option<u16>,
+    },
+    /// OGC WKT, given directly or read from a file (e.g. a `.prj`), which needs LAS 1.4
+    Wkt(String),
+}
+
+impl FromStr for Crs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(codes) = s
+            .get(..5)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("EPSG:"))
+            .map(|_| &s[5..])
+        {
+            let (horizontal, vertical) = match codes.split_once('+') {
+                Some((horizontal, vertical)) => (horizontal, Some(vertical)),
+                None => (codes, None),
+            };
+            let parse = |code: &str| {
+                code.trim()
+                    .parse::<u16>()
+                    .map_err(|_| format!("invalid EPSG code {code:?}, expected e.g. EPSG:25833"))
+            };
+            return Ok(Self::Epsg {
+                horizontal: parse(horizontal)?,
+                vertical: vertical.map(parse).transpose()?,
+            });
+        }
+        if s.contains('[') {
+            return Ok(Self::Wkt(s.trim().to_owned()));
+        }
+        match std::fs::read_to_string(s) {
+            Ok(wkt) if wkt.contains('[') => Ok(Self::Wkt(wkt.trim().to_owned())),
+            Ok(_) => Err(format!("{s} does not contain a WKT CRS")),
+            Err(_) => Err(format!(
+                "expected EPSG:<code>, WKT or a file with WKT, got {s:?}"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Crs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Crs::Epsg {
+                horizontal,
+                vertical: None,
+            } => write!(f, "EPSG:{horizontal}"),
+            Crs::Epsg {
+                horizontal,
+                vertical: Some(vertical),
+            } => write!(f, "EPSG:{horizontal}+{vertical}"),
+            // the name is the first quoted string, e.g. PROJCS["WGS 84 / UTM zone 33N",...
+            Crs::Wkt(wkt) => write!(f, "{}", wkt.split('"').nth(1).unwrap_or("WKT")),
+        }
+    }
+}
+
+impl Crs {
+    /// Check that the CRS can be declared in tiles with the given point format and LAS version
+    pub fn check(&self, format: &las::point::Format, version: Option<las::Version>) -> Result<()> {
+        match self {
+            Crs::Epsg { .. } => anyhow::ensure!(
+                !format.is_extended,
+                "{format} requires a WKT CRS, give --a-srs as WKT or a file with WKT instead of {self}"
+            ),
+            Crs::Wkt(_) => anyhow::ensure!(
+                version.is_none_or(|version| version >= las::Version::new(1, 4)),
+                "only LAS 1.4 can declare a WKT CRS, give --a-srs as EPSG:<code> instead"
+            ),
+        }
+        Ok(())
+    }
+
+    /// The CRS as PDAL and GDAL take it, `EPSG:<code>` or the WKT
+    pub fn definition(&self) -> String {
+        match self {
+            Crs::Epsg { .. } => self.to_string(),
+            Crs::Wkt(wkt) => wkt.clone(),
+        }
+    }
+
+    /// Replace the CRS in the headers of all inputs. WKT moves the headers to LAS 1.4, the only
+    /// version that can declare it.
+    pub fn assign(&self, headers: &mut [(InputFile, las::Header)]) -> Result<()> {
+        let vlr = self.vlr();
+        let mut replaced = 0;
+        for (_, header) in headers.iter_mut() {
+            let mut builder = las::Builder::from(header.clone());
+            let before = builder.vlrs.len() + builder.evlrs.len();
+            for vlrs in [&mut builder.vlrs, &mut builder.evlrs] {
+                vlrs.retain(|vlr| vlr.user_id.trim_end_matches('\0') != PROJECTION_USER_ID);
+            }
+            if builder.vlrs.len() + builder.evlrs.len() < before {
+                replaced += 1;
+            }
+            builder.vlrs.push(vlr.clone());
+            builder.has_wkt_crs = matches!(self, Crs::Wkt(_));
+            if builder.has_wkt_crs {
+                builder.version = builder.version.max(las::Version::new(1, 4));
+            }
+            *header = builder.into_header()?;
+        }
+        tracing::info!(
+            "Assigned the CRS {self} to {} inputs, replacing the CRS of {replaced}",
+            headers.len()
+        );
+        Ok(())
+    }
+
+    /// The VLR declaring the CRS
+    fn vlr(&self) -> las::Vlr {
+        let (record_id, description, data) = match self {
+            Crs::Epsg {
+                horizontal,
+                vertical,
+            } => {
+                // codes 4000-4999 are geographic CRSs, most others are projected
+                let (model_type, key) = if (4000..5000).contains(horizontal) {
+                    (MODEL_TYPE_GEOGRAPHIC, GEOGRAPHIC_TYPE_GEO_KEY)
+                } else {
+                    (MODEL_TYPE_PROJECTED, PROJECTED_CS_TYPE_GEO_KEY)
+                };
+                let mut keys = vec![
+                    [GT_MODEL_TYPE_GEO_KEY, model_type],
+                    [GT_RASTER_TYPE_GEO_KEY, RASTER_PIXEL_IS_AREA],
+                    [key, *horizontal],
+                ];
+                keys.extend(vertical.map(|code| [VERTICAL_CS_TYPE_GEO_KEY, code]));
+                // the key directory header is followed by the keys, with their values stored
+                // directly in the directory
+                let mut values = vec![1, 1, 0, keys.len() as u16];
+                for [id, value] in keys {
+                    values.extend([id, 0, 1, value]);
+                }
+                (
+                    GEO_KEY_DIRECTORY_RECORD_ID,
+                    "GeoTIFF GeoKeyDirectoryTag",
+                    values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+                )
+            }
+            Crs::Wkt(wkt) => {
+                let mut data = wkt.as_bytes().to_vec();
+                data.push(0);
+                (WKT_RECORD_ID, "OGC Coordinate System WKT", data)
+            }
+        };
+        las::Vlr {
+            user_id: PROJECTION_USER_ID.to_owned(),
+            record_id,
+            description: description.to_owned(),
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epsg_of_wkt1_is_the_authority_of_the_root() {
+        let wkt = r#"PROJCS["ETRS89 / UTM zone 33N",GEOGCS["ETRS89",DATUM["European_Terrestrial_Reference_System_1989",SPHEROID["GRS 1980",6378137,298.257222101,AUTHORITY["EPSG","7019"]],AUTHORITY["EPSG","6258"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433],AUTHORITY["EPSG","4258"]],PROJECTION["Transverse_Mercator"],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AUTHORITY["EPSG","25833"]]"#;
+        assert_eq!(wkt_epsg(wkt), Some(25833));
+    }
+
+    #[test]
+    fn epsg_of_wkt2_is_the_id_of_the_root() {
+        let wkt = r#"PROJCRS["ETRS89 / UTM zone 33N",BASEGEOGCRS["ETRS89",DATUM["European Terrestrial Reference System 1989",ELLIPSOID["GRS 1980",6378137,298.257222101]],ID["EPSG",4258]],CONVERSION["UTM zone 33N",METHOD["Transverse Mercator",ID["EPSG",9807]]],CS[Cartesian,2],ID["EPSG",25833]]"#;
+        assert_eq!(wkt_epsg(wkt), Some(25833));
+    }
+
+    #[test]
+    fn epsg_of_compound_wkt_is_the_horizontal_part() {
+        let wkt = r#"COMPD_CS["ETRS89 / UTM zone 33N + DHHN2016 height",PROJCS["ETRS89 / UTM zone 33N",GEOGCS["ETRS89",AUTHORITY["EPSG","4258"]],AUTHORITY["EPSG","25833"]],VERT_CS["DHHN2016 height",VERT_DATUM["Deutsches Haupthoehennetz 2016",2005],AUTHORITY["EPSG","7837"]]]"#;
+        assert_eq!(wkt_epsg(wkt), Some(25833));
+    }
+
+    #[test]
+    fn wkt_without_authority_has_no_epsg() {
+        let wkt = r#"PROJCS["local grid",GEOGCS["ETRS89",AUTHORITY["EPSG","4258"]],PROJECTION["Transverse_Mercator"],UNIT["metre",1]]"#;
+        assert_eq!(wkt_epsg(wkt), None);
+        assert_eq!(wkt_epsg("not a CRS"), None);
+    }
+
+    #[test]
+    fn user_defined_geo_keys_have_no_epsg() {
+        let geo_keys = |code: u16| -> Vec<u8> {
+            [
+                1,
+                1,
+                0,
+                2,
+                GT_MODEL_TYPE_GEO_KEY,
+                0,
+                1,
+                MODEL_TYPE_PROJECTED,
+            ]
+            .into_iter()
+            .chain([PROJECTED_CS_TYPE_GEO_KEY, 0, 1, code])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+        };
+        assert_eq!(geo_keys_epsg(&geo_keys(25833)), Some(25833));
+        assert_eq!(geo_keys_epsg(&geo_keys(USER_DEFINED)), None);
+    }
+}
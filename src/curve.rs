@@ -0,0 +1,47 @@
+//! Ordering the input files along a space-filling curve (`--input-order hilbert`), so that files
+//! next to each other are read one after another and few output tiles are open at the same time
+
+/// Number of bits per axis of the grid the centroids are snapped to
+const BITS: u32 = 16;
+
+/// Sort the inputs by the position of the centroid of their bounds along a Hilbert curve through
+/// the overall bounds. Inputs with the same position keep their order.
+pub fn sort_hilbert(headers: &mut [(crate::input::InputFile, las::Header)], bounds: &las::Bounds) {
+    let cells = f64::from((1u32 << BITS) - 1);
+    let cell = |v: f64, min: f64, max: f64| {
+        if max > min {
+            ((v - min) / (max - min) * cells).clamp(0.0, cells) as u32
+        } else {
+            0
+        }
+    };
+    headers.sort_by_cached_key(|(_, header)| {
+        let b = header.bounds();
+        hilbert_index(
+            cell((b.min.x + b.max.x) / 2.0, bounds.min.x, bounds.max.x),
+            cell((b.min.y + b.max.y) / 2.0, bounds.min.y, bounds.max.y),
+        )
+    });
+}
+
+/// The distance of a cell along the Hilbert curve filling the `2^BITS` by `2^BITS` grid
+fn hilbert_index(mut x: u32, mut y: u32) -> u64 {
+    let n = 1u32 << BITS;
+    let mut index = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        index += u64::from(s) * u64::from(s) * u64::from((3 * rx) ^ ry);
+        // rotate the quadrant, so the curve continues where the previous one ended
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
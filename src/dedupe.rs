@@ -0,0 +1,226 @@
+//! Removing duplicate points from overlapping inputs, e.g. swaths that were delivered twice
+
+use std::{
+    collections::HashSet,
+    hash::{BuildHasher, RandomState},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    postprocess::{create_writer, open_reader, temp_path},
+    stats::TileStats,
+};
+
+/// Number of points read at a time while filtering
+const BUFFER_SIZE: u64 = 64 * 1024;
+
+/// Approximate memory used per point while looking for duplicates: its key in the hash set,
+/// with room for the overhead of the set
+pub const KEY_BYTES: usize = 2 * size_of::<Key>();
+
+/// What makes two points the same
+type Key = [u64; 4];
+
+/// What makes two points the same: their exact coordinates, and GPS time if `with_time`
+fn key_of(point: &las::Point, with_time: bool) -> Key {
+    let time = if with_time {
+        point.gps_time.unwrap_or_default().to_bits()
+    } else {
+        0
+    };
+    [
+        point.x.to_bits(),
+        point.y.to_bits(),
+        point.z.to_bits(),
+        time,
+    ]
+}
+
+/// Remove the points of a tile whose coordinates (and GPS time if `with_time`) are identical to
+/// those of a point earlier in the tile. All tiles share the same quantization, so duplicates
+/// have exactly the same coordinates.
+///
+/// The keys of at most about `max_points` points are held in memory at a time. Larger tiles are
+/// split into parts by the hash of the keys, so that all duplicates of a point fall into the same
+/// part, and the tile is read once per part. Besides that, one bit per point marks the
+/// duplicates found.
+///
+/// If given, `stats` is rebuilt from the points that are kept. Returns the number of points
+/// removed.
+pub fn remove_exact(
+    path: &Path,
+    with_time: bool,
+    max_points: usize,
+    stats: Option<&mut TileStats>,
+) -> Result<u64> {
+    // pass 1: find the duplicates, most tiles have none
+    let header = open_reader(path)?.header().clone();
+    let parts = header
+        .number_of_points()
+        .div_ceil(max_points.max(1) as u64)
+        .max(1);
+    let mut duplicates = Duplicates::new(header.number_of_points());
+    let hasher = RandomState::new();
+    let mut points = Vec::new();
+    for part in 0..parts {
+        let mut scan = PartScan::new(part, parts, &hasher);
+        let mut reader = open_reader(path)?;
+        loop {
+            points.clear();
+            if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+                break;
+            }
+            for point in &points {
+                scan.add(key_of(point, with_time), &mut duplicates);
+            }
+        }
+    }
+    if duplicates.count == 0 {
+        return Ok(0);
+    }
+
+    // pass 2: rewrite the tile with only the first of each set of duplicates
+    let mut stats = stats;
+    if let Some(stats) = stats.as_deref_mut() {
+        stats.clear();
+    }
+
+    let temp = temp_path(path, "dedupe");
+    let result = (|| {
+        let mut reader = open_reader(path)?;
+        let mut writer = create_writer(&temp, &header)?;
+        let mut index = 0;
+        loop {
+            points.clear();
+            if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+                break;
+            }
+            for point in points.drain(..) {
+                let duplicate = duplicates.contains(index);
+                index += 1;
+                if duplicate {
+                    continue;
+                }
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.add(&point);
+                }
+                writer.write_point(point)?;
+            }
+        }
+        writer.close()?;
+        std::fs::rename(&temp, path)
+            .with_context(|| format!("replace {} with deduplicated tile", path.display()))?;
+        Ok(duplicates.count)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// The positions of the duplicate points in a tile, one bit per point
+struct Duplicates {
+    bits: Vec<u64>,
+    count: u64,
+}
+
+impl Duplicates {
+    fn new(points: u64) -> Self {
+        Self {
+            bits: vec![0; points.div_ceil(64) as usize],
+            count: 0,
+        }
+    }
+
+    fn mark(&mut self, index: u64) {
+        self.bits[(index / 64) as usize] |= 1 << (index % 64);
+        self.count += 1;
+    }
+
+    fn contains(&self, index: u64) -> bool {
+        self.bits
+            .get((index / 64) as usize)
+            .is_some_and(|bits| bits & (1 << (index % 64)) != 0)
+    }
+}
+
+/// One pass over the points of a tile, looking for duplicates among the points whose key falls
+/// into part `part` of `parts`
+struct PartScan<'a> {
+    part: u64,
+    parts: u64,
+    hasher: &'a RandomState,
+    seen: HashSet<Key>,
+    index: u64,
+}
+
+impl<'a> PartScan<'a> {
+    fn new(part: u64, parts: u64, hasher: &'a RandomState) -> Self {
+        Self {
+            part,
+            parts,
+            hasher,
+            seen: HashSet::new(),
+            index: 0,
+        }
+    }
+
+    /// Add the key of the next point, marking the point if it is a duplicate
+    fn add(&mut self, key: Key, duplicates: &mut Duplicates) {
+        let in_part = self.parts == 1 || self.hasher.hash_one(key) % self.parts == self.part;
+        if in_part && !self.seen.insert(key) {
+            duplicates.mark(self.index);
+        }
+        self.index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The positions of the duplicates among the keys, looking for them in `parts` parts
+    fn find(keys: &[Key], parts: u64) -> Vec<u64> {
+        let hasher = RandomState::new();
+        let mut duplicates = Duplicates::new(keys.len() as u64);
+        for part in 0..parts {
+            let mut scan = PartScan::new(part, parts, &hasher);
+            for &key in keys {
+                scan.add(key, &mut duplicates);
+            }
+        }
+        let found: Vec<u64> = (0..keys.len() as u64)
+            .filter(|&i| duplicates.contains(i))
+            .collect();
+        assert_eq!(found.len() as u64, duplicates.count);
+        found
+    }
+
+    #[test]
+    fn keeps_the_first_of_each_set_of_duplicates() {
+        let keys = [
+            [1, 2, 3, 0],
+            [4, 5, 6, 0],
+            [1, 2, 3, 0],
+            [1, 2, 3, 1],
+            [4, 5, 6, 0],
+        ];
+        assert_eq!(find(&keys, 1), vec![2, 4]);
+    }
+
+    #[test]
+    fn finds_the_same_duplicates_in_parts() {
+        // a swath delivered twice, and some points three times
+        let keys: Vec<Key> = (0..1000)
+            .chain(0..1000)
+            .chain((0..1000).step_by(7))
+            .map(|i| [i, i * 2, i * 3, 0])
+            .collect();
+        let expected: Vec<u64> = (1000..keys.len() as u64).collect();
+        assert_eq!(find(&keys, 1), expected);
+        assert_eq!(find(&keys, 5), expected);
+    }
+}
@@ -0,0 +1,253 @@
+//! Point density raster accumulated while routing points, written as a GeoTIFF or used to
+//! derive the footprint of the points
+
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+/// Maximum number of cells of the raster, keeps the grid (and the single strip of the TIFF)
+/// well below the 4 GiB limit of classic TIFF
+const MAX_CELLS: u64 = 256 * 1024 * 1024;
+
+/// TIFF field types
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const DOUBLE: u16 = 12;
+
+/// Record IDs of the GeoTIFF VLRs in `LASF_Projection`, which are the same as the TIFF tags
+const GEO_KEY_DIRECTORY_TAG: u16 = 34735;
+const GEO_DOUBLE_PARAMS_TAG: u16 = 34736;
+const GEO_ASCII_PARAMS_TAG: u16 = 34737;
+
+/// Number of points in each cell of a regular grid
+pub struct DensityGrid {
+    cell_size: f64,
+    /// Column of the west-most cell in multiples of `cell_size`
+    min_col: i64,
+    /// Row of the north-most cell in multiples of `cell_size`
+    max_row: i64,
+    width: usize,
+    height: usize,
+    /// Row-major from north to south
+    counts: Vec<u32>,
+}
+
+impl DensityGrid {
+    /// Create an empty grid covering the given bounds
+    pub fn new(min: &las::Vector<f64>, max: &las::Vector<f64>, cell_size: f64) -> Result<Self> {
+        anyhow::ensure!(cell_size > 0.0, "density cell size must be positive");
+        let min_col = (min.x / cell_size).floor() as i64;
+        let max_col = (max.x / cell_size).floor() as i64;
+        let min_row = (min.y / cell_size).floor() as i64;
+        let max_row = (max.y / cell_size).floor() as i64;
+        let width = (max_col - min_col + 1) as u64;
+        let height = (max_row - min_row + 1) as u64;
+        anyhow::ensure!(
+            width * height <= MAX_CELLS,
+            "density raster of {width}x{height} cells is too large, use a larger cell size"
+        );
+        Ok(Self {
+            cell_size,
+            min_col,
+            max_row,
+            width: width as usize,
+            height: height as usize,
+            counts: vec![0; (width * height) as usize],
+        })
+    }
+
+    /// Count a point, points outside the grid are ignored
+    pub fn add(&mut self, x: f64, y: f64) {
+        let col = (x / self.cell_size).floor() as i64 - self.min_col;
+        let row = self.max_row - (y / self.cell_size).floor() as i64;
+        if (0..self.width as i64).contains(&col) && (0..self.height as i64).contains(&row) {
+            let count = &mut self.counts[row as usize * self.width + col as usize];
+            *count = count.saturating_add(1);
+        }
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /// `[min column, min row, max column, max row]` of the cells of the grid, in multiples of
+    /// the cell size (rows increase towards the north)
+    pub fn extent(&self) -> [i64; 4] {
+        [
+            self.min_col,
+            self.max_row - self.height as i64 + 1,
+            self.min_col + self.width as i64 - 1,
+            self.max_row,
+        ]
+    }
+
+    /// The number of points in a cell, in multiples of the cell size like [`Self::extent`]
+    pub fn count(&self, col: i64, row: i64) -> u32 {
+        let (col, row) = (col - self.min_col, self.max_row - row);
+        if (0..self.width as i64).contains(&col) && (0..self.height as i64).contains(&row) {
+            self.counts[row as usize * self.width + col as usize]
+        } else {
+            0
+        }
+    }
+
+    /// Write the grid as a single band float32 GeoTIFF with the number of points per unit area.
+    /// The GeoTIFF keys of `header` are copied, if it has any, so the raster has the same CRS.
+    pub fn write(&self, path: &Path, header: &las::Header) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("create density raster: {}", path.display()))?;
+        self.write_tiff(BufWriter::new(file), header)
+            .with_context(|| format!("write density raster: {}", path.display()))
+    }
+
+    fn write_tiff(&self, mut w: impl Write, header: &las::Header) -> std::io::Result<()> {
+        let cell_area = self.cell_size * self.cell_size;
+        let pixel_bytes = (self.counts.len() * 4) as u32;
+        let (width, height) = (self.width as u32, self.height as u32);
+        let west = self.min_col as f64 * self.cell_size;
+        let north = (self.max_row + 1) as f64 * self.cell_size;
+
+        // the pixels are written right after the 8 byte header
+        let mut entries = vec![
+            Entry::longs(256, &[width]),
+            Entry::longs(257, &[height]),
+            Entry::shorts(258, &[32]),
+            // no compression
+            Entry::shorts(259, &[1]),
+            // black is zero
+            Entry::shorts(262, &[1]),
+            Entry::longs(273, &[8]),
+            Entry::shorts(277, &[1]),
+            Entry::longs(278, &[height]),
+            Entry::longs(279, &[pixel_bytes]),
+            Entry::shorts(284, &[1]),
+            // IEEE floating point
+            Entry::shorts(339, &[3]),
+            // ModelPixelScaleTag and ModelTiepointTag: the top-left corner of the raster
+            Entry::doubles(33550, &[self.cell_size, self.cell_size, 0.0]),
+            Entry::doubles(33922, &[0.0, 0.0, 0.0, west, north, 0.0]),
+        ];
+        entries.extend(geotiff_entries(header));
+
+        // values that do not fit into an entry follow the pixels, then the directory itself
+        let mut offset = 8 + pixel_bytes;
+        let mut values = Vec::new();
+        for entry in &mut entries {
+            if entry.data.len() > 4 {
+                offset += offset % 2;
+                entry.offset = Some(offset);
+                offset += entry.data.len() as u32;
+                values.push(&entry.data);
+            }
+        }
+        let ifd_offset = offset + offset % 2;
+
+        w.write_all(b"II")?;
+        w.write_all(&42u16.to_le_bytes())?;
+        w.write_all(&ifd_offset.to_le_bytes())?;
+        for &count in &self.counts {
+            w.write_all(&((count as f64 / cell_area) as f32).to_le_bytes())?;
+        }
+        let mut position = 8 + pixel_bytes;
+        for data in values {
+            if position % 2 == 1 {
+                w.write_all(&[0])?;
+                position += 1;
+            }
+            w.write_all(data)?;
+            position += data.len() as u32;
+        }
+        if position % 2 == 1 {
+            w.write_all(&[0])?;
+        }
+
+        w.write_all(&(entries.len() as u16).to_le_bytes())?;
+        for entry in &entries {
+            w.write_all(&entry.tag.to_le_bytes())?;
+            w.write_all(&entry.kind.to_le_bytes())?;
+            w.write_all(&entry.count.to_le_bytes())?;
+            match entry.offset {
+                Some(offset) => w.write_all(&offset.to_le_bytes())?,
+                None => {
+                    let mut inline = [0; 4];
+                    inline[..entry.data.len()].copy_from_slice(&entry.data);
+                    w.write_all(&inline)?;
+                }
+            }
+        }
+        // no further directories
+        w.write_all(&0u32.to_le_bytes())?;
+        w.flush()
+    }
+}
+
+/// A TIFF directory entry
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+    /// Where the data is stored if it does not fit into the entry
+    offset: Option<u32>,
+}
+
+impl Entry {
+    fn new(tag: u16, kind: u16, count: usize, data: Vec<u8>) -> Self {
+        Self {
+            tag,
+            kind,
+            count: count as u32,
+            data,
+            offset: None,
+        }
+    }
+
+    fn shorts(tag: u16, values: &[u16]) -> Self {
+        let data = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        Self::new(tag, SHORT, values.len(), data)
+    }
+
+    fn longs(tag: u16, values: &[u32]) -> Self {
+        let data = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        Self::new(tag, LONG, values.len(), data)
+    }
+
+    fn doubles(tag: u16, values: &[f64]) -> Self {
+        let data = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        Self::new(tag, DOUBLE, values.len(), data)
+    }
+}
+
+/// The GeoTIFF tags stored in the VLRs of a LAS header. The directory entries must be sorted by
+/// tag, which these are after the tags of the raster itself.
+fn geotiff_entries(header: &las::Header) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for tag in [
+        GEO_KEY_DIRECTORY_TAG,
+        GEO_DOUBLE_PARAMS_TAG,
+        GEO_ASCII_PARAMS_TAG,
+    ] {
+        let Some(vlr) = header.vlrs().iter().chain(header.evlrs()).find(|vlr| {
+            vlr.user_id.trim_end_matches('\0') == "LASF_Projection" && vlr.record_id == tag
+        }) else {
+            continue;
+        };
+        let (kind, size) = match tag {
+            GEO_KEY_DIRECTORY_TAG => (SHORT, 2),
+            GEO_DOUBLE_PARAMS_TAG => (DOUBLE, 8),
+            _ => (ASCII, 1),
+        };
+        let count = vlr.data.len() / size;
+        entries.push(Entry::new(
+            tag,
+            kind,
+            count,
+            vlr.data[..count * size].to_vec(),
+        ));
+    }
+    entries
+}
@@ -0,0 +1,74 @@
+//! Pre-flight check that the tiles, and the archive members extracted to read them, fit on their
+//! file systems, so that a long run is not started only to fail on a full disk near the end.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use indicatif::HumanBytes;
+
+/// Size of LAZ compressed points relative to uncompressed ones. Airborne data typically
+/// compresses 5 to 10 times (0.1 to 0.2), but noisy, sparse or attribute-heavy data compresses
+/// less, and the tiles are compressed in smaller chunks than their inputs. This errs on the large
+/// side, so that runs are refused rather than failing on a full disk; `--force` overrides it.
+const LAZ_RATIO: f64 = 0.25;
+
+/// Estimated size in bytes of `points` points written as records of `record_length` bytes
+pub fn estimate(points: u64, record_length: u16, compressed: bool) -> u64 {
+    let uncompressed = points * u64::from(record_length);
+    match compressed {
+        true => (uncompressed as f64 * LAZ_RATIO) as u64,
+        false => uncompressed,
+    }
+}
+
+/// The mount point and free space of the file system of `dir`, if they can be determined
+fn available(dir: &Path) -> Option<(PathBuf, u64)> {
+    let dir = dir.canonicalize().ok()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| dir.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| (disk.mount_point().to_owned(), disk.available_space()))
+}
+
+/// Check that the bytes needed in each folder fit into the free space of their file systems,
+/// adding up the needs of folders on the same file system. Fails if they do not, unless `force`
+/// is given, which only warns.
+pub fn check(needs: &[(&Path, u64)], force: bool) -> Result<()> {
+    let mut file_systems: BTreeMap<PathBuf, (&Path, u64, u64)> = BTreeMap::new();
+    for &(dir, needed) in needs {
+        let Some((mount_point, available)) = available(dir) else {
+            tracing::debug!("Could not determine the free space for {}", dir.display());
+            continue;
+        };
+        file_systems
+            .entry(mount_point)
+            .or_insert((dir, 0, available))
+            .1 += needed;
+    }
+    for (dir, needed, available) in file_systems.into_values() {
+        tracing::info!(
+            "Estimated space needed on the file system of {}: {} ({} free)",
+            dir.display(),
+            HumanBytes(needed),
+            HumanBytes(available)
+        );
+        if needed <= available {
+            continue;
+        }
+        let message = format!(
+            "the run needs about {} but only {} is free on the file system of {}",
+            HumanBytes(needed),
+            HumanBytes(available),
+            dir.display()
+        );
+        anyhow::ensure!(force, "{message} (use --force to start anyway)");
+        tracing::warn!("{message}, starting anyway because of --force");
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{schema::Document, tile::TileKey};
+
+/// GeoTIFF key describing whether the CRS is projected (1), geographic (2) or geocentric (3)
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+
+/// The kind of coordinate reference system of the input files, as far as it matters for
+/// checking the tile extents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrsKind {
+    /// Longitude/latitude in degrees
+    Geographic,
+    /// Projected, geocentric or unknown
+    Other,
+}
+
+/// Determine the kind of CRS from the GeoTIFF keys or WKT in the (extended) VLRs of a header
+pub fn crs_kind(header: &las::Header) -> CrsKind {
+    for vlr in header.vlrs().iter().chain(header.evlrs()) {
+        if vlr.user_id.trim_end_matches('\0') != "LASF_Projection" {
+            continue;
+        }
+        let geographic = match vlr.record_id {
+            34735 => geo_key(&vlr.data, GT_MODEL_TYPE_GEO_KEY) == Some(MODEL_TYPE_GEOGRAPHIC),
+            2112 => {
+                let wkt = String::from_utf8_lossy(&vlr.data);
+                let wkt = wkt.trim_start();
+                ["GEOGCS", "GEOGCRS", "GEOGRAPHICCRS"]
+                    .iter()
+                    .any(|prefix| wkt.starts_with(prefix))
+            }
+            _ => continue,
+        };
+        return if geographic {
+            CrsKind::Geographic
+        } else {
+            CrsKind::Other
+        };
+    }
+    CrsKind::Other
+}
+
+/// The kind of CRS of all inputs: geographic if any of them is, so that the tiles of geographic
+/// inputs are checked whichever input comes first
+pub fn crs_kind_of_all<'a>(headers: impl IntoIterator<Item = &'a las::Header>) -> CrsKind {
+    match headers
+        .into_iter()
+        .any(|header| crs_kind(header) == CrsKind::Geographic)
+    {
+        true => CrsKind::Geographic,
+        false => CrsKind::Other,
+    }
+}
+
+/// GeoTIFF keys holding the EPSG code of a projected or geographic CRS
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+
+/// A short description of the CRS in a header for display: the name from the WKT, or the EPSG
+/// code from the GeoTIFF keys
+pub fn crs_description(header: &las::Header) -> Option<String> {
+    for vlr in header.vlrs().iter().chain(header.evlrs()) {
+        if vlr.user_id.trim_end_matches('\0') != "LASF_Projection" {
+            continue;
+        }
+        match vlr.record_id {
+            34735 => {
+                let code = geo_key(&vlr.data, PROJECTED_CS_TYPE_GEO_KEY)
+                    .or_else(|| geo_key(&vlr.data, GEOGRAPHIC_TYPE_GEO_KEY))?;
+                return Some(format!("EPSG:{code}"));
+            }
+            2112 => {
+                // the name is the first quoted string, e.g. PROJCS["WGS 84 / UTM zone 33N",...
+                let wkt = String::from_utf8_lossy(&vlr.data);
+                let name = wkt.split('"').nth(1)?;
+                return Some(name.to_owned());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Look up the value of a GeoTIFF key stored directly in a GeoKeyDirectoryTag
+pub fn geo_key(data: &[u8], id: u16) -> Option<u16> {
+    let values: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    let number_of_keys = *values.get(3)? as usize;
+    values
+        .get(4..4 + number_of_keys * 4)?
+        .chunks_exact(4)
+        .find(|key| key[0] == id && key[1] == 0)
+        .map(|key| key[3])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtentProblem {
+    /// The tile crosses the ±180° meridian
+    CrossesAntimeridian,
+    /// The tile lies (partly) outside the valid area of the CRS
+    OutsideCrsArea,
+}
+
+/// A tile with nonsensical bounds, printed as a single JSON line so it can be picked up by
+/// other tools
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExtentWarning {
+    pub warning: ExtentProblem,
+    pub tile: String,
+    pub tile_index: (i32, i32),
+    /// The Z index of the tile with `--tile-size-z`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub z_index: Option<i32>,
+    /// `[min x, min y, max x, max y]`
+    pub bounds: [f64; 4],
+}
+
+impl Document for ExtentWarning {
+    const SCHEMA_VERSION: &'static str = "1.1";
+}
+
+/// Check whether a tile with the given `[min x, min y, max x, max y]` lies within the valid area
+/// of the CRS
+pub fn check_tile(
+    key: TileKey,
+    name: &str,
+    bounds: [f64; 4],
+    crs: CrsKind,
+) -> Option<ExtentWarning> {
+    let [x0, y0, x1, y1] = bounds;
+
+    let problem = match crs {
+        CrsKind::Geographic if x0 < 180.0 && x1 > 180.0 || x0 < -180.0 && x1 > -180.0 => {
+            ExtentProblem::CrossesAntimeridian
+        }
+        CrsKind::Geographic if x0 < -180.0 || x1 > 180.0 || y0 < -90.0 || y1 > 90.0 => {
+            ExtentProblem::OutsideCrsArea
+        }
+        _ => return None,
+    };
+
+    Some(ExtentWarning {
+        warning: problem,
+        tile: name.to_owned(),
+        tile_index: (key.x, key.y),
+        z_index: key.z,
+        bounds: [x0, y0, x1, y1],
+    })
+}
+
+/// Check that the tile indices of the given bounds can be represented, tiles are named by index
+pub fn check_bounds(
+    bounds: &las::Bounds,
+    [size_x, size_y]: [f64; 2],
+    tile_size_z: Option<f64>,
+) -> bool {
+    let horizontal = [
+        bounds.min.x / size_x,
+        bounds.min.y / size_y,
+        bounds.max.x / size_x,
+        bounds.max.y / size_y,
+    ];
+    let vertical = tile_size_z.map(|size| [bounds.min.z / size, bounds.max.z / size]);
+    horizontal
+        .into_iter()
+        .chain(vertical.into_iter().flatten())
+        .all(|index| index.is_finite() && index >= i32::MIN as f64 && index <= i32::MAX as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiling::{GridNames, Tiling};
+
+    #[test]
+    fn tiles_west_of_greenwich_are_checked_with_their_own_bounds() {
+        let tiling = Tiling::Grid {
+            size: [1.0; 2],
+            origin: [0.0; 2],
+            size_z: None,
+            names: GridNames::default(),
+        };
+        let check = |x: f64, y: f64| {
+            let key = tiling.key_of_xyz([x, y, 0.0], None).unwrap();
+            check_tile(key, "tile", tiling.bounds(key), CrsKind::Geographic)
+                .map(|warning| (warning.warning, warning.bounds))
+        };
+        assert_eq!(check(-179.5, -89.5), None);
+        assert_eq!(check(-0.5, 45.5), None);
+        assert_eq!(
+            check(-180.5, 10.5),
+            Some((ExtentProblem::OutsideCrsArea, [-181.0, 10.0, -180.0, 11.0]))
+        );
+        assert_eq!(
+            check(10.5, -90.5),
+            Some((ExtentProblem::OutsideCrsArea, [10.0, -91.0, 11.0, -90.0]))
+        );
+    }
+}
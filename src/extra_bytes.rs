@@ -0,0 +1,81 @@
+//! Extra bytes attributes, which are carried through to the output tiles as long as all inputs
+//! describe them the same way
+
+use anyhow::Result;
+
+use crate::input::InputFile;
+
+/// Record ID of the Extra Bytes VLR in `LASF_Spec`
+const EXTRA_BYTES_RECORD_ID: u16 = 4;
+
+/// Size of the description of a single attribute in the Extra Bytes VLR
+const DESCRIPTOR_SIZE: usize = 192;
+
+/// The extra bytes of the points of a file: their number and the description of the attributes
+/// stored in them, if the file has an Extra Bytes VLR
+#[derive(Debug, PartialEq)]
+struct Schema<'a> {
+    len: u16,
+    descriptors: Option<&'a [u8]>,
+}
+
+impl<'a> Schema<'a> {
+    fn of(header: &'a las::Header) -> Self {
+        let descriptors = header
+            .vlrs()
+            .iter()
+            .chain(header.evlrs())
+            .find(|vlr| {
+                vlr.user_id.trim_end_matches('\0') == "LASF_Spec"
+                    && vlr.record_id == EXTRA_BYTES_RECORD_ID
+            })
+            .map(|vlr| vlr.data.as_slice());
+        Self {
+            len: header.point_format().extra_bytes,
+            descriptors,
+        }
+    }
+
+    /// The names of the described attributes, for error messages
+    fn names(&self) -> Vec<String> {
+        self.descriptors
+            .unwrap_or_default()
+            .chunks_exact(DESCRIPTOR_SIZE)
+            .map(|descriptor| {
+                String::from_utf8_lossy(&descriptor[4..36])
+                    .trim_end_matches('\0')
+                    .to_owned()
+            })
+            .collect()
+    }
+}
+
+/// Check that all inputs have the same extra bytes attributes, so the attribute values of every
+/// point keep their meaning in the output tiles. The Extra Bytes VLR is copied to the tiles
+/// along with the rest of the header.
+pub fn check_compatible(headers: &[(InputFile, las::Header)]) -> Result<()> {
+    let Some((first_path, first)) = headers.first() else {
+        return Ok(());
+    };
+    let expected = Schema::of(first);
+    for (path, header) in headers {
+        let schema = Schema::of(header);
+        anyhow::ensure!(
+            schema == expected,
+            "{path} has {} extra bytes per point with attributes {:?}, which is incompatible with \
+             {} extra bytes with attributes {:?} in {first_path}",
+            schema.len,
+            schema.names(),
+            expected.len,
+            expected.names()
+        );
+    }
+    if expected.len > 0 {
+        tracing::info!(
+            "Keeping {} extra bytes per point with attributes {:?}",
+            expected.len,
+            expected.names()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use wasmi::{Engine, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::{
+    cli::{FilterArgs, GroundWith},
+    input::InputFile,
+    script::Script,
+};
+
+/// The user-supplied filters that every point passes through before it is written
+pub struct PointFilters {
+    ground: Option<GroundFilter>,
+    wasm: Option<WasmFilter>,
+    script: Option<Script>,
+}
+
+impl PointFilters {
+    pub fn load(args: &FilterArgs) -> Result<Self> {
+        Ok(Self {
+            ground: args
+                .ground_only
+                .then(|| GroundFilter::new(&args.ground_with)),
+            wasm: args
+                .wasm_filter
+                .as_deref()
+                .map(WasmFilter::load)
+                .transpose()?,
+            script: args.script.as_deref().map(Script::load).transpose()?,
+        })
+    }
+
+    /// Require the points moved by the WASM filter to stay within `bounds`, the bounds of the
+    /// input file they are read from, as the tiles are planned from those
+    pub fn confine(&mut self, bounds: las::Bounds) {
+        if let Some(filter) = &mut self.wasm {
+            filter.bounds = Some(bounds);
+        }
+    }
+
+    /// Run the points read from `source` through `--ground-only`, the WASM filter and then the
+    /// script
+    pub fn apply(&mut self, points: &mut Vec<las::Point>, source: &InputFile) -> Result<()> {
+        if let Some(ground) = &self.ground {
+            points.retain(|p| ground.keeps(p));
+        }
+        if let Some(filter) = &mut self.wasm {
+            filter
+                .apply(points)
+                .with_context(|| format!("run WASM filter on points of {source}"))?;
+        }
+        if let Some(script) = &mut self.script {
+            script
+                .apply(points)
+                .with_context(|| format!("run script on points of {source}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The points kept by `--ground-only`
+struct GroundFilter {
+    classes: Vec<u8>,
+    key_points: bool,
+}
+
+impl GroundFilter {
+    fn new(with: &[GroundWith]) -> Self {
+        let mut classes = vec![GROUND];
+        for with in with {
+            classes.push(match with {
+                GroundWith::Water => WATER,
+                GroundWith::KeyPoints => MODEL_KEY_POINT,
+            });
+        }
+        Self {
+            classes,
+            key_points: with.contains(&GroundWith::KeyPoints),
+        }
+    }
+
+    fn keeps(&self, point: &las::Point) -> bool {
+        self.classes.contains(&u8::from(point.classification))
+            || (self.key_points && point.is_key_point)
+    }
+}
+
+/// ASPRS classes of the points kept by `--ground-only`
+const GROUND: u8 = 2;
+const MODEL_KEY_POINT: u8 = 8;
+const WATER: u8 = 9;
+
+/// Size in bytes of a single point record shared with the WASM module.
+///
+/// All values are little-endian:
+///
+/// | offset | type | field                                                          |
+/// |--------|------|----------------------------------------------------------------|
+/// | 0      | f64  | x                                                              |
+/// | 8      | f64  | y                                                              |
+/// | 16     | f64  | z                                                              |
+/// | 24     | f64  | gps time (NaN if the point has none)                           |
+/// | 32     | f32  | scan angle                                                     |
+/// | 36     | u16  | intensity                                                      |
+/// | 38     | u16  | point source ID                                                |
+/// | 40     | u8   | classification                                                 |
+/// | 41     | u8   | return number                                                  |
+/// | 42     | u8   | number of returns                                              |
+/// | 43     | u8   | user data                                                      |
+/// | 44     | u8   | flags: synthetic (bit 0), key point, withheld, overlap (bit 3) |
+/// | 45     | u8   | keep: set to 1 by the host, set to 0 to drop the point         |
+/// | 46     | -    | reserved                                                       |
+pub const RECORD_SIZE: usize = 48;
+
+const KEEP_OFFSET: usize = 45;
+
+/// A point filter/transform loaded from a WASM module.
+///
+/// The module must export its `memory`, a function `alloc(size: i32) -> i32` returning a
+/// pointer to at least `size` bytes of memory, and a function `filter(ptr: i32, count: i32)`
+/// that modifies the `count` point records (see [`RECORD_SIZE`]) at `ptr` in place. Points
+/// are passed one read buffer at a time, in input order.
+pub struct WasmFilter {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+    filter: TypedFunc<(u32, u32), ()>,
+    /// Pointer to and size of the buffer allocated in the module, if any
+    buffer: Option<(u32, usize)>,
+    records: Vec<u8>,
+    /// Where the points may be moved to, see [`PointFilters::confine`]
+    bounds: Option<las::Bounds>,
+}
+
+impl WasmFilter {
+    pub fn load(path: &Path) -> Result<Self> {
+        let wasm =
+            std::fs::read(path).with_context(|| format!("read WASM module: {}", path.display()))?;
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wasm)
+            .with_context(|| format!("compile WASM module: {}", path.display()))?;
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .with_context(|| format!("instantiate WASM module: {}", path.display()))?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .context("WASM module does not export `memory`")?;
+        let alloc = instance
+            .get_typed_func(&store, "alloc")
+            .context("WASM module does not export `alloc(size: i32) -> i32`")?;
+        let filter = instance
+            .get_typed_func(&store, "filter")
+            .context("WASM module does not export `filter(ptr: i32, count: i32)`")?;
+
+        Ok(Self {
+            store,
+            memory,
+            alloc,
+            filter,
+            buffer: None,
+            records: Vec::new(),
+            bounds: None,
+        })
+    }
+
+    /// Run the filter on a buffer of points, updating them and removing the dropped ones
+    pub fn apply(&mut self, points: &mut Vec<las::Point>) -> Result<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        self.records.clear();
+        for point in points.iter() {
+            encode(point, &mut self.records);
+        }
+
+        let len = self.records.len();
+        let ptr = match self.buffer {
+            Some((ptr, size)) if size >= len => ptr,
+            _ => {
+                let size = u32::try_from(len).context("point buffer too large for WASM")?;
+                let ptr = self
+                    .alloc
+                    .call(&mut self.store, size)
+                    .context("call `alloc` in WASM module")?;
+                self.buffer = Some((ptr, len));
+                ptr
+            }
+        };
+
+        self.memory
+            .write(&mut self.store, ptr as usize, &self.records)
+            .map_err(|e| anyhow!("write points to WASM memory: {e}"))?;
+        self.filter
+            .call(&mut self.store, (ptr, points.len() as u32))
+            .context("call `filter` in WASM module")?;
+        self.memory
+            .read(&self.store, ptr as usize, &mut self.records)
+            .map_err(|e| anyhow!("read points from WASM memory: {e}"))?;
+
+        for (point, record) in points
+            .iter_mut()
+            .zip(self.records.chunks_exact(RECORD_SIZE))
+        {
+            let from = [point.x, point.y, point.z];
+            decode(record, point)?;
+            let to = [point.x, point.y, point.z];
+            if let Some(bounds) = &self.bounds
+                && record[KEEP_OFFSET] != 0
+                && from != to
+                && !contains(bounds, to)
+            {
+                anyhow::bail!(
+                    "the WASM filter moved the point at {from:?} to {to:?}, outside the bounds of its input file, where it has no tile to go to"
+                );
+            }
+        }
+        let mut keep = self
+            .records
+            .chunks_exact(RECORD_SIZE)
+            .map(|record| record[KEEP_OFFSET] != 0);
+        points.retain(|_| keep.next().unwrap_or(true));
+        Ok(())
+    }
+}
+
+fn contains(bounds: &las::Bounds, [x, y, z]: [f64; 3]) -> bool {
+    (bounds.min.x..=bounds.max.x).contains(&x)
+        && (bounds.min.y..=bounds.max.y).contains(&y)
+        && (bounds.min.z..=bounds.max.z).contains(&z)
+}
+
+fn encode(point: &las::Point, out: &mut Vec<u8>) {
+    out.extend_from_slice(&point.x.to_le_bytes());
+    out.extend_from_slice(&point.y.to_le_bytes());
+    out.extend_from_slice(&point.z.to_le_bytes());
+    out.extend_from_slice(&point.gps_time.unwrap_or(f64::NAN).to_le_bytes());
+    out.extend_from_slice(&point.scan_angle.to_le_bytes());
+    out.extend_from_slice(&point.intensity.to_le_bytes());
+    out.extend_from_slice(&point.point_source_id.to_le_bytes());
+    out.push(u8::from(point.classification));
+    out.push(point.return_number);
+    out.push(point.number_of_returns);
+    out.push(point.user_data);
+    out.push(
+        u8::from(point.is_synthetic)
+            | u8::from(point.is_key_point) << 1
+            | u8::from(point.is_withheld) << 2
+            | u8::from(point.is_overlap) << 3,
+    );
+    out.push(1);
+    out.extend_from_slice(&[0; RECORD_SIZE - KEEP_OFFSET - 1]);
+}
+
+fn decode(record: &[u8], point: &mut las::Point) -> Result<()> {
+    let f64_at = |i: usize| f64::from_le_bytes(record[i..i + 8].try_into().expect("8 bytes"));
+    let u16_at = |i: usize| u16::from_le_bytes(record[i..i + 2].try_into().expect("2 bytes"));
+
+    point.x = f64_at(0);
+    point.y = f64_at(8);
+    point.z = f64_at(16);
+    if point.gps_time.is_some() {
+        point.gps_time = Some(f64_at(24));
+    }
+    point.scan_angle = f32::from_le_bytes(record[32..36].try_into().expect("4 bytes"));
+    point.intensity = u16_at(36);
+    point.point_source_id = u16_at(38);
+    point.classification = las::point::Classification::new(record[40])
+        .with_context(|| format!("invalid classification from WASM filter: {}", record[40]))?;
+    point.return_number = record[41];
+    point.number_of_returns = record[42];
+    point.user_data = record[43];
+    let flags = record[44];
+    point.is_synthetic = flags & 1 != 0;
+    point.is_key_point = flags & (1 << 1) != 0;
+    point.is_withheld = flags & (1 << 2) != 0;
+    point.is_overlap = flags & (1 << 3) != 0;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! Georeferenced rasters from GeoTIFF files with a pixel scale and tie point, shared by
+//! `--z-adjust` and `--colorize`
+
+use std::io::{Read, Seek};
+
+use anyhow::{Context, Result};
+use tiff::{decoder::Decoder, tags::Tag};
+
+/// GeoTIFF key telling whether the values are for the area of a pixel (1) or its corner (2)
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+/// Where the pixels of a raster lie
+#[derive(Debug, Clone, Copy)]
+pub struct Georeference {
+    pub width: usize,
+    pub height: usize,
+    /// X and Y of the center of the top left pixel
+    origin: [f64; 2],
+    /// The size of a pixel along X and Y, Y going down
+    pixel_size: [f64; 2],
+}
+
+impl Georeference {
+    /// Read the georeference of the image of the decoder
+    pub fn read<R: Read + Seek>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let (width, height) = decoder.dimensions()?;
+        let scale = decoder
+            .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+            .context("no pixel scale, is it a GeoTIFF?")?;
+        let tie_point = decoder
+            .get_tag_f64_vec(Tag::ModelTiepointTag)
+            .context("no tie point, is it a GeoTIFF?")?;
+        anyhow::ensure!(
+            scale.len() >= 2 && tie_point.len() >= 6,
+            "invalid pixel scale or tie point"
+        );
+        let pixel_is_point = decoder
+            .get_tag_u16_vec(Tag::GeoKeyDirectoryTag)
+            .ok()
+            .and_then(|keys| {
+                keys.get(4..)?
+                    .chunks_exact(4)
+                    .find(|key| key[0] == GT_RASTER_TYPE_GEO_KEY && key[1] == 0)
+                    .map(|key| key[3] == RASTER_PIXEL_IS_POINT)
+            })
+            .unwrap_or(false);
+
+        // the tie point maps raster position (i, j) to (x, y)
+        let [i, j, _, x, y, ..] = tie_point[..] else {
+            unreachable!("checked above")
+        };
+        let center = if pixel_is_point { 0.0 } else { 0.5 };
+        Ok(Self {
+            width: width as usize,
+            height: height as usize,
+            origin: [x + (center - i) * scale[0], y - (center - j) * scale[1]],
+            pixel_size: [scale[0], scale[1]],
+        })
+    }
+
+    /// `[min x, min y, max x, max y]` of the area covered by the pixels
+    pub fn extent(&self) -> [f64; 4] {
+        let [x, y] = self.origin;
+        let [sx, sy] = self.pixel_size;
+        [
+            x - sx / 2.0,
+            y - (self.height as f64 - 0.5) * sy,
+            x + (self.width as f64 - 0.5) * sx,
+            y + sy / 2.0,
+        ]
+    }
+
+    /// The column and row of a position, in pixels from the center of the top left pixel, or
+    /// `None` outside the raster
+    pub fn position(&self, x: f64, y: f64) -> Option<[f64; 2]> {
+        let col = (x - self.origin[0]) / self.pixel_size[0];
+        let row = (self.origin[1] - y) / self.pixel_size[1];
+        ((-0.5..self.width as f64 - 0.5).contains(&col)
+            && (-0.5..self.height as f64 - 0.5).contains(&row))
+        .then_some([col, row])
+    }
+
+    /// The index of the pixel at a column and row, clamped to the raster
+    pub fn index(&self, col: f64, row: f64) -> usize {
+        let col = (col.max(0.0) as usize).min(self.width - 1);
+        let row = (row.max(0.0) as usize).min(self.height - 1);
+        row * self.width + col
+    }
+
+    /// The index of the pixel containing a position, or `None` outside the raster
+    pub fn nearest(&self, x: f64, y: f64) -> Option<usize> {
+        let [col, row] = self.position(x, y)?;
+        Some(self.index(col.round(), row.round()))
+    }
+}
+
+/// The no data value of the raster, if it has one
+pub fn no_data<R: Read + Seek>(decoder: &mut Decoder<R>) -> Result<Option<f64>> {
+    Ok(decoder
+        .find_tag(Tag::GdalNodata)?
+        .map(|value| value.into_string())
+        .transpose()?
+        .and_then(|value| value.trim_end_matches('\0').trim().parse::<f64>().ok()))
+}
@@ -0,0 +1,127 @@
+//! Standard grids (`--grid-scheme`) that set both the tile size and the tile names, so the tiles
+//! drop directly into national or European archive structures
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::{crs, input::InputFile, tile::TileKey};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GridScheme {
+    /// The 1 km cells of the EEA reference grid in ETRS89-LAEA (EPSG:3035), e.g.
+    /// `1kmE4321N3210.laz`
+    #[value(name = "eea-1km")]
+    Eea1km,
+    /// The 10 km cells of the EEA reference grid in ETRS89-LAEA (EPSG:3035), e.g.
+    /// `10kmE432N321.laz`
+    #[value(name = "eea-10km")]
+    Eea10km,
+    /// The 1 km cells of the INSPIRE grid in ETRS89-LAEA (EPSG:3035), e.g.
+    /// `CRS3035RES1000mN3210000E4321000.laz`
+    #[value(name = "inspire-1km")]
+    Inspire1km,
+    /// The 2.5 km index squares of Lantmäteriet in SWEREF 99 TM (EPSG:3006), named by the
+    /// northing and easting in 100 m and the size, e.g. `65825_5675_25.laz`
+    #[value(name = "se-2.5km")]
+    Se2500m,
+}
+
+impl GridScheme {
+    /// The size of the tiles along X and Y
+    pub fn size(self) -> [f64; 2] {
+        let size = match self {
+            GridScheme::Eea1km | GridScheme::Inspire1km => 1000.0,
+            GridScheme::Eea10km => 10_000.0,
+            GridScheme::Se2500m => 2500.0,
+        };
+        [size; 2]
+    }
+
+    /// The EPSG code of the CRS the grid is defined in
+    pub fn epsg(self) -> u32 {
+        match self {
+            GridScheme::Eea1km | GridScheme::Eea10km | GridScheme::Inspire1km => 3035,
+            GridScheme::Se2500m => 3006,
+        }
+    }
+
+    /// Check that all inputs are in the CRS of the grid, the names would be wrong otherwise.
+    /// Inputs without a CRS with an EPSG code can be given one with `--a-srs`.
+    pub fn check_crs(self, headers: &[(InputFile, las::Header)]) -> Result<()> {
+        let epsg = self.epsg();
+        for (path, header) in headers {
+            match crs::epsg(header) {
+                Some(code) if code == epsg => {}
+                Some(code) => anyhow::bail!(
+                    "{path} is in EPSG:{code}, but the grid scheme {} is defined in EPSG:{epsg}. Reproject the inputs first",
+                    self.to_possible_value()
+                        .expect("no skipped values")
+                        .get_name()
+                ),
+                None => anyhow::bail!(
+                    "{path} declares no CRS with an EPSG code, but the grid scheme {} is defined in EPSG:{epsg}. Assign it with --a-srs EPSG:{epsg} if the inputs are in it",
+                    self.to_possible_value()
+                        .expect("no skipped values")
+                        .get_name()
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// The file name of a tile, the Z index of `--tile-size-z` is appended
+    pub fn name(self, key: TileKey) -> String {
+        // the coordinates of the lower left corner
+        let [size_x, size_y] = self.size();
+        let (x, y) = (
+            (f64::from(key.x) * size_x) as i64,
+            (f64::from(key.y) * size_y) as i64,
+        );
+        let name = match self {
+            GridScheme::Eea1km => {
+                format!("1kmE{}N{}", x.div_euclid(1000), y.div_euclid(1000))
+            }
+            GridScheme::Eea10km => {
+                format!("10kmE{}N{}", x.div_euclid(10_000), y.div_euclid(10_000))
+            }
+            GridScheme::Inspire1km => format!("CRS3035RES1000mN{y}E{x}"),
+            GridScheme::Se2500m => format!("{}_{}_25", y.div_euclid(100), x.div_euclid(100)),
+        };
+        match key.z {
+            None => format!("{name}.laz"),
+            Some(z) => format!("{name}_{z}.laz"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(x: i32, y: i32) -> TileKey {
+        TileKey {
+            x,
+            y,
+            z: None,
+            part: None,
+        }
+    }
+
+    #[test]
+    fn names_are_the_lower_left_corner() {
+        assert_eq!(
+            GridScheme::Eea1km.name(key(4321, 3210)),
+            "1kmE4321N3210.laz"
+        );
+        assert_eq!(GridScheme::Eea10km.name(key(432, 321)), "10kmE432N321.laz");
+        assert_eq!(
+            GridScheme::Inspire1km.name(key(4321, 3210)),
+            "CRS3035RES1000mN3210000E4321000.laz"
+        );
+        assert_eq!(
+            GridScheme::Se2500m.name(key(227, 2633)),
+            "65825_5675_25.laz"
+        );
+        assert_eq!(GridScheme::Eea1km.name(key(-1, -2)), "1kmE-1N-2.laz");
+    }
+}
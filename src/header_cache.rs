@@ -0,0 +1,295 @@
+//! A cache of the headers of local input files (`--header-cache`), so that repeated runs over the
+//! same inputs, e.g. after changing the tile size, do not open thousands of unchanged files again.
+//!
+//! Each entry holds the complete header with its (extended) VLRs, and is used as long as the size
+//! and modification time of the file have not changed.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result};
+
+/// Start of every cache file, followed by the entries
+const MAGIC: &[u8] = b"lasretile header cache 1\n";
+
+/// What tells whether a file changed since its header was cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    size: u64,
+    /// Modification time as seconds and nanoseconds since the Unix epoch
+    modified: (u64, u32),
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified: (modified.as_secs(), modified.subsec_nanos()),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    stamp: Stamp,
+    header: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct HeaderCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    changed: AtomicBool,
+    hits: AtomicUsize,
+}
+
+impl HeaderCache {
+    /// Load the cache file, starting with an empty cache if it does not exist or can not be read
+    pub fn open(path: &Path) -> Self {
+        let entries = match std::fs::read(path) {
+            Ok(data) => parse(&data).unwrap_or_else(|e| {
+                tracing::warn!("ignoring invalid header cache {}: {e:#}", path.display());
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                tracing::warn!("ignoring unreadable header cache {}: {e}", path.display());
+                HashMap::new()
+            }
+        };
+        Self {
+            path: path.to_owned(),
+            entries: Mutex::new(entries),
+            changed: AtomicBool::new(false),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// The header of a local file, from the cache if the file did not change since, or else using
+    /// `read` and adding it to the cache
+    pub fn header(
+        &self,
+        file: &Path,
+        read: impl FnOnce() -> Result<las::Header>,
+    ) -> Result<las::Header> {
+        let key = std::path::absolute(file).unwrap_or_else(|_| file.to_owned());
+        let Some(stamp) = Stamp::of(file) else {
+            return read();
+        };
+
+        let cached = self
+            .entries
+            .lock()
+            .expect("not poisoned")
+            .get(&key)
+            .filter(|entry| entry.stamp == stamp)
+            .map(|entry| decode(&entry.header));
+        match cached {
+            Some(Ok(header)) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(header);
+            }
+            Some(Err(e)) => tracing::debug!("invalid cached header of {}: {e:#}", file.display()),
+            None => {}
+        }
+
+        let header = read()?;
+        match encode(&header) {
+            Ok(data) => {
+                let entry = Entry {
+                    stamp,
+                    header: data,
+                };
+                self.entries
+                    .lock()
+                    .expect("not poisoned")
+                    .insert(key, entry);
+                self.changed.store(true, Ordering::Relaxed);
+            }
+            Err(e) => tracing::debug!("not caching the header of {}: {e:#}", file.display()),
+        }
+        Ok(header)
+    }
+
+    /// Number of headers that were taken from the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Write the cache file if any header was added to it. Entries of files that were not part of
+    /// this run are kept, so one cache can be shared by jobs over different inputs.
+    pub fn save(&self) -> Result<()> {
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let entries = self.entries.lock().expect("not poisoned");
+        let data = serialize(&entries);
+
+        // write next to the cache and rename, so an interrupted run does not corrupt it
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".part");
+        let temp = PathBuf::from(temp);
+        let mut file = std::fs::File::create(&temp)
+            .with_context(|| format!("create header cache: {}", temp.display()))?;
+        file.write_all(&data)
+            .with_context(|| format!("write header cache: {}", temp.display()))?;
+        std::fs::rename(&temp, &self.path)
+            .with_context(|| format!("replace header cache: {}", self.path.display()))?;
+        tracing::debug!(
+            "Wrote {} headers to the header cache {}",
+            entries.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+/// The cache file with the given entries, see [`parse`]
+fn serialize(entries: &HashMap<PathBuf, Entry>) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    for (path, entry) in entries {
+        // the cache file is binary, but paths that are not valid UTF-8 are rare enough
+        let Some(path) = path.to_str() else {
+            continue;
+        };
+        write_bytes(&mut data, path.as_bytes());
+        data.extend(entry.stamp.size.to_le_bytes());
+        data.extend(entry.stamp.modified.0.to_le_bytes());
+        data.extend(entry.stamp.modified.1.to_le_bytes());
+        write_bytes(&mut data, &entry.header);
+    }
+    data
+}
+
+fn parse(data: &[u8]) -> Result<HashMap<PathBuf, Entry>> {
+    let mut read = data
+        .strip_prefix(MAGIC)
+        .context("not a header cache of this version")?;
+    let mut entries = HashMap::new();
+    while !read.is_empty() {
+        let path = String::from_utf8(read_bytes(&mut read)?).context("invalid path")?;
+        let stamp = Stamp {
+            size: u64::from_le_bytes(read_array(&mut read)?),
+            modified: (
+                u64::from_le_bytes(read_array(&mut read)?),
+                u32::from_le_bytes(read_array(&mut read)?),
+            ),
+        };
+        let header = read_bytes(&mut read)?;
+        entries.insert(path.into(), Entry { stamp, header });
+    }
+    Ok(entries)
+}
+
+/// The header as the raw LAS header followed by the number of VLRs and the VLRs, then the number
+/// of EVLRs and the EVLRs
+fn encode(header: &las::Header) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    header.clone().into_raw()?.write_to(&mut data)?;
+    for (vlrs, extended) in [(header.vlrs(), false), (header.evlrs(), true)] {
+        data.extend(u32::try_from(vlrs.len())?.to_le_bytes());
+        for vlr in vlrs {
+            vlr.clone().into_raw(extended)?.write_to(&mut data)?;
+        }
+    }
+    Ok(data)
+}
+
+fn decode(mut data: &[u8]) -> Result<las::Header> {
+    let raw = las::raw::Header::read_from(&mut data)?;
+    let mut builder = las::Builder::new(raw)?;
+    for extended in [false, true] {
+        let count = u32::from_le_bytes(read_array(&mut data)?);
+        let vlrs = if extended {
+            &mut builder.evlrs
+        } else {
+            &mut builder.vlrs
+        };
+        for _ in 0..count {
+            vlrs.push(las::Vlr::new(las::raw::Vlr::read_from(
+                &mut data, extended,
+            )?));
+        }
+    }
+    Ok(builder.into_header()?)
+}
+
+fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend((bytes.len() as u64).to_le_bytes());
+    data.extend(bytes);
+}
+
+fn read_bytes(read: &mut &[u8]) -> Result<Vec<u8>> {
+    let len = u64::from_le_bytes(read_array(read)?);
+    anyhow::ensure!(len <= read.len() as u64, "truncated entry");
+    let (bytes, rest) = read.split_at(len as usize);
+    *read = rest;
+    Ok(bytes.to_vec())
+}
+
+fn read_array<const N: usize>(read: &mut &[u8]) -> Result<[u8; N]> {
+    let mut array = [0; N];
+    read.read_exact(&mut array).context("truncated entry")?;
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> HashMap<PathBuf, Entry> {
+        HashMap::from([
+            (
+                PathBuf::from("/data/a.laz"),
+                Entry {
+                    stamp: Stamp {
+                        size: 1234,
+                        modified: (1_700_000_000, 999_999_999),
+                    },
+                    header: b"header of a".to_vec(),
+                },
+            ),
+            (
+                PathBuf::from("/data/b.las"),
+                Entry {
+                    stamp: Stamp {
+                        size: 0,
+                        modified: (0, 0),
+                    },
+                    header: Vec::new(),
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn cache_files_are_read_back() {
+        let parsed = parse(&serialize(&entries())).unwrap();
+        assert_eq!(parsed.len(), 2);
+        for (path, entry) in entries() {
+            let read = &parsed[&path];
+            assert_eq!(read.stamp, entry.stamp);
+            assert_eq!(read.header, entry.header);
+        }
+    }
+
+    #[test]
+    fn truncated_or_foreign_cache_files_are_refused() {
+        let data = serialize(&entries());
+        for len in [MAGIC.len() + 4, data.len() - 1] {
+            assert!(parse(&data[..len]).is_err(), "{len} bytes");
+        }
+        assert!(parse(b"lasretile header cache 0\n").is_err());
+        assert!(parse(MAGIC).unwrap().is_empty());
+    }
+}
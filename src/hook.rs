@@ -0,0 +1,156 @@
+//! Running a user command on each tile once it has been written (`--on-tile-complete`), e.g. to
+//! index, inspect or upload it. The commands run on a few worker threads, so that they overlap
+//! with the retiling of the other tiles.
+
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::{Context, Result};
+
+/// Number of tiles that can wait for a worker before the retiling blocks
+const QUEUE_DEPTH: usize = 64;
+
+pub struct TileHooks {
+    sender: mpsc::SyncSender<(String, PathBuf)>,
+    threads: Vec<JoinHandle<()>>,
+    failed: Arc<AtomicUsize>,
+}
+
+impl TileHooks {
+    /// Start `jobs` workers running `command` for the tiles, see [`command_line`]
+    pub fn new(command: &str, jobs: NonZeroUsize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<(String, PathBuf)>(QUEUE_DEPTH);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let failed = Arc::new(AtomicUsize::new(0));
+        let threads = (0..jobs.get())
+            .map(|_| {
+                let receiver = receiver.clone();
+                let failed = failed.clone();
+                let command = command.to_owned();
+                std::thread::spawn(move || {
+                    loop {
+                        // the lock is released before running the command
+                        let next = receiver.lock().expect("not poisoned").recv();
+                        let Ok((name, path)) = next else {
+                            break;
+                        };
+                        if let Err(e) = run(&command, &name, &path) {
+                            tracing::error!("--on-tile-complete for {name}: {e:#}");
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+            })
+            .collect();
+        Self {
+            sender,
+            threads,
+            failed,
+        }
+    }
+
+    /// Queue the command for a written tile, blocking while all workers are busy and the queue
+    /// is full
+    pub fn tile_complete(&self, name: &str, path: &Path) -> Result<()> {
+        self.sender
+            .send((name.to_owned(), path.to_owned()))
+            .ok()
+            .context("--on-tile-complete worker stopped unexpectedly")
+    }
+
+    /// Wait for the commands of all tiles to finish, failing if any of them failed
+    pub fn finish(self) -> Result<()> {
+        drop(self.sender);
+        for thread in self.threads {
+            thread
+                .join()
+                .map_err(|_| anyhow::anyhow!("--on-tile-complete worker panicked"))?;
+        }
+        let failed = self.failed.load(Ordering::Relaxed);
+        anyhow::ensure!(failed == 0, "--on-tile-complete failed for {failed} tiles");
+        Ok(())
+    }
+}
+
+/// The command for a tile, with `{path}` replaced by the quoted path of the tile and `{name}` by
+/// its quoted name
+fn command_line(command: &str, name: &str, path: &Path) -> Result<String> {
+    Ok(command
+        .replace("{path}", &quote(&path.to_string_lossy())?)
+        .replace("{name}", &quote(name)?))
+}
+
+/// Run the command for a tile in the shell, with its output passed through
+fn run(command: &str, name: &str, path: &Path) -> Result<()> {
+    let command = command_line(command, name, path)?;
+    tracing::debug!("Running {command}");
+    let status = shell(&command)
+        .status()
+        .with_context(|| format!("start {command:?}"))?;
+    anyhow::ensure!(status.success(), "{command:?} exited with {status}");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(unix)]
+fn quote(s: &str) -> Result<String> {
+    Ok(format!("'{}'", s.replace('\'', r"'\''")))
+}
+
+/// Quoted for `cmd`, which has no way to escape `"` within quotes and expands `%...%` even
+/// inside them, so paths and names with those are refused rather than run as another command
+#[cfg(not(unix))]
+fn quote(s: &str) -> Result<String> {
+    anyhow::ensure!(
+        !s.contains(['"', '%']),
+        "can not pass {s:?} to --on-tile-complete, as it contains `\"` or `%`"
+    );
+    Ok(format!("\"{s}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn names_and_paths_are_quoted_for_the_shell() {
+        let command = command_line(
+            "gzip {path} && echo {name}",
+            "it's.laz",
+            Path::new("/out/it's.laz"),
+        )
+        .unwrap();
+        assert_eq!(command, r"gzip '/out/it'\''s.laz' && echo 'it'\''s.laz'");
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn names_that_cmd_can_not_quote_are_refused() {
+        assert_eq!(quote("tile 1.laz").unwrap(), "\"tile 1.laz\"");
+        assert!(quote("50%.laz").is_err());
+        assert!(quote("a\"b.laz").is_err());
+    }
+}
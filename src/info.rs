@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+
+use crate::{cli::InfoArgs, extent, input, tiling::Tiling, units, vector_max, vector_min};
+
+/// Number of candidate tile sizes suggested when none are given
+const CANDIDATES: usize = 4;
+
+/// Print what is known about the inputs from their headers alone, without reading any points
+pub fn run(args: &InfoArgs) -> Result<()> {
+    let mut skipped = input::SkipReport::default();
+    let input_files = input::collect_inputs(&args.input, &mut skipped)?;
+    let headers = input::scan_headers(
+        input_files,
+        args.input.header_cache.as_deref(),
+        &mut skipped,
+    );
+    skipped.print_summary();
+    anyhow::ensure!(!headers.is_empty(), "no input files found");
+
+    let units = args.units.units();
+    for (path, header) in &headers {
+        let bounds = header.bounds();
+        println!("{path}");
+        println!(
+            "  {} points, LAS {}, {}, CRS {}",
+            header.number_of_points(),
+            header.version(),
+            header.point_format(),
+            extent::crs_description(header)
+                .as_deref()
+                .unwrap_or("unknown")
+        );
+        println!("  bounds: min={:?}, max={:?}", bounds.min, bounds.max);
+    }
+
+    let min = headers
+        .iter()
+        .map(|(_, h)| h.bounds().min)
+        .reduce(|a, b| vector_min(&a, &b))
+        .context("at least one input file")?;
+    let max = headers
+        .iter()
+        .map(|(_, h)| h.bounds().max)
+        .reduce(|a, b| vector_max(&a, &b))
+        .context("at least one input file")?;
+    let total_points: u64 = headers.iter().map(|(_, h)| h.number_of_points()).sum();
+    let area = (max.x - min.x) * (max.y - min.y);
+
+    println!();
+    println!(
+        "{} input files with a total of {total_points} points",
+        headers.len()
+    );
+    println!("Overall bounds: min={min:?}, max={max:?}");
+    println!(
+        "Overall size: x={}, y={}, z={}",
+        units.format_length(max.x - min.x),
+        units.format_length(max.y - min.y),
+        units.format_length(max.z - min.z)
+    );
+    println!(
+        "Overall area: {} ({})",
+        units.format_area(area),
+        units.format_density(total_points, area)
+    );
+
+    let sizes = match &args.tile_sizes {
+        Some(sizes) => sizes.clone(),
+        None => candidate_sizes(&headers),
+    };
+    println!();
+    println!("Tile size     Tiles   Points per tile");
+    for size in sizes {
+        // like retiling, assume the inputs have points everywhere within their bounds
+        let tiling = Tiling::Grid {
+            size: [size; 2],
+            origin: [0.0; 2],
+            size_z: None,
+            names: Default::default(),
+        };
+        let mut tiles = HashSet::new();
+        for (path, header) in &headers {
+            tiles.extend(
+                tiling
+                    .tiles_in(&header.bounds())
+                    .with_context(|| format!("find the tiles of {path}"))?,
+            );
+        }
+        println!(
+            "{:>9} {:>9} {:>17}",
+            units.format_length(size),
+            tiles.len(),
+            units::format_number(total_points as f64 / tiles.len() as f64, 0)
+        );
+    }
+    Ok(())
+}
+
+/// Tile sizes around the typical size of the input files, rounded to 1, 2 or 5 times a power
+/// of ten
+fn candidate_sizes(headers: &[(input::InputFile, las::Header)]) -> Vec<f64> {
+    let mut sides: Vec<f64> = headers
+        .iter()
+        .map(|(_, h)| {
+            let bounds = h.bounds();
+            (bounds.max.x - bounds.min.x).max(bounds.max.y - bounds.min.y)
+        })
+        .filter(|side| *side > 0.0)
+        .collect();
+    sides.sort_by(f64::total_cmp);
+    let Some(&median) = sides.get(sides.len() / 2) else {
+        return Vec::new();
+    };
+
+    let mut sizes: Vec<f64> = (0..CANDIDATES)
+        .map(|i| nice_size(median * 2f64.powi(i as i32 - 1)))
+        .collect();
+    sizes.dedup();
+    sizes
+}
+
+/// A tile size giving about `target` points per tile, rounded to two significant digits. The point
+/// density is estimated from the areas of the input files rather than their overall extent, so
+/// gaps between them (e.g. along a corridor) do not make the tiles too large.
+pub fn size_for_points(headers: &[(input::InputFile, las::Header)], target: u64) -> Option<f64> {
+    let points: u64 = headers.iter().map(|(_, h)| h.number_of_points()).sum();
+    let area: f64 = headers
+        .iter()
+        .map(|(_, h)| {
+            let bounds = h.bounds();
+            (bounds.max.x - bounds.min.x) * (bounds.max.y - bounds.min.y)
+        })
+        .sum();
+    if area <= 0.0 || points == 0 {
+        return None;
+    }
+    let size = (target as f64 * area / points as f64).sqrt();
+    // divide by exact powers of ten, so that e.g. 1.2 does not come out as 1.2000000000000002
+    let exponent = size.log10().floor() as i32 - 1;
+    let magnitude = 10f64.powi(exponent.abs());
+    Some(if exponent >= 0 {
+        (size / magnitude).round() * magnitude
+    } else {
+        (size * magnitude).round() / magnitude
+    })
+}
+
+/// Round to the nearest of 1, 2 or 5 times a power of ten
+fn nice_size(size: f64) -> f64 {
+    let magnitude = 10f64.powf(size.log10().floor());
+    let nice = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .min_by(|a, b| {
+            (size / magnitude - a)
+                .abs()
+                .total_cmp(&(size / magnitude - b).abs())
+        })
+        .expect("non-empty candidates");
+    nice * magnitude
+}
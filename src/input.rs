@@ -0,0 +1,542 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{BufRead, Read, Seek},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{
+    archive::{self, ArchiveMember},
+    cli::InputArgs,
+    header_cache::HeaderCache,
+    queued_io::QueuedFile,
+    remote::{self, RemoteFile},
+    stdio, threads,
+    throughput::Counting,
+};
+
+/// A readable and seekable source that can be handed to `las::Reader`
+pub trait ReadSeek: Read + Seek + Send + Sync {}
+impl<T: Read + Seek + Send + Sync> ReadSeek for T {}
+
+/// A single LAS/LAZ input, either on the local file system, in a remote object store, inside a
+/// ZIP or TAR archive or a stream on stdin (`-`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InputFile {
+    Local(PathBuf),
+    Remote(RemoteFile),
+    Archive(ArchiveMember),
+    Stdin,
+}
+
+impl InputFile {
+    /// Open the input for reading
+    pub fn open(&self) -> Result<Box<dyn ReadSeek>> {
+        Ok(match self {
+            InputFile::Local(path) => Box::new(Counting::input(
+                QueuedFile::open(path).with_context(|| format!("open file: {}", path.display()))?,
+            )),
+            InputFile::Remote(file) => Box::new(Counting::input(file.open()?)),
+            InputFile::Archive(member) => Box::new(Counting::input(member.open()?)),
+            InputFile::Stdin => Box::new(Counting::input(stdio::open_stdin()?)),
+        })
+    }
+
+    /// The components of the path of the input, ending with the file name. For remote inputs,
+    /// the first component is the host (bucket). Members of archives continue the path of the
+    /// archive with their path inside it.
+    fn components(&self) -> Vec<String> {
+        match self {
+            InputFile::Local(path) => path
+                .components()
+                .filter_map(|c| match c {
+                    std::path::Component::Normal(c) => Some(c.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect(),
+            InputFile::Remote(file) => {
+                let url = file.url();
+                url.host_str()
+                    .into_iter()
+                    .chain(url.path_segments().into_iter().flatten())
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }
+            InputFile::Archive(member) => {
+                let mut components = InputFile::Local(member.archive.clone()).components();
+                components.extend(
+                    member
+                        .name
+                        .split('/')
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_owned),
+                );
+                components
+            }
+            InputFile::Stdin => vec!["stdin".to_owned()],
+        }
+    }
+
+    /// The file name of the input, the last component of its path
+    pub fn file_name(&self) -> String {
+        self.components().pop().unwrap_or_default()
+    }
+
+    /// What tells whether the input changed between runs: the size and modification time of
+    /// local files, the ETag (or size and modification time) of remote objects, the archive and
+    /// checksum of archive members
+    pub fn version(&self) -> Result<String> {
+        match self {
+            InputFile::Local(path) => {
+                let metadata = std::fs::metadata(path)
+                    .with_context(|| format!("get metadata of {}", path.display()))?;
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                Ok(format!(
+                    "{}-{}.{:09}",
+                    metadata.len(),
+                    modified.as_secs(),
+                    modified.subsec_nanos()
+                ))
+            }
+            InputFile::Remote(file) => file.version(),
+            InputFile::Archive(member) => member.version(),
+            InputFile::Stdin => anyhow::bail!("a stream on stdin has no version"),
+        }
+    }
+
+    /// Open the input and create a LAS/LAZ reader for it
+    pub fn reader(&self, options: las::ReaderOptions) -> Result<las::Reader> {
+        las::Reader::with_options(self.open()?, options)
+            .with_context(|| format!("open LAS/LAZ file: {self}"))
+    }
+}
+
+impl std::fmt::Display for InputFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputFile::Local(path) => write!(f, "{}", path.display()),
+            InputFile::Remote(file) => write!(f, "{}", file.url()),
+            InputFile::Archive(member) => write!(f, "{member}"),
+            InputFile::Stdin => f.write_str("-"),
+        }
+    }
+}
+
+impl Serialize for InputFile {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl schemars::JsonSchema for InputFile {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "InputFile".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "description": "Local path or URL of an input file",
+        })
+    }
+}
+
+/// Names that identify each input uniquely: its file name, prefixed with as many parent folders
+/// as needed to tell it apart from other inputs with the same file name (e.g. when scanning
+/// folders recursively). Use these instead of plain file names for anything keyed by input.
+pub fn unique_names(files: &[InputFile]) -> Vec<String> {
+    let components: Vec<Vec<String>> = files.iter().map(InputFile::components).collect();
+    let max_depth = components.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut names: Vec<Option<String>> = vec![None; files.len()];
+    for depth in 1..=max_depth {
+        let suffixes: Vec<String> = components
+            .iter()
+            .map(|c| c[c.len().saturating_sub(depth)..].join("/"))
+            .collect();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for suffix in &suffixes {
+            *counts.entry(suffix).or_default() += 1;
+        }
+        for (name, suffix) in names.iter_mut().zip(&suffixes) {
+            if name.is_none() && (counts[suffix.as_str()] == 1 || depth == max_depth) {
+                *name = Some(suffix.clone());
+            }
+        }
+    }
+    names.into_iter().map(Option::unwrap_or_default).collect()
+}
+
+/// Maximum number of headers read at the same time. Reading a header is mostly waiting for the
+/// storage, especially on network file systems and object stores.
+const SCAN_CONCURRENCY: usize = 32;
+
+/// Read the headers of all input files, skipping the ones that can not be read or contain no
+/// points. The headers are read concurrently, but returned in the order of `files`. Headers of
+/// local files are taken from the header `cache` if one is given and the file did not change.
+pub fn scan_headers(
+    files: Vec<InputFile>,
+    cache: Option<&Path>,
+    skipped: &mut SkipReport,
+) -> Vec<(InputFile, las::Header)> {
+    let cache = cache.map(HeaderCache::open);
+    let results = read_headers(&files, cache.as_ref());
+    if let Some(cache) = &cache {
+        tracing::info!(
+            "Read {} of {} headers from the header cache",
+            cache.hits(),
+            files.len()
+        );
+        if let Err(e) = cache.save() {
+            tracing::warn!("could not update the header cache: {e:#}");
+        }
+    }
+    let mut headers = Vec::new();
+    for (path, result) in files.into_iter().zip(results) {
+        let header = match result {
+            Ok(header) => header,
+            Err(e) => {
+                tracing::warn!("skipping unreadable file {path}: {e:#}");
+                skipped.add(SkipReason::Unreadable, path);
+                continue;
+            }
+        };
+
+        if header.number_of_points() == 0 {
+            skipped.add(SkipReason::ZeroPoints, path);
+            continue;
+        }
+        headers.push((path, header));
+    }
+    headers
+}
+
+/// Read the header of each file using up to [`SCAN_CONCURRENCY`] threads, or the limit of
+/// `--threads`
+fn read_headers(files: &[InputFile], cache: Option<&HeaderCache>) -> Vec<Result<las::Header>> {
+    let concurrency = threads::limit().map_or(SCAN_CONCURRENCY, NonZeroUsize::get);
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Result<las::Header>>>> =
+        files.iter().map(|_| Mutex::new(None)).collect();
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.min(files.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(i) else {
+                        break;
+                    };
+                    let read = || {
+                        file.reader(las::ReaderOptions::default())
+                            .map(|reader| reader.header().clone())
+                    };
+                    let header = match (file, cache) {
+                        (InputFile::Local(path), Some(cache)) => cache.header(path, read),
+                        _ => read(),
+                    };
+                    *results[i].lock().expect("not poisoned") = Some(header);
+                }
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| {
+            result
+                .into_inner()
+                .expect("not poisoned")
+                .expect("every file was read")
+        })
+        .collect()
+}
+
+/// How far points may lie outside the bounds in their header along each axis: coordinates are
+/// quantized by the scale factors, allow for that plus some rounding
+pub fn bounds_tolerance(header: &las::Header) -> [f64; 3] {
+    let transforms = header.transforms();
+    [transforms.x.scale, transforms.y.scale, transforms.z.scale]
+        .map(|scale| scale.abs() * 1.5 + 1e-9)
+}
+
+/// A copy of the header with changes to its raw fields, e.g. the bounds, keeping its VLRs
+pub fn modify_header(
+    header: &las::Header,
+    modify: impl FnOnce(&mut las::raw::Header),
+) -> Result<las::Header> {
+    let mut raw = header.clone().into_raw()?;
+    modify(&mut raw);
+    let mut builder = las::Builder::new(raw)?;
+    // the raw header does not hold the (extended) VLRs
+    builder.vlrs = header.vlrs().to_vec();
+    builder.evlrs = header.evlrs().to_vec();
+    Ok(builder.into_header()?)
+}
+
+/// Returns true if the extension is the one of a LAS/LAZ file
+pub fn is_pointcloud_extension(ext: Option<&str>) -> bool {
+    ext.is_some_and(|ext| ext == "las" || ext == "laz")
+}
+
+/// The reason an input entry was not processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    NotAFile,
+    WrongExtension,
+    Unreadable,
+    ZeroPoints,
+    OutsideClip,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SkipReason::NotAFile => "not a file",
+            SkipReason::WrongExtension => "wrong extension",
+            SkipReason::Unreadable => "unreadable",
+            SkipReason::ZeroPoints => "zero points",
+            SkipReason::OutsideClip => "outside --clip/--bounds",
+        })
+    }
+}
+
+/// Keeps track of all inputs that were skipped during the scan, grouped by reason
+#[derive(Debug, Default)]
+pub struct SkipReport {
+    skipped: BTreeMap<SkipReason, Vec<String>>,
+}
+
+impl SkipReport {
+    pub fn add(&mut self, reason: SkipReason, entry: impl ToString) {
+        self.skipped
+            .entry(reason)
+            .or_default()
+            .push(entry.to_string());
+    }
+
+    /// Log the number of skipped entries per reason, and each skipped entry at debug level
+    pub fn print_summary(&self) {
+        for (reason, paths) in &self.skipped {
+            tracing::info!("Skipped {} input entries: {reason}", paths.len());
+            for path in paths {
+                tracing::debug!("Skipped {path}: {reason}");
+            }
+        }
+    }
+}
+
+/// Collect all LAS/LAZ files to process from the given input paths and the optional input list.
+///
+/// Folders (and remote prefixes like `s3://bucket/prefix/`) are scanned for `.las` and `.laz`
+/// files, including sub folders if `--recursive` is given, while paths to files and HTTP(S) URLs
+/// are used as-is. ZIP and TAR archives, given directly or found in folders, are expanded into
+/// their LAS/LAZ members. The same file is only returned once, in the order it was first seen,
+/// also when it is given by different paths.
+/// Folder and archive entries that are not LAS/LAZ files are recorded in `skipped`.
+pub fn collect_inputs(args: &InputArgs, skipped: &mut SkipReport) -> Result<Vec<InputFile>> {
+    let mut entries = args.inputs.clone();
+    if let Some(list) = &args.input_list {
+        entries.extend(read_input_list(list)?);
+    }
+
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    let mut add = |file: InputFile| {
+        // the same file given by different paths (e.g. `./a.laz` and `a.laz`, or through a
+        // link) is only read once, under the first path it was given by
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        let identity = match &file {
+            InputFile::Local(path) => InputFile::Local(canonical(path)),
+            InputFile::Archive(member) => InputFile::Archive(ArchiveMember {
+                archive: canonical(&member.archive),
+                name: member.name.clone(),
+            }),
+            InputFile::Remote(_) | InputFile::Stdin => file.clone(),
+        };
+        if seen.insert(identity) {
+            files.push(file);
+        }
+    };
+    for entry in entries {
+        if stdio::is_stdio(&entry) {
+            anyhow::ensure!(
+                args.input_list
+                    .as_deref()
+                    .is_none_or(|list| !stdio::is_stdio(list)),
+                "stdin can either hold a LAS/LAZ stream or the input list, not both"
+            );
+            add(InputFile::Stdin);
+        } else if let Some(url) = entry.to_str().and_then(remote::parse_remote_url) {
+            let file = RemoteFile::new(url.clone())?;
+            if is_pointcloud_extension(file.extension()) || file.is_http() {
+                add(InputFile::Remote(file));
+            } else {
+                // anything else is treated as a prefix ("folder") to scan
+                for file in scan_remote_prefix(&url, args.recursive, skipped)? {
+                    add(file);
+                }
+            }
+        } else {
+            let paths = match entry.is_dir() {
+                true => scan_folder(&entry, args.recursive, skipped)?,
+                false => vec![entry],
+            };
+            for path in paths {
+                if archive::is_archive(&path) {
+                    for member in archive::members(&path, skipped)? {
+                        add(InputFile::Archive(member));
+                    }
+                } else {
+                    add(InputFile::Local(path));
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Read a list of input paths, one per line. Empty lines and lines starting with `#` are ignored.
+/// A path of `-` reads the list from stdin.
+fn read_input_list(list: &Path) -> Result<Vec<PathBuf>> {
+    let lines: Vec<String> = if list == Path::new("-") {
+        std::io::stdin()
+            .lock()
+            .lines()
+            .collect::<std::io::Result<_>>()
+            .context("read input list from stdin")?
+    } else {
+        let file = std::fs::File::open(list)
+            .with_context(|| format!("open input list: {}", list.display()))?;
+        std::io::BufReader::new(file)
+            .lines()
+            .collect::<std::io::Result<_>>()
+            .with_context(|| format!("read input list: {}", list.display()))?
+    };
+
+    Ok(lines
+        .iter()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Find all .las and .laz files, and archives of them, inside the given folder, and optionally its
+/// sub folders
+fn scan_folder(folder: &Path, recursive: bool, skipped: &mut SkipReport) -> Result<Vec<PathBuf>> {
+    scan_folder_once(folder, recursive, &mut HashSet::new(), skipped)
+}
+
+/// See [`scan_folder`]. Links to folders are followed, but each folder is only scanned once, so
+/// that a link to a parent folder does not recurse forever. `visited` holds the canonical paths
+/// of the folders scanned so far.
+fn scan_folder_once(
+    folder: &Path,
+    recursive: bool,
+    visited: &mut HashSet<PathBuf>,
+    skipped: &mut SkipReport,
+) -> Result<Vec<PathBuf>> {
+    let canonical = folder
+        .canonicalize()
+        .with_context(|| format!("read input folder: {}", folder.display()))?;
+    if !visited.insert(canonical) {
+        tracing::debug!("Skipping {}, which was scanned already", folder.display());
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for file in std::fs::read_dir(folder)
+        .with_context(|| format!("read input folder: {}", folder.display()))?
+    {
+        let file = file?;
+
+        let path = file.path();
+
+        if recursive && path.is_dir() {
+            files.extend(scan_folder_once(&path, recursive, visited, skipped)?);
+            continue;
+        }
+
+        if !file.file_type()?.is_file() {
+            skipped.add(SkipReason::NotAFile, path.display());
+            continue;
+        }
+
+        // only process .las and .laz files, and the archives they may be in
+        if !is_pointcloud_extension(path.extension().and_then(|e| e.to_str()))
+            && !archive::is_archive(&path)
+        {
+            skipped.add(SkipReason::WrongExtension, path.display());
+            continue;
+        }
+
+        files.push(path);
+    }
+
+    // read_dir order is platform dependent, sort to get a deterministic processing order
+    files.sort();
+    Ok(files)
+}
+
+/// Find all .las and .laz objects below the given remote prefix, and optionally its sub prefixes
+fn scan_remote_prefix(
+    url: &url::Url,
+    recursive: bool,
+    skipped: &mut SkipReport,
+) -> Result<Vec<InputFile>> {
+    let (objects, prefixes) = RemoteFile::list(url)?;
+
+    let mut files = Vec::new();
+    for prefix in prefixes {
+        if recursive {
+            files.extend(scan_remote_prefix(&prefix, recursive, skipped)?);
+        } else {
+            skipped.add(SkipReason::NotAFile, prefix);
+        }
+    }
+
+    for object in objects {
+        if !is_pointcloud_extension(object.extension()) {
+            skipped.add(SkipReason::WrongExtension, object.url());
+            continue;
+        }
+        files.push(InputFile::Remote(object));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn recursive_scan_stops_at_links_to_parent_folders() {
+        let dir = std::env::temp_dir().join(format!("lasretile-scan-{}", std::process::id()));
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(dir.join("a.laz"), b"").unwrap();
+        std::fs::write(sub.join("b.las"), b"").unwrap();
+        std::os::unix::fs::symlink(&dir, sub.join("parent")).unwrap();
+
+        let files = scan_folder(&dir, true, &mut SkipReport::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files.unwrap(), [dir.join("a.laz"), sub.join("b.las")]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Normalizing the intensity across flight lines (`--normalize-intensity`), so that the tiles do
+//! not show stripes where flight lines with different intensity levels overlap. A first pass
+//! collects the mean and standard deviation of the intensity of each point source ID, and while
+//! writing, each is mapped onto the mean and standard deviation of all points (gain and offset).
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::reader::InputReader;
+
+/// Sums of the intensities of a set of points
+#[derive(Debug, Default, Clone, Copy)]
+struct Moments {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Moments {
+    fn add(&mut self, intensity: u16) {
+        let intensity = f64::from(intensity);
+        self.count += 1;
+        self.sum += intensity;
+        self.sum_squares += intensity * intensity;
+    }
+
+    fn merge(&mut self, other: &Moments) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    fn std_dev(&self) -> f64 {
+        (self.sum_squares / self.count as f64 - self.mean().powi(2))
+            .max(0.0)
+            .sqrt()
+    }
+}
+
+/// The gain and offset of each point source ID
+#[derive(Debug)]
+pub struct IntensityNormalization {
+    gains: HashMap<u16, (f64, f64)>,
+}
+
+impl IntensityNormalization {
+    /// Collect the intensity statistics of all points of the `files` inputs read by `reader`
+    /// (the first pass)
+    pub fn collect(reader: InputReader, files: usize) -> Result<Self> {
+        let mut moments: HashMap<u16, Moments> = HashMap::new();
+        for _ in 0..files {
+            while let Some(points) = reader.next()? {
+                for p in &points {
+                    moments
+                        .entry(p.point_source_id)
+                        .or_default()
+                        .add(p.intensity);
+                }
+                reader.recycle(points);
+            }
+        }
+
+        let mut all = Moments::default();
+        for m in moments.values() {
+            all.merge(m);
+        }
+        if all.count == 0 {
+            return Ok(Self {
+                gains: HashMap::new(),
+            });
+        }
+        let (mean, std_dev) = (all.mean(), all.std_dev());
+        tracing::info!(
+            "Normalizing the intensity of {} point source IDs to a mean of {mean:.1} and a standard deviation of {std_dev:.1}",
+            moments.len()
+        );
+        let gains = moments
+            .into_iter()
+            .map(|(psid, m)| {
+                // flight lines with a constant intensity are only shifted
+                let gain = match m.std_dev() {
+                    0.0 => 1.0,
+                    s => std_dev / s,
+                };
+                let offset = mean - m.mean() * gain;
+                tracing::debug!(
+                    "Point source ID {psid}: {} points, mean {:.1}, standard deviation {:.1}, gain {gain:.3}, offset {offset:.1}",
+                    m.count,
+                    m.mean(),
+                    m.std_dev()
+                );
+                (psid, (gain, offset))
+            })
+            .collect();
+        Ok(Self { gains })
+    }
+
+    /// Normalize the intensity of the points, with their original point source IDs
+    pub fn apply(&self, points: &mut [las::Point]) {
+        for p in points {
+            if let Some((gain, offset)) = self.gains.get(&p.point_source_id) {
+                p.intensity = (f64::from(p.intensity) * gain + offset)
+                    .round()
+                    .clamp(0.0, f64::from(u16::MAX)) as u16;
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Stopping a run cleanly on Ctrl-C (SIGINT) or SIGTERM. The run stops like when its budget is
+//! used up: the tiles written so far are closed and finalized, and a checkpoint to resume from is
+//! written. A second signal exits right away. Only on unix, elsewhere Ctrl-C ends the process.
+
+use std::sync::{
+    Arc, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+
+/// Exit code after a second signal, as if killed by SIGINT
+#[cfg(unix)]
+const EXIT_CODE: i32 = 130;
+
+fn flag() -> &'static Arc<AtomicBool> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    FLAG.get_or_init(|| Arc::new(AtomicBool::new(false)))
+}
+
+/// Start catching the signals
+pub fn install() -> Result<()> {
+    #[cfg(unix)]
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        // registered first, so that it only sees the flag set by an earlier signal
+        signal_hook::flag::register_conditional_shutdown(signal, EXIT_CODE, flag().clone())?;
+        signal_hook::flag::register(signal, flag().clone())?;
+    }
+    Ok(())
+}
+
+/// Whether the run should stop
+pub fn requested() -> bool {
+    flag().load(Ordering::Relaxed)
+}
@@ -0,0 +1,161 @@
+//! Output directory layouts (`--output-layout`), to spread the tiles over a hierarchy of folders
+//! instead of millions of files in one folder
+
+use anyhow::Result;
+
+use crate::tile::TileKey;
+
+/// A part of a layout template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    /// `{name}`, the file name the tile would have without a layout
+    Name,
+    /// `{x}`, `{y}` or `{z}`, the index of the tile
+    Index(Axis),
+    /// `{x_div_N}` or `{y_div_N}`, the index of the tile divided by `N`, rounded down
+    Div(Axis, i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// The path of each tile relative to the output folder, from a template like
+/// `{x_div_10}/{y_div_10}/tile_{x}_{y}.laz`
+#[derive(Debug)]
+pub struct OutputLayout {
+    segments: Vec<Segment>,
+}
+
+impl OutputLayout {
+    pub fn new(template: &str) -> Result<Self> {
+        anyhow::ensure!(
+            [".laz", ".las", "{name}"]
+                .iter()
+                .any(|end| template.ends_with(end)),
+            "--output-layout must end with .laz, .las or {{name}}, got {template:?}"
+        );
+        anyhow::ensure!(
+            !template.starts_with('/')
+                && !template.contains('\\')
+                && template
+                    .split('/')
+                    .all(|part| !part.is_empty() && part != ".."),
+            "--output-layout must be a relative path within the output folder, got {template:?}"
+        );
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_owned()));
+            }
+            let Some(end) = rest[start..].find('}') else {
+                anyhow::bail!("unclosed {{ in --output-layout {template:?}");
+            };
+            let field = &rest[start + 1..start + end];
+            segments.push(match field {
+                "name" => Segment::Name,
+                "x" => Segment::Index(Axis::X),
+                "y" => Segment::Index(Axis::Y),
+                "z" => Segment::Index(Axis::Z),
+                _ => {
+                    let (axis, divisor) = field
+                        .strip_prefix("x_div_")
+                        .map(|n| (Axis::X, n))
+                        .or(field.strip_prefix("y_div_").map(|n| (Axis::Y, n)))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "unknown placeholder {{{field}}} in --output-layout, expected \
+                                 {{name}}, {{x}}, {{y}}, {{z}}, {{x_div_N}} or {{y_div_N}}"
+                            )
+                        })?;
+                    match divisor.parse::<i32>() {
+                        Ok(n) if n > 0 => Segment::Div(axis, n),
+                        _ => anyhow::bail!("invalid divisor in {{{field}}} of --output-layout"),
+                    }
+                }
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_owned()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// The path of a tile, given the file name it would have without a layout
+    pub fn path(&self, key: TileKey, name: &str) -> String {
+        let index = |axis| match axis {
+            Axis::X => key.x,
+            Axis::Y => key.y,
+            Axis::Z => key.z.unwrap_or_default(),
+        };
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Name => name.to_owned(),
+                Segment::Index(axis) => index(*axis).to_string(),
+                Segment::Div(axis, n) => index(*axis).div_euclid(*n).to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(x: i32, y: i32, z: Option<i32>) -> TileKey {
+        TileKey {
+            x,
+            y,
+            z,
+            part: None,
+        }
+    }
+
+    #[test]
+    fn tiles_are_placed_by_their_indices() {
+        let layout = OutputLayout::new("{x_div_10}/{y_div_10}/tile_{x}_{y}_{z}.laz").unwrap();
+        assert_eq!(
+            layout.path(key(23, 7, Some(1)), "tile_23_7_1.laz"),
+            "2/0/tile_23_7_1.laz"
+        );
+        let layout = OutputLayout::new("{x}/{name}").unwrap();
+        assert_eq!(
+            layout.path(key(5, 6, None), "tile_5_6.laz"),
+            "5/tile_5_6.laz"
+        );
+    }
+
+    #[test]
+    fn negative_indices_are_divided_rounding_down() {
+        let layout = OutputLayout::new("{x_div_10}/{y_div_10}/{name}").unwrap();
+        assert_eq!(layout.path(key(-1, -10, None), "t.laz"), "-1/-1/t.laz");
+        assert_eq!(layout.path(key(-11, 9, None), "t.laz"), "-2/0/t.laz");
+    }
+
+    #[test]
+    fn invalid_templates_are_refused() {
+        for template in [
+            "{x}/{y}",
+            "/abs/{name}",
+            "../{name}",
+            "a//{name}",
+            "a\\{name}",
+            "{x/{name}",
+            "{w}/{name}",
+            "{x_div_0}/{name}",
+            "{x_div_-2}/{name}",
+            "{z_div_2}/{name}",
+        ] {
+            assert!(OutputLayout::new(template).is_err(), "{template:?}");
+        }
+    }
+}
@@ -1,56 +1,223 @@
+mod affine;
+mod archive;
+mod bench;
+mod boundary;
+mod budget;
+mod checkpoint;
+mod cli;
+mod clip;
+mod colorize;
+mod compact;
+mod compression;
+mod config;
+mod count;
+mod crs;
+mod curve;
+mod dedupe;
+mod density;
+mod disk_space;
+mod extent;
+mod extra_bytes;
+mod filter;
+mod geotiff;
+mod grid_scheme;
+mod header_cache;
+mod hook;
+mod info;
+mod input;
+mod intensity;
+mod interrupt;
+mod layout;
+mod logging;
+mod manifest;
+mod memory;
+mod merge;
+mod output;
+mod overlap;
+mod overview;
+mod partition;
+mod passthrough;
+mod pause;
+mod pdal;
+mod point_format;
+mod pool;
+mod postprocess;
+mod progress;
+mod psid;
+mod quadtree;
+mod quantization;
+mod queued_io;
+mod raw;
+mod reader;
+mod remote;
+mod schema;
+mod script;
+mod shard;
+mod sidecar;
+mod sort;
+mod source_id;
+mod spatial_index;
+mod split;
+mod stats;
+mod stdio;
+mod summary;
+mod threads;
+mod throughput;
+mod tile;
+mod tile_index;
+mod tiling;
+mod units;
+mod update;
+mod validate;
+mod verify;
+mod watch;
+mod z_adjust;
+
 use std::{
-    collections::{HashMap, HashSet},
-    fs::File,
-    io::BufWriter,
-    path::Path,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, hash_map::Entry},
+    num::NonZeroUsize,
+    ops::Range,
+    sync::Arc,
+    time::Instant,
 };
 
 use anyhow::{Context, Result};
-
-// compute the number of elements we can buffer for 200MB of memory usage during LAZ/LAS reading
-const LAZ_BUFFER_SIZE: usize = 200 * 1024 * 1024 / (size_of::<las::Point>());
+use clap::{CommandFactory, Parser};
 
 fn main() -> Result<()> {
-    // Usage: [input folder] [output folder] [tile size]
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 4 {
-        eprintln!(
-            "Usage: {} [input folder] [output folder] [tile size]",
-            args[0]
+    let args: Vec<_> = std::env::args_os().collect();
+    let legacy = cli::legacy_args(&args);
+    let args = config::expand_args(legacy.clone().unwrap_or(args), &cli::Cli::command())?;
+    let cli = cli::Cli::parse_from(args);
+    logging::init(&cli.log)?;
+    if legacy.is_some() {
+        tracing::warn!(
+            "`lasretile <INPUT> <OUTPUT> <TILE_SIZE>` is deprecated, use `lasretile <INPUT> --output <OUTPUT> --tile-size <TILE_SIZE>`"
         );
-        std::process::exit(1);
     }
+    if let Some(threads) = cli.threads {
+        threads::configure(threads)?;
+    }
+    let result = match cli.command {
+        Some(cli::Command::Merge(args)) => merge::run(&args),
+        Some(cli::Command::Split(args)) => split::run(&args),
+        Some(cli::Command::Compact(args)) => compact::run(&args),
+        Some(cli::Command::Info(args)) => info::run(&args),
+        Some(cli::Command::Validate(args)) => validate::run(&args),
+        Some(cli::Command::Verify(args)) => verify::run(&args),
+        Some(cli::Command::Update(args)) => update::run(*args),
+        None => retile(cli.args),
+    };
+    archive::cleanup();
+    stdio::cleanup();
+    result
+}
+
+fn retile(args: cli::Args) -> Result<()> {
+    if let Some(name) = args.schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema::json_schema(name))?
+        );
+        return Ok(());
+    }
+    interrupt::install()?;
+    if args.watch {
+        return watch::run(&args);
+    }
+    retile_inputs(&args, None)
+}
 
-    let input_folder = Path::new(&args[1]);
-    let output_folder = Path::new(&args[2]);
-    let tile_size: f64 = args[3].parse().context("parse tile size")?;
+/// Retile the inputs. With `rebuild`, only the tiles of the inputs that changed since an earlier
+/// run are written again, from all of their inputs.
+fn retile_inputs(args: &cli::Args, rebuild: Option<&update::Rebuild>) -> Result<()> {
+    let started = Instant::now();
+    if args.bench {
+        bench::enable();
+    }
+    if let Some(chunk_size) = args.laz_chunk_size {
+        compression::configure(chunk_size);
+    }
+    if args.chunk_passthrough {
+        // copied chunks hold any number of points
+        compression::configure(compression::ChunkSize::Variable);
+    }
+    #[cfg(all(feature = "async-io", unix))]
+    if let Some(depth) = args.async_io {
+        queued_io::configure(depth.get());
+    }
+    let mut budget = budget::Budget::new(args.max_points, args.max_runtime);
 
-    let mut headers = Vec::new();
+    let mut skipped = input::SkipReport::default();
+    let input_files = input::collect_inputs(&args.input, &mut skipped)?;
+    // compressed archive members are extracted when they are first read, starting with the headers
+    disk_space::check(
+        &[(&std::env::temp_dir(), archive::to_extract())],
+        args.force,
+    )?;
 
     // Step1: iterate over all input files and load their LAS headers to know their size
-    for file in std::fs::read_dir(input_folder)? {
-        let file = file?;
-
-        if !file.file_type()?.is_file() {
-            continue;
+    let mut headers = input::scan_headers(
+        input_files,
+        args.input.header_cache.as_deref(),
+        &mut skipped,
+    );
+    // everything after reading the points uses the transformed coordinates, starting with the
+    // bounds in the headers
+    let affine = affine::Affine::new(args.affine.as_deref(), args.translate.as_deref())?;
+    if let Some(affine) = &affine {
+        for (path, header) in &mut headers {
+            *header = affine
+                .header(header)
+                .with_context(|| format!("transform the header of {path}"))?;
         }
-
-        let path = file.path();
-
-        // only process .las and .laz files
-        let Some(ext) = path.extension() else {
-            continue;
-        };
-        if ext != "las" && ext != "laz" {
-            continue;
+    }
+    let z_adjust = args
+        .z_adjust
+        .as_deref()
+        .map(z_adjust::ZAdjust::load)
+        .transpose()?;
+    if let Some(z_adjust) = &z_adjust {
+        let [x0, y0, x1, y1] = z_adjust.extent();
+        for (path, header) in &mut headers {
+            let bounds = header.bounds();
+            anyhow::ensure!(
+                bounds.min.x >= x0
+                    && bounds.min.y >= y0
+                    && bounds.max.x <= x1
+                    && bounds.max.y <= y1,
+                "the --z-adjust raster ({x0}, {y0}, {x1}, {y1}) does not cover {path}"
+            );
+            *header = z_adjust
+                .header(header)
+                .with_context(|| format!("adjust the header of {path}"))?;
         }
-
-        let reader = las::Reader::from_path(&path)
-            .with_context(|| format!("open LAS/LAZ file: {}", path.display()))?;
-
-        let header = reader.header();
-        headers.push((path.to_owned(), header.clone()));
     }
+    let corrections = reader::Corrections {
+        affine,
+        z_adjust: z_adjust.map(Arc::new),
+        colorize: args
+            .colorize
+            .as_deref()
+            .map(colorize::Colorize::load)
+            .transpose()?
+            .map(Arc::new),
+    };
+    // input files completely outside the clip area are not needed at all
+    let clip = clip::Clip::new(args.clip.as_deref(), args.bounds.as_deref())?;
+    if let Some(clip) = &clip {
+        headers.retain(|(path, header)| {
+            let bounds = header.bounds();
+            let rect = [bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y];
+            let outside = clip.coverage(rect) == clip::Coverage::Outside;
+            if outside {
+                skipped.add(input::SkipReason::OutsideClip, path);
+            }
+            !outside
+        });
+    }
+    skipped.print_summary();
+    let scanned = started.elapsed();
 
     let min = headers
         .iter()
@@ -66,39 +233,152 @@ fn main() -> Result<()> {
 
     let total_points: u64 = headers.iter().map(|(_, h)| h.number_of_points()).sum();
 
-    println!(
+    if args.input_order == cli::InputOrder::Hilbert {
+        curve::sort_hilbert(&mut headers, &las::Bounds { min, max });
+    }
+
+    let target_size = match args.target_points {
+        Some(target) => {
+            let size = info::size_for_points(&headers, target.get())
+                .context("cannot pick a tile size for --target-points, the inputs have no area")?;
+            tracing::info!(
+                "Picked tile size {size} for about {target} points per tile (--target-points)"
+            );
+            Some(size)
+        }
+        None => None,
+    };
+    let [grid_size, origin] = match args.grid_cells {
+        Some(grid) => {
+            let [size, origin] = grid.cells(&min, &max)?;
+            tracing::info!(
+                "Dividing the extent into {grid} tiles of {}",
+                tiling::grid_size_name(size)
+            );
+            [Some(size), Some(origin)]
+        }
+        None => [args.grid_size().or(target_size.map(|size| [size; 2])), None],
+    };
+    let tiling = match (&args.tiles_from, grid_size) {
+        (Some(path), _) => tiling::Tiling::Index(tiling::TileIndex::load(path)?),
+        (None, Some(size)) => tiling::Tiling::Grid {
+            size,
+            origin: origin.unwrap_or_default(),
+            size_z: args.tile_size_z,
+            names: match args.grid_scheme {
+                Some(scheme) => tiling::GridNames::Scheme(scheme),
+                None => tiling::GridNames::Tiles(args.tile_names),
+            },
+        },
+        // only partitioned by --split-by, required by clap
+        (None, None) => tiling::Tiling::Whole {
+            bounds: [min.x, min.y, max.x, max.y],
+        },
+    };
+    let mut partitioner = args
+        .split_by
+        .map(|split| partition::Partitioner::new(split, args.flightline_gap));
+    let transforms = quantization::common_transforms(
+        &headers,
+        &las::Bounds { min, max },
+        args.scale.as_deref(),
+        args.offset.as_deref(),
+    )?;
+    extra_bytes::check_compatible(&headers)?;
+    let mut point_format =
+        point_format::common_format(&headers, args.point_format, args.strip_waveforms)?;
+    if args.colorize.is_some() {
+        colorize::with_color(&mut point_format, args.point_format)?;
+    }
+    let las_version = args.las_version.map(cli::LasVersion::version);
+    if let Some(crs) = &args.a_srs {
+        crs.check(&point_format, las_version)?;
+        crs.assign(&mut headers)?;
+    }
+    crs::check_consistent(&headers, args.ignore_crs_mismatch)?;
+    if let Some(scheme) = args.grid_scheme {
+        scheme.check_crs(&headers)?;
+    }
+    if let Some(version) = las_version {
+        point_format::check_version(&headers, &point_format, version)?;
+    }
+
+    if args.tile_order == cli::TileOrder::GpsTime {
+        for (path, header) in &headers {
+            anyhow::ensure!(
+                header.point_format().has_gps_time,
+                "cannot sort tiles by GPS time: {path} has point format {} without GPS time",
+                header.point_format()
+            );
+        }
+    }
+    if args.split_by == Some(cli::SplitBy::Flightline) {
+        for (path, header) in &headers {
+            anyhow::ensure!(
+                header.point_format().has_gps_time,
+                "cannot detect flight lines: {path} has point format {} without GPS time",
+                header.point_format()
+            );
+        }
+    }
+
+    tracing::info!(
         "Found {} input files with a total {}M points.",
         headers.len(),
         total_points / 1_000_000
     );
 
-    println!("Overall bounds: min={:?}, max={:?}", min, max);
-    println!(
+    tracing::info!("Overall bounds: min={:?}, max={:?}", min, max);
+    let units = args.units();
+    let area = (max.x - min.x) * (max.y - min.y);
+    tracing::info!(
         "Overall size: x={}, y={}, z={}",
-        max.x - min.x,
-        max.y - min.y,
-        max.z - min.z
+        units.format_length(max.x - min.x),
+        units.format_length(max.y - min.y),
+        units.format_length(max.z - min.z)
+    );
+    tracing::info!(
+        "Overall area: {} ({})",
+        units.format_area(area),
+        units.format_density(total_points, area)
     );
 
-    // make sure the files do not overlap (TODO: this is N^2, optimize?)
-    let mut overlap_found = false;
-    for (i, (_, h1)) in headers.iter().enumerate() {
-        for (j, (_, h2)) in headers.iter().enumerate() {
-            if i == j {
-                continue;
-            }
-
-            if bounds_intersect(&h1.bounds(), &h2.bounds()) {
-                eprintln!(
-                    "Error: Input files {} and {} have overlapping bounds",
-                    headers[i].0.display(),
-                    headers[j].0.display()
-                );
-                overlap_found = true;
-            }
+    // make sure the files do not overlap
+    let bounds: Vec<_> = headers.iter().map(|(_, h)| h.bounds()).collect();
+    let overlaps = overlap::find_overlapping(&bounds);
+    let mut overlaps_per_file: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for &(i, j) in &overlaps {
+        overlaps_per_file.entry(i).or_default().push(j);
+        overlaps_per_file.entry(j).or_default().push(i);
+    }
+    // overlapping inputs are fine if we are going to thin the overlap anyway, or if the user
+    // knows the bounds are padded and do not reflect real overlap
+    let overlap_allowed =
+        args.allow_overlap || args.overlap_prefer.is_some() || args.dedupe.is_some();
+    for (i, mut others) in overlaps_per_file {
+        others.sort();
+        let others: Vec<_> = others.iter().map(|&j| headers[j].0.to_string()).collect();
+        if overlap_allowed {
+            tracing::warn!(
+                "Input file {} has overlapping bounds with {} other files: {}",
+                headers[i].0,
+                others.len(),
+                others.join(", ")
+            );
+        } else {
+            tracing::error!(
+                "Input file {} has overlapping bounds with {} other files: {}",
+                headers[i].0,
+                others.len(),
+                others.join(", ")
+            );
         }
     }
-    anyhow::ensure!(!overlap_found, "overlapping files found");
+    anyhow::ensure!(
+        overlaps.is_empty() || overlap_allowed,
+        "{} pairs of overlapping files found (use --allow-overlap to proceed anyway)",
+        overlaps.len()
+    );
 
     // Step2: Create a plan of how to retile and which tiles that need to be read in which order
 
@@ -107,155 +387,1552 @@ fn main() -> Result<()> {
     // Then we can read each input file, and write the points to the appropriate output files.
     // or we can open the files on demand when we need them.
 
-    let options = las::ReaderOptions::default().with_laz_parallelism(las::LazParallelism::Yes);
+    // decompressing in parallel needs a buffer per thread, not worth it on tiny machines
+    let options = las::ReaderOptions::default().with_laz_parallelism(if args.low_memory {
+        las::LazParallelism::No
+    } else {
+        las::LazParallelism::Yes
+    });
 
     // Create the mapping from input to output beforehand. Automatically close files that
     // have been written completely to avoid having too many files open at once.
     // Assume the input files have points "everywhere" in their bounds.
-    let mut output_files: HashMap<(i32, i32), OutTile> = std::collections::HashMap::new();
-    for (i, (_, header)) in headers.iter().enumerate() {
-        // since each tile is rectangular, we can compute the range of tiles that this file intersects and make sure they are instantiated
-        let bounds = header.bounds();
+    let collect_stats = args.stats.is_some() || args.tile_index.is_some();
+    let layout = args
+        .output_layout
+        .as_deref()
+        .map(layout::OutputLayout::new)
+        .transpose()?;
+    let tile_name = |key| {
+        let name = tiling.name(key);
+        match &layout {
+            Some(layout) => layout.path(key, &name),
+            None => name,
+        }
+    };
+    let mut output_files: HashMap<tile::TileKey, tile::OutTile> = std::collections::HashMap::new();
+    for (i, (path, header)) in headers.iter().enumerate() {
+        // compute the tiles that this file intersects and make sure they are instantiated
+        let keys = tiling
+            .tiles_in(&header.bounds())
+            .with_context(|| format!("find the tiles of {path}"))?;
+        for key in keys {
+            let tile = output_files.entry(key).or_insert_with(|| tile::OutTile {
+                tile_index: key,
+                name: tile_name(key),
+                bounds: tiling.bounds(key),
+                input_files: HashSet::new(),
+                sources: BTreeSet::new(),
+                stats: collect_stats.then(|| stats::TileStats::new(args.z_bin_size)),
+                clipped: false,
+                file_source_id: None,
+            });
+            tile.input_files.insert(i);
+        }
+    }
 
-        let min_x = (bounds.min.x / tile_size) as i32;
-        let max_x = (bounds.max.x / tile_size) as i32;
-        let min_y = (bounds.min.y / tile_size) as i32;
-        let max_y = (bounds.max.y / tile_size) as i32;
-
-        for tx in min_x..=max_x {
-            for ty in min_y..=max_y {
-                let tile = output_files.entry((tx, ty)).or_insert_with(|| OutTile {
-                    tile_index: (tx, ty),
-                    input_files: HashSet::new(),
-                    writer: None,
-                });
-                tile.input_files.insert(i);
+    if layout.is_some() {
+        let mut names = HashSet::new();
+        if let Some(tile) = output_files.values().find(|tile| !names.insert(&tile.name)) {
+            anyhow::bail!(
+                "--output-layout gives several tiles the path {}, use {{name}} or {{x}} and {{y}}",
+                tile.name
+            );
+        }
+    }
+
+    // all machines agree on the shards, as they are cut from the same plan before anything that
+    // depends on the state of the output folder
+    let shard = args
+        .shard
+        .map(|spec| shard::Shard::new(spec, output_files.keys().copied()));
+
+    // only create the tiles that overlap the clip area
+    if let Some(clip) = &clip {
+        let before = output_files.len();
+        output_files.retain(|_, tile| match clip.coverage(tile.bounds) {
+            clip::Coverage::Outside => false,
+            clip::Coverage::Inside => true,
+            clip::Coverage::Partial => {
+                tile.clipped = true;
+                true
             }
+        });
+        tracing::info!(
+            "Skipping {} tiles outside the clip area",
+            before - output_files.len()
+        );
+    }
+
+    // detect tiles with nonsensical bounds, e.g. beyond ±180° for geographic coordinates
+    let crs = extent::crs_kind_of_all(headers.iter().map(|(_, header)| header));
+    let mut invalid_tiles = Vec::new();
+    for (&key, tile) in &output_files {
+        if let Some(warning) = extent::check_tile(key, &tile.name, tile.bounds, crs) {
+            // printed as is rather than logged, other tools parse these lines
+            eprintln!(
+                "{}",
+                serde_json::to_string(&schema::Versioned::new(&warning))?
+            );
+            invalid_tiles.push(key);
         }
     }
+    invalid_tiles.sort();
+    match args.invalid_extent {
+        _ if invalid_tiles.is_empty() => {}
+        cli::ExtentPolicy::Warn => {}
+        cli::ExtentPolicy::Skip => {
+            for key in &invalid_tiles {
+                output_files.remove(key);
+            }
+            tracing::warn!(
+                "Skipping {} tiles outside the valid area of the CRS",
+                invalid_tiles.len()
+            );
+        }
+        cli::ExtentPolicy::Error => anyhow::bail!(
+            "{} tiles lie outside the valid area of the CRS",
+            invalid_tiles.len()
+        ),
+    }
 
-    println!("Output files to create: {}", output_files.len());
+    let output = output::Output::new(
+        args.output.as_deref().expect("required by clap"),
+        args.staging_dir.as_deref(),
+    )?;
+    anyhow::ensure!(
+        !(output.is_stdout()
+            && args.progress == cli::ProgressMode::Json
+            && args.progress_fd.is_none()),
+        "the tiles are written to stdout, use --progress-fd for the JSON progress events"
+    );
+    anyhow::ensure!(
+        args.on_tile_complete.is_none() || output.is_local(),
+        "--on-tile-complete needs a local output folder"
+    );
 
-    std::fs::create_dir_all(output_folder)
-        .with_context(|| format!("create output folder: {}", output_folder.display()))?;
+    // number the tiles in a stable order, the parts of tiles are numbered as they are created
+    let mut source_ids = args
+        .file_source_id
+        .as_deref()
+        .map(source_id::FileSourceIds::new);
+    if args.split_by.is_none()
+        && let Some(ids) = &mut source_ids
+    {
+        let mut keys: Vec<_> = output_files.keys().copied().collect();
+        keys.sort();
+        for key in keys {
+            let tile = output_files.get_mut(&key).expect("tile exists");
+            tile.file_source_id = Some(ids.assign(key, &tile.name)?);
+        }
+    }
 
-    let pb = indicatif::ProgressBar::new(total_points);
-    pb.set_style(indicatif::ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{msg}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({percent}%) ({eta})")
-        .unwrap()
-        .with_key("eta", |state: &indicatif::ProgressState, w: &mut dyn std::fmt::Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
-        .progress_chars("#>-"));
-    let mut processed_points = 0;
-    for (i_file, (path, header)) in headers.iter().enumerate() {
-        pb.set_message(format!("{}/{}", i_file + 1, headers.len()));
+    // only read the inputs that touch a tile of this shard, or when rebuilding a tile of the
+    // changed inputs
+    let mut reading = vec![true; headers.len()];
+    let mut total_points = total_points;
+    let planned = output_files.len();
+    if let Some(shard) = &shard {
+        output_files.retain(|&key, _| shard.contains(key));
+    }
+    if let Some(rebuild) = rebuild {
+        output_files.retain(|_, tile| {
+            tile.input_files
+                .iter()
+                .any(|&i| rebuild.changed.contains(&headers[i].0))
+                || rebuild
+                    .stale
+                    .iter()
+                    .any(|name| is_file_of(args, &tile.name, name))
+        });
+    }
+    if shard.is_some() || rebuild.is_some() {
+        reading.fill(false);
+        for tile in output_files.values() {
+            for &i in &tile.input_files {
+                reading[i] = true;
+            }
+        }
+        total_points = headers
+            .iter()
+            .zip(&reading)
+            .filter(|(_, reading)| **reading)
+            .map(|((_, h), _)| h.number_of_points())
+            .sum();
+        let read = reading.iter().filter(|r| **r).count();
+        match (args.shard, rebuild) {
+            (Some(spec), _) => tracing::info!(
+                "Shard {spec}: {} of {planned} tiles, reading {read} of {} input files",
+                output_files.len(),
+                headers.len()
+            ),
+            (None, Some(rebuild)) => tracing::info!(
+                "Rebuilding {} of {planned} tiles of {} changed inputs, reading {read} of {} input files",
+                output_files.len(),
+                rebuild.changed.len(),
+                headers.len()
+            ),
+            (None, None) => {}
+        }
+    }
+    // the tiles written before that are replaced by this run
+    let rebuilt: HashSet<String> = match rebuild {
+        Some(_) => output_files
+            .values()
+            .map(|tile| tile.name.clone())
+            .collect(),
+        None => HashSet::new(),
+    };
+    // taken before reading, so that inputs modified during the run are updated by the next one
+    let manifest_inputs = match args.manifest {
+        true => headers
+            .iter()
+            .filter(|(path, _)| *path != input::InputFile::Stdin)
+            .map(|(path, _)| {
+                Ok(manifest::ManifestInput {
+                    path: path.to_string(),
+                    version: path.version()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+        false => Vec::new(),
+    };
+
+    // with --clip or --bounds, only read the parts of the inputs near the clip area that their
+    // spatial index (a .lax file or the COPC hierarchy) points to
+    let mut ranges: Vec<Option<Vec<Range<u64>>>> = vec![None; headers.len()];
+    if let Some(clip) = &clip {
+        let mut indexed = 0;
+        for (i, (path, header)) in headers.iter().enumerate() {
+            if !reading[i] {
+                continue;
+            }
+            // the cells of the index hold points on their borders as well
+            let tolerance = input::bounds_tolerance(header);
+            let wanted = |[x0, y0, x1, y1]: [f64; 4]| {
+                let cell = [
+                    x0 - tolerance[0],
+                    y0 - tolerance[1],
+                    x1 + tolerance[0],
+                    y1 + tolerance[1],
+                ];
+                // the index is in the coordinates of the file, read cells that can not be
+                // transformed without Z anyway
+                let cell = match &corrections.affine {
+                    Some(affine) => match affine.rect(cell) {
+                        Some(cell) => cell,
+                        None => return true,
+                    },
+                    None => cell,
+                };
+                clip.coverage(cell) != clip::Coverage::Outside
+            };
+            match spatial_index::point_ranges(path, header, wanted) {
+                Ok(Some(file_ranges)) => {
+                    let points = header.number_of_points();
+                    let wanted: u64 = file_ranges
+                        .iter()
+                        .map(|r| r.end.min(points).saturating_sub(r.start))
+                        .sum();
+                    total_points -= points - wanted;
+                    ranges[i] = Some(file_ranges);
+                    indexed += 1;
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("not using the spatial index of {path}: {e:#}"),
+            }
+        }
+        if indexed > 0 {
+            tracing::info!(
+                "Reading only the points near the clip area of {indexed} inputs with a spatial index"
+            );
+        }
+    }
+
+    // the inputs to read, with the ranges of points to read of the ones with a spatial index
+    let read_list = |ranges: &[Option<Vec<Range<u64>>>], records: &[bool]| {
+        headers
+            .iter()
+            .zip(&reading)
+            .zip(ranges)
+            .zip(records)
+            .filter(|(((_, reading), _), _)| **reading)
+            .map(|((((path, _), _), ranges), records)| (path.clone(), ranges.clone(), *records))
+            .collect()
+    };
+
+    // tiles written by an earlier run into the same output folder
+    let mut existing_tiles = Vec::new();
+    // the tiles of changed inputs are rebuilt from all of their inputs
+    let if_exists = match rebuild {
+        Some(_) => cli::ExistingPolicy::Overwrite,
+        None => args.if_exists,
+    };
+    if if_exists != cli::ExistingPolicy::Overwrite {
+        let mut existing = output.existing_files()?;
+        // tiles of a run that stopped early may be missing points, they are written again
+        for name in checkpoint::Checkpoint::read_incomplete_tiles(&output)? {
+            existing.remove(&name);
+        }
+        for (&key, tile) in &output_files {
+            // the tile may have been subdivided into quadtree leaves
+            let leaves = args.max_points_per_tile.is_some();
+            let exists = match args.split_by {
+                Some(split) => existing
+                    .iter()
+                    .any(|name| partition::is_part_of(&tile.name, split, name, leaves)),
+                None if leaves => existing
+                    .iter()
+                    .any(|name| quadtree::is_leaf_of(&tile.name, name)),
+                None => existing.contains(&tile.name),
+            };
+            if exists {
+                existing_tiles.push(key);
+            }
+        }
+        existing_tiles.sort();
+    }
+    match if_exists {
+        _ if existing_tiles.is_empty() => {}
+        cli::ExistingPolicy::Overwrite => {}
+        cli::ExistingPolicy::Skip => {
+            for key in &existing_tiles {
+                output_files.remove(key);
+            }
+            tracing::info!(
+                "Skipping {} tiles that already exist in the output folder",
+                existing_tiles.len()
+            );
+        }
+        cli::ExistingPolicy::Error => anyhow::bail!(
+            "{} tiles already exist in the output folder, e.g. {} (see --if-exists)",
+            existing_tiles.len(),
+            output.location(&output_files[&existing_tiles[0]].name)
+        ),
+        cli::ExistingPolicy::Append => {
+            anyhow::ensure!(
+                args.split_by.is_none() && args.max_points_per_tile.is_none(),
+                "--if-exists append can not add points to the parts of --split-by or --max-points-per-tile tiles"
+            );
+            anyhow::ensure!(
+                args.max_points.is_none() && args.max_runtime.is_none(),
+                "--if-exists append can not be combined with --max-points or --max-runtime, resuming would add the points twice"
+            );
+            tracing::info!(
+                "Adding the points to {} tiles that already exist in the output folder",
+                existing_tiles.len()
+            );
+        }
+    }
+    // the tiles to add the points to, and the local files the ones being written are read from
+    let mut appending: HashSet<tile::TileKey> = match if_exists {
+        cli::ExistingPolicy::Append => existing_tiles.iter().copied().collect(),
+        _ => HashSet::new(),
+    };
+    let mut appended: HashMap<tile::TileKey, std::path::PathBuf> = HashMap::new();
+
+    // --low-memory only provides defaults, explicit limits take precedence
+    let max_open_files = args
+        .max_open_files
+        .or(args.low_memory.then_some(memory::LOW_MEMORY_MAX_OPEN_FILES));
+    let memory_budget = args
+        .memory
+        .or(args.low_memory.then_some(memory::LOW_MEMORY_BUDGET))
+        .or_else(|| memory::detect_budget(args.memory_percent));
+
+    // with --two-pass, count the points of each tile before writing anything, so that tiles only
+    // wait for the inputs that have points in them and small tiles are known up front
+    let mut small_tiles = Vec::new();
+    if args.two_pass {
+        let counting = Instant::now();
+        tracing::info!("Counting the points of each tile (first pass)");
+        let reader = reader::InputReader::spawn_ranges(
+            read_list(&ranges, &vec![false; headers.len()]),
+            options,
+            memory::MemoryPlan::new(memory_budget, 1, 1).read_buffer_points,
+            corrections.clone(),
+        );
+        let counts = count::count_points(
+            &headers,
+            &reading,
+            reader,
+            &tiling,
+            clip.as_ref(),
+            args.out_of_bounds == cli::OutOfBoundsPolicy::Clip,
+        )?;
+        let planned = output_files.len();
+        output_files.retain(|key, tile| match counts.get(key) {
+            None => false,
+            Some(count)
+                if args
+                    .min_tile_points
+                    .is_some_and(|min| count.points < min.get()) =>
+            {
+                small_tiles.push(*key);
+                false
+            }
+            Some(count) => {
+                tile.input_files = count.input_files.clone();
+                true
+            }
+        });
+        small_tiles.sort();
+        tracing::info!(
+            "Counted the points of {} tiles in {:.1?}, skipping {} empty tiles and {} tiles below --min-tile-points",
+            counts.len(),
+            counting.elapsed(),
+            planned - output_files.len() - small_tiles.len(),
+            small_tiles.len()
+        );
+    }
 
-        // open the file for reading
-        let mut reader = las::Reader::with_options(std::fs::File::open(path)?, options)
-            .expect("Could not create reader");
+    // with --normalize-intensity, collect the intensity statistics before writing anything. All
+    // inputs are read, so that all shards normalize alike.
+    let intensity = if args.normalize_intensity {
+        let collecting = Instant::now();
+        tracing::info!("Collecting the intensity statistics of each point source ID (first pass)");
+        let reader = reader::InputReader::spawn(
+            headers.iter().map(|(path, _)| path.clone()).collect(),
+            options,
+            memory::MemoryPlan::new(memory_budget, 1, 1).read_buffer_points,
+        );
+        let intensity = intensity::IntensityNormalization::collect(reader, headers.len())?;
+        tracing::info!(
+            "Collected the intensity statistics in {:.1?}",
+            collecting.elapsed()
+        );
+        Some(intensity)
+    } else {
+        None
+    };
 
-        // read LAZ_BUFFER_SIZE points at a time, this allows the reading to happen in parallel
-        let mut points = Vec::with_capacity(LAZ_BUFFER_SIZE);
-        loop {
-            points.clear();
-            let n = reader.read_points_into(LAZ_BUFFER_SIZE as u64, &mut points)?;
+    if let Some(path) = &args.export_pdal {
+        let options = pdal::WriterOptions {
+            transforms: &transforms,
+            point_format: &point_format,
+            las_version,
+            crs: args.a_srs.as_ref(),
+            affine: corrections.affine.as_ref(),
+        };
+        pdal::write(
+            path,
+            &headers,
+            &tiling,
+            &output_files,
+            clip.as_ref(),
+            &output,
+            &options,
+        )?;
+        let missing: Vec<&str> = [
+            (args.filters.wasm_filter.is_some(), "--wasm-filter"),
+            (args.filters.script.is_some(), "--script"),
+            (args.filters.ground_only, "--ground-only"),
+            (args.split_by.is_some(), "--split-by"),
+            (args.adaptive, "--adaptive"),
+            (args.overviews.is_some(), "--overviews"),
+            (args.z_adjust.is_some(), "--z-adjust"),
+            (args.colorize.is_some(), "--colorize"),
+            (args.overlap_prefer.is_some(), "--overlap-prefer"),
+            (args.dedupe.is_some(), "--dedupe"),
+            (args.renumber_psid, "--renumber-psid"),
+            (args.normalize_intensity, "--normalize-intensity"),
+            (args.tile_order != cli::TileOrder::Input, "--tile-order"),
+        ]
+        .into_iter()
+        .filter_map(|(used, name)| used.then_some(name))
+        .collect();
+        if !missing.is_empty() {
+            tracing::warn!("The PDAL pipeline does not include {}", missing.join(", "));
+        }
+    }
 
-            if n == 0 {
-                break;
+    // with --chunk-passthrough, the chunks of COPC inputs within a single tile are copied into
+    // it and not read
+    let mut copied_chunks: Vec<Vec<(tile::TileKey, passthrough::Chunk)>> =
+        vec![Vec::new(); headers.len()];
+    if args.chunk_passthrough {
+        anyhow::ensure!(
+            args.out_of_bounds == cli::OutOfBoundsPolicy::Warn
+                && if_exists != cli::ExistingPolicy::Append,
+            "--chunk-passthrough can not be used with --out-of-bounds clip or strict, or --if-exists append"
+        );
+        let mut copied_points = 0;
+        for (i, (path, header)) in headers.iter().enumerate() {
+            let input::InputFile::Local(local) = path else {
+                continue;
+            };
+            if !reading[i] || !passthrough::compatible(header, &transforms, &point_format)? {
+                continue;
+            }
+            let chunks = match spatial_index::copc_chunks(path, header) {
+                Ok(Some(chunks)) => chunks,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("not copying the chunks of {path}: {e:#}");
+                    continue;
+                }
+            };
+            let key_at = |x, y| {
+                let corner = las::Point {
+                    x,
+                    y,
+                    ..Default::default()
+                };
+                tiling.key_of(&corner, None)
+            };
+            for chunk in chunks {
+                let [x0, y0, x1, y1] = chunk.bounds;
+                let Some(key) = key_at(x0, y0).filter(|&key| key_at(x1, y1) == Some(key)) else {
+                    continue;
+                };
+                if !output_files
+                    .get(&key)
+                    .is_some_and(|tile| !tile.clipped && tile.name.ends_with(".laz"))
+                    || invalid_tiles.binary_search(&key).is_ok()
+                {
+                    continue;
+                }
+                copied_points += chunk.points.end - chunk.points.start;
+                copied_chunks[i].push((
+                    key,
+                    passthrough::Chunk {
+                        path: local.clone(),
+                        points: chunk.points,
+                        offset: chunk.offset,
+                        bytes: chunk.bytes,
+                    },
+                ));
             }
+            if !copied_chunks[i].is_empty() {
+                let all = 0..header.number_of_points();
+                let copied: Vec<Range<u64>> = copied_chunks[i]
+                    .iter()
+                    .map(|(_, chunk)| chunk.points.clone())
+                    .collect();
+                ranges[i] = Some(spatial_index::subtract(
+                    ranges[i].as_deref().unwrap_or(std::slice::from_ref(&all)),
+                    &copied,
+                ));
+            }
+        }
+        tracing::info!(
+            "Copying {} LAZ chunks with {copied_points} points into the tiles as they are",
+            copied_chunks.iter().map(Vec::len).sum::<usize>()
+        );
+    }
 
-            // To reduce the number of hashmap lookups: iterate the points until
-            // they no longer fit into the current tile, then do a single lookup and write all
-            // points at once.
+    tracing::info!("Output files to create: {}", output_files.len());
+    let writer_threads = args.writer_threads.unwrap_or_else(|| {
+        if args.low_memory {
+            NonZeroUsize::MIN
+        } else {
+            threads::default_threads()
+        }
+    });
+
+    let open_writers =
+        max_open_files.map_or(output_files.len(), |max| max.get().min(output_files.len()));
+    let points_of = |i: usize| headers[i].1.number_of_points();
+    if let Some(version) = las_version {
+        // refuse before any output is created rather than failing when the header is written
+        for tile in output_files.values() {
+            let points = tile.input_files.iter().copied().map(points_of).sum();
+            point_format::check_tile_points(&tile.name, points, version)?;
+        }
+    }
+    {
+        let points = (0..headers.len())
+            .filter(|&i| reading[i])
+            .map(points_of)
+            .sum();
+        let compressed = output_files
+            .values()
+            .any(|tile| tile.name.ends_with(".laz"));
+        let mut needed = disk_space::estimate(points, point_format.len(), compressed);
+        if open_writers < output_files.len() {
+            // tiles whose writers are closed are reopened as part files, which are merged into a
+            // copy next to them when the tile is finished, by each writer thread at the same
+            // time. A tile holds at most the points of the inputs overlapping it.
+            let mut tile_points: Vec<u64> = output_files
+                .values()
+                .map(|tile| tile.input_files.iter().copied().map(points_of).sum())
+                .collect();
+            tile_points.sort_unstable_by(|a, b| b.cmp(a));
+            let merged = tile_points.iter().take(writer_threads.get()).sum::<u64>();
+            needed += disk_space::estimate(merged.min(points), point_format.len(), compressed);
+        }
+        // tiles of remote outputs and stdout are staged in the output folder, and compressed
+        // archive members not read yet are still to be extracted
+        disk_space::check(
+            &[
+                (output.dir(), needed),
+                (&std::env::temp_dir(), archive::to_extract()),
+            ],
+            args.force,
+        )?;
+    }
+    let memory = memory::MemoryPlan::new(memory_budget, open_writers, output_files.len());
+    tracing::info!(
+        "Buffers: {} points read ahead, {} points per batch, {} batches queued, {} write buffer per open tile",
+        memory.read_buffer_points,
+        memory.batch_points,
+        memory.queued_batches,
+        indicatif::HumanBytes(memory.write_buffer_bytes as u64)
+    );
+    let mut writers = tile::TileWriters::new(
+        output.dir(),
+        max_open_files,
+        memory.write_buffer_bytes,
+        memory.batch_points,
+        memory.queued_batches,
+        writer_threads,
+    );
+
+    let mut filters = filter::PointFilters::load(&args.filters)?;
+    let pause = pause::PauseControl::new(args.pause_file.clone())?;
+
+    let mut progress = progress::Progress::new(args.progress, args.progress_fd, total_points)?;
+    let mut processed_points = 0;
+    let mut complete_tiles = Vec::new();
+    let mut records = TileRecords {
+        overviews: args.overviews.map(|levels| {
+            overview::Overviews::new(levels, output.dir(), output_files.keys().copied())
+        }),
+        sidecars: args.sidecar.then(|| {
+            sidecar::Sidecars::new(
+                headers.iter().map(|(p, _)| p.clone()).collect(),
+                args.units(),
+            )
+        }),
+        inputs: headers.iter().map(|(path, _)| path.to_string()).collect(),
+        hooks: args
+            .on_tile_complete
+            .as_deref()
+            .map(|command| hook::TileHooks::new(command, args.on_tile_complete_jobs)),
+        ..Default::default()
+    };
+    let mut dropped = summary::DroppedPoints::default();
+    let mut input_points = vec![0; headers.len()];
+    let mut out_of_bounds = vec![0; headers.len()];
+    // tiles that have been written, they can not receive any more points
+    let mut finished_tiles = HashSet::new();
+    let mut renumbering = args.renumber_psid.then(psid::Renumbering::default);
+    let mut density = args
+        .density_raster
+        .as_ref()
+        .map(|_| density::DensityGrid::new(&min, &max, args.density_cell_size))
+        .transpose()?;
+    let mut coverage = args
+        .boundary
+        .as_ref()
+        .map(|_| density::DensityGrid::new(&min, &max, args.boundary_cell_size))
+        .transpose()?;
+    let mut file_timings = bench::FileTimings::default();
+    let mut stopped = None;
+    // inputs in the point format and with the scales and offsets of the tiles are routed as point
+    // records when nothing but the coordinates of their points is looked at
+    let records_only = args.filters.wasm_filter.is_none()
+        && args.filters.script.is_none()
+        && !args.filters.ground_only
+        && corrections.affine.is_none()
+        && corrections.z_adjust.is_none()
+        && corrections.colorize.is_none()
+        && intensity.is_none()
+        && renumbering.is_none()
+        && partitioner.is_none()
+        && !collect_stats
+        && density.is_none()
+        && coverage.is_none()
+        && args.max_points.is_none()
+        && args.out_of_bounds == cli::OutOfBoundsPolicy::Warn;
+    let read_records: Vec<bool> = headers
+        .iter()
+        .map(|(path, header)| {
+            records_only
+                && matches!(path, input::InputFile::Local(_))
+                && !header.point_format().is_compressed
+                && !point_format::needs_conversion(header.point_format(), &point_format)
+                && header.transforms() == &transforms
+        })
+        .collect();
+    // decode the input files on a separate thread while the points are routed to their tiles
+    // here and compressed by the tile writer threads
+    let reader = reader::InputReader::spawn_ranges(
+        read_list(&ranges, &read_records),
+        options,
+        memory.read_buffer_points,
+        corrections,
+    );
+    'files: for (i_file, (path, header)) in headers.iter().enumerate() {
+        if !reading[i_file] {
+            // no tile of this shard needs this file
+            continue;
+        }
+        let _span = tracing::info_span!("input", file = %path).entered();
+        progress.file_started(path, i_file, headers.len());
+        let file_started = Instant::now();
+
+        // all tiles use the same quantization and point format, whichever input writes to
+        // them first
+        let mut builder = las::Builder::from(header.clone());
+        builder.transforms = transforms;
+        point_format::apply(&mut builder, &point_format, las_version);
+        let template = Arc::new(builder.into_header()?);
+        // unless the version is given, tiles that may get more points than the version of the
+        // template can count are written as LAS 1.4
+        let extended_template = match las_version {
+            None => Some(Arc::new(point_format::extended_counts(&template)?)),
+            Some(_) => None,
+        };
+        let convert = point_format::needs_conversion(header.point_format(), &point_format);
+        let bounds = header.bounds();
+        let tolerance = input::bounds_tolerance(header);
+        let outside_xyz = |[x, y, z]: [f64; 3]| {
+            x < bounds.min.x - tolerance[0]
+                || x > bounds.max.x + tolerance[0]
+                || y < bounds.min.y - tolerance[1]
+                || y > bounds.max.y + tolerance[1]
+                || z < bounds.min.z - tolerance[2]
+                || z > bounds.max.z + tolerance[2]
+        };
+        let outside_bounds = |p: &las::Point| outside_xyz([p.x, p.y, p.z]);
+        // points moved by the WASM filter must stay where the tiles of this file were planned,
+        // also along Z with layers
+        let layered = args.tile_size_z.is_some();
+        filters.confine(las::Bounds {
+            min: las::Vector {
+                x: bounds.min.x - tolerance[0],
+                y: bounds.min.y - tolerance[1],
+                z: if layered {
+                    bounds.min.z - tolerance[2]
+                } else {
+                    f64::NEG_INFINITY
+                },
+            },
+            max: las::Vector {
+                x: bounds.max.x + tolerance[0],
+                y: bounds.max.y + tolerance[1],
+                z: if layered {
+                    bounds.max.z + tolerance[2]
+                } else {
+                    f64::INFINITY
+                },
+            },
+        });
+
+        let mut file_points = 0;
+        for (key, chunk) in copied_chunks[i_file].drain(..) {
+            let tile = output_files.get_mut(&key).expect("tile exists");
+            let header = match tile.sources.is_empty() {
+                true => first_header(&template, extended_template.as_ref(), tile, &headers, 0),
+                false => &template,
+            };
+            file_points += chunk.len();
+            input_points[i_file] += chunk.len();
+            processed_points += chunk.len();
+            writers.copy(key, tile, header, chunk)?;
+            tile.sources.insert(i_file);
+        }
+        progress.set_position(processed_points);
+        // the points of records that can not be written as they are
+        let mut decoded = Vec::new();
+        let mut tile_runs = tiling::TileRuns::default();
+        while let Some(batch) = reader.next_batch()? {
+            let routing = bench::start();
+            let (mut points, from_records, read) = match batch {
+                reader::Batch::Points(points) => {
+                    let read = points.len();
+                    (points, false, read)
+                }
+                reader::Batch::Records(records) => {
+                    let length = usize::from(header.point_format().len());
+                    let mut points = std::mem::take(&mut decoded);
+                    points.clear();
+                    let mut routed = 0;
+                    tile_runs.clear();
+                    tile_runs.extend(
+                        records
+                            .chunks_exact(length)
+                            .map(|record| raw::coordinates(record, header.transforms())),
+                    );
+                    tiling.split_runs(&mut tile_runs);
+                    let mut i = 0;
+                    for &(tile_index, count) in tile_runs.runs() {
+                        let run = &records[i * length..(i + count) * length];
+                        i += count;
+
+                        // records for tiles that need more than their coordinates, or do not
+                        // exist yet, take the way of all points below
+                        let writable = tile_index
+                            .filter(|key| !appending.contains(key))
+                            .and_then(|key| Some(key).zip(output_files.get_mut(&key)))
+                            .filter(|(_, tile)| !tile.clipped);
+                        let Some((key, tile)) = writable else {
+                            for record in run.chunks_exact(length) {
+                                let raw =
+                                    las::raw::Point::read_from(record, header.point_format())?;
+                                points.push(las::Point::new(raw, header.transforms()));
+                            }
+                            continue;
+                        };
+                        let tile_header = match tile.sources.is_empty() {
+                            true => first_header(
+                                &template,
+                                extended_template.as_ref(),
+                                tile,
+                                &headers,
+                                0,
+                            ),
+                            false => &template,
+                        };
+                        writers.write_records(key, tile, tile_header, run)?;
+                        tile.sources.insert(i_file);
+                        out_of_bounds[i_file] += run
+                            .chunks_exact(length)
+                            .filter(|record| {
+                                outside_xyz(raw::coordinates(record, header.transforms()))
+                            })
+                            .count() as u64;
+                        routed += count as u64;
+                    }
+                    file_points += routed;
+                    input_points[i_file] += routed;
+                    processed_points += routed;
+                    progress.set_position(processed_points);
+                    let read = records.len() / length;
+                    reader.recycle_records(records);
+                    (points, true, read)
+                }
+            };
+            // stop if we have used up our budget (but only if there are still points left in
+            // this file, otherwise it is complete)
+            if let Some(reason) = budget.exhausted(processed_points) {
+                stopped = Some((reason, i_file, file_points));
+                break 'files;
+            }
+            if pause.is_paused() {
+                // let the writers catch up so that no I/O happens while paused
+                writers.drain()?;
+                budget.extend(progress.paused(|| pause.wait()));
+            }
+            let n = budget.limit_points(processed_points, points.len() as u64);
+            points.truncate(n as usize);
+            file_points += n;
+            input_points[i_file] += n;
+            let before = points.len();
+            filters.apply(&mut points, path)?;
+            dropped.filtered += (before - points.len()) as u64;
+            if convert {
+                for p in &mut points {
+                    point_format::convert(p, &point_format);
+                }
+            }
+            if let Some(intensity) = &intensity {
+                intensity.apply(&mut points);
+            }
+            if let Some(renumbering) = &mut renumbering {
+                renumbering.apply(i_file, path, &mut points)?;
+            }
+            match args.out_of_bounds {
+                cli::OutOfBoundsPolicy::Warn => {
+                    out_of_bounds[i_file] +=
+                        points.iter().filter(|p| outside_bounds(p)).count() as u64;
+                }
+                cli::OutOfBoundsPolicy::Clip => {
+                    let before = points.len();
+                    points.retain(|p| !outside_bounds(p));
+                    out_of_bounds[i_file] += (before - points.len()) as u64;
+                    dropped.out_of_bounds += (before - points.len()) as u64;
+                }
+                cli::OutOfBoundsPolicy::Strict => {
+                    if let Some(p) = points.iter().find(|p| outside_bounds(p)) {
+                        anyhow::bail!(
+                            "point ({}, {}, {}) lies outside the bounds in the header of {path} (see --out-of-bounds)",
+                            p.x,
+                            p.y,
+                            p.z
+                        );
+                    }
+                }
+            }
+            // To reduce the number of hashmap lookups: split the points into runs of points of
+            // the same tile, then do a single lookup per run and write all its points at once.
+            tile_runs.clear();
+            tile_runs.extend(points.iter().map(|p| [p.x, p.y, p.z]));
+            tiling.split_runs(&mut tile_runs);
+            if let Some(partitioner) = &mut partitioner {
+                tile_runs.split_parts(|i| partitioner.part_of(&points[i]));
+            }
             let mut i = 0;
-            while i < n as usize {
-                let mut tile_index = None;
-                let mut count = 0;
-                for p in &points[i..] {
-                    let nx: i32 = (p.x / tile_size) as i32;
-                    let ny: i32 = (p.y / tile_size) as i32;
-
-                    if let Some((tx, ty)) = tile_index {
-                        if (nx, ny) != (tx, ty) {
-                            // this point is in a different tile, stop here
-                            break;
-                        }
+            for &(tile_index, count) in tile_runs.runs() {
+                let Some(key) = tile_index else {
+                    // not inside any tile of the tile index
+                    dropped.outside_tiles += count as u64;
+                    i += count;
+                    processed_points += count as u64;
+                    continue;
+                };
+
+                if args.invalid_extent == cli::ExtentPolicy::Skip
+                    && invalid_tiles.binary_search(&key.tile()).is_ok()
+                {
+                    dropped.invalid_extent += count as u64;
+                    i += count;
+                    processed_points += count as u64;
+                    continue;
+                }
+                if shard.as_ref().is_some_and(|shard| !shard.contains(key)) {
+                    dropped.other_shards += count as u64;
+                    i += count;
+                    processed_points += count as u64;
+                    continue;
+                }
+                if existing_tiles.binary_search(&key.tile()).is_ok() {
+                    dropped.existing_tiles += count as u64;
+                    i += count;
+                    processed_points += count as u64;
+                    continue;
+                }
+                if small_tiles.binary_search(&key.tile()).is_ok() {
+                    dropped.small_tiles += count as u64;
+                    i += count;
+                    processed_points += count as u64;
+                    continue;
+                }
+
+                if let Entry::Vacant(entry) = output_files.entry(key.tile()) {
+                    // tiles outside the clip area are never created, and tiles that only
+                    // receive points outside the header bounds of their inputs are created as
+                    // needed
+                    let bounds = tiling.bounds(key.tile());
+                    let coverage = clip
+                        .as_ref()
+                        .map_or(clip::Coverage::Inside, |clip| clip.coverage(bounds));
+                    if coverage == clip::Coverage::Outside {
+                        dropped.clipped += count as u64;
                     } else {
-                        tile_index = Some((nx, ny));
+                        anyhow::ensure!(
+                            args.out_of_bounds == cli::OutOfBoundsPolicy::Warn,
+                            "tile should exist, was a point moved outside its input file?"
+                        );
                     }
-                    count += 1;
+                    if coverage == clip::Coverage::Outside || finished_tiles.contains(&key.tile()) {
+                        if coverage != clip::Coverage::Outside {
+                            dropped.out_of_bounds += count as u64;
+                        }
+                        i += count;
+                        processed_points += count as u64;
+                        continue;
+                    }
+                    // keep the tile open until the end, later inputs may add to it as well
+                    let mut tile = tile::OutTile {
+                        tile_index: key.tile(),
+                        name: tile_name(key.tile()),
+                        bounds,
+                        input_files: (i_file..headers.len()).collect(),
+                        sources: BTreeSet::new(),
+                        stats: collect_stats.then(|| stats::TileStats::new(args.z_bin_size)),
+                        clipped: coverage == clip::Coverage::Partial,
+                        file_source_id: None,
+                    };
+                    if args.split_by.is_none()
+                        && let Some(ids) = &mut source_ids
+                    {
+                        tile.file_source_id = Some(ids.assign(tile.tile_index, &tile.name)?);
+                    }
+                    entry.insert(tile);
+                }
+                // the parts of a tile are created as their points show up, and closed along with
+                // the tile
+                if let (Some(part), Some(split)) = (key.part, args.split_by)
+                    && !output_files.contains_key(&key)
+                {
+                    let tile = &output_files[&key.tile()];
+                    let name = partition::file_name(&tile.name, split, part);
+                    let part_tile = tile::OutTile {
+                        tile_index: key,
+                        file_source_id: source_ids
+                            .as_mut()
+                            .map(|ids| ids.assign(key, &name))
+                            .transpose()?,
+                        name,
+                        bounds: tile.bounds,
+                        input_files: tile.input_files.clone(),
+                        sources: BTreeSet::new(),
+                        stats: collect_stats.then(|| stats::TileStats::new(args.z_bin_size)),
+                        clipped: tile.clipped,
+                    };
+                    output_files.insert(key, part_tile);
                 }
+                let tile = output_files.get_mut(&key).expect("tile exists");
 
-                let (nx, ny) = tile_index.context("at least one point to process")?;
+                let mut run = &points[i..(i + count)];
+                let inside: Vec<las::Point>;
+                if tile.clipped
+                    && let Some(clip) = &clip
+                {
+                    inside = run
+                        .iter()
+                        .filter(|p| clip.contains(p.x, p.y))
+                        .cloned()
+                        .collect();
+                    dropped.clipped += (run.len() - inside.len()) as u64;
+                    run = &inside;
+                }
 
-                let writer = output_files
-                    .get_mut(&(nx, ny))
-                    .context("tile should exist")?
-                    .get_writer(output_folder, header)
-                    .context("Could not get writer")?;
+                if let Some(stats) = &mut tile.stats {
+                    for p in run {
+                        stats.add(p);
+                    }
+                }
+                for grid in [&mut density, &mut coverage].into_iter().flatten() {
+                    for p in run {
+                        grid.add(p.x, p.y);
+                    }
+                }
 
-                for p in &points[i..(i + count)] {
-                    writer
-                        .write_point(p.clone())
-                        .context("Could not write point")?;
+                if !run.is_empty() {
+                    // with --if-exists append, the points of the existing tile are written first
+                    let mut existing = None;
+                    if tile.sources.is_empty() && appending.remove(&key) {
+                        let path = output.fetch(&tile.name, &format!("{}.appending", tile.name))?;
+                        existing = Some(postprocess::open_reader(&path)?);
+                        appended.insert(key, path);
+                    }
+                    let mut header = &template;
+                    if tile.sources.is_empty() {
+                        let existing = existing
+                            .as_ref()
+                            .map_or(0, |reader| reader.header().number_of_points());
+                        header = first_header(
+                            &template,
+                            extended_template.as_ref(),
+                            tile,
+                            &headers,
+                            existing,
+                        );
+                    }
+                    if let Some(mut reader) = existing {
+                        let mut points = Vec::new();
+                        loop {
+                            points.clear();
+                            if reader.read_points_into(memory.batch_points as u64, &mut points)?
+                                == 0
+                            {
+                                break;
+                            }
+                            if let Some(stats) = &mut tile.stats {
+                                for p in &points {
+                                    stats.add(p);
+                                }
+                            }
+                            for grid in [&mut density, &mut coverage].into_iter().flatten() {
+                                for p in &points {
+                                    grid.add(p.x, p.y);
+                                }
+                            }
+                            writers.write(key, tile, header, &points)?;
+                        }
+                    }
+                    writers.write(key, tile, header, run)?;
+                    tile.sources.insert(i_file);
                 }
                 i += count;
                 processed_points += count as u64;
-                pb.set_position(processed_points);
+                progress.set_position(processed_points);
             }
+            bench::record(bench::Stage::Route, routing, read);
+            match from_records {
+                true => decoded = points,
+                false => reader.recycle(points),
+            }
+        }
+
+        tracing::debug!("Read {file_points} points");
+        if out_of_bounds[i_file] > 0 {
+            tracing::warn!(
+                "{} points lie outside the bounds in the header",
+                out_of_bounds[i_file]
+            );
         }
 
         // finished reading this input file, we should remove it from any output files and close
         // any output files that are now complete
-
-        output_files.retain(|_, tile| {
+        let mut finished = Vec::new();
+        for (key, tile) in output_files.iter_mut() {
             // remove the file we just processed from the list
             tile.input_files.remove(&i_file);
 
-            // drop this entry if it has no more input files
-            !tile.input_files.is_empty()
-        });
+            // close this entry if it has no more input files
+            if tile.input_files.is_empty() {
+                finished.push(*key);
+            }
+        }
+        let written = writers.close(&finished)?;
+        for (key, written) in finished.into_iter().zip(written) {
+            let mut tile = output_files.remove(&key).expect("tile exists");
+            finished_tiles.insert(key);
+            if written {
+                let names = finalize_tile(&output, &mut tile, args, &memory, &mut records)?;
+                for name in &names {
+                    progress.tile_closed(name);
+                }
+                complete_tiles.extend(names);
+            }
+            if let Some(path) = appended.remove(&key) {
+                output.release(&tile.name, &path)?;
+            }
+            if let Some(overviews) = &mut records.overviews {
+                for name in overviews.tile_finished(&output, key)? {
+                    progress.tile_closed(&name);
+                }
+            }
+        }
+        if args.bench {
+            file_timings.add(path, file_started.elapsed(), file_points);
+        }
+    }
+
+    if let Some((reason, i_file, file_points)) = stopped {
+        progress.stopped(&reason.to_string());
+        if reason == budget::StopReason::Interrupted {
+            tracing::warn!(
+                "Interrupted, closing the tiles written so far (press Ctrl-C again to abort)"
+            );
+        }
+
+        // tiles being added to are left as they were rather than replaced by their points and
+        // only part of the new ones
+        let (discarded, keys): (Vec<_>, Vec<_>) = output_files
+            .keys()
+            .copied()
+            .partition(|key| appended.contains_key(key));
+        writers.discard(&discarded)?;
+        for key in discarded {
+            let tile = output_files.remove(&key).expect("tile exists");
+            output.release(&tile.name, &appended[&key])?;
+        }
+
+        // finalize all tiles that have been written so far, even though they are incomplete
+        let mut incomplete_tiles = Vec::new();
+        let written = writers.close(&keys)?;
+        writers.join()?;
+        for (key, written) in keys.into_iter().zip(written) {
+            let tile = output_files.get_mut(&key).expect("tile exists");
+            if written {
+                incomplete_tiles.extend(finalize_tile(&output, tile, args, &memory, &mut records)?);
+            }
+        }
+        if let Some(overviews) = &mut records.overviews {
+            incomplete_tiles.extend(overviews.finish(&output)?);
+        }
+        complete_tiles.sort();
+        incomplete_tiles.sort();
+        let (complete, incomplete) = (complete_tiles.len(), incomplete_tiles.len());
+
+        let checkpoint = checkpoint::Checkpoint {
+            stop_reason: reason.to_string(),
+            tile_size: args
+                .tile_size
+                .or(target_size)
+                .or(args.grid_scheme.map(|scheme| scheme.size()[0])),
+            tile_size_x: args.tile_size_x,
+            tile_size_y: args.tile_size_y,
+            tile_size_z: args.tile_size_z,
+            tiles_from: args.tiles_from.clone(),
+            points_processed: processed_points,
+            completed_inputs: headers[..i_file].iter().map(|(p, _)| p.clone()).collect(),
+            partial_input: (file_points > 0).then(|| checkpoint::PartialInput {
+                path: headers[i_file].0.clone(),
+                points_processed: file_points,
+            }),
+            remaining_inputs: headers[i_file + usize::from(file_points > 0)..]
+                .iter()
+                .map(|(p, _)| p.clone())
+                .collect(),
+            complete_tiles,
+            incomplete_tiles,
+        };
+        checkpoint.write(&output)?;
+        if let Some(renumbering) = &renumbering {
+            renumbering.write(&output)?;
+        }
+        if args.summary {
+            dropped.overlap = records.overlap_points;
+            dropped.duplicates = records.duplicate_points;
+            summary::Summary::new(
+                Some(reason.to_string()),
+                &headers,
+                &input_points,
+                &out_of_bounds,
+                dropped,
+                records.summary,
+                summary::Timings::new(scanned, started.elapsed()),
+            )
+            .write(&output)?;
+        }
+        write_reports(
+            args,
+            records.stats,
+            &records.footprints,
+            density.as_ref(),
+            coverage.as_ref(),
+            &headers[0].1,
+        )?;
+        if let Some(hooks) = records.hooks {
+            hooks.finish()?;
+        }
+        output.finish()?;
+        if args.bench {
+            bench::print_report(&file_timings, started.elapsed());
+        }
+
+        let location = output.location(checkpoint::CHECKPOINT_FILE_NAME);
+        // an interrupted run fails, so that scripts do not carry on with partial tiles
+        anyhow::ensure!(
+            reason != budget::StopReason::Interrupted,
+            "interrupted after {processed_points} of {total_points} points with {complete} complete and {incomplete} incomplete tiles, checkpoint written to {location}"
+        );
+        tracing::warn!(
+            "Stopped early ({reason}) after {processed_points} of {total_points} points with {complete} complete and {incomplete} incomplete tiles, checkpoint written to {location}"
+        );
+        return Ok(());
+    }
+    progress.finished();
+    if clip.is_some() {
+        tracing::info!("Dropped {} points outside the clip area", dropped.clipped);
+    }
+    if dropped.outside_tiles > 0 {
+        tracing::warn!(
+            "Dropped {} points outside the tiles of the tile index",
+            dropped.outside_tiles
+        );
+    }
+    if args.dedupe.is_some() {
+        tracing::info!("Removed {} duplicate points", records.duplicate_points);
+    }
+    if dropped.out_of_bounds > 0 {
+        tracing::warn!(
+            "Dropped {} points outside the header bounds of their input file",
+            dropped.out_of_bounds
+        );
+    }
+
+    let reopened = writers.join()?;
+    if reopened > 0 {
+        tracing::warn!("Reopened tile writers {reopened} times to stay within --max-open-files");
     }
-    pb.finish_with_message("Done");
 
     // make sure all output files are closed
     anyhow::ensure!(output_files.is_empty(), "all output files should be closed");
 
+    // a complete run supersedes any checkpoint from an earlier run
+    checkpoint::Checkpoint::remove_stale(&output)?;
+    write_reports(
+        args,
+        records.stats,
+        &records.footprints,
+        density.as_ref(),
+        coverage.as_ref(),
+        &headers[0].1,
+    )?;
+    if let Some(renumbering) = &renumbering {
+        renumbering.write(&output)?;
+    }
+    let mut tiles = records.manifest;
+    if let Some(previous) = rebuild.and_then(|rebuild| rebuild.previous.as_ref()) {
+        // keep the tiles that were not written again, and remove the ones that are gone
+        let written: HashSet<&str> = tiles.iter().map(|tile| tile.name.as_str()).collect();
+        let mut kept = Vec::new();
+        let mut removed = 0;
+        for entry in &previous.tiles {
+            let replaced = rebuild.is_some_and(|rebuild| rebuild.stale.contains(&entry.name))
+                || rebuilt.contains(&entry.name)
+                || ((args.split_by.is_some() || args.max_points_per_tile.is_some())
+                    && rebuilt
+                        .iter()
+                        .any(|tile| is_file_of(args, tile, &entry.name)));
+            if !replaced {
+                kept.push(entry.clone());
+            } else if !written.contains(entry.name.as_str()) {
+                output.remove(&entry.name)?;
+                tracing::debug!("Removed {}", output.location(&entry.name));
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            tracing::info!("Removed {removed} tiles without points of the current inputs");
+        }
+        tiles.extend(kept);
+    }
+    let manifest = manifest::Manifest::new(manifest_inputs, tiles);
+    if args.manifest {
+        manifest.write(&output)?;
+    }
+    if args.summary {
+        dropped.overlap = records.overlap_points;
+        dropped.duplicates = records.duplicate_points;
+        summary::Summary::new(
+            None,
+            &headers,
+            &input_points,
+            &out_of_bounds,
+            dropped,
+            records.summary,
+            summary::Timings::new(scanned, started.elapsed()),
+        )
+        .write(&output)?;
+    }
+    if let Some(hooks) = records.hooks {
+        hooks.finish()?;
+    }
+    output.finish()?;
+    if args.bench {
+        bench::print_report(&file_timings, started.elapsed());
+    }
+
+    if let Some(path) = &args.compare_manifest {
+        let reference = manifest::Manifest::read(path)?;
+        let differences = manifest.compare(&reference);
+        for (name, difference) in &differences {
+            tracing::error!("{name}: {difference}");
+        }
+        anyhow::ensure!(
+            differences.is_empty(),
+            "{} tiles differ from {}",
+            differences.len(),
+            path.display()
+        );
+        tracing::info!(
+            "All {} tiles match {}",
+            manifest.tiles.len(),
+            path.display()
+        );
+    }
+
     Ok(())
 }
 
-struct OutTile {
-    /// the index of this tile
-    tile_index: (i32, i32),
+/// What is recorded about the finalized tiles for the reports written at the end of a run
+#[derive(Default)]
+struct TileRecords {
+    stats: Vec<stats::TileSummary>,
+    manifest: Vec<manifest::ManifestEntry>,
+    summary: Vec<summary::SummaryTile>,
+    /// Name and bounds of each tile for --boundary
+    footprints: Vec<(String, [f64; 4])>,
+    /// Points removed by --overlap-prefer
+    overlap_points: u64,
+    duplicate_points: u64,
+    /// The overview tiles being written with --overviews
+    overviews: Option<overview::Overviews>,
+    sidecars: Option<sidecar::Sidecars>,
+    /// The commands of --on-tile-complete
+    hooks: Option<hook::TileHooks>,
+    /// The path of each input, for the sources of the tiles in the manifest
+    inputs: Vec<String>,
+}
+
+/// Whether the file `name` in the output folder was written for the tile with the name `tile`,
+/// which may have been split into parts or subdivided into quadtree leaves
+fn is_file_of(args: &cli::Args, tile: &str, name: &str) -> bool {
+    let leaves = args.max_points_per_tile.is_some();
+    match args.split_by {
+        Some(split) => partition::is_part_of(tile, split, name, leaves),
+        None if leaves => quadtree::is_leaf_of(tile, name),
+        None => tile == name,
+    }
+}
+
+/// A progress bar for processing the given number of points
+fn progress_bar(total_points: u64) -> indicatif::ProgressBar {
+    let pb = indicatif::ProgressBar::new(total_points);
+    pb.set_style(indicatif::ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{msg}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({percent}%) ({eta})")
+        .unwrap()
+        .with_key("eta", |state: &indicatif::ProgressState, w: &mut dyn std::fmt::Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
+        .progress_chars("#>-"));
+    pb
+}
 
-    /// The input files that contribute to this tile
-    input_files: HashSet<usize>,
+/// The header for the first points written to a tile: the template, or the one with extended
+/// point counts if the points of the inputs not read yet (and the `existing` points of the tile)
+/// may be more than the template can count
+fn first_header<'a>(
+    template: &'a Arc<las::Header>,
+    extended: Option<&'a Arc<las::Header>>,
+    tile: &tile::OutTile,
+    headers: &[(input::InputFile, las::Header)],
+    existing: u64,
+) -> &'a Arc<las::Header> {
+    let Some(extended) = extended else {
+        return template;
+    };
+    let points = tile
+        .input_files
+        .iter()
+        .map(|&i| headers[i].1.number_of_points())
+        .sum::<u64>()
+        + existing;
+    if !point_format::needs_extended_counts(template, points) {
+        return template;
+    }
+    tracing::info!(
+        "Writing {} as LAS 1.4, it may get more than {} points",
+        tile.name,
+        u32::MAX
+    );
+    extended
+}
 
-    /// The writer to this file, might be None if not opened yet
-    writer: Option<las::Writer<BufWriter<File>>>,
+/// Write the optional reports about the written tiles
+fn write_reports(
+    args: &cli::Args,
+    mut tiles: Vec<stats::TileSummary>,
+    footprints: &[(String, [f64; 4])],
+    density: Option<&density::DensityGrid>,
+    coverage: Option<&density::DensityGrid>,
+    header: &las::Header,
+) -> Result<()> {
+    tiles.sort_by(|a, b| {
+        a.tile_index
+            .cmp(&b.tile_index)
+            .then_with(|| a.quadtree_path.cmp(&b.quadtree_path))
+    });
+    if let Some(path) = &args.stats {
+        stats::write_report(path, &args.units(), &tiles)?;
+    }
+    if let Some(path) = &args.tile_index {
+        tile_index::write(path, &args.units(), &tiles)?;
+    }
+    if let (Some(path), Some(density)) = (&args.density_raster, density) {
+        density.write(path, header)?;
+    }
+    if let (Some(path), Some(coverage)) = (&args.boundary, coverage) {
+        boundary::write(path, coverage, footprints)?;
+    }
+    Ok(())
 }
 
-impl OutTile {
-    pub fn get_writer(
-        &mut self,
-        output_folder: &Path,
-        header: &las::Header,
-    ) -> Result<&mut las::Writer<BufWriter<File>>> {
-        if self.writer.is_none() {
-            let tile_path = output_folder.join(format!(
-                "tile_{}_{}.laz",
-                self.tile_index.0, self.tile_index.1
-            ));
-            let mut new_header = header.clone();
-            new_header.clear();
-
-            let new_writer = las::Writer::from_path(&tile_path, new_header)
-                .context("Could not create writer")?;
-
-            let writer = self.writer.insert(new_writer);
-            return Ok(writer);
-        }
-        // we know writer is Some here
-        Ok(self.writer.as_mut().expect("unreachable"))
+/// Post-process a tile after all its points have been written and the writer has been closed,
+/// then publish it (or with `--adaptive`, its quadtree leaves) to its final location. Returns the
+/// names of the published files and adds what is recorded about them to `records`.
+fn finalize_tile(
+    output: &output::Output,
+    tile: &mut tile::OutTile,
+    args: &cli::Args,
+    memory: &memory::MemoryPlan,
+    records: &mut TileRecords,
+) -> Result<Vec<String>> {
+    let _span = tracing::info_span!("tile", name = %tile.name).entered();
+    let path = output.path(&tile.name);
+    match args.dedupe {
+        None => {}
+        Some(cli::Dedupe::Exact) => {
+            records.duplicate_points += dedupe::remove_exact(
+                &path,
+                args.dedupe_gps_time,
+                memory.dedupe_points,
+                tile.stats.as_mut(),
+            )
+            .with_context(|| format!("remove duplicates in tile: {}", path.display()))?;
+        }
+        Some(cli::Dedupe::KeepLatest) => {
+            records.duplicate_points += overlap::thin(
+                &path,
+                args.dedupe_tolerance,
+                overlap::Keep::Latest,
+                tile.stats.as_mut(),
+            )
+            .with_context(|| format!("remove older points in tile: {}", path.display()))?;
+        }
+    }
+    match args.overlap_prefer {
+        None => {}
+        Some(cli::OverlapPreference::Nadir) => {
+            records.overlap_points += overlap::thin(
+                &path,
+                args.overlap_cell_size,
+                overlap::Keep::Nadir,
+                tile.stats.as_mut(),
+            )
+            .with_context(|| format!("thin overlap in tile: {}", path.display()))?;
+        }
+    }
+    match args.tile_order {
+        cli::TileOrder::Input => {}
+        cli::TileOrder::GpsTime => sort::sort_by_gps_time(&path, memory.sort_block_points)
+            .with_context(|| format!("sort tile by GPS time: {}", path.display()))?,
     }
+
+    if let Some(overviews) = &mut records.overviews {
+        overviews
+            .add(&path, tile.tile_index)
+            .with_context(|| format!("add tile to overviews: {}", path.display()))?;
+    }
+    if args.boundary.is_some() {
+        records.footprints.push((tile.name.clone(), tile.bounds));
+    }
+
+    let whole = quadtree::Leaf {
+        path: String::new(),
+        bounds: tile.bounds,
+        stats: tile.stats.take(),
+    };
+    let leaves = match args.max_points_per_tile {
+        Some(max) => quadtree::subdivide(output.dir(), &tile.name, whole, max.get())
+            .with_context(|| format!("subdivide tile: {}", path.display()))?,
+        None => vec![whole],
+    };
+
+    let mut names = Vec::with_capacity(leaves.len());
+    for leaf in leaves {
+        let name = quadtree::file_name(&tile.name, &leaf.path);
+        if args.manifest || args.compare_manifest.is_some() {
+            records.manifest.push(manifest::ManifestEntry::new(
+                &output.path(&name),
+                &name,
+                tile.sources
+                    .iter()
+                    .map(|&i| records.inputs[i].clone())
+                    .collect(),
+            )?);
+        }
+        if args.summary {
+            records
+                .summary
+                .push(summary::SummaryTile::new(&output.path(&name), &name)?);
+        }
+        if let Some(sidecars) = &records.sidecars {
+            sidecars
+                .write(
+                    output,
+                    &name,
+                    tile.tile_index,
+                    &leaf.path,
+                    leaf.bounds,
+                    tile.sources.iter().copied(),
+                )
+                .with_context(|| {
+                    format!("write sidecar of tile: {}", output.path(&name).display())
+                })?;
+        }
+        output.publish(&name)?;
+        tracing::debug!("Published {}", output.location(&name));
+        if let Some(hooks) = &records.hooks {
+            hooks.tile_complete(&name, &output.path(&name))?;
+        }
+        records
+            .stats
+            .extend(leaf.stats.map(|stats| stats::TileSummary {
+                name: name.clone(),
+                tile_index: tile.tile_index,
+                quadtree_path: leaf.path,
+                bounds: leaf.bounds,
+                stats,
+            }));
+        names.push(name);
+    }
+    Ok(names)
 }
 
 fn vector_min(a: &las::Vector<f64>, b: &las::Vector<f64>) -> las::Vector<f64> {
@@ -273,12 +1950,3 @@ fn vector_max(a: &las::Vector<f64>, b: &las::Vector<f64>) -> las::Vector<f64> {
         z: a.z.max(b.z),
     }
 }
-
-fn bounds_intersect(a: &las::Bounds, b: &las::Bounds) -> bool {
-    !(a.min.x > b.max.x
-        || a.max.x < b.min.x
-        || a.min.y > b.max.y
-        || a.max.y < b.min.y
-        || a.min.z > b.max.z
-        || a.max.z < b.min.z)
-}
@@ -0,0 +1,212 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    output::Output,
+    postprocess::open_reader,
+    schema::{Document, Versioned},
+};
+
+/// Name of the manifest file written to the output folder
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Relative difference below which bounds are considered equal, they do not survive the round
+/// trip through JSON bit for bit
+const BOUNDS_TOLERANCE: f64 = 1e-9;
+
+/// The tiles written by a complete run, to detect differences between runs
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Manifest {
+    /// The inputs of the run, for `lasretile update` to find the ones that changed after it
+    #[serde(default)]
+    pub inputs: Vec<ManifestInput>,
+    /// All written tiles, ordered by name
+    pub tiles: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ManifestInput {
+    /// Path or URL of the input
+    pub path: String,
+    /// The size and modification time of local files, the ETag of remote objects
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub points: u64,
+    /// `[min x, min y, min z]` from the tile header
+    pub min: [f64; 3],
+    /// `[max x, max y, max z]` from the tile header
+    pub max: [f64; 3],
+    /// SHA-256 checksum of the tile file, as a hex string
+    pub sha256: String,
+    /// Paths or URLs of the inputs that wrote points to the tile
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+impl Document for Manifest {
+    const SCHEMA_VERSION: &'static str = "1.1";
+}
+
+/// How a tile differs from the same tile in another manifest
+#[derive(Debug)]
+pub enum Difference {
+    Missing,
+    Unexpected,
+    Points { old: u64, new: u64 },
+    Bounds,
+    Checksum,
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Missing => write!(f, "not written"),
+            Difference::Unexpected => write!(f, "not in the reference manifest"),
+            Difference::Points { old, new } => write!(f, "{new} points instead of {old}"),
+            Difference::Bounds => write!(f, "different bounds"),
+            Difference::Checksum => write!(f, "same points and bounds but different content"),
+        }
+    }
+}
+
+impl ManifestEntry {
+    /// Describe a written tile file
+    pub fn new(path: &Path, name: &str, sources: Vec<String>) -> Result<Self> {
+        let header = open_reader(path)?.header().clone();
+        let bounds = header.bounds();
+
+        let mut file =
+            std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("compute checksum of {}", path.display()))?;
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        Ok(Self {
+            name: name.to_owned(),
+            points: header.number_of_points(),
+            min: [bounds.min.x, bounds.min.y, bounds.min.z],
+            max: [bounds.max.x, bounds.max.y, bounds.max.z],
+            sha256,
+            sources,
+        })
+    }
+
+    /// How this tile differs from the same tile in an earlier run, if it does
+    fn compare(&self, old: &ManifestEntry) -> Option<Difference> {
+        if self.sha256 == old.sha256 {
+            return None;
+        }
+        if self.points != old.points {
+            return Some(Difference::Points {
+                old: old.points,
+                new: self.points,
+            });
+        }
+        let same_bounds = self
+            .min
+            .iter()
+            .chain(&self.max)
+            .zip(old.min.iter().chain(&old.max))
+            .all(|(a, b)| (a - b).abs() <= BOUNDS_TOLERANCE * a.abs().max(b.abs()).max(1.0));
+        Some(if same_bounds {
+            Difference::Checksum
+        } else {
+            Difference::Bounds
+        })
+    }
+}
+
+impl Manifest {
+    pub fn new(inputs: Vec<ManifestInput>, mut tiles: Vec<ManifestEntry>) -> Self {
+        tiles.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { inputs, tiles }
+    }
+
+    pub fn write(&self, output: &Output) -> Result<()> {
+        let path = output.path(MANIFEST_FILE_NAME);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("create manifest: {}", path.display()))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &Versioned::new(self))
+            .with_context(|| format!("write manifest: {}", path.display()))?;
+        output.publish(MANIFEST_FILE_NAME)
+    }
+
+    /// Read a manifest written by an earlier run
+    pub fn read(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("open manifest: {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("read manifest: {}", path.display()))
+    }
+
+    /// Read the manifest written by an earlier run into the output folder, if there is one
+    pub fn load(output: &Output) -> Result<Option<Self>> {
+        output
+            .read(MANIFEST_FILE_NAME)?
+            .map(|data| {
+                Self::parse(&data).with_context(|| {
+                    format!("read manifest: {}", output.location(MANIFEST_FILE_NAME))
+                })
+            })
+            .transpose()
+    }
+
+    fn parse(data: &[u8]) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Stored {
+            schema_version: String,
+            #[serde(flatten)]
+            manifest: Manifest,
+        }
+
+        let stored: Stored = serde_json::from_slice(data)?;
+        let major = |version: &str| version.split('.').next().unwrap_or_default().to_owned();
+        anyhow::ensure!(
+            major(&stored.schema_version) == major(Self::SCHEMA_VERSION),
+            "unsupported schema version {}",
+            stored.schema_version
+        );
+        Ok(stored.manifest)
+    }
+
+    /// The differences of the tiles in this manifest compared to an earlier one, by tile name
+    pub fn compare<'a>(&'a self, old: &'a Manifest) -> Vec<(&'a str, Difference)> {
+        let by_name = |manifest: &'a Manifest| -> HashMap<&'a str, &'a ManifestEntry> {
+            manifest
+                .tiles
+                .iter()
+                .map(|t| (t.name.as_str(), t))
+                .collect()
+        };
+        let (new_tiles, old_tiles) = (by_name(self), by_name(old));
+
+        let mut differences = Vec::new();
+        for entry in &old.tiles {
+            match new_tiles.get(entry.name.as_str()) {
+                Some(new) => differences.extend(
+                    new.compare(entry)
+                        .map(|difference| (entry.name.as_str(), difference)),
+                ),
+                None => differences.push((entry.name.as_str(), Difference::Missing)),
+            }
+        }
+        for entry in &self.tiles {
+            if !old_tiles.contains_key(entry.name.as_str()) {
+                differences.push((entry.name.as_str(), Difference::Unexpected));
+            }
+        }
+        differences
+    }
+}
@@ -0,0 +1,152 @@
+use std::num::NonZeroUsize;
+
+use crate::dedupe;
+
+/// Size of the point read buffers when no memory budget is given
+const DEFAULT_READ_BUFFER_BYTES: usize = 200 * 1024 * 1024;
+
+/// Size of the write buffer of each tile writer when no memory budget is given
+const DEFAULT_WRITE_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Number of points collected per tile before they are handed to a writer thread, when no
+/// memory budget is given. Also the upper bound when derived from a budget.
+const DEFAULT_BATCH_POINTS: usize = 4096;
+
+/// Number of batches that can be queued for the writer threads in total before routing blocks,
+/// when no memory budget is given. Also the upper bound when derived from a budget.
+const DEFAULT_QUEUED_BATCHES: usize = 256;
+
+/// Memory used for finishing a tile (sorting a block of points by GPS time, or the points seen
+/// by `--dedupe exact`), when no memory budget is given
+const DEFAULT_FINISH_BYTES: usize = 256 * 1024 * 1024;
+
+/// Fraction of the memory budget that is used for finishing a tile. Tiles are finished one at a
+/// time once they are written, and each step of finishing a tile runs after the other.
+const FINISH_FRACTION: usize = 4;
+
+/// Never sort fewer points at a time than this, regardless of the budget
+const MIN_SORT_BLOCK_POINTS: usize = 64 * 1024;
+
+/// Never check fewer points for duplicates at a time than this, regardless of the budget
+const MIN_DEDUPE_POINTS: usize = 64 * 1024;
+
+/// Never queue fewer batches than this, regardless of the budget
+const MIN_QUEUED_BATCHES: usize = 4;
+
+/// Bounds for the write buffer size of each tile writer when derived from a memory budget
+const MIN_WRITE_BUFFER_BYTES: usize = 8 * 1024;
+const MAX_WRITE_BUFFER_BYTES: usize = 16 * 1024 * 1024;
+
+/// Fraction of the memory budget that is used for write buffers
+const WRITE_BUFFER_FRACTION: usize = 4;
+
+/// Fraction of the memory budget that is used for the point batches of all tiles
+const BATCH_FRACTION: usize = 4;
+
+/// Fraction of the memory budget that is used for the batches queued for the writer threads
+const QUEUE_FRACTION: usize = 8;
+
+/// Never buffer fewer points than this, regardless of the budget
+const MIN_READ_BUFFER_POINTS: usize = 1024;
+const MIN_BATCH_POINTS: usize = 64;
+
+/// Default share of the available memory used as the budget when none is given, in percent
+pub const DEFAULT_MEMORY_PERCENT: u8 = 25;
+
+/// Upper bound of a budget taken from the available memory, larger buffers do not make
+/// retiling any faster
+const MAX_DETECTED_BUDGET: u64 = 4 << 30;
+
+/// Memory budget used by `--low-memory`, unless `--memory` is given
+pub const LOW_MEMORY_BUDGET: u64 = 128 * 1024 * 1024;
+
+/// Maximum number of open tile writers used by `--low-memory`, unless `--max-open-files` is
+/// given. Every open LAZ writer keeps a chunk of compressed points in memory.
+pub const LOW_MEMORY_MAX_OPEN_FILES: NonZeroUsize = NonZeroUsize::new(16).expect("not zero");
+
+/// A memory budget of `percent` of the memory currently available, if the platform reports it
+pub fn detect_budget(percent: u8) -> Option<u64> {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    let available = system.available_memory();
+    if available == 0 {
+        return None;
+    }
+    let budget = (available / 100 * u64::from(percent)).min(MAX_DETECTED_BUDGET);
+    tracing::info!(
+        "Memory budget: {} ({percent}% of {} available)",
+        indicatif::HumanBytes(budget),
+        indicatif::HumanBytes(available)
+    );
+    Some(budget)
+}
+
+/// How the memory budget is divided between the different buffers
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryPlan {
+    /// Number of points buffered between reading the input files and routing them to tiles
+    pub read_buffer_points: usize,
+
+    /// Size in bytes of the write buffer of each tile writer
+    pub write_buffer_bytes: usize,
+
+    /// Number of points collected per tile before they are handed to a writer thread
+    pub batch_points: usize,
+
+    /// Number of batches that can be queued for all writer threads together. Routing blocks
+    /// once they are full, so that slow compression or a slow disk stalls reading instead of
+    /// piling up batches in memory.
+    pub queued_batches: usize,
+
+    /// Number of points sorted in memory at a time when ordering a tile by GPS time
+    pub sort_block_points: usize,
+
+    /// Number of points whose keys are held in memory at a time by `--dedupe exact`
+    pub dedupe_points: usize,
+}
+
+impl MemoryPlan {
+    /// Divide the memory budget (in bytes) between the point read buffer, the point batches of
+    /// `tiles` tiles, the batches queued for the writer threads and the write buffers of at most
+    /// `open_writers` simultaneously open tile writers, and sets aside a share for finishing
+    /// tiles (sorting them by GPS time or removing duplicates). Without a budget, the defaults
+    /// are used.
+    ///
+    /// Note that this does not account for memory used internally by the LAZ (de)compressors,
+    /// so the actual usage will be somewhat higher.
+    pub fn new(budget: Option<u64>, open_writers: usize, tiles: usize) -> Self {
+        let Some(budget) = budget else {
+            return Self {
+                read_buffer_points: DEFAULT_READ_BUFFER_BYTES / size_of::<las::Point>(),
+                write_buffer_bytes: DEFAULT_WRITE_BUFFER_BYTES,
+                batch_points: DEFAULT_BATCH_POINTS,
+                queued_batches: DEFAULT_QUEUED_BATCHES,
+                sort_block_points: DEFAULT_FINISH_BYTES / size_of::<las::Point>(),
+                dedupe_points: DEFAULT_FINISH_BYTES / dedupe::KEY_BYTES,
+            };
+        };
+        let budget = usize::try_from(budget).unwrap_or(usize::MAX);
+
+        let write_buffer_bytes = (budget / WRITE_BUFFER_FRACTION / open_writers.max(1))
+            .clamp(MIN_WRITE_BUFFER_BYTES, MAX_WRITE_BUFFER_BYTES);
+        let batch_points = (budget / BATCH_FRACTION / tiles.max(1) / size_of::<las::Point>())
+            .clamp(MIN_BATCH_POINTS, DEFAULT_BATCH_POINTS);
+        let batch_bytes = batch_points * size_of::<las::Point>();
+        let queued_batches = (budget / QUEUE_FRACTION / batch_bytes)
+            .clamp(MIN_QUEUED_BATCHES, DEFAULT_QUEUED_BATCHES);
+        let read_bytes = budget
+            .saturating_sub(write_buffer_bytes * open_writers)
+            .saturating_sub(batch_bytes * tiles)
+            .saturating_sub(batch_bytes * queued_batches);
+
+        Self {
+            read_buffer_points: (read_bytes / size_of::<las::Point>()).max(MIN_READ_BUFFER_POINTS),
+            write_buffer_bytes,
+            batch_points,
+            queued_batches,
+            sort_block_points: (budget / FINISH_FRACTION / size_of::<las::Point>())
+                .max(MIN_SORT_BLOCK_POINTS),
+            dedupe_points: (budget / FINISH_FRACTION / dedupe::KEY_BYTES).max(MIN_DEDUPE_POINTS),
+        }
+    }
+}
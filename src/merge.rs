@@ -0,0 +1,152 @@
+use std::{
+    fmt::Debug,
+    io::{Seek, Write},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    cli::MergeArgs,
+    filter::PointFilters,
+    input::{self, InputFile},
+    memory::MemoryPlan,
+    point_format,
+    postprocess::create_writer,
+    progress_bar,
+    reader::InputReader,
+    stats::TileStats,
+    stdio,
+};
+
+/// Concatenate all inputs into a single LAS/LAZ file, or a stream on stdout (`-`)
+pub fn run(args: &MergeArgs) -> Result<()> {
+    let mut skipped = input::SkipReport::default();
+    let input_files = input::collect_inputs(&args.input, &mut skipped)?;
+    let headers = input::scan_headers(
+        input_files,
+        args.input.header_cache.as_deref(),
+        &mut skipped,
+    );
+    skipped.print_summary();
+
+    let total_points: u64 = headers.iter().map(|(_, h)| h.number_of_points()).sum();
+    let first = point_format::for_points(
+        common_header(&headers)?,
+        total_points,
+        &args.output.display().to_string(),
+    )?;
+    tracing::info!(
+        "Merging {} input files with a total {}M points.",
+        headers.len(),
+        total_points / 1_000_000
+    );
+
+    let mut filters = PointFilters::load(&args.filters)?;
+    let written = if stdio::is_stdio(&args.output) {
+        // the header is updated once all points are written, so the stream is assembled in a
+        // file first. It is compressed like the first input.
+        let extension = if first.point_format().is_compressed {
+            "laz"
+        } else {
+            "las"
+        };
+        let path = stdio::spool_path(&format!("merged.{extension}"))?;
+        let mut writer = create_writer(&path, &first)?;
+        let written = merge_into(&headers, &mut writer, &mut filters, total_points)?;
+        writer.close().context("close stream")?;
+        stdio::copy_to_stdout(&path)?;
+        written
+    } else {
+        let mut writer = create_writer(&args.output, &first)?;
+        let written = merge_into(&headers, &mut writer, &mut filters, total_points)?;
+        writer
+            .close()
+            .with_context(|| format!("close {}", args.output.display()))?;
+        written
+    };
+
+    tracing::info!("Wrote {written} points to {}", args.output.display());
+    Ok(())
+}
+
+/// Write all points of the inputs to `writer`, returning the number of points written
+fn merge_into<W: 'static + Write + Seek + Debug + Send>(
+    headers: &[(InputFile, las::Header)],
+    writer: &mut las::Writer<W>,
+    filters: &mut PointFilters,
+    total_points: u64,
+) -> Result<u64> {
+    let options = las::ReaderOptions::default().with_laz_parallelism(las::LazParallelism::Yes);
+    let reader = InputReader::spawn(
+        headers.iter().map(|(path, _)| path.clone()).collect(),
+        options,
+        MemoryPlan::new(None, 1, 1).read_buffer_points,
+    );
+
+    let pb = progress_bar(total_points);
+    let mut written = 0;
+    for i_file in 0..headers.len() {
+        pb.set_message(format!("{}/{}", i_file + 1, headers.len()));
+        written += append(
+            &reader,
+            &headers[i_file..=i_file],
+            writer,
+            filters,
+            &pb,
+            None,
+        )?;
+    }
+    pb.finish_with_message("Done");
+    Ok(written)
+}
+
+/// Check that the points of all inputs can be written with the header of the first one, and
+/// return that header
+pub fn common_header(headers: &[(InputFile, las::Header)]) -> Result<&las::Header> {
+    let (_, first) = headers.first().context("no input files to merge")?;
+    for (path, header) in headers {
+        anyhow::ensure!(
+            same_attributes(header.point_format(), first.point_format()),
+            "cannot merge {path} with point format {} into point format {}",
+            header.point_format(),
+            first.point_format()
+        );
+    }
+    Ok(first)
+}
+
+/// Write all points of the given inputs, which must be the next ones read by `reader`, to
+/// `writer`, optionally collecting their statistics. Returns the number of points written.
+pub fn append<W: 'static + Write + Seek + Debug + Send>(
+    reader: &InputReader,
+    inputs: &[(InputFile, las::Header)],
+    writer: &mut las::Writer<W>,
+    filters: &mut PointFilters,
+    pb: &indicatif::ProgressBar,
+    mut stats: Option<&mut TileStats>,
+) -> Result<u64> {
+    let mut written = 0;
+    for (path, _) in inputs {
+        while let Some(mut points) = reader.next()? {
+            pb.inc(points.len() as u64);
+            filters.apply(&mut points, path)?;
+            written += points.len() as u64;
+            for point in points.drain(..) {
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.add(&point);
+                }
+                writer.write_point(point).context("Could not write point")?;
+            }
+            reader.recycle(points);
+        }
+    }
+    Ok(written)
+}
+
+/// Whether points of both formats have the same attributes, regardless of compression
+fn same_attributes(a: &las::point::Format, b: &las::point::Format) -> bool {
+    let (mut a, mut b) = (*a, *b);
+    a.is_compressed = false;
+    b.is_compressed = false;
+    a == b
+}
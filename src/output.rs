@@ -0,0 +1,220 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    remote::{self, RemoteDir},
+    stdio::{self, TarStream},
+};
+
+/// Where output files end up. Files are always written to a local folder first, and uploaded
+/// once finished when the output is a remote object store, or added to a tar archive on stdout
+/// when the output is `-`.
+#[derive(Debug)]
+pub struct Output {
+    dir: PathBuf,
+    remote: Option<RemoteDir>,
+    stdout: Option<TarStream>,
+}
+
+impl Output {
+    /// Create the output for the given output folder, remote URL or `-` for stdout. Files for
+    /// remote outputs and stdout are staged in `staging_dir`, or a folder in the system temporary
+    /// directory if not given.
+    pub fn new(output: &Path, staging_dir: Option<&Path>) -> Result<Self> {
+        let remote = output
+            .to_str()
+            .and_then(remote::parse_remote_url)
+            .map(RemoteDir::new)
+            .transpose()?;
+        let stdout = stdio::is_stdio(output).then(TarStream::new);
+
+        let staged = remote.is_some() || stdout.is_some();
+        let dir = match (staged, staging_dir) {
+            (false, _) => output.to_owned(),
+            (true, Some(staging)) => staging.to_owned(),
+            (true, None) => std::env::temp_dir().join(format!("lasretile-{}", std::process::id())),
+        };
+
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("create output folder: {}", dir.display()))?;
+
+        Ok(Self {
+            dir,
+            remote,
+            stdout,
+        })
+    }
+
+    /// Whether the files are written to stdout
+    pub fn is_stdout(&self) -> bool {
+        self.stdout.is_some()
+    }
+
+    /// Whether the files stay in the local output folder
+    pub fn is_local(&self) -> bool {
+        self.remote.is_none() && self.stdout.is_none()
+    }
+
+    /// The local folder where output files should be written
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The local path of the output file with the given name
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// The final location of the output file with the given name, for display purposes
+    pub fn location(&self, name: &str) -> String {
+        match (&self.remote, &self.stdout) {
+            (Some(remote), _) => remote.url_of(name),
+            (None, Some(_)) => format!("{name} (stdout)"),
+            (None, None) => self.path(name).display().to_string(),
+        }
+    }
+
+    /// The names of the files that already exist at the final location, with `/` between
+    /// subfolders
+    pub fn existing_files(&self) -> Result<HashSet<String>> {
+        if let Some(remote) = &self.remote {
+            return Ok(remote.list_names()?.into_iter().collect());
+        }
+        if self.stdout.is_some() {
+            return Ok(HashSet::new());
+        }
+
+        let mut names = HashSet::new();
+        let mut folders = vec![String::new()];
+        while let Some(folder) = folders.pop() {
+            let dir = self.dir.join(&folder);
+            for entry in std::fs::read_dir(&dir)
+                .with_context(|| format!("list output folder: {}", dir.display()))?
+            {
+                let entry =
+                    entry.with_context(|| format!("list output folder: {}", dir.display()))?;
+                let Some(name) = entry
+                    .file_name()
+                    .to_str()
+                    .map(|name| match folder.as_str() {
+                        "" => name.to_owned(),
+                        folder => format!("{folder}/{name}"),
+                    })
+                else {
+                    continue;
+                };
+                match entry.file_type() {
+                    Ok(t) if t.is_file() => {
+                        names.insert(name);
+                    }
+                    Ok(t) if t.is_dir() => folders.push(name),
+                    _ => {}
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Read the file with the given name from its final location, or `None` if it does not exist
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(remote) = &self.remote {
+            return Ok(remote.download(name)?.map(Vec::from));
+        }
+        if self.stdout.is_some() {
+            return Ok(None);
+        }
+
+        let path = self.path(name);
+        match std::fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("read {}", path.display())),
+        }
+    }
+
+    /// A local file with the contents of the output file with the given name, to read it back
+    /// before it is written again. Local files are read in place, tiles are only replaced once
+    /// the new file is complete. Remote files are downloaded to the local file `to` in the
+    /// staging folder, see [`Output::release`].
+    pub fn fetch(&self, name: &str, to: &str) -> Result<PathBuf> {
+        let Some(remote) = &self.remote else {
+            return Ok(self.path(name));
+        };
+        let path = self.path(to);
+        let data = remote
+            .download(name)?
+            .with_context(|| format!("{} does not exist", remote.url_of(name)))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("create folder: {}", parent.display()))?;
+        }
+        std::fs::write(&path, data).with_context(|| format!("write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Remove the local copy made by [`Output::fetch`] of the file with the given name, if it
+    /// made one
+    pub fn release(&self, name: &str, fetched: &Path) -> Result<()> {
+        if fetched == self.path(name) {
+            return Ok(());
+        }
+        std::fs::remove_file(fetched).with_context(|| format!("remove {}", fetched.display()))
+    }
+
+    /// Make a finished file available at its final location. For remote outputs this uploads
+    /// the staged file and for stdout adds it to the tar archive, removing the local copy.
+    pub fn publish(&self, name: &str) -> Result<()> {
+        let path = self.path(name);
+        match (&self.remote, &self.stdout) {
+            (Some(remote), _) => remote.upload(&path, name)?,
+            (None, Some(stdout)) => stdout.append(&path, name)?,
+            (None, None) => return Ok(()),
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| format!("remove staged file: {}", path.display()))?;
+        // remove the staged subfolders of --output-layout once they are empty
+        for folder in Path::new(name).ancestors().skip(1) {
+            if folder.as_os_str().is_empty() || std::fs::remove_dir(self.dir.join(folder)).is_err()
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the output file with the given name from its final location, if it exists
+    pub fn remove(&self, name: &str) -> Result<()> {
+        if let Some(remote) = &self.remote {
+            return remote.delete(name);
+        }
+        if self.stdout.is_some() {
+            // nothing can be taken back from the stream
+            return Ok(());
+        }
+
+        let path = self.path(name);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Clean up the staging folder of remote outputs and stdout once all files have been
+    /// published, and end the tar archive on stdout
+    pub fn finish(&self) -> Result<()> {
+        if let Some(stdout) = &self.stdout {
+            stdout.finish()?;
+        }
+        if self.remote.is_some() || self.stdout.is_some() {
+            // only removes the folder if it is empty, so nothing unpublished is lost
+            let _ = std::fs::remove_dir(&self.dir);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    postprocess::{create_writer, open_reader, temp_path},
+    stats::TileStats,
+};
+
+/// Number of points read at a time while filtering
+const BUFFER_SIZE: u64 = 64 * 1024;
+
+/// Which flightline to keep where flightlines overlap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// The one with the smallest mean absolute scan angle. Points closer to nadir have better
+    /// geometry and accuracy than points at the edge of a swath.
+    Nadir,
+    /// The one with the most recent GPS time, so that reflown areas replace older data
+    Latest,
+}
+
+/// Statistics of the points from one flightline within a cell
+#[derive(Debug)]
+struct SourceStats {
+    point_source_id: u16,
+    /// Sum of the absolute scan angles
+    sum_angle: f64,
+    latest_time: f64,
+    count: u64,
+}
+
+impl SourceStats {
+    /// Lower is better
+    fn score(&self, keep: Keep) -> f64 {
+        match keep {
+            Keep::Nadir => self.sum_angle / self.count as f64,
+            Keep::Latest => -self.latest_time,
+        }
+    }
+}
+
+/// The point source ID of the flightline to keep in a cell, the lowest one on ties
+fn preferred_source(sources: &[SourceStats], keep: Keep) -> Option<u16> {
+    let best = sources.iter().min_by(|a, b| {
+        a.score(keep)
+            .total_cmp(&b.score(keep))
+            .then(a.point_source_id.cmp(&b.point_source_id))
+    })?;
+    Some(best.point_source_id)
+}
+
+fn cell_of(point: &las::Point, cell_size: f64) -> (i64, i64) {
+    (
+        (point.x / cell_size).floor() as i64,
+        (point.y / cell_size).floor() as i64,
+    )
+}
+
+/// Find all pairs of bounds that overlap. Bounds that only touch, such as neighboring tiles
+/// sharing an edge, do not overlap.
+///
+/// Sweeps over the bounds sorted by their minimum X, only comparing bounds whose X ranges
+/// overlap. That is `O(N log N + N·K)`, where K is the number of bounds with a common X range,
+/// e.g. the tiles in a column of a grid (about `√N` for a square grid), instead of comparing all
+/// pairs.
+pub fn find_overlapping(bounds: &[las::Bounds]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..bounds.len()).collect();
+    order.sort_by(|&a, &b| bounds[a].min.x.total_cmp(&bounds[b].min.x));
+
+    let mut pairs = Vec::new();
+    let mut active: Vec<usize> = Vec::new();
+    for i in order {
+        let current = &bounds[i];
+        // bounds that end before this one starts can not overlap any of the remaining ones
+        active.retain(|&j| bounds[j].max.x >= current.min.x);
+        for &j in &active {
+            if bounds_intersect(current, &bounds[j]) {
+                pairs.push((i.min(j), i.max(j)));
+            }
+        }
+        active.push(i);
+    }
+    pairs.sort();
+    pairs
+}
+
+fn bounds_intersect(a: &las::Bounds, b: &las::Bounds) -> bool {
+    ranges_overlap(a.min.x, a.max.x, b.min.x, b.max.x)
+        && ranges_overlap(a.min.y, a.max.y, b.min.y, b.max.y)
+        && ranges_overlap(a.min.z, a.max.z, b.min.z, b.max.z)
+}
+
+/// Whether the ranges `[a0, a1]` and `[b0, b1]` have more than an end in common. A range of a
+/// single value (e.g. flat data, or a file with a single point) overlaps the ranges containing it.
+fn ranges_overlap(a0: f64, a1: f64, b0: f64, b1: f64) -> bool {
+    if a0 == a1 || b0 == b1 {
+        a0 <= b1 && b0 <= a1
+    } else {
+        a0 < b1 && b0 < a1
+    }
+}
+
+/// Thin the overlap between flightlines in a tile by keeping, in each cell of `cell_size`, only
+/// the points of the flightline (point source ID) chosen by `keep`.
+///
+/// Cells covered by a single flightline are left untouched. If given, `stats` is rebuilt from
+/// the points that are kept. Returns the number of points removed.
+pub fn thin(path: &Path, cell_size: f64, keep: Keep, stats: Option<&mut TileStats>) -> Result<u64> {
+    // pass 1: collect the statistics of each flightline per cell
+    let mut reader = open_reader(path)?;
+    let header = reader.header().clone();
+    let mut cells: HashMap<(i64, i64), Vec<SourceStats>> = HashMap::new();
+    let mut points = Vec::new();
+    loop {
+        points.clear();
+        if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+            break;
+        }
+        for point in &points {
+            let sources = cells.entry(cell_of(point, cell_size)).or_default();
+            let angle = f64::from(point.scan_angle.abs());
+            let time = point.gps_time.unwrap_or(f64::NEG_INFINITY);
+            match sources
+                .iter_mut()
+                .find(|s| s.point_source_id == point.point_source_id)
+            {
+                Some(source) => {
+                    source.sum_angle += angle;
+                    source.latest_time = source.latest_time.max(time);
+                    source.count += 1;
+                }
+                None => sources.push(SourceStats {
+                    point_source_id: point.point_source_id,
+                    sum_angle: angle,
+                    latest_time: time,
+                    count: 1,
+                }),
+            }
+        }
+    }
+    drop(reader);
+
+    // only cells with more than one flightline are overlapping
+    let keep: HashMap<(i64, i64), u16> = cells
+        .into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .filter_map(|(cell, sources)| Some((cell, preferred_source(&sources, keep)?)))
+        .collect();
+    if keep.is_empty() {
+        return Ok(0);
+    }
+
+    // pass 2: rewrite the tile with only the preferred flightline in overlapping cells
+    let mut stats = stats;
+    if let Some(stats) = stats.as_deref_mut() {
+        stats.clear();
+    }
+
+    let temp = temp_path(path, "overlap");
+    let result = (|| {
+        let mut reader = open_reader(path)?;
+        let mut writer = create_writer(&temp, &header)?;
+        let mut removed = 0;
+        loop {
+            points.clear();
+            if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+                break;
+            }
+            for point in points.drain(..) {
+                let preferred = keep.get(&cell_of(&point, cell_size));
+                if preferred.is_some_and(|&id| id != point.point_source_id) {
+                    removed += 1;
+                    continue;
+                }
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.add(&point);
+                }
+                writer.write_point(point)?;
+            }
+        }
+        writer.close()?;
+        std::fs::rename(&temp, path)
+            .with_context(|| format!("replace {} with thinned tile", path.display()))?;
+        Ok(removed)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(min: [f64; 3], max: [f64; 3]) -> las::Bounds {
+        las::Bounds {
+            min: las::Vector {
+                x: min[0],
+                y: min[1],
+                z: min[2],
+            },
+            max: las::Vector {
+                x: max[0],
+                y: max[1],
+                z: max[2],
+            },
+        }
+    }
+
+    #[test]
+    fn neighboring_tiles_do_not_overlap() {
+        // a 3x3 grid of tiles sharing their edges and corners
+        let grid: Vec<_> = (0..9)
+            .map(|i| {
+                let (x, y) = (f64::from(i % 3) * 10.0, f64::from(i / 3) * 10.0);
+                bounds([x, y, 0.0], [x + 10.0, y + 10.0, 5.0])
+            })
+            .collect();
+        assert_eq!(find_overlapping(&grid), vec![]);
+    }
+
+    #[test]
+    fn finds_overlapping_and_nested_bounds() {
+        let inputs = [
+            bounds([0.0, 0.0, 0.0], [10.0, 10.0, 5.0]),
+            // overlaps the first one
+            bounds([5.0, 5.0, 0.0], [15.0, 15.0, 5.0]),
+            // inside the first one
+            bounds([2.0, 2.0, 1.0], [3.0, 3.0, 2.0]),
+            // far away
+            bounds([100.0, 0.0, 0.0], [110.0, 10.0, 5.0]),
+        ];
+        assert_eq!(find_overlapping(&inputs), vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn flat_and_single_point_bounds_overlap_what_contains_them() {
+        let inputs = [
+            bounds([0.0, 0.0, 0.0], [10.0, 10.0, 0.0]),
+            bounds([5.0, 5.0, 0.0], [15.0, 15.0, 0.0]),
+            bounds([1.0, 1.0, 0.0], [1.0, 1.0, 0.0]),
+        ];
+        assert_eq!(find_overlapping(&inputs), vec![(0, 1), (0, 2)]);
+    }
+
+    fn source(point_source_id: u16, mean_angle: f64, latest_time: f64) -> SourceStats {
+        SourceStats {
+            point_source_id,
+            sum_angle: mean_angle * 10.0,
+            latest_time,
+            count: 10,
+        }
+    }
+
+    #[test]
+    fn keeps_the_flightline_closest_to_nadir_or_the_latest() {
+        let sources = [
+            source(1, 12.0, 300.0),
+            source(2, 3.0, 100.0),
+            source(3, 8.0, 200.0),
+        ];
+        assert_eq!(preferred_source(&sources, Keep::Nadir), Some(2));
+        assert_eq!(preferred_source(&sources, Keep::Latest), Some(1));
+    }
+
+    #[test]
+    fn ties_keep_the_lowest_point_source_id() {
+        let sources = [source(7, 5.0, 100.0), source(4, 5.0, 100.0)];
+        assert_eq!(preferred_source(&sources, Keep::Nadir), Some(4));
+        assert_eq!(preferred_source(&sources, Keep::Latest), Some(4));
+    }
+}
@@ -0,0 +1,184 @@
+//! Coarser overview tiles (`--overviews`), like the overviews of a raster. Level `n` has tiles of
+//! `2^n` times the tile size that hold every `4^n`-th point of the tiles they cover, so viewers can
+//! load little data when zoomed out.
+
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    output::Output,
+    postprocess::{create_writer, open_reader},
+    tile::TileKey,
+};
+
+/// Number of points read at a time from a finished tile
+const BUFFER_SIZE: u64 = 64 * 1024;
+
+/// The file name of an overview tile, e.g. `overview2_1_3.laz`
+pub fn file_name(level: u32, key: TileKey) -> String {
+    format!("overview{level}_{}_{}.laz", key.x, key.y)
+}
+
+/// The overview tile at `level` that covers the grid tile. Grid keys are rounded down, so the
+/// overview keys are as well: each overview tile covers `2^level` by `2^level` grid tiles, also
+/// on the negative side of the origin.
+fn parent(level: u32, key: TileKey) -> TileKey {
+    TileKey {
+        x: key.x.div_euclid(1 << level),
+        y: key.y.div_euclid(1 << level),
+        z: None,
+        part: None,
+    }
+}
+
+/// The overview tiles being written. Each is published as soon as all tiles it covers are
+/// finished.
+pub struct Overviews {
+    levels: u32,
+    dir: PathBuf,
+    /// The planned tiles that are not finished yet
+    planned: HashSet<TileKey>,
+    /// Number of planned tiles not finished yet for each overview tile
+    remaining: HashMap<(u32, TileKey), usize>,
+    writers: HashMap<(u32, TileKey), las::Writer<BufWriter<File>>>,
+}
+
+impl Overviews {
+    /// Overviews with the given number of levels over the planned grid tiles, written to `dir`
+    pub fn new(levels: u32, dir: &Path, tiles: impl IntoIterator<Item = TileKey>) -> Self {
+        let planned: HashSet<TileKey> = tiles.into_iter().collect();
+        let mut remaining = HashMap::new();
+        for &key in &planned {
+            for level in 1..=levels {
+                *remaining.entry((level, parent(level, key))).or_default() += 1;
+            }
+        }
+        Self {
+            levels,
+            dir: dir.to_owned(),
+            planned,
+            remaining,
+            writers: HashMap::new(),
+        }
+    }
+
+    /// Add the thinned points of a finished grid tile, before it is published. Tiles that were not
+    /// planned, only created for points outside the header bounds of their input, are left out as
+    /// their overview tiles may already be published.
+    pub fn add(&mut self, path: &Path, key: TileKey) -> Result<()> {
+        if !self.planned.contains(&key) {
+            return Ok(());
+        }
+        let mut reader = open_reader(path)?;
+        let header = reader.header().clone();
+        let mut index = 0u64;
+        let mut points = Vec::new();
+        loop {
+            points.clear();
+            if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+                break;
+            }
+            for point in points.drain(..) {
+                // the points kept at a level are a subset of the ones kept at the finer levels
+                for level in 1..=self.levels {
+                    if !index.is_multiple_of(1 << (2 * level)) {
+                        break;
+                    }
+                    let overview = (level, parent(level, key));
+                    let writer = match self.writers.entry(overview) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let path = self.dir.join(file_name(level, overview.1));
+                            entry.insert(create_writer(&path, &header)?)
+                        }
+                    };
+                    writer.write_point(point.clone())?;
+                }
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark a planned grid tile as finished, whether it received points or not, and publish the
+    /// overview tiles that are now complete. Returns their names.
+    pub fn tile_finished(&mut self, output: &Output, key: TileKey) -> Result<Vec<String>> {
+        if !self.planned.remove(&key) {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for level in 1..=self.levels {
+            let overview = (level, parent(level, key));
+            let remaining = self
+                .remaining
+                .get_mut(&overview)
+                .context("overview tile is planned")?;
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.remaining.remove(&overview);
+                names.extend(self.close(output, overview)?);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Publish all overview tiles that are still open, e.g. when stopping early. Returns their
+    /// names.
+    pub fn finish(&mut self, output: &Output) -> Result<Vec<String>> {
+        let mut open: Vec<_> = self.writers.keys().copied().collect();
+        open.sort();
+        let mut names = Vec::new();
+        for overview in open {
+            names.extend(self.close(output, overview)?);
+        }
+        Ok(names)
+    }
+
+    /// Close and publish an overview tile, if any point was written to it
+    fn close(&mut self, output: &Output, overview: (u32, TileKey)) -> Result<Option<String>> {
+        let Some(mut writer) = self.writers.remove(&overview) else {
+            return Ok(None);
+        };
+        let name = file_name(overview.0, overview.1);
+        writer
+            .close()
+            .with_context(|| format!("close overview tile {name}"))?;
+        output.publish(&name)?;
+        tracing::debug!("Published {}", output.location(&name));
+        Ok(Some(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(x: i32, y: i32) -> TileKey {
+        TileKey {
+            x,
+            y,
+            z: None,
+            part: None,
+        }
+    }
+
+    #[test]
+    fn each_overview_tile_covers_the_same_number_of_tiles() {
+        let mut covered: HashMap<TileKey, usize> = HashMap::new();
+        for x in -8..8 {
+            for y in -8..8 {
+                *covered.entry(parent(2, key(x, y))).or_default() += 1;
+            }
+        }
+        assert_eq!(covered.len(), 16);
+        assert!(covered.values().all(|&n| n == 16), "{covered:?}");
+        assert_eq!(parent(1, key(-1, -2)), key(-1, -1));
+        assert_eq!(parent(1, key(1, 2)), key(0, 1));
+    }
+}
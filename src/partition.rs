@@ -0,0 +1,112 @@
+//! Partitioning each output tile further into one file per group of points (`--split-by`)
+
+use std::collections::HashMap;
+
+use crate::{cli::SplitBy, quadtree};
+
+/// A flight line: a time interval of the points of one point source ID
+struct Flightline {
+    start: f64,
+    end: f64,
+    id: u32,
+}
+
+/// Assigns each point to the part of its tile it is written to
+pub struct Partitioner {
+    split: SplitBy,
+    /// Minimum time between two flight lines of the same point source ID
+    gap: f64,
+    flightlines: HashMap<u16, Vec<Flightline>>,
+    next_flightline: u32,
+}
+
+impl Partitioner {
+    pub fn new(split: SplitBy, gap: f64) -> Self {
+        Self {
+            split,
+            gap,
+            flightlines: HashMap::new(),
+            next_flightline: 0,
+        }
+    }
+
+    /// The part of its tile the point belongs to
+    pub fn part_of(&mut self, point: &las::Point) -> u32 {
+        match self.split {
+            SplitBy::Flightline => self.flightline_of(point),
+            SplitBy::Psid => point.point_source_id.into(),
+            SplitBy::Class => u8::from(point.classification).into(),
+        }
+    }
+
+    /// Flight lines are numbered in the order they are first seen. Points of the same point
+    /// source ID belong to the same flight line unless their GPS times are more than `gap`
+    /// apart, which also separates the lines of files that reuse one ID for a whole mission.
+    fn flightline_of(&mut self, point: &las::Point) -> u32 {
+        let time = point.gps_time.unwrap_or_default();
+        let lines = self.flightlines.entry(point.point_source_id).or_default();
+        // the points of a flight line are usually read in order, check the latest one first
+        let found = lines
+            .iter_mut()
+            .rev()
+            .find(|line| time >= line.start - self.gap && time <= line.end + self.gap);
+        match found {
+            Some(line) => {
+                line.start = line.start.min(time);
+                line.end = line.end.max(time);
+                line.id
+            }
+            None => {
+                let id = self.next_flightline;
+                self.next_flightline += 1;
+                lines.push(Flightline {
+                    start: time,
+                    end: time,
+                    id,
+                });
+                id
+            }
+        }
+    }
+}
+
+/// The file name of a part of a tile, e.g. `tile_5_7_l3.laz` for flight line 3 of `tile_5_7`
+pub fn file_name(tile: &str, split: SplitBy, part: u32) -> String {
+    let suffix = match split {
+        // the ASPRS classes are usually written with two digits
+        SplitBy::Class => format!("{}{part:02}", prefix(split)),
+        _ => format!("{}{part}", prefix(split)),
+    };
+    quadtree::file_name(tile, &suffix)
+}
+
+/// Whether `name` is the file name of a part of the tile with the given name, or of a quadtree
+/// leaf of such a part if `leaves` is set
+pub fn is_part_of(tile: &str, split: SplitBy, name: &str, leaves: bool) -> bool {
+    let (stem, _) = tile.rsplit_once('.').unwrap_or((tile, ""));
+    let Some(rest) = name
+        .strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('_'))
+        .and_then(|rest| rest.strip_prefix(prefix(split)))
+    else {
+        return false;
+    };
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let Ok(part) = rest[..digits].parse() else {
+        return false;
+    };
+    let part_name = file_name(tile, split, part);
+    if leaves {
+        quadtree::is_leaf_of(&part_name, name)
+    } else {
+        part_name == name
+    }
+}
+
+fn prefix(split: SplitBy) -> &'static str {
+    match split {
+        SplitBy::Flightline => "l",
+        SplitBy::Psid => "p",
+        SplitBy::Class => "c",
+    }
+}
@@ -0,0 +1,155 @@
+//! Copying the compressed LAZ chunks of COPC inputs into the tiles as they are
+//! (`--chunk-passthrough`). Each node of a COPC octree is stored as one LAZ chunk, and when a node
+//! lies within a single tile and its points need no changes, its chunk does not need to be
+//! decompressed and compressed again. The chunks are added to the tile when it is closed, after
+//! the points written to it, and its chunk table and header are updated for them.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use laz::laszip::{ChunkTable, ChunkTableEntry};
+
+use crate::{
+    compression::{self, ChunkSize},
+    postprocess::{open_reader, temp_path},
+    raw,
+    throughput::{self, Counting},
+};
+
+/// A compressed chunk of an input to copy into a tile
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// The input file holding the chunk
+    pub path: PathBuf,
+    /// The indices of the points of the chunk in the input
+    pub points: Range<u64>,
+    /// The position of the chunk in the input
+    pub offset: u64,
+    /// The size of the chunk in bytes
+    pub bytes: u64,
+}
+
+impl Chunk {
+    pub fn len(&self) -> u64 {
+        self.points.end - self.points.start
+    }
+}
+
+/// Whether the chunks of an input can be copied into tiles written in `format` with
+/// `transforms`: the points must be stored exactly as in the tiles, with the same scales and
+/// offsets and compressed in the same way
+pub fn compatible(
+    header: &las::Header,
+    transforms: &las::Vector<las::Transform>,
+    format: &las::point::Format,
+) -> Result<bool> {
+    if !header.point_format().is_compressed
+        || crate::point_format::needs_conversion(header.point_format(), format)
+        || header.transforms() != transforms
+    {
+        return Ok(false);
+    }
+    let Some(vlr) = header.vlrs().iter().find(|vlr| {
+        vlr.user_id.trim_end_matches('\0') == laz::LazVlr::USER_ID
+            && vlr.record_id == laz::LazVlr::RECORD_ID
+    }) else {
+        return Ok(false);
+    };
+    let tiles = compression::laz_vlr_data(format, ChunkSize::Variable)?;
+    // the compressor and the items with their versions, but not the chunking
+    Ok(vlr.data.len() >= 32 && vlr.data[..2] == tiles[..2] && vlr.data[32..] == tiles[32..])
+}
+
+/// Add the chunks to the LAZ tile at `path`, after the points written to it. The tile is written
+/// again with all chunks and a chunk table for them, and the counts and bounds in its header
+/// updated for the points of the chunks, which are decompressed only for that.
+pub fn assemble(path: &Path, chunks: &[Chunk]) -> Result<()> {
+    let mut tile = BufReader::new(File::open(path)?);
+
+    // the header and VLRs are kept, apart from the counts and bounds
+    let mut prefix = raw::read_prefix(&mut tile)?;
+    let point_data = prefix.len();
+    let laz_vlr = raw::laz_vlr(&prefix)?;
+    let table = ChunkTable::read_from(&mut tile, &laz_vlr).context("read chunk table")?;
+
+    let mut counts = raw::Counts::read(&prefix);
+    let mut readers = HashMap::new();
+    let mut points = Vec::new();
+    for chunk in chunks {
+        let reader = match readers.entry(&chunk.path) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(open_reader(&chunk.path)?),
+        };
+        reader.seek(chunk.points.start)?;
+        points.clear();
+        reader.read_points_into(chunk.len(), &mut points)?;
+        anyhow::ensure!(
+            points.len() as u64 == chunk.len(),
+            "chunk of {} ends early",
+            chunk.path.display()
+        );
+        for point in &points {
+            counts.add(point);
+        }
+    }
+    counts.write(&mut prefix);
+
+    let assembled = temp_path(path, "chunks");
+    let mut out = BufWriter::new(Counting::tile(
+        File::create(&assembled).with_context(|| format!("create {}", assembled.display()))?,
+    ));
+    out.write_all(&prefix)?;
+    // the offset of the chunk table, written once it is known
+    out.write_all(&0i64.to_le_bytes())?;
+
+    let mut entries: Vec<ChunkTableEntry> = table.as_ref().to_vec();
+    let written: u64 = entries.iter().map(|entry| entry.byte_count).sum();
+    tile.seek(SeekFrom::Start(point_data as u64 + 8))?;
+    let copied = std::io::copy(&mut (&mut tile).take(written), &mut out)?;
+    anyhow::ensure!(copied == written, "truncated chunks in {}", path.display());
+
+    let mut inputs = HashMap::new();
+    for chunk in chunks {
+        let input = match inputs.entry(&chunk.path) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let file = File::open(&chunk.path)
+                    .with_context(|| format!("open {}", chunk.path.display()))?;
+                entry.insert(BufReader::new(Counting::input(file)))
+            }
+        };
+        input.seek(SeekFrom::Start(chunk.offset))?;
+        let copied = std::io::copy(&mut input.take(chunk.bytes), &mut out)?;
+        anyhow::ensure!(
+            copied == chunk.bytes,
+            "truncated chunk in {}",
+            chunk.path.display()
+        );
+        throughput::encoded(chunk.len() as usize, raw::record_length(&prefix));
+        entries.push(ChunkTableEntry {
+            point_count: chunk.len(),
+            byte_count: chunk.bytes,
+        });
+    }
+
+    let table_offset = out.stream_position()?;
+    let mut table = ChunkTable::default();
+    for entry in entries {
+        table.push(entry);
+    }
+    table
+        .write_to(&mut out, &laz_vlr)
+        .context("write chunk table")?;
+    out.seek(SeekFrom::Start(point_data as u64))?;
+    out.write_all(&i64::try_from(table_offset)?.to_le_bytes())?;
+    out.flush()?;
+    drop(out);
+
+    std::fs::rename(&assembled, path).with_context(|| format!("replace {}", path.display()))
+}
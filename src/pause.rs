@@ -0,0 +1,82 @@
+//! Pausing a running job from the outside, using a control file or SIGUSR1
+
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+
+/// How often to check whether the job should be resumed
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tells whether the job should currently be paused: while the control file exists, or between
+/// two SIGUSR1 signals
+pub struct PauseControl {
+    file: Option<PathBuf>,
+    toggled: Arc<AtomicBool>,
+}
+
+impl PauseControl {
+    /// Start listening for SIGUSR1 (on unix) and watch the optional control file
+    pub fn new(file: Option<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            file,
+            toggled: toggled()?,
+        })
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.toggled.load(Ordering::Relaxed) || self.file.as_ref().is_some_and(|f| f.exists())
+    }
+
+    /// Block until the job is resumed, returns how long it was paused
+    pub fn wait(&self) -> Duration {
+        let start = std::time::Instant::now();
+        while self.is_paused() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        start.elapsed()
+    }
+}
+
+/// The flag toggled by SIGUSR1. The signal is listened for once per process, from the first
+/// control on, so that runs repeated with `--watch` share it rather than each adding a listener.
+fn toggled() -> Result<Arc<AtomicBool>> {
+    static TOGGLED: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+    let mut toggled = TOGGLED.lock().expect("not poisoned");
+    if let Some(toggled) = &*toggled {
+        return Ok(toggled.clone());
+    }
+    let flag = Arc::new(AtomicBool::new(false));
+
+    #[cfg(unix)]
+    {
+        let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1])?;
+        let flag = flag.clone();
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                flag.fetch_xor(true, Ordering::Relaxed);
+            }
+        });
+    }
+
+    Ok(toggled.insert(flag).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controls_share_one_signal_listener() {
+        let first = PauseControl::new(None).unwrap();
+        let second = PauseControl::new(None).unwrap();
+        assert!(Arc::ptr_eq(&first.toggled, &second.toggled));
+        assert!(!second.is_paused());
+    }
+}
@@ -0,0 +1,163 @@
+//! Exporting the retiling plan as an equivalent PDAL pipeline (`--export-pdal`), to audit it or
+//! to run it with PDAL instead
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+
+use crate::{
+    affine::Affine,
+    clip::Clip,
+    crs::Crs,
+    input::InputFile,
+    output::Output,
+    tile::{OutTile, TileKey},
+    tiling::Tiling,
+};
+
+/// What the tiles are written with, as far as PDAL can be told about it
+pub struct WriterOptions<'a> {
+    pub transforms: &'a las::Vector<las::Transform>,
+    pub point_format: &'a las::point::Format,
+    pub las_version: Option<las::Version>,
+    /// The CRS assigned to the inputs with `--a-srs`
+    pub crs: Option<&'a Crs>,
+    /// The transformation of the points with `--affine` or `--translate`
+    pub affine: Option<&'a Affine>,
+}
+
+/// Write a PDAL pipeline that reads all inputs, merges them, crops them to `--bounds` and the
+/// `--clip` polygons and writes one tile per crop filter. PDAL crops with closed bounds, so points
+/// exactly on the border between two tiles end up in both.
+pub fn write(
+    path: &Path,
+    headers: &[(InputFile, las::Header)],
+    tiling: &Tiling,
+    tiles: &HashMap<TileKey, OutTile>,
+    clip: Option<&Clip>,
+    output: &Output,
+    options: &WriterOptions,
+) -> Result<()> {
+    let mut stages = Vec::new();
+    let mut readers = Vec::with_capacity(headers.len());
+    for (i, (input, _)) in headers.iter().enumerate() {
+        let tag = format!("input_{i}");
+        let mut reader = json!({
+            "type": "readers.las",
+            "filename": input.to_string(),
+            "tag": tag,
+        });
+        if let Some(crs) = options.crs {
+            reader["override_srs"] = crs.definition().into();
+        }
+        stages.push(reader);
+        readers.push(tag);
+    }
+    stages.push(json!({
+        "type": "filters.merge",
+        "inputs": readers,
+        "tag": "merged",
+    }));
+    let mut source = "merged";
+    if let Some(affine) = options.affine {
+        let matrix: Vec<String> = affine.matrix().iter().map(f64::to_string).collect();
+        stages.push(json!({
+            "type": "filters.transformation",
+            "inputs": [source],
+            "matrix": matrix.join(" "),
+            "tag": "transformed",
+        }));
+        source = "transformed";
+    }
+    if let Some([x0, y0, x1, y1]) = clip.and_then(Clip::bounds) {
+        stages.push(json!({
+            "type": "filters.crop",
+            "inputs": [source],
+            "bounds": format!("([{x0}, {x1}], [{y0}, {y1}])"),
+            "tag": "bounded",
+        }));
+        source = "bounded";
+    }
+    if let Some(wkt) = clip.and_then(Clip::wkt) {
+        stages.push(json!({
+            "type": "filters.crop",
+            "inputs": [source],
+            "polygon": wkt,
+            "tag": "clipped",
+        }));
+        source = "clipped";
+    }
+
+    let mut keys: Vec<_> = tiles.keys().copied().collect();
+    keys.sort();
+    for (i, key) in keys.into_iter().enumerate() {
+        let tile = &tiles[&key];
+        let tag = format!("tile_{i}");
+        let mut crop = json!({
+            "type": "filters.crop",
+            "inputs": [source],
+            "tag": tag,
+        });
+        match tiling.wkt(key) {
+            Some(wkt) => crop["polygon"] = wkt.into(),
+            None => crop["bounds"] = crop_bounds(tile.bounds, tiling.z_bounds(key)).into(),
+        }
+        stages.push(crop);
+        stages.push(writer_stage(&tag, tile, output, options)?);
+    }
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create PDAL pipeline: {}", path.display()))?;
+    serde_json::to_writer_pretty(
+        std::io::BufWriter::new(file),
+        &json!({ "pipeline": stages }),
+    )
+    .with_context(|| format!("write PDAL pipeline: {}", path.display()))?;
+    tracing::info!(
+        "Wrote PDAL pipeline for {} tiles to {}",
+        tiles.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// PDAL bounds, `([min x, max x], [min y, max y])` with an optional Z range
+fn crop_bounds([x0, y0, x1, y1]: [f64; 4], z: Option<[f64; 2]>) -> String {
+    match z {
+        Some([z0, z1]) => format!("([{x0}, {x1}], [{y0}, {y1}], [{z0}, {z1}])"),
+        None => format!("([{x0}, {x1}], [{y0}, {y1}])"),
+    }
+}
+
+fn writer_stage(
+    input: &str,
+    tile: &OutTile,
+    output: &Output,
+    options: &WriterOptions,
+) -> Result<Value> {
+    let transforms = options.transforms;
+    let mut stage = json!({
+        "type": "writers.las",
+        "inputs": [input],
+        "filename": output.location(&tile.name),
+        "forward": "all",
+        "dataformat_id": options.point_format.to_u8()?,
+        "scale_x": transforms.x.scale,
+        "scale_y": transforms.y.scale,
+        "scale_z": transforms.z.scale,
+        "offset_x": transforms.x.offset,
+        "offset_y": transforms.y.offset,
+        "offset_z": transforms.z.offset,
+    });
+    if tile.name.ends_with(".laz") {
+        stage["compression"] = "laszip".into();
+    }
+    if let Some(version) = options.las_version {
+        stage["minor_version"] = version.minor.into();
+    }
+    if let Some(id) = tile.file_source_id {
+        stage["filesource_id"] = id.into();
+    }
+    Ok(stage)
+}
@@ -0,0 +1,215 @@
+//! The point format shared by all output tiles, and converting points from other formats to it
+
+use anyhow::{Context, Result};
+
+use crate::input::InputFile;
+
+/// User ID of the VLRs of the LAS specification
+const SPEC_USER_ID: &str = "LASF_Spec";
+
+/// Whether the (E)VLR describes or holds waveform packets: the wave packet descriptors (record
+/// IDs 100 to 354) and the packets themselves (65535)
+fn is_waveform_vlr(vlr: &las::Vlr) -> bool {
+    vlr.user_id.trim_end_matches('\0') == SPEC_USER_ID && matches!(vlr.record_id, 100..=354 | 65535)
+}
+
+/// Choose the point format of the output tiles: the `requested` format number, the format of
+/// the inputs if they all agree, or else the smallest format that has every attribute of any
+/// input so that nothing is lost. The extra bytes are kept, they are the same for all inputs.
+///
+/// The waveform packets of the inputs are not copied to the tiles, so the points of inputs with
+/// waveforms can only be written without them, with `strip_waveforms`.
+pub fn common_format(
+    headers: &[(InputFile, las::Header)],
+    requested: Option<u8>,
+    strip_waveforms: bool,
+) -> Result<las::point::Format> {
+    let mut format = choose_format(headers, requested)?;
+    if strip_waveforms {
+        if format.has_waveform {
+            format.has_waveform = false;
+            tracing::info!("Writing all tiles with {format}, without waveforms");
+        }
+    } else if format.has_waveform
+        && let Some((path, header)) = headers
+            .iter()
+            .find(|(_, header)| header.point_format().has_waveform)
+    {
+        anyhow::bail!(
+            "{path} has point format {} with waveforms, whose packets can not be copied to the \
+             tiles. Use --strip-waveforms to write the points without them.",
+            header.point_format()
+        );
+    }
+    Ok(format)
+}
+
+fn choose_format(
+    headers: &[(InputFile, las::Header)],
+    requested: Option<u8>,
+) -> Result<las::point::Format> {
+    let (_, first) = headers.first().context("at least one input file")?;
+    let mut first_format = *first.point_format();
+    first_format.is_compressed = false;
+    let formats: Vec<_> = headers
+        .iter()
+        .map(|(_, header)| {
+            let mut format = *header.point_format();
+            format.is_compressed = false;
+            format
+        })
+        .collect();
+
+    let mut format = match requested {
+        Some(n) => las::point::Format::new(n).with_context(|| format!("point format {n}"))?,
+        None if formats.iter().all(|f| *f == first_format) => return Ok(first_format),
+        None => {
+            let any = |attribute: fn(&las::point::Format) -> bool| formats.iter().any(attribute);
+            let richest = (0..=10)
+                .filter_map(|n| las::point::Format::new(n).ok())
+                .find(|f| {
+                    (f.has_gps_time || !any(|f| f.has_gps_time))
+                        && (f.has_color || !any(|f| f.has_color))
+                        && (f.has_nir || !any(|f| f.has_nir))
+                        && (f.has_waveform || !any(|f| f.has_waveform))
+                        && (f.is_extended || !any(|f| f.is_extended))
+                })
+                .expect("format 10 has all attributes");
+            tracing::info!(
+                "Inputs have different point formats, writing all tiles with {}",
+                richest
+            );
+            richest
+        }
+    };
+    format.extra_bytes = first_format.extra_bytes;
+    Ok(format)
+}
+
+/// Use `format` for the points of a tile header with the given LAS version, or else the version
+/// of the header, moved to a newer one if the format requires it. Without waveforms, the VLRs
+/// describing them are removed.
+pub fn apply(
+    builder: &mut las::Builder,
+    format: &las::point::Format,
+    version: Option<las::Version>,
+) {
+    builder.point_format = *format;
+    builder.version = version.unwrap_or(builder.version.max(required_version(format)));
+    if !format.has_waveform {
+        builder.vlrs.retain(|vlr| !is_waveform_vlr(vlr));
+        builder.evlrs.retain(|vlr| !is_waveform_vlr(vlr));
+    }
+    if builder.version < las::Version::new(1, 4) {
+        // only LAS 1.4 has extended VLRs, the others have to fit into regular ones
+        let evlrs = std::mem::take(&mut builder.evlrs);
+        builder.vlrs.extend(evlrs);
+    }
+}
+
+/// The oldest LAS version that supports the point format
+pub fn required_version(format: &las::point::Format) -> las::Version {
+    match format.to_u8() {
+        Ok(0 | 1) | Err(_) => las::Version::new(1, 0),
+        Ok(2 | 3) => las::Version::new(1, 2),
+        Ok(4 | 5) => las::Version::new(1, 3),
+        Ok(_) => las::Version::new(1, 4),
+    }
+}
+
+/// Whether a file written with the header may get more points than its LAS version can count:
+/// the versions before 1.4 count the points in 32 bits
+pub fn needs_extended_counts(header: &las::Header, points: u64) -> bool {
+    header.version() < las::Version::new(1, 4) && points > u64::from(u32::MAX)
+}
+
+/// The header moved to LAS 1.4, which counts the points in 64 bits
+pub fn extended_counts(header: &las::Header) -> Result<las::Header> {
+    let mut builder = las::Builder::from(header.clone());
+    builder.version = las::Version::new(1, 4);
+    Ok(builder.into_header()?)
+}
+
+/// The header for a file with up to `points` points: moved to LAS 1.4 if its version can not
+/// count them, otherwise unchanged
+pub fn for_points(header: &las::Header, points: u64, name: &str) -> Result<las::Header> {
+    if !needs_extended_counts(header, points) {
+        return Ok(header.clone());
+    }
+    tracing::info!(
+        "Writing {name} as LAS 1.4 instead of {}, it may get more than {} points",
+        header.version(),
+        u32::MAX
+    );
+    extended_counts(header)
+}
+
+/// Check that tiles with the point format can be written as the given LAS version
+pub fn check_version(
+    headers: &[(InputFile, las::Header)],
+    format: &las::point::Format,
+    version: las::Version,
+) -> Result<()> {
+    anyhow::ensure!(
+        required_version(format) <= version,
+        "{format} requires LAS {} or newer, choose an older --point-format for LAS {version}",
+        required_version(format)
+    );
+    if version < las::Version::new(1, 4)
+        && let Some((path, _)) = headers.iter().find(|(_, h)| h.has_wkt_crs())
+    {
+        anyhow::bail!("{path} has a WKT CRS, which only LAS 1.4 can declare");
+    }
+    Ok(())
+}
+
+/// Check that a tile getting at most `points` points can count them in the header of `version`
+pub fn check_tile_points(name: &str, points: u64, version: las::Version) -> Result<()> {
+    anyhow::ensure!(
+        version >= las::Version::new(1, 4) || points <= u64::from(u32::MAX),
+        "Tile {name} may get {points} points, more than LAS {version} can count ({}), use --las-version 1.4",
+        u32::MAX
+    );
+    Ok(())
+}
+
+/// Whether points of the given format need to be converted to be written with `target`
+pub fn needs_conversion(format: &las::point::Format, target: &las::point::Format) -> bool {
+    let mut format = *format;
+    format.is_compressed = target.is_compressed;
+    format != *target
+}
+
+/// Add the attributes of `format` that the point lacks with default values, and drop the ones
+/// it does not have
+pub fn convert(point: &mut las::Point, format: &las::point::Format) {
+    point.gps_time = format.has_gps_time.then(|| point.gps_time.unwrap_or(0.0));
+    point.color = format.has_color.then(|| point.color.unwrap_or_default());
+    point.nir = format.has_nir.then(|| point.nir.unwrap_or(0));
+    point.waveform = format
+        .has_waveform
+        .then(|| point.waveform.unwrap_or_default());
+    if !format.is_extended {
+        // only the extended formats have scanner channels
+        point.scanner_channel = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_tiles_that_may_overflow_the_legacy_point_count() {
+        let too_many = u64::from(u32::MAX) + 1;
+        let err = check_tile_points("tile_1_2.laz", too_many, las::Version::new(1, 2))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("tile_1_2.laz"), "{err}");
+        assert!(err.contains("--las-version 1.4"), "{err}");
+        assert!(check_tile_points("tile_1_2.laz", too_many, las::Version::new(1, 3)).is_err());
+
+        check_tile_points("tile_1_2.laz", u64::from(u32::MAX), las::Version::new(1, 2)).unwrap();
+        check_tile_points("tile_1_2.laz", too_many, las::Version::new(1, 4)).unwrap();
+    }
+}
@@ -0,0 +1,47 @@
+//! Buffers for the batches of points routed on the main thread to the tile writer threads. Instead
+//! of allocating a new `Vec` for every batch and freeing it once written, the writer threads hand
+//! the emptied buffers back, so that long runs reuse the same few allocations.
+
+use std::sync::Mutex;
+
+/// Emptied buffers waiting to be filled again
+pub struct BufferPool<T> {
+    free: Mutex<Vec<Vec<T>>>,
+    /// Buffers handed back beyond this are freed, so that the pool does not keep the peak
+    /// number of buffers in flight for the rest of the run
+    max_free: usize,
+}
+
+impl<T> BufferPool<T> {
+    pub fn new(max_free: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::with_capacity(max_free)),
+            max_free,
+        }
+    }
+
+    /// An empty buffer with room for at least `capacity` items, reusing one that was handed
+    /// back if possible
+    pub fn take(&self, capacity: usize) -> Vec<T> {
+        let buffer = self.free.lock().expect("buffer pool poisoned").pop();
+        match buffer {
+            Some(mut buffer) => {
+                buffer.reserve(capacity);
+                buffer
+            }
+            None => Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Hand back a buffer once its contents have been consumed
+    pub fn give(&self, mut buffer: Vec<T>) {
+        if buffer.capacity() == 0 {
+            return;
+        }
+        buffer.clear();
+        let mut free = self.free.lock().expect("buffer pool poisoned");
+        if free.len() < self.max_free {
+            free.push(buffer);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Helpers shared by the steps that rewrite a tile after its writer has been closed
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::compression;
+
+/// The suffixes given to [`temp_path`] by the steps rewriting a tile, without any number
+const TEMP_SUFFIXES: [&str; 6] = ["part", "merged", "merge", "sorted", "overlap", "dedupe"];
+
+/// The path of a temporary file next to `path`, keeping the extension so that the writer picks
+/// the same (compressed or uncompressed) format.
+pub fn temp_path(path: &Path, suffix: &str) -> PathBuf {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("laz");
+    path.with_extension(format!("{suffix}.{ext}"))
+}
+
+/// Whether a file name is one of a temporary file made by [`temp_path`], e.g. `tile_3_4.part1.laz`
+pub fn is_temp_name(name: &str) -> bool {
+    let Some((stem, _)) = name.rsplit_once('.') else {
+        return false;
+    };
+    let Some((_, suffix)) = stem.rsplit_once('.') else {
+        return false;
+    };
+    TEMP_SUFFIXES.contains(&suffix.trim_end_matches(|c: char| c.is_ascii_digit()))
+}
+
+/// Open a written tile for reading
+pub fn open_reader(path: &Path) -> Result<las::Reader> {
+    let options = las::ReaderOptions::default().with_laz_parallelism(las::LazParallelism::No);
+    las::Reader::with_options(
+        std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?,
+        options,
+    )
+    .with_context(|| format!("read {}", path.display()))
+}
+
+/// Create a writer for a rewritten version of a tile with the given (original) header
+pub fn create_writer(
+    path: &Path,
+    header: &las::Header,
+) -> Result<las::Writer<std::io::BufWriter<std::fs::File>>> {
+    let compressed = path.extension().is_some_and(|ext| ext == "laz");
+    las::Writer::from_path(path, new_header(header, compressed)?)
+        .with_context(|| format!("create {}", path.display()))
+}
+
+/// An empty header for writing points with the given header, compressed or not
+pub fn new_header(header: &las::Header, compressed: bool) -> Result<las::Header> {
+    let mut builder = las::Builder::from(header.clone());
+    builder.point_format.is_compressed = compressed;
+    compression::apply(&mut builder)?;
+    let mut new_header = builder.into_header()?;
+    new_header.clear();
+    Ok(new_header)
+}
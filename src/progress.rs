@@ -0,0 +1,249 @@
+//! Progress reporting of a retiling run, as a progress bar or as JSON lines for other programs
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    cli::ProgressMode,
+    input::InputFile,
+    progress_bar,
+    schema::{Document, Versioned},
+    throughput,
+};
+
+/// Minimum time between two `progress` events
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A progress event of `--progress json`, written as a single JSON line
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Reading an input file started
+    FileStarted {
+        file: &'a InputFile,
+        /// Position of the file among the inputs, from 0
+        index: usize,
+        files: usize,
+    },
+    /// The position of the run, at most once per second and once more before `finished`
+    Progress {
+        points_processed: u64,
+        total_points: u64,
+        percent: f64,
+        bytes_read: u64,
+        bytes_written: u64,
+        /// Uncompressed point size per byte written to the tiles, if any were written
+        compression_ratio: Option<f64>,
+        open_tiles: u64,
+    },
+    /// A tile was finished
+    TileClosed { tile: &'a str },
+    /// The run paused because of `--pause-file` or SIGUSR1
+    Paused,
+    /// The run continued after `paused`
+    Resumed,
+    /// The run stopped early
+    Stopped {
+        reason: &'a str,
+        points_processed: u64,
+    },
+    /// The run completed
+    Finished { points_processed: u64 },
+}
+
+impl Document for Event<'_> {
+    const SCHEMA_VERSION: &'static str = "1.0";
+}
+
+pub enum Progress {
+    Bar(indicatif::ProgressBar),
+    Json {
+        out: Box<dyn Write>,
+        total_points: u64,
+        position: u64,
+        last_event: Option<Instant>,
+    },
+}
+
+impl Progress {
+    /// Report progress in the given mode. JSON events are written to stdout, or to the file
+    /// descriptor `fd` if given (unix only).
+    pub fn new(mode: ProgressMode, fd: Option<i32>, total_points: u64) -> Result<Self> {
+        Ok(match mode {
+            ProgressMode::Bar => {
+                let pb = progress_bar(total_points);
+                pb.set_style(bar_style());
+                Progress::Bar(pb)
+            }
+            ProgressMode::Json => {
+                let out: Box<dyn Write> = match fd {
+                    Some(fd) => Box::new(
+                        std::fs::OpenOptions::new()
+                            .append(true)
+                            .open(format!("/dev/fd/{fd}"))
+                            .with_context(|| format!("open file descriptor {fd} for progress"))?,
+                    ),
+                    None => Box::new(std::io::stdout()),
+                };
+                Progress::Json {
+                    out,
+                    total_points,
+                    position: 0,
+                    last_event: None,
+                }
+            }
+        })
+    }
+
+    fn emit(out: &mut dyn Write, event: &Event) {
+        // progress is best effort, a closed pipe must not abort the run
+        if let Ok(line) = serde_json::to_string(&Versioned::new(event)) {
+            let _ = writeln!(out, "{line}").and_then(|_| out.flush());
+        }
+    }
+
+    pub fn file_started(&mut self, file: &InputFile, index: usize, files: usize) {
+        match self {
+            Progress::Bar(pb) => {
+                pb.set_message(format!("{}/{} {}", index + 1, files, file.file_name()))
+            }
+            Progress::Json { out, .. } => {
+                Self::emit(out.as_mut(), &Event::FileStarted { file, index, files })
+            }
+        }
+    }
+
+    pub fn set_position(&mut self, points_processed: u64) {
+        match self {
+            Progress::Bar(pb) => pb.set_position(points_processed),
+            Progress::Json {
+                out,
+                total_points,
+                position,
+                last_event,
+            } => {
+                *position = points_processed;
+                if last_event.is_some_and(|last| last.elapsed() < EVENT_INTERVAL) {
+                    return;
+                }
+                *last_event = Some(Instant::now());
+                Self::emit(
+                    out.as_mut(),
+                    &progress_event(points_processed, *total_points),
+                );
+            }
+        }
+    }
+
+    pub fn tile_closed(&mut self, tile: &str) {
+        if let Progress::Json { out, .. } = self {
+            Self::emit(out.as_mut(), &Event::TileClosed { tile });
+        }
+    }
+
+    /// Run `wait` while showing that the run is paused
+    pub fn paused<T>(&mut self, wait: impl FnOnce() -> T) -> T {
+        match self {
+            Progress::Bar(pb) => {
+                let message = pb.message();
+                pb.set_message("paused");
+                let result = wait();
+                pb.set_message(message);
+                result
+            }
+            Progress::Json { out, .. } => {
+                Self::emit(out.as_mut(), &Event::Paused);
+                let result = wait();
+                Self::emit(out.as_mut(), &Event::Resumed);
+                result
+            }
+        }
+    }
+
+    pub fn stopped(&mut self, reason: &str) {
+        match self {
+            Progress::Bar(pb) => pb.abandon_with_message(format!("Stopped ({reason})")),
+            Progress::Json { out, position, .. } => Self::emit(
+                out.as_mut(),
+                &Event::Stopped {
+                    reason,
+                    points_processed: *position,
+                },
+            ),
+        }
+    }
+
+    pub fn finished(&mut self) {
+        match self {
+            Progress::Bar(pb) => pb.finish_with_message("Done"),
+            Progress::Json {
+                out,
+                total_points,
+                position,
+                ..
+            } => {
+                // always report the final position, even if it was throttled
+                Self::emit(out.as_mut(), &progress_event(*position, *total_points));
+                Self::emit(
+                    out.as_mut(),
+                    &Event::Finished {
+                        points_processed: *position,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// The progress bar of [`progress_bar`] with the throughput below it
+fn bar_style() -> indicatif::ProgressStyle {
+    indicatif::ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{msg}] [{wide_bar:.cyan/blue}] {human_pos}/{human_len} ({percent}%) ({eta})\n  {per_sec}, {io}")
+        .unwrap()
+        .with_key("eta", |state: &indicatif::ProgressState, w: &mut dyn std::fmt::Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
+        .with_key("io", |state: &indicatif::ProgressState, w: &mut dyn std::fmt::Write| write!(w, "{}", throughput::snapshot().line(state.elapsed())).unwrap())
+        .progress_chars("#>-")
+}
+
+fn progress_event(points_processed: u64, total_points: u64) -> Event<'static> {
+    let io = throughput::snapshot();
+    Event::Progress {
+        points_processed,
+        total_points,
+        percent: percent(points_processed, total_points),
+        bytes_read: io.read_bytes,
+        bytes_written: io.written_bytes,
+        compression_ratio: io.compression_ratio,
+        open_tiles: io.open_writers,
+    }
+}
+
+fn percent(points: u64, total: u64) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        points as f64 / total as f64 * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_carry_the_schema_version() {
+        let line = serde_json::to_string(&Versioned::new(&Event::TileClosed {
+            tile: "tile_3_4.laz",
+        }))
+        .unwrap();
+        assert_eq!(
+            line,
+            r#"{"schema_version":"1.0","event":"tile_closed","tile":"tile_3_4.laz"}"#
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Renumbering point source IDs into a compact range (`--renumber-psid`), for merged deliveries
+//! whose IDs collide
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    input::InputFile,
+    output::Output,
+    schema::{Document, Versioned},
+};
+
+/// Name of the mapping file written to the output folder
+pub const PSID_MAPPING_FILE_NAME: &str = "psid_mapping.json";
+
+/// The new point source ID of each pair of input file and original point source ID
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct PsidMapping {
+    /// Ordered by new point source ID, which are assigned from 1 in the order the pairs are read
+    pub mappings: Vec<PsidEntry>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PsidEntry {
+    pub input: InputFile,
+    /// The point source ID in the input file
+    pub original: u16,
+    /// The point source ID in the output tiles
+    pub psid: u16,
+}
+
+impl Document for PsidMapping {
+    const SCHEMA_VERSION: &'static str = "1.0";
+}
+
+#[derive(Default)]
+pub struct Renumbering {
+    ids: HashMap<(usize, u16), u16>,
+    mapping: PsidMapping,
+}
+
+impl Renumbering {
+    /// Replace the point source IDs of points read from the input with index `i_file`
+    pub fn apply(
+        &mut self,
+        i_file: usize,
+        path: &InputFile,
+        points: &mut [las::Point],
+    ) -> Result<()> {
+        for point in points {
+            let original = point.point_source_id;
+            point.point_source_id = match self.ids.get(&(i_file, original)) {
+                Some(&psid) => psid,
+                None => {
+                    let psid = u16::try_from(self.mapping.mappings.len() + 1)
+                        .ok()
+                        .context("more than 65535 distinct point source IDs to renumber")?;
+                    self.ids.insert((i_file, original), psid);
+                    self.mapping.mappings.push(PsidEntry {
+                        input: path.clone(),
+                        original,
+                        psid,
+                    });
+                    psid
+                }
+            };
+        }
+        Ok(())
+    }
+
+    /// Write the mapping to the output folder
+    pub fn write(&self, output: &Output) -> Result<()> {
+        let path = output.path(PSID_MAPPING_FILE_NAME);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("create point source ID mapping: {}", path.display()))?;
+        serde_json::to_writer_pretty(
+            std::io::BufWriter::new(file),
+            &Versioned::new(&self.mapping),
+        )
+        .with_context(|| format!("write point source ID mapping: {}", path.display()))?;
+        output.publish(PSID_MAPPING_FILE_NAME)
+    }
+}
@@ -0,0 +1,140 @@
+//! Adaptive subdivision of dense tiles into quadtree leaves
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    postprocess::{create_writer, open_reader},
+    stats::TileStats,
+};
+
+/// Number of points read at a time when subdividing a tile
+const BUFFER_SIZE: u64 = 64 * 1024;
+
+/// Maximum number of times a tile is subdivided, stops runaway recursion when many points share
+/// the same location
+const MAX_DEPTH: usize = 16;
+
+/// A tile or quadrant of a tile that is not subdivided any further
+pub struct Leaf {
+    /// The quadrants taken from the tile to reach this leaf (`0` = south-west, `1` = south-east,
+    /// `2` = north-west, `3` = north-east), empty for the whole tile
+    pub path: String,
+
+    /// `[min x, min y, max x, max y]` of the area covered by this leaf
+    pub bounds: [f64; 4],
+
+    /// Statistics of the points in this leaf, if requested
+    pub stats: Option<TileStats>,
+}
+
+/// The file name of a quadtree leaf of the tile with the given name, e.g. `tile_3_4_02.laz`
+pub fn file_name(tile: &str, path: &str) -> String {
+    if path.is_empty() {
+        return tile.to_owned();
+    }
+    match tile.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}_{path}.{extension}"),
+        None => format!("{tile}_{path}"),
+    }
+}
+
+/// Whether `name` is the file name of a quadtree leaf of the tile with the given name, including
+/// the whole tile
+pub fn is_leaf_of(tile: &str, name: &str) -> bool {
+    if name == tile {
+        return true;
+    }
+    let (stem, extension) = tile.rsplit_once('.').unwrap_or((tile, ""));
+    name.strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('_'))
+        .and_then(|rest| rest.strip_suffix(extension))
+        .and_then(|rest| match extension {
+            "" => Some(rest),
+            _ => rest.strip_suffix('.'),
+        })
+        .is_some_and(|path| !path.is_empty() && path.bytes().all(|b| (b'0'..=b'3').contains(&b)))
+}
+
+/// Recursively split the (already written) tile into four quadrants until each of them holds
+/// fewer than `max_points` points. Empty quadrants are not written. Returns the leaves, ordered
+/// by their path.
+pub fn subdivide(dir: &Path, name: &str, tile: Leaf, max_points: u64) -> Result<Vec<Leaf>> {
+    let mut leaves = Vec::new();
+    let mut pending = vec![tile];
+    while let Some(cell) = pending.pop() {
+        let path = dir.join(file_name(name, &cell.path));
+        let points = open_reader(&path)?.header().number_of_points();
+        if points < max_points || cell.path.len() >= MAX_DEPTH {
+            leaves.push(cell);
+            continue;
+        }
+
+        pending.extend(split(dir, name, &cell)?);
+        std::fs::remove_file(&path).with_context(|| format!("remove {}", path.display()))?;
+    }
+    leaves.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(leaves)
+}
+
+/// Write the points of a cell to (up to) four new files, one per non-empty quadrant
+fn split(dir: &Path, name: &str, cell: &Leaf) -> Result<Vec<Leaf>> {
+    let [x0, y0, x1, y1] = cell.bounds;
+    let (mid_x, mid_y) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+
+    let mut reader = open_reader(&dir.join(file_name(name, &cell.path)))?;
+    let header = reader.header().clone();
+
+    let mut quadrants: [Option<_>; 4] = Default::default();
+    let mut points = Vec::new();
+    loop {
+        points.clear();
+        if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+            break;
+        }
+        for point in points.drain(..) {
+            let quadrant = usize::from(point.x >= mid_x) + 2 * usize::from(point.y >= mid_y);
+            let (writer, stats) = match &mut quadrants[quadrant] {
+                Some(open) => open,
+                None => {
+                    let path = dir.join(file_name(name, &format!("{}{quadrant}", cell.path)));
+                    let stats = cell.stats.as_ref().map(|stats| {
+                        let mut stats = stats.clone();
+                        stats.clear();
+                        stats
+                    });
+                    quadrants[quadrant].insert((create_writer(&path, &header)?, stats))
+                }
+            };
+            if let Some(stats) = stats {
+                stats.add(&point);
+            }
+            writer.write_point(point)?;
+        }
+    }
+
+    let mut children = Vec::new();
+    for (quadrant, open) in quadrants.into_iter().enumerate() {
+        let Some((mut writer, stats)) = open else {
+            continue;
+        };
+        writer.close()?;
+        let (min_x, max_x) = if quadrant & 1 == 0 {
+            (x0, mid_x)
+        } else {
+            (mid_x, x1)
+        };
+        let (min_y, max_y) = if quadrant & 2 == 0 {
+            (y0, mid_y)
+        } else {
+            (mid_y, y1)
+        };
+        children.push(Leaf {
+            path: format!("{}{quadrant}", cell.path),
+            bounds: [min_x, min_y, max_x, max_y],
+            stats,
+        });
+    }
+    Ok(children)
+}
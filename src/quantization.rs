@@ -0,0 +1,125 @@
+//! The scale factors and offsets shared by all output tiles, so that points from inputs with a
+//! different quantization end up on the same grid
+
+use anyhow::Result;
+
+use crate::input::InputFile;
+
+/// Choose the transforms for the output tiles. Explicit `scale` and `offset` values (one value
+/// for all axes or one per axis) take precedence. Otherwise the inputs' transforms are kept if
+/// they all agree, and if not the finest scale of any input is used together with an offset
+/// near the minimum of `bounds`, so that no precision is lost.
+pub fn common_transforms(
+    headers: &[(InputFile, las::Header)],
+    bounds: &las::Bounds,
+    scale: Option<&[f64]>,
+    offset: Option<&[f64]>,
+) -> Result<las::Vector<las::Transform>> {
+    let scale = scale
+        .map(|values| per_axis(values, "--scale"))
+        .transpose()?;
+    let offset = offset
+        .map(|values| per_axis(values, "--offset"))
+        .transpose()?;
+    let min = [bounds.min.x, bounds.min.y, bounds.min.z];
+    let max = [bounds.max.x, bounds.max.y, bounds.max.z];
+
+    let inputs: Vec<[las::Transform; 3]> = headers
+        .iter()
+        .map(|(_, header)| {
+            let transforms = header.transforms();
+            [transforms.x, transforms.y, transforms.z]
+        })
+        .collect();
+    let mut transforms = inputs[0];
+    for axis in 0..3 {
+        let same_scale = inputs
+            .iter()
+            .all(|t| t[axis].scale == transforms[axis].scale);
+        let same_offset = inputs
+            .iter()
+            .all(|t| t[axis].offset == transforms[axis].offset);
+        transforms[axis].scale = match scale {
+            Some(scale) => scale[axis],
+            None if same_scale => transforms[axis].scale,
+            None => inputs
+                .iter()
+                .map(|t| t[axis].scale.abs())
+                .fold(f64::INFINITY, f64::min),
+        };
+        let scale = transforms[axis].scale;
+        anyhow::ensure!(
+            scale.is_finite() && scale > 0.0,
+            "scale factors must be positive"
+        );
+        transforms[axis].offset = match offset {
+            Some(offset) => offset[axis],
+            // a shared offset only keeps the points on their grid if the scale is kept as well
+            None if same_offset && same_scale => transforms[axis].offset,
+            None => (min[axis] / scale).floor() * scale,
+        };
+
+        // the quantized coordinates are stored as 32 bit integers
+        let offset = transforms[axis].offset;
+        let range = (min[axis] - offset) / scale..=(max[axis] - offset) / scale;
+        anyhow::ensure!(
+            *range.start() >= i32::MIN as f64 && *range.end() <= i32::MAX as f64,
+            "the {} coordinates from {} to {} do not fit with scale {scale} and offset {offset}, \
+             choose a different --scale or --offset",
+            ["x", "y", "z"][axis],
+            min[axis],
+            max[axis]
+        );
+    }
+
+    // re-quantizing is only lossless if the grid of an input is part of the output grid
+    for (axis, name) in ["x", "y", "z"].into_iter().enumerate() {
+        let output = transforms[axis];
+        let coarser = inputs
+            .iter()
+            .filter(|t| t[axis].scale < output.scale)
+            .count();
+        let off_grid = inputs
+            .iter()
+            .filter(|t| t[axis].scale >= output.scale && !on_grid(t[axis], output))
+            .count();
+        if coarser > 0 {
+            tracing::warn!(
+                "The {name} scale {} is coarser than the one of {coarser} inputs, their {name} coordinates lose precision",
+                output.scale
+            );
+        }
+        if off_grid > 0 {
+            tracing::warn!(
+                "The {name} coordinates of {off_grid} inputs are not on the grid of scale {} and offset {}, they are rounded to it",
+                output.scale,
+                output.offset
+            );
+        }
+    }
+
+    if inputs.iter().any(|t| *t != transforms) {
+        tracing::info!(
+            "Inputs have different scales or offsets, writing all tiles with scale {:?} and offset {:?}",
+            transforms.map(|t| t.scale),
+            transforms.map(|t| t.offset)
+        );
+    }
+    let [x, y, z] = transforms;
+    Ok(las::Vector { x, y, z })
+}
+
+/// Whether every value quantized with `input` can be stored exactly with `output`
+fn on_grid(input: las::Transform, output: las::Transform) -> bool {
+    let is_integer = |v: f64| (v - v.round()).abs() < 1e-6;
+    is_integer(input.scale / output.scale)
+        && is_integer((input.offset - output.offset) / output.scale)
+}
+
+fn per_axis(values: &[f64], name: &str) -> Result<[f64; 3]> {
+    match *values {
+        [value] => Ok([value; 3]),
+        [x, y, z] => Ok([x, y, z]),
+        _ => anyhow::bail!("{name} takes either one value or one for each of x, y and z"),
+    }
+}
@@ -0,0 +1,313 @@
+//! The files the inputs are read from and the tiles are written to. With the `async-io` feature
+//! and `--async-io`, local files are accessed through positioned reads and writes that are kept
+//! in flight on the blocking pool of the tokio runtime, so that network file systems and NVMe
+//! arrays with deep queues stay busy instead of waiting on one synchronous syscall at a time.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+#[cfg(all(feature = "async-io", unix))]
+use std::{collections::VecDeque, os::unix::fs::FileExt, sync::Arc, sync::OnceLock};
+
+/// Size of each positioned read or write
+#[cfg(all(feature = "async-io", unix))]
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Number of reads or writes kept in flight per file, set by `--async-io`
+#[cfg(all(feature = "async-io", unix))]
+static DEPTH: OnceLock<usize> = OnceLock::new();
+
+/// Access all files opened from now on through this many positioned reads or writes in flight
+#[cfg(all(feature = "async-io", unix))]
+pub fn configure(depth: usize) {
+    // set once at startup, later calls keep the first value
+    let _ = DEPTH.set(depth.max(1));
+}
+
+/// A local file opened for reading or writing
+#[derive(Debug)]
+pub enum QueuedFile {
+    Sync(File),
+    #[cfg(all(feature = "async-io", unix))]
+    ReadAhead(ReadAhead),
+    #[cfg(all(feature = "async-io", unix))]
+    WriteBehind(WriteBehind),
+}
+
+impl QueuedFile {
+    /// Open a file for reading
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        #[cfg(all(feature = "async-io", unix))]
+        if let Some(&depth) = DEPTH.get() {
+            return Ok(QueuedFile::ReadAhead(ReadAhead::new(file, depth)?));
+        }
+        Ok(QueuedFile::Sync(file))
+    }
+
+    /// Create a file for writing, truncating it if it exists
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        #[cfg(all(feature = "async-io", unix))]
+        if let Some(&depth) = DEPTH.get() {
+            return Ok(QueuedFile::WriteBehind(WriteBehind::new(file, depth)));
+        }
+        Ok(QueuedFile::Sync(file))
+    }
+}
+
+impl Read for QueuedFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            QueuedFile::Sync(file) => file.read(buf),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::ReadAhead(file) => file.read(buf),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::WriteBehind(_) => Err(std::io::Error::other("file is open for writing")),
+        }
+    }
+}
+
+impl Write for QueuedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            QueuedFile::Sync(file) => file.write(buf),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::WriteBehind(file) => file.write(buf),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::ReadAhead(_) => Err(std::io::Error::other("file is open for reading")),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            QueuedFile::Sync(file) => file.flush(),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::WriteBehind(file) => file.flush(),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::ReadAhead(_) => Ok(()),
+        }
+    }
+}
+
+impl Seek for QueuedFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            QueuedFile::Sync(file) => file.seek(pos),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::ReadAhead(file) => file.seek(pos),
+            #[cfg(all(feature = "async-io", unix))]
+            QueuedFile::WriteBehind(file) => file.seek(pos),
+        }
+    }
+}
+
+/// The new position of a seek from `position` in a file of `size` bytes
+#[cfg(all(feature = "async-io", unix))]
+fn seek_position(pos: SeekFrom, position: u64, size: u64) -> std::io::Result<u64> {
+    match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::End(offset) => size.checked_add_signed(offset),
+        SeekFrom::Current(offset) => position.checked_add_signed(offset),
+    }
+    .ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+/// Wait for a read or write running on the blocking pool
+#[cfg(all(feature = "async-io", unix))]
+fn wait<T>(task: tokio::task::JoinHandle<std::io::Result<T>>) -> std::io::Result<T> {
+    crate::remote::runtime()
+        .block_on(task)
+        .map_err(std::io::Error::other)?
+}
+
+/// Reads a file sequentially while the reads of the next blocks are already in flight
+#[cfg(all(feature = "async-io", unix))]
+#[derive(Debug)]
+pub struct ReadAhead {
+    file: Arc<File>,
+    size: u64,
+    depth: usize,
+    position: u64,
+    /// The block the current position is in
+    buffer: Vec<u8>,
+    buffer_start: u64,
+    /// The blocks being read, in order
+    pending: VecDeque<(u64, tokio::task::JoinHandle<std::io::Result<Vec<u8>>>)>,
+    /// Where the next block to read starts
+    next: u64,
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl ReadAhead {
+    fn new(file: File, depth: usize) -> std::io::Result<Self> {
+        Ok(Self {
+            size: file.metadata()?.len(),
+            file: Arc::new(file),
+            depth,
+            position: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            pending: VecDeque::new(),
+            next: 0,
+        })
+    }
+
+    /// Start reading blocks until `depth` reads are in flight
+    fn submit(&mut self) {
+        while self.pending.len() < self.depth && self.next < self.size {
+            let (file, offset) = (self.file.clone(), self.next);
+            let len = (BLOCK_SIZE as u64).min(self.size - offset) as usize;
+            let read = crate::remote::runtime().spawn_blocking(move || {
+                let mut block = vec![0; len];
+                file.read_exact_at(&mut block, offset)?;
+                Ok(block)
+            });
+            self.pending.push_back((offset, read));
+            self.next += len as u64;
+        }
+    }
+
+    /// Make the block of the current position the buffer
+    fn fill_buffer(&mut self) -> std::io::Result<()> {
+        let ahead = self.pending.front().is_some_and(|(offset, _)| {
+            (*offset..offset + BLOCK_SIZE as u64).contains(&self.position)
+        });
+        if !ahead {
+            // the reads in flight were for the position before a seek
+            self.pending.clear();
+            self.next = self.position;
+        }
+        self.submit();
+        let (offset, read) = self
+            .pending
+            .pop_front()
+            .expect("position is before the end of the file");
+        self.buffer = wait(read)?;
+        self.buffer_start = offset;
+        self.submit();
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            self.fill_buffer()?;
+        }
+
+        let offset = (self.position - self.buffer_start) as usize;
+        let available = &self.buffer[offset..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl Seek for ReadAhead {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = seek_position(pos, self.position, self.size)?;
+        Ok(self.position)
+    }
+}
+
+/// Writes a file in blocks, continuing while the writes of the previous blocks are in flight.
+/// Errors of a write are returned by one of the following calls.
+#[cfg(all(feature = "async-io", unix))]
+#[derive(Debug)]
+pub struct WriteBehind {
+    file: Arc<File>,
+    depth: usize,
+    /// The bytes written at `position` that are not submitted yet
+    buffer: Vec<u8>,
+    position: u64,
+    /// End of the data written so far
+    size: u64,
+    pending: VecDeque<tokio::task::JoinHandle<std::io::Result<()>>>,
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl WriteBehind {
+    fn new(file: File, depth: usize) -> Self {
+        Self {
+            file: Arc::new(file),
+            depth,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            position: 0,
+            size: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Start writing the buffer, waiting for earlier writes while `depth` are in flight
+    fn submit(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let block = std::mem::replace(&mut self.buffer, Vec::with_capacity(BLOCK_SIZE));
+        let (file, offset) = (self.file.clone(), self.position);
+        self.position += block.len() as u64;
+        self.size = self.size.max(self.position);
+        self.pending.push_back(
+            crate::remote::runtime().spawn_blocking(move || file.write_all_at(&block, offset)),
+        );
+        while self.pending.len() > self.depth {
+            wait(self.pending.pop_front().expect("writes are in flight"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl Write for WriteBehind {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= BLOCK_SIZE {
+            self.submit()?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Wait until all bytes are written
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.submit()?;
+        while let Some(write) = self.pending.pop_front() {
+            wait(write)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl Seek for WriteBehind {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        // writes after the seek may overlap the ones before it
+        self.flush()?;
+        self.position = seek_position(pos, self.position, self.size)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(all(feature = "async-io", unix))]
+impl Drop for WriteBehind {
+    fn drop(&mut self) {
+        // like a `BufWriter`, errors are only reported by an explicit flush
+        let _ = self.flush();
+    }
+}
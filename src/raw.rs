@@ -0,0 +1,271 @@
+//! Point records as they are stored in LAS files. Inputs in the point format and with the scales
+//! and offsets of the tiles are routed record by record: only the coordinates are decoded to find
+//! the tile of a record, and the record is written to it as it is, instead of going through
+//! `las::Point`. The header of such a tile is written by las, and its counts and bounds are
+//! filled in when it is closed.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{queued_io::QueuedFile, throughput::Counting};
+
+/// Size of the header of LAS 1.4 files, which has the 64 bit point counts
+const HEADER_SIZE_1_4: usize = 375;
+/// Size of the header of LAS 1.0 to 1.2 files, the smallest one
+const HEADER_SIZE_1_2: usize = 227;
+
+/// The coordinates of a point record
+pub fn coordinates(record: &[u8], transforms: &las::Vector<las::Transform>) -> [f64; 3] {
+    let int = |at: usize| i32::from_le_bytes(record[at..at + 4].try_into().expect("4 bytes"));
+    [
+        transforms.x.direct(int(0)),
+        transforms.y.direct(int(4)),
+        transforms.z.direct(int(8)),
+    ]
+}
+
+/// The return number of a point record, which has 3 bits in the legacy formats and 4 bits in
+/// the extended ones
+fn return_number(record: &[u8], extended: bool) -> u8 {
+    match extended {
+        true => record[14] & 0x0f,
+        false => record[14] & 0x07,
+    }
+}
+
+/// The point counts and bounds in a header
+pub struct Counts {
+    points: u64,
+    by_return: [u64; 15],
+    min: [f64; 3],
+    max: [f64; 3],
+}
+
+impl Counts {
+    /// The counts in the raw bytes of a header
+    pub fn read(header: &[u8]) -> Self {
+        let u32_at =
+            |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("4 bytes"));
+        let u64_at =
+            |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().expect("8 bytes"));
+        let f64_at =
+            |at: usize| f64::from_le_bytes(header[at..at + 8].try_into().expect("8 bytes"));
+        let (points, by_return) = match header_size(header) >= HEADER_SIZE_1_4 {
+            true => (u64_at(247), std::array::from_fn(|i| u64_at(255 + 8 * i))),
+            false => (
+                u64::from(u32_at(107)),
+                std::array::from_fn(|i| match i {
+                    0..5 => u64::from(u32_at(111 + 4 * i)),
+                    _ => 0,
+                }),
+            ),
+        };
+        let mut counts = Counts {
+            points,
+            by_return,
+            min: [f64_at(187), f64_at(203), f64_at(219)],
+            max: [f64_at(179), f64_at(195), f64_at(211)],
+        };
+        if counts.points == 0 {
+            // the bounds of an empty file are meaningless
+            counts.min = [f64::INFINITY; 3];
+            counts.max = [f64::NEG_INFINITY; 3];
+        }
+        counts
+    }
+
+    pub fn add(&mut self, point: &las::Point) {
+        self.add_xyz([point.x, point.y, point.z], point.return_number);
+    }
+
+    fn add_xyz(&mut self, xyz: [f64; 3], return_number: u8) {
+        self.points += 1;
+        if let 1..=15 = return_number {
+            self.by_return[usize::from(return_number) - 1] += 1;
+        }
+        for (axis, value) in xyz.into_iter().enumerate() {
+            self.min[axis] = self.min[axis].min(value);
+            self.max[axis] = self.max[axis].max(value);
+        }
+    }
+
+    /// Write the counts into the raw bytes of a header. The legacy counts are left at zero
+    /// where LAS 1.4 requires it.
+    pub fn write(&self, header: &mut [u8]) {
+        let format = header[104] & 0x3f;
+        let legacy = match u32::try_from(self.points) {
+            Ok(points) if format < 6 => Some(points),
+            _ => None,
+        };
+        header[107..111].copy_from_slice(&legacy.unwrap_or(0).to_le_bytes());
+        for i in 0..5 {
+            let count = legacy
+                .and(u32::try_from(self.by_return[i]).ok())
+                .unwrap_or(0);
+            header[111 + 4 * i..115 + 4 * i].copy_from_slice(&count.to_le_bytes());
+        }
+        for (axis, at) in [(0, 179), (1, 195), (2, 211)] {
+            header[at..at + 8].copy_from_slice(&self.max[axis].to_le_bytes());
+            header[at + 8..at + 16].copy_from_slice(&self.min[axis].to_le_bytes());
+        }
+        if header_size(header) >= HEADER_SIZE_1_4 {
+            header[247..255].copy_from_slice(&self.points.to_le_bytes());
+            for (i, count) in self.by_return.iter().enumerate() {
+                header[255 + 8 * i..263 + 8 * i].copy_from_slice(&count.to_le_bytes());
+            }
+        }
+    }
+}
+
+fn header_size(header: &[u8]) -> usize {
+    usize::from(u16::from_le_bytes([header[94], header[95]]))
+}
+
+/// Read the header and VLRs of a LAS/LAZ file, up to its point data. Files with extended VLRs
+/// after the points are not supported.
+pub fn read_prefix(mut read: impl Read) -> Result<Vec<u8>> {
+    let mut prefix = vec![0; HEADER_SIZE_1_2];
+    read.read_exact(&mut prefix).context("truncated header")?;
+    let point_data = u32::from_le_bytes(prefix[96..100].try_into().expect("4 bytes")) as usize;
+    anyhow::ensure!(
+        point_data >= HEADER_SIZE_1_2,
+        "invalid offset to point data"
+    );
+    prefix.resize(point_data, 0);
+    read.read_exact(&mut prefix[HEADER_SIZE_1_2..])
+        .context("truncated VLRs")?;
+    anyhow::ensure!(
+        header_size(&prefix) < HEADER_SIZE_1_4
+            || u32::from_le_bytes(prefix[243..247].try_into().expect("4 bytes")) == 0,
+        "files with extended VLRs are not supported"
+    );
+    Ok(prefix)
+}
+
+/// The length of the point records of a header
+pub fn record_length(header: &[u8]) -> u16 {
+    u16::from_le_bytes([header[105], header[106]])
+}
+
+/// The LASzip VLR among the VLRs following the header
+pub fn laz_vlr(prefix: &[u8]) -> Result<laz::LazVlr> {
+    let count = u32::from_le_bytes(prefix[100..104].try_into().expect("4 bytes"));
+    let mut at = header_size(prefix);
+    for _ in 0..count {
+        let vlr = prefix.get(at..at + 54).context("truncated VLR")?;
+        let user_id = String::from_utf8_lossy(&vlr[2..18]);
+        let record_id = u16::from_le_bytes([vlr[18], vlr[19]]);
+        let length = usize::from(u16::from_le_bytes([vlr[20], vlr[21]]));
+        let data = prefix
+            .get(at + 54..at + 54 + length)
+            .context("truncated VLR")?;
+        if user_id.trim_end_matches('\0') == laz::LazVlr::USER_ID
+            && record_id == laz::LazVlr::RECORD_ID
+        {
+            return Ok(laz::LazVlr::read_from(data)?);
+        }
+        at += 54 + length;
+    }
+    anyhow::bail!("no LASzip VLR")
+}
+
+type Output = BufWriter<Counting<QueuedFile>>;
+
+enum Sink {
+    Plain(Output),
+    Compressed(Box<laz::LasZipCompressor<'static, Output>>),
+}
+
+/// Writes point records to a LAS/LAZ file as they are, and points encoded into records
+pub struct RecordWriter {
+    sink: Sink,
+    /// The header and VLRs, written again with the counts once the file is closed
+    prefix: Vec<u8>,
+    counts: Counts,
+    format: las::point::Format,
+    transforms: las::Vector<las::Transform>,
+    /// Buffer for encoding points
+    record: Vec<u8>,
+}
+
+impl RecordWriter {
+    /// Create a file for records in the format of `header`, compressed if the header says so
+    pub fn create(path: &Path, header: las::Header, buffer_size: usize) -> Result<Self> {
+        let format = *header.point_format();
+        let transforms = *header.transforms();
+        // las writes the header and VLRs of an empty file, with the point counts filled in later
+        las::Writer::from_path(path, header)?.close()?;
+        let prefix = read_prefix(File::open(path)?)?;
+
+        let mut output =
+            BufWriter::with_capacity(buffer_size, Counting::tile(QueuedFile::create(path)?));
+        output.write_all(&prefix)?;
+        let sink = match format.is_compressed {
+            true => Sink::Compressed(Box::new(laz::LasZipCompressor::new(
+                output,
+                laz_vlr(&prefix)?,
+            )?)),
+            false => Sink::Plain(output),
+        };
+        Ok(Self {
+            sink,
+            counts: Counts::read(&prefix),
+            prefix,
+            format,
+            transforms,
+            record: Vec::with_capacity(usize::from(format.len())),
+        })
+    }
+
+    /// Write records in the format of the file
+    pub fn write_records(&mut self, records: &[u8]) -> Result<()> {
+        let length = usize::from(self.format.len());
+        for record in records.chunks_exact(length) {
+            self.counts.add_xyz(
+                coordinates(record, &self.transforms),
+                return_number(record, self.format.is_extended),
+            );
+        }
+        match &mut self.sink {
+            Sink::Plain(output) => output.write_all(records)?,
+            Sink::Compressed(compressor) => compressor.compress_many(records)?,
+        }
+        Ok(())
+    }
+
+    pub fn write_point(&mut self, point: las::Point) -> Result<()> {
+        self.counts.add(&point);
+        self.record.clear();
+        point
+            .into_raw(&self.transforms)?
+            .write_to(&mut self.record, &self.format)?;
+        match &mut self.sink {
+            Sink::Plain(output) => output.write_all(&self.record)?,
+            Sink::Compressed(compressor) => compressor.compress_one(&self.record)?,
+        }
+        Ok(())
+    }
+
+    /// Finish the file, writing the chunk table of a compressed file and the counts and bounds
+    /// into the header
+    pub fn close(self) -> Result<()> {
+        let mut output = match self.sink {
+            Sink::Plain(output) => output,
+            Sink::Compressed(mut compressor) => {
+                compressor.done()?;
+                compressor.into_inner()
+            }
+        };
+        let mut prefix = self.prefix;
+        self.counts.write(&mut prefix);
+        output.seek(SeekFrom::Start(0))?;
+        output.write_all(&prefix)?;
+        output.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    ops::Range,
+    path::Path,
+    sync::{Arc, OnceLock, mpsc},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    affine::Affine,
+    bench::{self, Stage},
+    colorize::Colorize,
+    input::InputFile,
+    throughput,
+    z_adjust::ZAdjust,
+};
+
+/// Number of point buffers in flight between the reader thread and its consumer, so that the
+/// next buffer is decoded while the previous ones are being routed and written
+const BUFFERS: usize = 4;
+
+/// Whether uncompressed local inputs are read from a memory mapping, see [`disable_mapping`]
+static MAPPING: OnceLock<bool> = OnceLock::new();
+
+/// Read uncompressed local inputs through a buffered reader instead of a memory mapping, for
+/// inputs that may be truncated or rewritten while they are read (e.g. when watching folders):
+/// that fails a buffered read, but crashes the process when reading a mapping.
+pub fn disable_mapping() {
+    // set once at startup, later calls keep the first value
+    let _ = MAPPING.set(false);
+}
+
+enum Chunk {
+    Points(Vec<las::Point>),
+    Records(Vec<u8>),
+    EndOfFile,
+}
+
+/// A buffer of points of the current file
+pub enum Batch {
+    Points(Vec<las::Point>),
+    /// Point records as they are stored in the file, for files read as records
+    Records(Vec<u8>),
+}
+
+/// A file to read, with the ranges of point indices to read from it and whether to read it as
+/// point records
+pub type ReadFile = (InputFile, Option<Vec<Range<u64>>>, bool);
+
+/// Decodes the input files on a separate thread, one after the other, handing out the points
+/// in buffers of a fixed size.
+///
+/// Buffers are handed back using [`InputReader::recycle`] once their points have been
+/// processed, which limits the amount of points read ahead.
+pub struct InputReader {
+    chunks: mpsc::Receiver<Result<Chunk>>,
+    recycle: mpsc::Sender<Vec<las::Point>>,
+    recycle_records: mpsc::Sender<Vec<u8>>,
+    corrections: Corrections,
+}
+
+/// Corrections applied to the coordinates of the points as they are read, before they are
+/// assigned to tiles
+#[derive(Debug, Clone, Default)]
+pub struct Corrections {
+    /// `--affine` and `--translate`
+    pub affine: Option<Affine>,
+    /// `--z-adjust`, after the affine transformation
+    pub z_adjust: Option<Arc<ZAdjust>>,
+    /// `--colorize`, at the corrected coordinates
+    pub colorize: Option<Arc<Colorize>>,
+}
+
+impl Corrections {
+    fn apply(&self, points: &mut [las::Point]) -> Result<()> {
+        if let Some(affine) = &self.affine {
+            affine.apply_points(points);
+        }
+        if let Some(z_adjust) = &self.z_adjust {
+            z_adjust.apply(points)?;
+        }
+        if let Some(colorize) = &self.colorize {
+            colorize.apply(points);
+        }
+        Ok(())
+    }
+}
+
+impl InputReader {
+    /// Start reading the files in order, keeping at most `buffer_points` points in memory
+    pub fn spawn(files: Vec<InputFile>, options: las::ReaderOptions, buffer_points: usize) -> Self {
+        let files = files.into_iter().map(|file| (file, None, false)).collect();
+        Self::spawn_ranges(files, options, buffer_points, Corrections::default())
+    }
+
+    /// Like [`InputReader::spawn`], but only reading the given ranges of point indices of the
+    /// files that have them, and applying the corrections to the points. Uncompressed local
+    /// files marked to be read as records are handed out as [`Batch::Records`].
+    pub fn spawn_ranges(
+        files: Vec<ReadFile>,
+        options: las::ReaderOptions,
+        buffer_points: usize,
+        corrections: Corrections,
+    ) -> Self {
+        let chunk_points = (buffer_points / BUFFERS).max(1);
+        // the buffers bound the points read ahead, the channel also the errors and ends of files
+        let (chunk_sender, chunks) = mpsc::sync_channel(BUFFERS);
+        let (recycle, free_points) = mpsc::channel();
+        let (recycle_records, free_records) = mpsc::channel();
+        for _ in 0..BUFFERS {
+            recycle
+                .send(Vec::with_capacity(chunk_points))
+                .expect("receiver is alive");
+            // allocated once records are read
+            recycle_records.send(Vec::new()).expect("receiver is alive");
+        }
+
+        std::thread::spawn(move || {
+            let mut buffers = Buffers {
+                points: free_points,
+                records: free_records,
+                spare_points: None,
+                spare_records: None,
+            };
+            for (file, ranges, records) in files {
+                let result = read_file(
+                    &file,
+                    ranges.as_deref(),
+                    records,
+                    options,
+                    chunk_points,
+                    &mut buffers,
+                    &chunk_sender,
+                );
+                let stop = match result {
+                    Ok(done) => !done,
+                    Err(e) => {
+                        let _ = chunk_sender.send(Err(e));
+                        true
+                    }
+                };
+                // stop if reading failed or the consumer went away
+                if stop || chunk_sender.send(Ok(Chunk::EndOfFile)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            chunks,
+            recycle,
+            recycle_records,
+            corrections,
+        }
+    }
+
+    /// The next buffer of points of the current file, or `None` once all points of the current
+    /// file have been returned. The next call will then return points of the next file.
+    pub fn next(&self) -> Result<Option<Vec<las::Point>>> {
+        match self.next_batch()? {
+            Some(Batch::Points(points)) => Ok(Some(points)),
+            Some(Batch::Records(_)) => anyhow::bail!("point records were not requested"),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`InputReader::next`], for files that may be read as records
+    pub fn next_batch(&self) -> Result<Option<Batch>> {
+        match self
+            .chunks
+            .recv()
+            .context("input reader thread stopped unexpectedly")??
+        {
+            Chunk::Points(mut points) => {
+                self.corrections.apply(&mut points)?;
+                Ok(Some(Batch::Points(points)))
+            }
+            Chunk::Records(records) => Ok(Some(Batch::Records(records))),
+            Chunk::EndOfFile => Ok(None),
+        }
+    }
+
+    /// Hand back a buffer returned by [`InputReader::next`] so it can be filled again
+    pub fn recycle(&self, points: Vec<las::Point>) {
+        // the reader thread may already be done
+        let _ = self.recycle.send(points);
+    }
+
+    /// Hand back a buffer of [`Batch::Records`] so it can be filled again
+    pub fn recycle_records(&self, records: Vec<u8>) {
+        let _ = self.recycle_records.send(records);
+    }
+}
+
+/// The free buffers of the reader thread. `spare_points` and `spare_records` keep a buffer that
+/// was taken but not filled for the next file.
+struct Buffers {
+    points: mpsc::Receiver<Vec<las::Point>>,
+    records: mpsc::Receiver<Vec<u8>>,
+    spare_points: Option<Vec<las::Point>>,
+    spare_records: Option<Vec<u8>>,
+}
+
+/// Read all points of a file, or only the given ranges of point indices, into buffers. With
+/// `records`, uncompressed local files are read as point records. Returns false if the consumer
+/// went away.
+fn read_file(
+    file: &InputFile,
+    ranges: Option<&[Range<u64>]>,
+    records: bool,
+    options: las::ReaderOptions,
+    chunk_points: usize,
+    buffers: &mut Buffers,
+    chunks: &mpsc::SyncSender<Result<Chunk>>,
+) -> Result<bool> {
+    let mut reader = PointSource::open(file, options)?;
+    let all = 0..u64::MAX;
+    let mut position = 0;
+    for range in ranges.unwrap_or(std::slice::from_ref(&all)) {
+        if range.start != position {
+            reader
+                .seek(range.start)
+                .with_context(|| format!("seek to point {} of {file}", range.start))?;
+            position = range.start;
+        }
+        while position < range.end {
+            let n = (chunk_points as u64).min(range.end - position);
+            let started = bench::start();
+            let (read, chunk) = match &mut reader {
+                PointSource::Mapped(mapped) if records => {
+                    let Some(mut buffer) = buffers
+                        .spare_records
+                        .take()
+                        .or_else(|| buffers.records.recv().ok())
+                    else {
+                        return Ok(false);
+                    };
+                    buffer.clear();
+                    let read = mapped.read_records_into(n, &mut buffer);
+                    if read == 0 {
+                        buffers.spare_records = Some(buffer);
+                        break;
+                    }
+                    (read, Chunk::Records(buffer))
+                }
+                _ => {
+                    let Some(mut points) = buffers
+                        .spare_points
+                        .take()
+                        .or_else(|| buffers.points.recv().ok())
+                    else {
+                        return Ok(false);
+                    };
+                    points.clear();
+                    let read = reader
+                        .read_points_into(n, &mut points)
+                        .with_context(|| format!("read points from {file}"))?;
+                    if read == 0 {
+                        buffers.spare_points = Some(points);
+                        break;
+                    }
+                    (read, Chunk::Points(points))
+                }
+            };
+            bench::record(Stage::Decode, started, read as usize);
+            position += read;
+            if chunks.send(Ok(chunk)).is_err() {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Where the points of a file are decoded from
+enum PointSource {
+    Reader(Box<las::Reader>),
+    /// Uncompressed local files are parsed straight from a memory mapping, instead of copying
+    /// every record through a buffered reader first
+    Mapped(MappedPoints),
+}
+
+impl PointSource {
+    fn open(file: &InputFile, options: las::ReaderOptions) -> Result<Self> {
+        let reader = file.reader(options)?;
+        Ok(match file {
+            InputFile::Local(path)
+                if !reader.header().point_format().is_compressed
+                    && *MAPPING.get().unwrap_or(&true) =>
+            {
+                PointSource::Mapped(MappedPoints::open(path, reader.header())?)
+            }
+            _ => PointSource::Reader(Box::new(reader)),
+        })
+    }
+
+    fn seek(&mut self, position: u64) -> Result<()> {
+        match self {
+            PointSource::Reader(reader) => Ok(reader.seek(position)?),
+            PointSource::Mapped(mapped) => {
+                mapped.position = position.min(mapped.points);
+                Ok(())
+            }
+        }
+    }
+
+    fn read_points_into(&mut self, n: u64, points: &mut Vec<las::Point>) -> Result<u64> {
+        match self {
+            PointSource::Reader(reader) => Ok(reader.read_points_into(n, points)?),
+            PointSource::Mapped(mapped) => mapped.read_points_into(n, points),
+        }
+    }
+}
+
+/// The point records of an uncompressed LAS file, mapped into memory
+struct MappedPoints {
+    map: memmap2::Mmap,
+    /// Offset of the first point record in the file
+    start: usize,
+    record_length: usize,
+    points: u64,
+    format: las::point::Format,
+    transforms: las::Vector<las::Transform>,
+    position: u64,
+}
+
+impl MappedPoints {
+    fn open(path: &Path, header: &las::Header) -> Result<Self> {
+        let file =
+            std::fs::File::open(path).with_context(|| format!("open file: {}", path.display()))?;
+        // SAFETY: the inputs must not be modified while they are read, which would already
+        // corrupt the points read through a buffered reader. Truncating a mapped file makes
+        // reading it crash rather than fail, so mapping is disabled where inputs may still
+        // change (`--watch`).
+        let map = unsafe { memmap2::Mmap::map(&file) }
+            .with_context(|| format!("map file: {}", path.display()))?;
+        let raw = las::raw::Header::read_from(&map[..])
+            .with_context(|| format!("read header: {}", path.display()))?;
+        let format = *header.point_format();
+        let mapped = Self {
+            start: raw.offset_to_point_data as usize,
+            record_length: usize::from(format.len()),
+            points: header.number_of_points(),
+            format,
+            transforms: *header.transforms(),
+            position: 0,
+            map,
+        };
+        anyhow::ensure!(
+            mapped.start as u64 + mapped.points * mapped.record_length as u64
+                <= mapped.map.len() as u64,
+            "{} is too short for its {} points",
+            path.display(),
+            mapped.points
+        );
+        Ok(mapped)
+    }
+
+    /// Copy up to `n` point records as they are
+    fn read_records_into(&mut self, n: u64, records: &mut Vec<u8>) -> u64 {
+        let n = n.min(self.points - self.position);
+        let start = self.start + self.position as usize * self.record_length;
+        records.extend_from_slice(&self.map[start..start + n as usize * self.record_length]);
+        self.position += n;
+        throughput::read(n as usize * self.record_length);
+        n
+    }
+
+    fn read_points_into(&mut self, n: u64, points: &mut Vec<las::Point>) -> Result<u64> {
+        let n = n.min(self.points - self.position);
+        let start = self.start + self.position as usize * self.record_length;
+        let records = &self.map[start..start + n as usize * self.record_length];
+        points.reserve(n as usize);
+        for record in records.chunks_exact(self.record_length) {
+            let raw = las::raw::Point::read_from(record, &self.format)?;
+            points.push(las::Point::new(raw, &self.transforms));
+        }
+        self.position += n;
+        throughput::read(records.len());
+        Ok(n)
+    }
+}
@@ -0,0 +1,412 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use object_store::{ObjectStore, WriteMultipart, path::Path as ObjectPath};
+use url::Url;
+
+use crate::threads;
+
+/// URL schemes that are handled by `object_store`
+const OBJECT_STORE_SCHEMES: &[&str] = &[
+    "s3", "s3a", "gs", "az", "adl", "azure", "abfs", "abfss", "http", "https",
+];
+
+/// Number of bytes fetched by the first range request of a reader. Subsequent sequential reads
+/// double the size up to `MAX_READ_AHEAD` so that scanning headers stays cheap while streaming
+/// point data uses large requests.
+const MIN_READ_AHEAD: u64 = 64 * 1024;
+const MAX_READ_AHEAD: u64 = 16 * 1024 * 1024;
+
+/// Size of each part of a multipart upload, and the number of parts uploaded concurrently
+const UPLOAD_PART_SIZE: usize = 16 * 1024 * 1024;
+const UPLOAD_CONCURRENCY: usize = 4;
+
+/// The runtime used to drive the async `object_store` requests from the synchronous readers, and
+/// the reads and writes of `--async-io`
+pub fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        if let Some(threads) = threads::limit() {
+            builder
+                .worker_threads(threads.get())
+                .max_blocking_threads(threads.get());
+        }
+        builder.enable_all().build().expect("create tokio runtime")
+    })
+}
+
+/// Parse `input` as a remote URL if it uses one of the supported schemes
+pub fn parse_remote_url(input: &str) -> Option<Url> {
+    let url = Url::parse(input).ok()?;
+    OBJECT_STORE_SCHEMES.contains(&url.scheme()).then_some(url)
+}
+
+/// Returns true for plain HTTP(S) URLs
+fn is_http(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// Create the store for the given URL, configured from the environment (e.g. `AWS_*`,
+/// `GOOGLE_*` and `AZURE_*` variables) like the official CLIs of each provider.
+///
+/// HTTP(S) URLs are always accessed as plain HTTP (even if they point at e.g. a public S3
+/// bucket) so that no credentials are needed.
+fn store_for_url(url: &Url) -> Result<(Arc<dyn ObjectStore>, ObjectPath)> {
+    if is_http(url) {
+        let (base, name) = http_base(url);
+        let store = object_store::http::HttpBuilder::new()
+            .with_url(base)
+            .build()
+            .with_context(|| format!("create HTTP client for {url}"))?;
+        let path =
+            ObjectPath::from_url_path(name).with_context(|| format!("invalid path in {url}"))?;
+        return Ok((Arc::new(store), path));
+    }
+
+    let options = std::env::vars().map(|(k, v)| (k.to_ascii_lowercase(), v));
+    let (store, path) = object_store::parse_url_opts(url, options)
+        .with_context(|| format!("create object store for {url}"))?;
+    Ok((Arc::from(store), path))
+}
+
+/// Split an HTTP(S) URL into the base URL of its store and the last segment of its path. The
+/// store appends the paths of its objects to the path of the base URL, keeping its query, which
+/// presigned URLs (e.g. `X-Amz-Signature=...`) need on every request.
+fn http_base(url: &Url) -> (Url, &str) {
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default();
+    let mut base = url.clone();
+    base.set_fragment(None);
+    if let Ok(mut segments) = base.path_segments_mut() {
+        segments.pop();
+    }
+    (base, name)
+}
+
+/// A single object in a remote object store
+#[derive(Debug, Clone)]
+pub struct RemoteFile {
+    url: Url,
+    store: Arc<dyn ObjectStore>,
+    location: ObjectPath,
+}
+
+impl RemoteFile {
+    pub fn new(url: Url) -> Result<Self> {
+        let (store, location) = store_for_url(&url)?;
+        Ok(Self {
+            url,
+            store,
+            location,
+        })
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Whether the object is a plain HTTP(S) resource. Those can not be listed, so they always
+    /// refer to a single file.
+    pub fn is_http(&self) -> bool {
+        is_http(&self.url)
+    }
+
+    /// The file extension of the object, if any
+    pub fn extension(&self) -> Option<&str> {
+        self.location.extension()
+    }
+
+    /// List the objects directly below the prefix in `url`. Objects are returned in sorted order,
+    /// and the returned prefixes are the "sub folders" found.
+    pub fn list(url: &Url) -> Result<(Vec<RemoteFile>, Vec<Url>)> {
+        let (store, prefix) = store_for_url(url)?;
+        let listing = runtime()
+            .block_on(store.list_with_delimiter(Some(&prefix)))
+            .with_context(|| format!("list objects in {url}"))?;
+
+        let to_url = |location: &ObjectPath| {
+            let mut object_url = url.clone();
+            object_url.set_path(&format!("/{location}"));
+            object_url
+        };
+
+        let mut files: Vec<_> = listing
+            .objects
+            .into_iter()
+            .map(|meta| RemoteFile {
+                url: to_url(&meta.location),
+                store: store.clone(),
+                location: meta.location,
+            })
+            .collect();
+        files.sort();
+
+        let prefixes = listing.common_prefixes.iter().map(to_url).collect();
+
+        Ok((files, prefixes))
+    }
+
+    /// The ETag of the object, or its size and modification time if the store has no ETags
+    pub fn version(&self) -> Result<String> {
+        let meta = runtime()
+            .block_on(self.store.head(&self.location))
+            .with_context(|| format!("get object metadata: {}", self.url))?;
+        Ok(meta
+            .e_tag
+            .unwrap_or_else(|| format!("{}-{}", meta.size, meta.last_modified.to_rfc3339())))
+    }
+
+    /// Open the object for reading using range requests
+    pub fn open(&self) -> Result<RangeReader> {
+        let meta = runtime()
+            .block_on(self.store.head(&self.location))
+            .with_context(|| format!("get object metadata: {}", self.url))?;
+
+        Ok(RangeReader {
+            store: self.store.clone(),
+            location: self.location.clone(),
+            size: meta.size,
+            position: 0,
+            buffer: Bytes::new(),
+            buffer_start: 0,
+            read_ahead: MIN_READ_AHEAD,
+        })
+    }
+}
+
+impl PartialEq for RemoteFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+    }
+}
+
+impl Eq for RemoteFile {}
+
+impl std::hash::Hash for RemoteFile {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.url.hash(state);
+    }
+}
+
+impl PartialOrd for RemoteFile {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RemoteFile {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.url.cmp(&other.url)
+    }
+}
+
+/// A prefix ("folder") in a remote object store that files can be uploaded to
+#[derive(Debug, Clone)]
+pub struct RemoteDir {
+    url: Url,
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+}
+
+impl RemoteDir {
+    pub fn new(url: Url) -> Result<Self> {
+        anyhow::ensure!(
+            !is_http(&url),
+            "HTTP(S) URLs can only be used as inputs, not as output: {url}"
+        );
+        let (store, prefix) = store_for_url(&url)?;
+        Ok(Self { url, store, prefix })
+    }
+
+    /// The URL of the object with the given name in this prefix
+    pub fn url_of(&self, name: &str) -> String {
+        format!("{}/{name}", self.url.as_str().trim_end_matches('/'))
+    }
+
+    /// The location of the object `name` in this prefix, which may contain `/` for objects in
+    /// subfolders (see `--output-layout`)
+    fn location(&self, name: &str) -> ObjectPath {
+        name.split('/')
+            .fold(self.prefix.clone(), |location, part| location.child(part))
+    }
+
+    /// Upload the local file to the object `name` in this prefix using a multipart upload
+    pub fn upload(&self, local: &std::path::Path, name: &str) -> Result<()> {
+        let location = self.location(name);
+        let mut file = std::fs::File::open(local)
+            .with_context(|| format!("open {} for upload", local.display()))?;
+
+        runtime()
+            .block_on(async {
+                let upload = self.store.put_multipart(&location).await?;
+                let mut writer = WriteMultipart::new_with_chunk_size(upload, UPLOAD_PART_SIZE);
+
+                let mut buffer = vec![0; UPLOAD_PART_SIZE];
+                loop {
+                    let n = match file.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(e) => {
+                            writer.abort().await?;
+                            return Err(e.into());
+                        }
+                    };
+                    writer.wait_for_capacity(UPLOAD_CONCURRENCY).await?;
+                    writer.write(&buffer[..n]);
+                }
+                writer.finish().await?;
+                anyhow::Ok(())
+            })
+            .with_context(|| format!("upload {}", self.url_of(name)))
+    }
+
+    /// Download the object `name` in this prefix, or `None` if it does not exist
+    pub fn download(&self, name: &str) -> Result<Option<Bytes>> {
+        let location = self.location(name);
+        let result = runtime().block_on(async {
+            let object = self.store.get(&location).await?;
+            object.bytes().await
+        });
+        match result {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("download {}", self.url_of(name))),
+        }
+    }
+
+    /// The names of the objects below this prefix, with `/` between subfolders
+    pub fn list_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut prefixes = vec![self.prefix.clone()];
+        while let Some(prefix) = prefixes.pop() {
+            let listing = runtime()
+                .block_on(self.store.list_with_delimiter(Some(&prefix)))
+                .with_context(|| format!("list objects in {}", self.url))?;
+            for meta in listing.objects {
+                if let Some(parts) = meta.location.prefix_match(&self.prefix) {
+                    let parts: Vec<_> = parts.map(|part| part.as_ref().to_owned()).collect();
+                    names.push(parts.join("/"));
+                }
+            }
+            prefixes.extend(listing.common_prefixes);
+        }
+        Ok(names)
+    }
+
+    /// Delete the object `name` in this prefix, if it exists
+    pub fn delete(&self, name: &str) -> Result<()> {
+        match runtime().block_on(self.store.delete(&self.location(name))) {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("delete {}", self.url_of(name))),
+        }
+    }
+}
+
+/// A `Read + Seek` adapter over an object in an object store, fetching data with range
+/// requests as needed
+pub struct RangeReader {
+    store: Arc<dyn ObjectStore>,
+    location: ObjectPath,
+    size: u64,
+    position: u64,
+    buffer: Bytes,
+    buffer_start: u64,
+    read_ahead: u64,
+}
+
+impl RangeReader {
+    /// Fetch the data starting at the current position into the buffer
+    fn fill_buffer(&mut self) -> std::io::Result<()> {
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+
+        // grow the request size while reading sequentially, start over after a seek
+        self.read_ahead = if self.position == buffer_end && !self.buffer.is_empty() {
+            (self.read_ahead * 2).min(MAX_READ_AHEAD)
+        } else {
+            MIN_READ_AHEAD
+        };
+
+        let end = (self.position + self.read_ahead).min(self.size);
+        self.buffer = runtime()
+            .block_on(self.store.get_range(&self.location, self.position..end))
+            .map_err(std::io::Error::other)?;
+        self.buffer_start = self.position;
+        Ok(())
+    }
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            self.fill_buffer()?;
+        }
+
+        let offset = (self.position - self.buffer_start) as usize;
+        let available = &self.buffer[offset..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = new_position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_base_keeps_the_query() {
+        let url = Url::parse("https://example.com/a/b%20c.laz?X-Amz-Signature=1&x=2#top").unwrap();
+        let (base, name) = http_base(&url);
+        assert_eq!(base.as_str(), "https://example.com/a?X-Amz-Signature=1&x=2");
+        assert_eq!(name, "b%20c.laz");
+
+        // how the store requests the object
+        let mut request = base.clone();
+        request
+            .path_segments_mut()
+            .unwrap()
+            .extend(ObjectPath::from_url_path(name).unwrap().parts());
+        assert_eq!(
+            request.as_str(),
+            "https://example.com/a/b%20c.laz?X-Amz-Signature=1&x=2"
+        );
+    }
+
+    #[test]
+    fn http_base_of_a_folder() {
+        let url = Url::parse("https://example.com/tiles/").unwrap();
+        let (base, name) = http_base(&url);
+        assert_eq!(base.as_str(), "https://example.com/tiles");
+        assert_eq!(name, "");
+    }
+}
@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    checkpoint::Checkpoint, extent::ExtentWarning, manifest::Manifest, progress::Event,
+    psid::PsidMapping, sidecar::Sidecar, stats::StatsReport, summary::Summary,
+    validate::ValidationReport,
+};
+
+/// A machine-readable output with a versioned schema.
+///
+/// Versions are `major.minor`. Within a major version, fields are only ever added (bumping the
+/// minor version), so consumers should ignore fields they do not know. Removing, renaming or
+/// changing the meaning of a field bumps the major version.
+pub trait Document: Serialize + JsonSchema {
+    const SCHEMA_VERSION: &'static str;
+}
+
+/// A document together with its schema version, as it is written
+#[derive(Serialize, JsonSchema)]
+pub struct Versioned<'a, T> {
+    /// Version of the schema of this document, see `--schema`
+    schema_version: &'static str,
+    #[serde(flatten)]
+    document: &'a T,
+}
+
+impl<'a, T: Document> Versioned<'a, T> {
+    pub fn new(document: &'a T) -> Self {
+        Self {
+            schema_version: T::SCHEMA_VERSION,
+            document,
+        }
+    }
+}
+
+/// The machine-readable outputs whose schema can be printed with `--schema`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaName {
+    /// The per-tile statistics report written by `--stats`
+    Stats,
+    /// The `checkpoint.json` written when a run stops early
+    Checkpoint,
+    /// The JSON lines printed on stderr for tiles with invalid extents
+    Warning,
+    /// The `manifest.json` written by `--manifest`
+    Manifest,
+    /// The `summary.json` written by `--summary`
+    Summary,
+    /// The report printed by the `validate` and `verify` commands
+    Validation,
+    /// The `psid_mapping.json` written by `--renumber-psid`
+    PsidMapping,
+    /// The `<tile>.json` written next to each tile by `--sidecar`
+    Sidecar,
+    /// The JSON lines written by `--progress json`
+    Progress,
+}
+
+/// The JSON schema of a machine-readable output
+pub fn json_schema(name: SchemaName) -> schemars::Schema {
+    match name {
+        SchemaName::Stats => versioned_schema::<StatsReport>(),
+        SchemaName::Checkpoint => versioned_schema::<Checkpoint>(),
+        SchemaName::Warning => versioned_schema::<ExtentWarning>(),
+        SchemaName::Manifest => versioned_schema::<Manifest>(),
+        SchemaName::Summary => versioned_schema::<Summary>(),
+        SchemaName::Validation => versioned_schema::<ValidationReport>(),
+        SchemaName::PsidMapping => versioned_schema::<PsidMapping>(),
+        SchemaName::Sidecar => versioned_schema::<Sidecar>(),
+        SchemaName::Progress => versioned_schema::<Event<'static>>(),
+    }
+}
+
+fn versioned_schema<T: Document + 'static>() -> schemars::Schema {
+    let mut schema = schemars::schema_for!(Versioned<'static, T>);
+    // describe the document itself, not the wrapper
+    schema.remove("description");
+    schema.insert("title".into(), T::schema_name().into());
+    schema.insert("x-schema-version".into(), T::SCHEMA_VERSION.into());
+    schema
+}
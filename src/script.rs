@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use rhai::{AST, Dynamic, Engine, EvalAltResult, Scope};
+
+/// A per-point transform written in Rhai.
+///
+/// The script is evaluated once for every point, with the point available as the `point`
+/// variable. Its attributes can be read and modified (e.g. `point.classification = 2`), and the
+/// point is dropped if the script evaluates to `false`. This is much slower than the built-in
+/// processing and meant for one-off transformations.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut engine = Engine::new();
+        register_point(&mut engine);
+        let ast = engine
+            .compile_file(path.to_owned())
+            .map_err(|e| anyhow!("compile script {}: {e}", path.display()))?;
+
+        Ok(Self {
+            engine,
+            ast,
+            scope: Scope::new(),
+        })
+    }
+
+    /// Run the script on every point, updating them and removing the dropped ones
+    pub fn apply(&mut self, points: &mut Vec<las::Point>) -> Result<()> {
+        let mut result = Ok(());
+        points.retain_mut(|point| {
+            if result.is_err() {
+                return true;
+            }
+            match self.run(point) {
+                Ok(keep) => keep,
+                Err(e) => {
+                    result = Err(e);
+                    true
+                }
+            }
+        });
+        result
+    }
+
+    fn run(&mut self, point: &mut las::Point) -> Result<bool> {
+        self.scope.clear();
+        self.scope.push("point", std::mem::take(point));
+        let value: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut self.scope, &self.ast)
+            .map_err(|e| anyhow!("run script: {e}"))?;
+        *point = self
+            .scope
+            .get_value("point")
+            .ok_or_else(|| anyhow!("script replaced `point` with a value of another type"))?;
+        Ok(value.as_bool().unwrap_or(true))
+    }
+}
+
+/// Convert an integer from a script to the type of a point attribute
+fn to_attribute<T: TryFrom<i64>>(name: &str, value: i64) -> Result<T, Box<EvalAltResult>> {
+    T::try_from(value).map_err(|_| format!("{name} out of range: {value}").into())
+}
+
+/// Make the attributes of `las::Point` available to scripts
+fn register_point(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<las::Point>("Point")
+        .register_get_set(
+            "x",
+            |p: &mut las::Point| p.x,
+            |p: &mut las::Point, v| p.x = v,
+        )
+        .register_get_set(
+            "y",
+            |p: &mut las::Point| p.y,
+            |p: &mut las::Point, v| p.y = v,
+        )
+        .register_get_set(
+            "z",
+            |p: &mut las::Point| p.z,
+            |p: &mut las::Point, v| p.z = v,
+        )
+        .register_get_set(
+            "intensity",
+            |p: &mut las::Point| i64::from(p.intensity),
+            |p: &mut las::Point, v| {
+                p.intensity = to_attribute("intensity", v)?;
+                Ok(())
+            },
+        )
+        .register_get_set(
+            "classification",
+            |p: &mut las::Point| i64::from(u8::from(p.classification)),
+            |p: &mut las::Point, v| {
+                p.classification =
+                    las::point::Classification::new(to_attribute("classification", v)?)
+                        .map_err(|e| format!("invalid classification {v}: {e}"))?;
+                Ok(())
+            },
+        )
+        .register_get_set(
+            "return_number",
+            |p: &mut las::Point| i64::from(p.return_number),
+            |p: &mut las::Point, v| {
+                p.return_number = to_attribute("return_number", v)?;
+                Ok(())
+            },
+        )
+        .register_get_set(
+            "number_of_returns",
+            |p: &mut las::Point| i64::from(p.number_of_returns),
+            |p: &mut las::Point, v| {
+                p.number_of_returns = to_attribute("number_of_returns", v)?;
+                Ok(())
+            },
+        )
+        .register_get_set(
+            "user_data",
+            |p: &mut las::Point| i64::from(p.user_data),
+            |p: &mut las::Point, v| {
+                p.user_data = to_attribute("user_data", v)?;
+                Ok(())
+            },
+        )
+        .register_get_set(
+            "point_source_id",
+            |p: &mut las::Point| i64::from(p.point_source_id),
+            |p: &mut las::Point, v| {
+                p.point_source_id = to_attribute("point_source_id", v)?;
+                Ok(())
+            },
+        )
+        .register_get_set(
+            "scan_angle",
+            |p: &mut las::Point| f64::from(p.scan_angle),
+            |p: &mut las::Point, v: f64| p.scan_angle = v as f32,
+        )
+        .register_get("gps_time", |p: &mut las::Point| {
+            p.gps_time.map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_set("gps_time", |p: &mut las::Point, v: f64| {
+            match &mut p.gps_time {
+                Some(gps_time) => {
+                    *gps_time = v;
+                    Ok(())
+                }
+                None => Err(Box::<EvalAltResult>::from(
+                    "the point format has no GPS time",
+                )),
+            }
+        })
+        .register_get_set(
+            "is_synthetic",
+            |p: &mut las::Point| p.is_synthetic,
+            |p: &mut las::Point, v| p.is_synthetic = v,
+        )
+        .register_get_set(
+            "is_key_point",
+            |p: &mut las::Point| p.is_key_point,
+            |p: &mut las::Point, v| p.is_key_point = v,
+        )
+        .register_get_set(
+            "is_withheld",
+            |p: &mut las::Point| p.is_withheld,
+            |p: &mut las::Point, v| p.is_withheld = v,
+        )
+        .register_get_set(
+            "is_overlap",
+            |p: &mut las::Point| p.is_overlap,
+            |p: &mut las::Point, v| p.is_overlap = v,
+        );
+}
@@ -0,0 +1,106 @@
+//! Splitting one job across machines without a coordinator (`--shard i/n`)
+
+use std::str::FromStr;
+
+use crate::tile::TileKey;
+
+/// Shard `index` (starting at 1) of `count`, as given on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardSpec {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl FromStr for ShardSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once('/')
+            .and_then(|(index, count)| Some((index.parse().ok()?, count.parse().ok()?)));
+        match parsed {
+            Some((index, count)) if (1..=count).contains(&index) => Ok(Self { index, count }),
+            _ => Err(format!(
+                "expected <index>/<count> with an index from 1 to count, e.g. 2/8, got {s:?}"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ShardSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// The tiles of one shard. The planned tiles of the whole job are sorted by their key and cut
+/// into consecutive runs of (almost) the same length, so neighbouring tiles end up in the same
+/// shard and every shard reads as few inputs as possible. Every machine plans the same tiles
+/// from the same inputs, so they agree on the shards without talking to each other.
+pub struct Shard {
+    spec: ShardSpec,
+    /// The first tile of each shard after the first one
+    boundaries: Vec<TileKey>,
+}
+
+impl Shard {
+    pub fn new(spec: ShardSpec, tiles: impl IntoIterator<Item = TileKey>) -> Self {
+        let mut tiles: Vec<_> = tiles.into_iter().map(TileKey::tile).collect();
+        tiles.sort();
+        tiles.dedup();
+        let count = spec.count as usize;
+        let boundaries = (1..count)
+            .filter_map(|i| tiles.get(i * tiles.len() / count).copied())
+            .collect();
+        Self { spec, boundaries }
+    }
+
+    /// Whether a tile belongs to this shard. Tiles that were not planned, e.g. for points outside
+    /// the bounds of their input, belong to the shard of the planned tiles around them.
+    pub fn contains(&self, key: TileKey) -> bool {
+        let shard = self.boundaries.partition_point(|b| *b <= key.tile());
+        shard + 1 == self.spec.index as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(x: i32) -> TileKey {
+        TileKey {
+            x,
+            y: 0,
+            z: None,
+            part: None,
+        }
+    }
+
+    #[test]
+    fn shard_specs_are_parsed() {
+        assert_eq!(
+            "2/8".parse::<ShardSpec>(),
+            Ok(ShardSpec { index: 2, count: 8 })
+        );
+        assert_eq!("1/1".parse::<ShardSpec>().unwrap().to_string(), "1/1");
+        for invalid in ["0/4", "5/4", "1/0", "2", "2/", "/2", "a/b", "-1/4"] {
+            assert!(invalid.parse::<ShardSpec>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn every_tile_is_in_exactly_one_shard() {
+        let tiles: Vec<_> = (-5..6).map(key).collect();
+        let shards: Vec<_> = (1..=3)
+            .map(|index| Shard::new(ShardSpec { index, count: 3 }, tiles.clone()))
+            .collect();
+        // including tiles that were not planned
+        for x in -10..10 {
+            let owners = shards.iter().filter(|shard| shard.contains(key(x))).count();
+            assert_eq!(owners, 1, "tile {x}");
+        }
+        for shard in &shards {
+            assert!(tiles.iter().any(|tile| shard.contains(*tile)));
+        }
+    }
+}
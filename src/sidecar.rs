@@ -0,0 +1,167 @@
+//! Sidecar metadata (`--sidecar`): a `<tile>.json` file next to each tile describing it, so
+//! catalogs (e.g. STAC) can be built without reading the LAZ files again
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    input::InputFile,
+    output::Output,
+    postprocess::open_reader,
+    schema::{Document, Versioned},
+    tile::TileKey,
+    units::{UnitNames, Units},
+};
+
+/// Number of points read at a time when counting the classes of a tile
+const BUFFER_SIZE: u64 = 64 * 1024;
+
+/// The file name of the sidecar of the tile with the given name, e.g. `tile_3_4.json`
+pub fn file_name(tile: &str) -> String {
+    Path::new(tile).with_extension("json").display().to_string()
+}
+
+/// The metadata of a single tile
+#[derive(Serialize, JsonSchema)]
+pub struct Sidecar<'a> {
+    /// The file name of the tile
+    name: &'a str,
+    tile_index: (i32, i32),
+    /// The Z index of the tile with `--tile-size-z`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    z_index: Option<i32>,
+    /// The quadrants of the tile covered by this file with `--adaptive`
+    #[serde(skip_serializing_if = "str::is_empty")]
+    quadtree_path: &'a str,
+    /// `[min x, min y, max x, max y]` of the area covered by the tile
+    bounds: [f64; 4],
+    points: u64,
+    /// `[min x, min y, min z]` of the points, from the tile header
+    min: [f64; 3],
+    /// `[max x, max y, max z]` of the points, from the tile header
+    max: [f64; 3],
+    /// The units of `area` and `density`
+    units: UnitNames,
+    area: f64,
+    density: f64,
+    /// Number of points of each classification
+    classes: BTreeMap<u8, u64>,
+    point_format: u8,
+    las_version: String,
+    /// The scale factors of the coordinates, `[x, y, z]`
+    scale: [f64; 3],
+    /// The offsets of the coordinates, `[x, y, z]`
+    offset: [f64; 3],
+    /// The input files with points in the tile
+    sources: Vec<&'a InputFile>,
+    processing: &'a Processing,
+}
+
+impl Document for Sidecar<'_> {
+    const SCHEMA_VERSION: &'static str = "1.0";
+}
+
+/// How the tiles were made
+#[derive(Serialize, JsonSchema)]
+pub struct Processing {
+    /// Name and version of the program
+    software: String,
+    /// The command line arguments of the run
+    arguments: Vec<String>,
+}
+
+/// Writes the sidecars of the finished tiles
+pub struct Sidecars {
+    inputs: Vec<InputFile>,
+    units: Units,
+    processing: Processing,
+}
+
+impl Sidecars {
+    /// Sidecars for a run over the given inputs, in processing order
+    pub fn new(inputs: Vec<InputFile>, units: Units) -> Self {
+        Self {
+            inputs,
+            units,
+            processing: Processing {
+                software: format!("lasretile {}", env!("CARGO_PKG_VERSION")),
+                arguments: std::env::args().skip(1).collect(),
+            },
+        }
+    }
+
+    /// Write the sidecar of a finished tile file with the points of the given inputs, covering
+    /// `bounds`, and publish it
+    pub fn write(
+        &self,
+        output: &Output,
+        name: &str,
+        tile_index: TileKey,
+        quadtree_path: &str,
+        bounds: [f64; 4],
+        sources: impl IntoIterator<Item = usize>,
+    ) -> Result<()> {
+        let path = output.path(name);
+        let mut reader = open_reader(&path)?;
+        let header = reader.header().clone();
+        let mut classes = BTreeMap::new();
+        let mut points = Vec::new();
+        loop {
+            points.clear();
+            if reader.read_points_into(BUFFER_SIZE, &mut points)? == 0 {
+                break;
+            }
+            for point in &points {
+                *classes.entry(u8::from(point.classification)).or_default() += 1;
+            }
+        }
+
+        let [x0, y0, x1, y1] = bounds;
+        let area = (x1 - x0) * (y1 - y0);
+        let header_bounds = header.bounds();
+        let transforms = header.transforms();
+        let sidecar = Sidecar {
+            name,
+            tile_index: (tile_index.x, tile_index.y),
+            z_index: tile_index.z,
+            quadtree_path,
+            bounds,
+            points: header.number_of_points(),
+            min: [
+                header_bounds.min.x,
+                header_bounds.min.y,
+                header_bounds.min.z,
+            ],
+            max: [
+                header_bounds.max.x,
+                header_bounds.max.y,
+                header_bounds.max.z,
+            ],
+            units: self.units.names(),
+            area: self.units.area(area),
+            density: self.units.density(header.number_of_points(), area),
+            classes,
+            point_format: header.point_format().to_u8()?,
+            las_version: header.version().to_string(),
+            scale: [transforms.x.scale, transforms.y.scale, transforms.z.scale],
+            offset: [
+                transforms.x.offset,
+                transforms.y.offset,
+                transforms.z.offset,
+            ],
+            sources: sources.into_iter().map(|i| &self.inputs[i]).collect(),
+            processing: &self.processing,
+        };
+
+        let sidecar_name = file_name(name);
+        let sidecar_path = output.path(&sidecar_name);
+        let file = std::fs::File::create(&sidecar_path)
+            .with_context(|| format!("create sidecar: {}", sidecar_path.display()))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &Versioned::new(&sidecar))
+            .with_context(|| format!("write sidecar: {}", sidecar_path.display()))?;
+        output.publish(&sidecar_name)
+    }
+}
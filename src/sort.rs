@@ -0,0 +1,344 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::postprocess::{create_writer, open_reader, temp_path};
+
+/// Maximum number of sorted runs that are merged at once, which bounds the number of open
+/// readers. If a tile has more runs than this, they are merged in several passes through
+/// temporary files.
+const MAX_MERGE_WAYS: usize = 64;
+
+/// Number of points buffered per run while merging
+const RUN_BUFFER_SIZE: u64 = 16 * 1024;
+
+/// A temporary file of points sorted by GPS time
+#[derive(Debug, Clone)]
+struct Run {
+    path: PathBuf,
+    len: u64,
+}
+
+/// Sort the points of a LAS/LAZ file by GPS time, replacing the file.
+///
+/// The points are read in blocks of at most `block_points` points, each of which is sorted in
+/// memory and written to a temporary file. The sorted blocks are then combined using a k-way
+/// merge, keeping memory usage bounded by the block size regardless of the size of the tile and
+/// of the order of its points. Points with the same GPS time keep their order. A tile that is
+/// already sorted is left as it is.
+pub fn sort_by_gps_time(path: &Path, block_points: usize) -> Result<()> {
+    let mut temp_files = Vec::new();
+    let result = (|| {
+        let Some((header, mut runs)) = sort_blocks(path, block_points, &mut temp_files)? else {
+            return Ok(());
+        };
+
+        // merge the runs in passes of at most `MAX_MERGE_WAYS` runs each, until the remaining
+        // runs can be merged directly into the final file. Each point is merged once per pass,
+        // and the temporary files of a pass are removed as soon as they are merged, so at most
+        // two copies of the points are on disk next to the tile.
+        while runs.len() > MAX_MERGE_WAYS {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(MAX_MERGE_WAYS));
+            for group in runs.chunks(MAX_MERGE_WAYS) {
+                let temp_path = temp_path(path, &format!("merge{}", temp_files.len()));
+                temp_files.push(temp_path.clone());
+                let len = merge_runs(group, &temp_path, &header)?;
+                remove_merged(group)?;
+                merged.push(Run {
+                    path: temp_path,
+                    len,
+                });
+            }
+            runs = merged;
+        }
+
+        let sorted_path = match runs.as_slice() {
+            // a tile that fits in a single block was sorted in memory
+            [run] => run.path.clone(),
+            _ => {
+                let sorted_path = temp_path(path, "sorted");
+                temp_files.push(sorted_path.clone());
+                merge_runs(&runs, &sorted_path, &header)?;
+                remove_merged(&runs)?;
+                sorted_path
+            }
+        };
+        std::fs::rename(&sorted_path, path)
+            .with_context(|| format!("replace {} with sorted tile", path.display()))
+    })();
+
+    for temp in &temp_files {
+        // the merged files have already been removed and the sorted file renamed if everything
+        // went well
+        let _ = std::fs::remove_file(temp);
+    }
+    result
+}
+
+/// Remove the temporary files of runs that were merged
+fn remove_merged(runs: &[Run]) -> Result<()> {
+    for run in runs {
+        std::fs::remove_file(&run.path)
+            .with_context(|| format!("remove {}", run.path.display()))?;
+    }
+    Ok(())
+}
+
+fn gps_time(point: &las::Point) -> Result<f64> {
+    point.gps_time.context("point without GPS time")
+}
+
+/// Sort the points in memory by GPS time, keeping the order of points with the same time
+fn sort_block(points: &mut [las::Point]) -> Result<()> {
+    for point in points.iter() {
+        gps_time(point)?;
+    }
+    points.sort_by(|a, b| {
+        let time = |point: &las::Point| point.gps_time.unwrap_or_default();
+        time(a).total_cmp(&time(b))
+    });
+    Ok(())
+}
+
+/// Read the file in blocks of at most `block_points` points, and write each block sorted by GPS
+/// time to a temporary file. Returns `None` without keeping any temporary file if the points are
+/// already sorted.
+fn sort_blocks(
+    path: &Path,
+    block_points: usize,
+    temp_files: &mut Vec<PathBuf>,
+) -> Result<Option<(las::Header, Vec<Run>)>> {
+    let mut reader = open_reader(path)?;
+    let header = reader.header().clone();
+
+    let mut runs = Vec::new();
+    let mut sorted = true;
+    let mut previous = f64::NEG_INFINITY;
+    let mut points = Vec::new();
+    loop {
+        points.clear();
+        if reader.read_points_into(block_points.max(1) as u64, &mut points)? == 0 {
+            break;
+        }
+        for point in &points {
+            let time = gps_time(point)?;
+            sorted &= time >= previous;
+            previous = time;
+        }
+        if sorted && runs.is_empty() && header.number_of_points() == points.len() as u64 {
+            // the whole tile was read and is already in order
+            return Ok(None);
+        }
+        sort_block(&mut points)?;
+        let len = points.len() as u64;
+
+        let run_path = temp_path(path, &format!("merge{}", temp_files.len()));
+        temp_files.push(run_path.clone());
+        let mut writer = create_writer(&run_path, &header)?;
+        for point in points.drain(..) {
+            writer.write_point(point)?;
+        }
+        writer.close()?;
+        runs.push(Run {
+            path: run_path,
+            len,
+        });
+    }
+
+    if sorted {
+        for run in &runs {
+            let _ = std::fs::remove_file(&run.path);
+        }
+        return Ok(None);
+    }
+    Ok(Some((header, runs)))
+}
+
+/// A source of points sorted by GPS time
+trait SortedPoints {
+    fn next_point(&mut self) -> Result<Option<las::Point>>;
+}
+
+/// Buffered sequential reading of the points in a single run
+struct RunCursor {
+    reader: las::Reader,
+    remaining: u64,
+    buffer: VecDeque<las::Point>,
+}
+
+impl RunCursor {
+    fn open(run: &Run) -> Result<Self> {
+        Ok(Self {
+            reader: open_reader(&run.path)?,
+            remaining: run.len,
+            buffer: VecDeque::new(),
+        })
+    }
+}
+
+impl SortedPoints for RunCursor {
+    fn next_point(&mut self) -> Result<Option<las::Point>> {
+        if self.buffer.is_empty() && self.remaining > 0 {
+            let mut points = Vec::new();
+            let n = self
+                .reader
+                .read_points_into(self.remaining.min(RUN_BUFFER_SIZE), &mut points)?;
+            anyhow::ensure!(n > 0, "unexpected end of points while merging");
+            self.remaining -= n;
+            self.buffer.extend(points);
+        }
+        Ok(self.buffer.pop_front())
+    }
+}
+
+/// Heap entry ordering the next point of each run by GPS time, then by run to keep the merge
+/// stable
+struct HeapEntry {
+    time: f64,
+    run: usize,
+    point: las::Point,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.time
+            .total_cmp(&other.time)
+            .then(self.run.cmp(&other.run))
+    }
+}
+
+/// Merge the sorted runs into a new file at `output`, returning the number of points written
+fn merge_runs(runs: &[Run], output: &Path, header: &las::Header) -> Result<u64> {
+    let mut cursors = runs
+        .iter()
+        .map(RunCursor::open)
+        .collect::<Result<Vec<_>>>()?;
+    let mut writer = create_writer(output, header)?;
+    let written = merge_sorted(&mut cursors, |point| Ok(writer.write_point(point)?))?;
+    writer.close()?;
+    Ok(written)
+}
+
+/// Pass the points of all sources to `write` in order of GPS time, taking points with the same
+/// time from the earlier source first. Returns the number of points written.
+fn merge_sorted(
+    sources: &mut [impl SortedPoints],
+    mut write: impl FnMut(las::Point) -> Result<()>,
+) -> Result<u64> {
+    let mut heap = BinaryHeap::with_capacity(sources.len());
+    for (run, source) in sources.iter_mut().enumerate() {
+        if let Some(point) = source.next_point()? {
+            heap.push(Reverse(HeapEntry {
+                time: gps_time(&point)?,
+                run,
+                point,
+            }));
+        }
+    }
+
+    let mut written = 0;
+    while let Some(Reverse(entry)) = heap.pop() {
+        write(entry.point)?;
+        written += 1;
+
+        if let Some(point) = sources[entry.run].next_point()? {
+            heap.push(Reverse(HeapEntry {
+                time: gps_time(&point)?,
+                run: entry.run,
+                point,
+            }));
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl SortedPoints for std::vec::IntoIter<las::Point> {
+        fn next_point(&mut self) -> Result<Option<las::Point>> {
+            Ok(self.next())
+        }
+    }
+
+    /// Points with the given GPS times, numbered by their position in the intensity
+    fn points(times: impl IntoIterator<Item = f64>) -> Vec<las::Point> {
+        times
+            .into_iter()
+            .enumerate()
+            .map(|(i, time)| las::Point {
+                gps_time: Some(time),
+                intensity: i as u16,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Sort the points the way a tile is sorted, in blocks that are merged
+    fn sort_in_blocks(points: &[las::Point], block_points: usize) -> Vec<las::Point> {
+        let mut blocks: Vec<_> = points
+            .chunks(block_points)
+            .map(|block| {
+                let mut block = block.to_vec();
+                sort_block(&mut block).unwrap();
+                block.into_iter()
+            })
+            .collect();
+        let mut sorted = Vec::new();
+        let written = merge_sorted(&mut blocks, |point| {
+            sorted.push(point);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(written, points.len() as u64);
+        sorted
+    }
+
+    fn times(points: &[las::Point]) -> Vec<f64> {
+        points.iter().map(|point| point.gps_time.unwrap()).collect()
+    }
+
+    #[test]
+    fn sorts_reversed_points() {
+        let reversed = points((0..1000).rev().map(f64::from));
+        let sorted = sort_in_blocks(&reversed, 64);
+        assert_eq!(times(&sorted), (0..1000).map(f64::from).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_the_order_of_points_with_the_same_time() {
+        // interleaved flightlines with repeated times, unsorted within each block
+        let unsorted = points((0..500).map(|i| f64::from((i * 7919) % 50)));
+        let sorted = sort_in_blocks(&unsorted, 37);
+
+        let mut expected = unsorted.clone();
+        expected.sort_by(|a, b| a.gps_time.unwrap().total_cmp(&b.gps_time.unwrap()));
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn rejects_points_without_time() {
+        let mut block = points([2.0, 1.0]);
+        block[1].gps_time = None;
+        assert!(sort_block(&mut block).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! File Source IDs of the output tiles (`--file-source-id`)
+
+use anyhow::{Context, Result};
+
+use crate::tile::TileKey;
+
+/// Assigns the File Source ID of each output tile from a template like `{n}` or `1{x}{y}`
+pub struct FileSourceIds {
+    template: String,
+    /// The sequential number of the next tile, starting at 1
+    next: u32,
+}
+
+impl FileSourceIds {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_owned(),
+            next: 1,
+        }
+    }
+
+    /// The File Source ID of a new tile. `{n}` in the template is replaced by the sequential
+    /// number of the tile, `{x}`, `{y}` and `{z}` by its index and `{part}` by its part with
+    /// `--split-by`.
+    pub fn assign(&mut self, key: TileKey, name: &str) -> Result<u16> {
+        let n = self.next;
+        self.next += 1;
+        let id = self
+            .template
+            .replace("{n}", &n.to_string())
+            .replace("{x}", &key.x.to_string())
+            .replace("{y}", &key.y.to_string())
+            .replace("{z}", &key.z.unwrap_or_default().to_string())
+            .replace("{part}", &key.part.unwrap_or_default().to_string());
+        id.parse().with_context(|| {
+            format!("File Source ID {id:?} of tile {name} is not a number from 0 to 65535")
+        })
+    }
+}
@@ -0,0 +1,378 @@
+//! Existing spatial indexes of the inputs, a LASindex `.lax` file next to a LAS/LAZ file or the
+//! hierarchy of a COPC file, to only read the points of an input near the area of interest
+
+use std::{
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use anyhow::{Context, Result};
+
+use crate::input::InputFile;
+
+/// The ranges of point indices of an input that may hold points within the area of interest, if
+/// it has a spatial index. `wanted` tells whether a cell `[min x, min y, max x, max y]` of the
+/// index overlaps the area of interest.
+pub fn point_ranges(
+    file: &InputFile,
+    header: &las::Header,
+    wanted: impl Fn([f64; 4]) -> bool,
+) -> Result<Option<Vec<Range<u64>>>> {
+    let ranges = if let Some(info) = header
+        .vlrs()
+        .iter()
+        .find(|vlr| vlr.user_id == "copc" && vlr.record_id == 1)
+    {
+        copc_ranges(file, &info.data, wanted)
+            .with_context(|| format!("read COPC hierarchy of {file}"))?
+    } else if let InputFile::Local(path) = file
+        && let lax = path.with_extension("lax")
+        && lax.is_file()
+    {
+        let data = std::fs::read(&lax).with_context(|| format!("read {}", lax.display()))?;
+        lax_ranges(&data, wanted).with_context(|| format!("read {}", lax.display()))?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(merge(ranges)))
+}
+
+/// Sort the ranges and join the ones that touch
+fn merge(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// The parts of the sorted, disjoint `ranges` that are not in the sorted, disjoint `removed`
+pub fn subtract(ranges: &[Range<u64>], removed: &[Range<u64>]) -> Vec<Range<u64>> {
+    let mut left = Vec::with_capacity(ranges.len());
+    let mut removed = removed.iter().peekable();
+    for range in ranges {
+        let mut start = range.start;
+        while start < range.end {
+            // skip the removed ranges ending before this point
+            while removed.next_if(|r| r.end <= start).is_some() {}
+            match removed.peek() {
+                Some(r) if r.start < range.end => {
+                    if start < r.start {
+                        left.push(start..r.start);
+                    }
+                    start = r.end;
+                }
+                _ => {
+                    left.push(start..range.end);
+                    break;
+                }
+            }
+        }
+    }
+    left
+}
+
+/// The point ranges of the cells of a LASindex quadtree (`lasindex`) that are wanted. The file
+/// holds the spatial index (`LASS`) with its quadtree (`LASQ`), followed by the point intervals
+/// of the cells (`LASV`), each part starting with its signature and version.
+fn lax_ranges(mut data: &[u8], wanted: impl Fn([f64; 4]) -> bool) -> Result<Vec<Range<u64>>> {
+    let read = &mut data;
+    signature(read, b"LASX")?;
+    let _version = u32::from_le_bytes(array(read)?);
+
+    signature(read, b"LASS")?;
+    let kind = u32::from_le_bytes(array(read)?);
+    anyhow::ensure!(kind == 0, "unsupported spatial index type {kind}");
+    signature(read, b"LASQ")?;
+    let _version = u32::from_le_bytes(array(read)?);
+    let _levels = u32::from_le_bytes(array(read)?);
+    let _level_index = u32::from_le_bytes(array(read)?);
+    let _implicit_levels = u32::from_le_bytes(array(read)?);
+    let [min_x, max_x, min_y, max_y] = [array(read)?, array(read)?, array(read)?, array(read)?]
+        .map(|bytes| f64::from(f32::from_le_bytes(bytes)));
+
+    signature(read, b"LASV")?;
+    let _version = u32::from_le_bytes(array(read)?);
+    let cells = u32::from_le_bytes(array(read)?);
+    let mut ranges = Vec::new();
+    for _ in 0..cells {
+        let cell = i32::from_le_bytes(array(read)?);
+        let intervals = u32::from_le_bytes(array(read)?);
+        let _points = u32::from_le_bytes(array(read)?);
+        let keep = u32::try_from(cell).map_or(true, |cell| {
+            wanted(quadtree_cell(cell, [min_x, min_y, max_x, max_y]))
+        });
+        for _ in 0..intervals {
+            let start = u32::from_le_bytes(array(read)?);
+            let end = u32::from_le_bytes(array(read)?);
+            if keep {
+                // the end of an interval is inclusive
+                ranges.push(u64::from(start)..u64::from(end) + 1);
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// `[min x, min y, max x, max y]` of a cell of a LASindex quadtree. The cells are numbered level
+/// by level, and within a level by the quadrants taken from the root, two bits per level.
+fn quadtree_cell(cell: u32, [mut x0, mut y0, mut x1, mut y1]: [f64; 4]) -> [f64; 4] {
+    let mut index = u64::from(cell);
+    let mut level = 0;
+    while index >= 1 << (2 * level) {
+        index -= 1 << (2 * level);
+        level += 1;
+    }
+    for level in (0..level).rev() {
+        let quadrant = (index >> (2 * level)) & 3;
+        let (mid_x, mid_y) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+        if quadrant & 1 == 0 {
+            x1 = mid_x;
+        } else {
+            x0 = mid_x;
+        }
+        if quadrant & 2 == 0 {
+            y1 = mid_y;
+        } else {
+            y0 = mid_y;
+        }
+    }
+    [x0, y0, x1, y1]
+}
+
+/// A node of the octree of a COPC file with points
+struct CopcNode {
+    bounds: [f64; 4],
+    offset: u64,
+    bytes: u64,
+    points: u64,
+}
+
+/// A LAZ chunk of a COPC file, holding the points of one node of its octree
+#[derive(Debug, Clone)]
+pub struct CopcChunk {
+    /// `[min x, min y, max x, max y]` of the node, all points of the chunk lie within it
+    pub bounds: [f64; 4],
+    /// The indices of the points of the chunk in the file
+    pub points: Range<u64>,
+    /// The position of the compressed chunk in the file
+    pub offset: u64,
+    /// The size of the compressed chunk
+    pub bytes: u64,
+}
+
+/// The LAZ chunks of a COPC file in the order of their offsets, `None` for other files
+pub fn copc_chunks(file: &InputFile, header: &las::Header) -> Result<Option<Vec<CopcChunk>>> {
+    let Some(info) = header
+        .vlrs()
+        .iter()
+        .find(|vlr| vlr.user_id == "copc" && vlr.record_id == 1)
+    else {
+        return Ok(None);
+    };
+    let nodes =
+        copc_nodes(file, &info.data).with_context(|| format!("read COPC hierarchy of {file}"))?;
+    let mut start = 0;
+    let chunks = nodes
+        .into_iter()
+        .map(|node| {
+            let chunk = CopcChunk {
+                bounds: node.bounds,
+                points: start..start + node.points,
+                offset: node.offset,
+                bytes: node.bytes,
+            };
+            start += node.points;
+            chunk
+        })
+        .collect();
+    Ok(Some(chunks))
+}
+
+/// The point ranges of the nodes of a COPC octree that are wanted. Each node is stored as one LAZ
+/// chunk, in the order of their offsets in the file.
+fn copc_ranges(
+    file: &InputFile,
+    info: &[u8],
+    wanted: impl Fn([f64; 4]) -> bool,
+) -> Result<Vec<Range<u64>>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for node in copc_nodes(file, info)? {
+        if wanted(node.bounds) {
+            ranges.push(start..start + node.points);
+        }
+        start += node.points;
+    }
+    Ok(ranges)
+}
+
+/// The nodes of a COPC octree with points, sorted by their offsets in the file
+fn copc_nodes(file: &InputFile, mut info: &[u8]) -> Result<Vec<CopcNode>> {
+    let read = &mut info;
+    let center_x = f64::from_le_bytes(array(read)?);
+    let center_y = f64::from_le_bytes(array(read)?);
+    let _center_z = f64::from_le_bytes(array(read)?);
+    let halfsize = f64::from_le_bytes(array(read)?);
+    let _spacing = f64::from_le_bytes(array(read)?);
+    let root_offset = u64::from_le_bytes(array(read)?);
+    let root_size = u64::from_le_bytes(array(read)?);
+
+    let mut input = file.open()?;
+    let mut nodes = Vec::new();
+    let mut pages = vec![(root_offset, root_size)];
+    while let Some((offset, size)) = pages.pop() {
+        let mut page = vec![0; usize::try_from(size)?];
+        input.seek(SeekFrom::Start(offset))?;
+        input
+            .read_exact(&mut page)
+            .context("truncated hierarchy page")?;
+        for mut entry in page.chunks_exact(32) {
+            let read = &mut entry;
+            let [depth, x, y, _z] =
+                [array(read)?, array(read)?, array(read)?, array(read)?].map(i32::from_le_bytes);
+            let offset = u64::from_le_bytes(array(read)?);
+            let byte_size = i32::from_le_bytes(array(read)?);
+            let point_count = i32::from_le_bytes(array(read)?);
+            match point_count {
+                -1 => pages.push((offset, u64::try_from(byte_size)?)),
+                0 => {}
+                points => {
+                    let side = 2.0 * halfsize / f64::from(1u32 << depth.clamp(0, 31));
+                    let (x0, y0) = (
+                        center_x - halfsize + f64::from(x) * side,
+                        center_y - halfsize + f64::from(y) * side,
+                    );
+                    nodes.push(CopcNode {
+                        bounds: [x0, y0, x0 + side, y0 + side],
+                        offset,
+                        bytes: u64::try_from(byte_size)?,
+                        points: u64::try_from(points)?,
+                    });
+                }
+            }
+        }
+    }
+    nodes.sort_by_key(|node| node.offset);
+    Ok(nodes)
+}
+
+fn signature(read: &mut &[u8], expected: &[u8; 4]) -> Result<()> {
+    let found: [u8; 4] = array(read)?;
+    anyhow::ensure!(
+        &found == expected,
+        "expected {:?}, found {:?}",
+        String::from_utf8_lossy(expected),
+        String::from_utf8_lossy(&found)
+    );
+    Ok(())
+}
+
+fn array<const N: usize>(read: &mut &[u8]) -> Result<[u8; N]> {
+    let mut array = [0; N];
+    read.read_exact(&mut array).context("truncated index")?;
+    Ok(array)
+}
+
+#[cfg(test)]
+// the expected ranges are written as arrays, also when there is only one
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    /// A `.lax` file laid out as `lasindex` writes it: a quadtree of one level over
+    /// `[0, 100] x [0, 100]`, with points 0-9 in the lower left cell and points 10-19 and 30-39 in
+    /// the upper right cell
+    fn lax() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(b"LASX");
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"LASS");
+        data.extend(0u32.to_le_bytes()); // quadtree
+        data.extend(b"LASQ");
+        data.extend(0u32.to_le_bytes());
+        for value in [1u32, 0, 0] {
+            // levels, level index, implicit levels
+            data.extend(value.to_le_bytes());
+        }
+        for bound in [0f32, 100.0, 0.0, 100.0] {
+            // min x, max x, min y, max y
+            data.extend(bound.to_le_bytes());
+        }
+        data.extend(b"LASV");
+        data.extend(0u32.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        // cell, intervals, points, then the inclusive intervals
+        for value in [1u32, 1, 10, 0, 9, 4, 2, 20, 10, 19, 30, 39] {
+            data.extend(value.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn lax_ranges_of_the_wanted_cells() {
+        let overlaps = |area: [f64; 4]| {
+            move |[x0, y0, x1, y1]: [f64; 4]| {
+                x0 < area[2] && x1 > area[0] && y0 < area[3] && y1 > area[1]
+            }
+        };
+        assert_eq!(
+            lax_ranges(&lax(), overlaps([60.0, 60.0, 70.0, 70.0])).unwrap(),
+            [10..20, 30..40]
+        );
+        assert_eq!(
+            lax_ranges(&lax(), overlaps([10.0, 10.0, 20.0, 20.0])).unwrap(),
+            [0..10]
+        );
+        assert_eq!(
+            lax_ranges(&lax(), overlaps([10.0, 60.0, 20.0, 70.0])).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn lax_without_quadtree_signature_is_an_error() {
+        let mut data = lax();
+        data.drain(16..24);
+        assert!(lax_ranges(&data, |_| true).is_err());
+    }
+
+    #[test]
+    fn subtract_removes_the_overlapping_parts() {
+        assert_eq!(subtract(&[0..10, 12..20], &[]), [0..10, 12..20]);
+        assert_eq!(subtract(&[0..10, 12..20], &[5..15]), [0..5, 15..20]);
+        assert_eq!(subtract(&[0..10], &[0..2, 4..6, 8..10]), [2..4, 6..8]);
+        assert_eq!(subtract(&[0..10, 12..20], &[0..30]), []);
+        // touching ranges remove nothing
+        assert_eq!(subtract(&[5..10], &[0..5, 10..12]), [5..10]);
+    }
+
+    #[test]
+    fn subtract_agrees_with_removing_point_by_point() {
+        let ranges = [0..7, 9..15, 20..31, 33..34];
+        let removed = [2..3, 6..10, 14..22, 25..26, 30..40];
+        let points =
+            |ranges: &[Range<u64>]| -> Vec<u64> { ranges.iter().cloned().flatten().collect() };
+        let expected: Vec<u64> = points(&ranges)
+            .into_iter()
+            .filter(|i| !removed.iter().any(|r| r.contains(i)))
+            .collect();
+        assert_eq!(points(&subtract(&ranges, &removed)), expected);
+    }
+
+    #[test]
+    fn quadtree_cells_are_numbered_level_by_level() {
+        let bounds = [0.0, 0.0, 100.0, 100.0];
+        assert_eq!(quadtree_cell(0, bounds), bounds);
+        assert_eq!(quadtree_cell(1, bounds), [0.0, 0.0, 50.0, 50.0]);
+        assert_eq!(quadtree_cell(2, bounds), [50.0, 0.0, 100.0, 50.0]);
+        assert_eq!(quadtree_cell(3, bounds), [0.0, 50.0, 50.0, 100.0]);
+        assert_eq!(quadtree_cell(4, bounds), [50.0, 50.0, 100.0, 100.0]);
+        // the first cell of level 2, the lower left quadrant of the lower left quadrant
+        assert_eq!(quadtree_cell(5, bounds), [0.0, 0.0, 25.0, 25.0]);
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    cli::SplitArgs, filter::PointFilters, input, memory::MemoryPlan, output::Output,
+    postprocess::create_writer, progress_bar, reader::InputReader,
+};
+
+/// The file being written for the current chunk of an input
+struct Chunk {
+    name: String,
+    writer: las::Writer<std::io::BufWriter<std::fs::File>>,
+    points: u64,
+}
+
+/// Split each input into files of at most `--max-points` points, named after the input with the
+/// index of the chunk appended (`<name>_<index>.<ext>`). Inputs with the same file name in
+/// different folders are prefixed with their parent folders (`<folder>_<name>_<index>.<ext>`),
+/// and those that still end up with the same name are numbered (`<folder>_<name>-2_<index>.<ext>`).
+pub fn run(args: &SplitArgs) -> Result<()> {
+    let mut skipped = input::SkipReport::default();
+    let input_files = input::collect_inputs(&args.input, &mut skipped)?;
+    let headers = input::scan_headers(
+        input_files,
+        args.input.header_cache.as_deref(),
+        &mut skipped,
+    );
+    skipped.print_summary();
+    anyhow::ensure!(!headers.is_empty(), "no input files to split");
+
+    let total_points: u64 = headers.iter().map(|(_, h)| h.number_of_points()).sum();
+    tracing::info!(
+        "Splitting {} input files with a total {}M points.",
+        headers.len(),
+        total_points / 1_000_000
+    );
+
+    let output = Output::new(&args.output, None)?;
+    let mut filters = PointFilters::load(&args.filters)?;
+    let options = las::ReaderOptions::default().with_laz_parallelism(las::LazParallelism::Yes);
+    let files: Vec<_> = headers.iter().map(|(path, _)| path.clone()).collect();
+    let stems = output_stems(&input::unique_names(&files));
+    let reader = InputReader::spawn(
+        files,
+        options,
+        MemoryPlan::new(None, 1, 1).read_buffer_points,
+    );
+
+    let pb = progress_bar(total_points);
+    let mut files_written = 0;
+    for (i_file, (path, header)) in headers.iter().enumerate() {
+        pb.set_message(format!("{}/{}", i_file + 1, headers.len()));
+        let stem = &stems[i_file];
+        let extension = if header.point_format().is_compressed {
+            "laz"
+        } else {
+            "las"
+        };
+
+        let mut chunk: Option<Chunk> = None;
+        let mut index = 0;
+        while let Some(mut points) = reader.next()? {
+            pb.inc(points.len() as u64);
+            filters.apply(&mut points, path)?;
+            for point in points.drain(..) {
+                let current = match &mut chunk {
+                    Some(current) => current,
+                    None => {
+                        let name = format!("{stem}_{index}.{extension}");
+                        index += 1;
+                        files_written += 1;
+                        let writer = create_writer(&output.path(&name), header)?;
+                        chunk.insert(Chunk {
+                            name,
+                            writer,
+                            points: 0,
+                        })
+                    }
+                };
+                current
+                    .writer
+                    .write_point(point)
+                    .context("Could not write point")?;
+                current.points += 1;
+                if current.points >= args.max_points.get() {
+                    finish_chunk(&output, chunk.take().expect("chunk is open"))?;
+                }
+            }
+            reader.recycle(points);
+        }
+        if let Some(current) = chunk {
+            finish_chunk(&output, current)?;
+        }
+    }
+    pb.finish_with_message("Done");
+    output.finish()?;
+
+    tracing::info!("Wrote {files_written} files to {}", args.output.display());
+    Ok(())
+}
+
+/// The names to base the output files of the inputs on: their unique names without extension and
+/// with folder separators replaced, numbered where that makes them the same (e.g. `a/b_c.laz`
+/// and `a_b/c.laz`)
+fn output_stems(names: &[String]) -> Vec<String> {
+    let stems: Vec<String> = names.iter().map(|name| output_stem(name)).collect();
+    let mut taken: HashSet<String> = stems.iter().cloned().collect();
+    let mut seen = HashSet::new();
+    stems
+        .into_iter()
+        .map(|stem| {
+            if seen.insert(stem.clone()) {
+                return stem;
+            }
+            let numbered = (2..)
+                .map(|n| format!("{stem}-{n}"))
+                .find(|numbered| !taken.contains(numbered))
+                .expect("some number is free");
+            taken.insert(numbered.clone());
+            numbered
+        })
+        .collect()
+}
+
+/// The unique name of an input without its extension and with folder separators replaced
+fn output_stem(name: &str) -> String {
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem,
+        _ => name,
+    };
+    stem.replace('/', "_")
+}
+
+fn finish_chunk(output: &Output, mut chunk: Chunk) -> Result<()> {
+    chunk
+        .writer
+        .close()
+        .with_context(|| format!("close {}", chunk.name))?;
+    output.publish(&chunk.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stems(names: &[&str]) -> Vec<String> {
+        output_stems(
+            &names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn output_stems_replace_folder_separators() {
+        assert_eq!(
+            stems(&["a/b.laz", "c.las", ".hidden"]),
+            ["a_b", "c", ".hidden"]
+        );
+    }
+
+    #[test]
+    fn output_stems_that_collide_are_numbered() {
+        assert_eq!(
+            stems(&["a/b_c.laz", "a_b/c.laz", "a_b_c-2.laz"]),
+            ["a_b_c", "a_b_c-3", "a_b_c-2"]
+        );
+    }
+}
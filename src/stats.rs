@@ -0,0 +1,263 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    schema::{Document, Versioned},
+    tile::TileKey,
+    units::{UnitNames, Units},
+};
+
+/// Maximum number of bins in a histogram before the bin size is doubled. Prevents outliers (e.g.
+/// noise points far away) from using unbounded memory.
+const MAX_HISTOGRAM_BINS: i64 = 1 << 16;
+
+/// Histogram with fixed-size bins that grows to cover all values added to it
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    requested_bin_size: f64,
+    bin_size: f64,
+    first_bin: i64,
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn new(bin_size: f64) -> Self {
+        Self {
+            requested_bin_size: bin_size,
+            bin_size,
+            first_bin: 0,
+            counts: Vec::new(),
+        }
+    }
+
+    /// Remove all values, restoring the originally requested bin size
+    pub fn clear(&mut self) {
+        *self = Self::new(self.requested_bin_size);
+    }
+
+    pub fn bin_size(&self) -> f64 {
+        self.bin_size
+    }
+
+    pub fn add(&mut self, value: f64) {
+        let mut bin = (value / self.bin_size).floor() as i64;
+        while !self.counts.is_empty() && self.span_with(bin) > MAX_HISTOGRAM_BINS {
+            self.coarsen();
+            bin = (value / self.bin_size).floor() as i64;
+        }
+
+        if self.counts.is_empty() {
+            self.first_bin = bin;
+        } else if bin < self.first_bin {
+            let grow = (self.first_bin - bin) as usize;
+            self.counts.splice(0..0, std::iter::repeat_n(0, grow));
+            self.first_bin = bin;
+        }
+
+        let index = (bin - self.first_bin) as usize;
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+    }
+
+    /// The number of bins needed to also cover `bin`
+    fn span_with(&self, bin: i64) -> i64 {
+        let last_bin = self.first_bin + self.counts.len() as i64 - 1;
+        bin.max(last_bin) - bin.min(self.first_bin) + 1
+    }
+
+    /// Double the bin size, merging the counts of neighboring bins
+    fn coarsen(&mut self) {
+        let first_bin = self.first_bin.div_euclid(2);
+        let mut counts = vec![0; self.counts.len() / 2 + 2];
+        for (i, count) in self.counts.iter().enumerate() {
+            let bin = (self.first_bin + i as i64).div_euclid(2);
+            counts[(bin - first_bin) as usize] += count;
+        }
+        while counts.last() == Some(&0) {
+            counts.pop();
+        }
+
+        self.bin_size *= 2.0;
+        self.first_bin = first_bin;
+        self.counts = counts;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Estimate the value at the given percentile (0-100) by interpolating within the bin that
+    /// contains it
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+
+        let target = (percentile / 100.0 * total as f64).clamp(0.0, total as f64);
+        let mut cumulative = 0;
+        for (i, &count) in self.counts.iter().enumerate() {
+            if count > 0 && (cumulative + count) as f64 >= target {
+                let fraction = (target - cumulative as f64) / count as f64;
+                let lower = (self.first_bin + i as i64) as f64 * self.bin_size;
+                return Some(lower + fraction * self.bin_size);
+            }
+            cumulative += count;
+        }
+        None
+    }
+
+    /// The non-empty bins as `(lower bound, count)` pairs
+    pub fn bins(&self) -> Vec<(f64, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, &count)| ((self.first_bin + i as i64) as f64 * self.bin_size, count))
+            .collect()
+    }
+}
+
+/// Statistics about the points written to a single tile
+#[derive(Debug, Clone)]
+pub struct TileStats {
+    pub points: u64,
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+    pub z_histogram: Histogram,
+}
+
+impl TileStats {
+    pub fn new(z_bin_size: f64) -> Self {
+        Self {
+            points: 0,
+            min: [f64::INFINITY; 3],
+            max: [f64::NEG_INFINITY; 3],
+            z_histogram: Histogram::new(z_bin_size),
+        }
+    }
+
+    /// Reset the statistics as if no points were added
+    pub fn clear(&mut self) {
+        self.points = 0;
+        self.min = [f64::INFINITY; 3];
+        self.max = [f64::NEG_INFINITY; 3];
+        self.z_histogram.clear();
+    }
+
+    pub fn add(&mut self, point: &las::Point) {
+        self.points += 1;
+        for (i, v) in [point.x, point.y, point.z].into_iter().enumerate() {
+            self.min[i] = self.min[i].min(v);
+            self.max[i] = self.max[i].max(v);
+        }
+        self.z_histogram.add(point.z);
+    }
+
+    /// The given Z percentile, clamped to the actual Z range of the points
+    pub fn z_percentile(&self, percentile: f64) -> Option<f64> {
+        self.z_histogram
+            .percentile(percentile)
+            .map(|z| z.clamp(self.min[2], self.max[2]))
+    }
+}
+
+/// Statistics of a finished tile together with its identity
+#[derive(Debug, Clone)]
+pub struct TileSummary {
+    pub name: String,
+    pub tile_index: TileKey,
+    /// The quadrants of the tile covered with `--adaptive`, empty for the whole tile
+    pub quadtree_path: String,
+    /// `[min x, min y, max x, max y]` of the area covered
+    pub bounds: [f64; 4],
+    pub stats: TileStats,
+}
+
+impl TileSummary {
+    /// The area covered, in coordinate units
+    pub fn area(&self) -> f64 {
+        let [x0, y0, x1, y1] = self.bounds;
+        (x1 - x0) * (y1 - y0)
+    }
+}
+
+/// Statistics of all written tiles
+#[derive(Serialize, JsonSchema)]
+pub struct StatsReport<'a> {
+    /// The units of the `area` and `density` of each tile
+    units: UnitNames,
+    tiles: Vec<TileStatsReport<'a>>,
+}
+
+impl Document for StatsReport<'_> {
+    const SCHEMA_VERSION: &'static str = "1.2";
+}
+
+#[derive(Serialize, JsonSchema)]
+struct TileStatsReport<'a> {
+    name: &'a str,
+    tile_index: (i32, i32),
+    /// The Z index of the tile with `--tile-size-z`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    z_index: Option<i32>,
+    /// The quadrants of the tile covered by this file with `--adaptive` (`0` = south-west, `1` =
+    /// south-east, `2` = north-west, `3` = north-east)
+    #[serde(skip_serializing_if = "str::is_empty")]
+    quadtree_path: &'a str,
+    points: u64,
+    min: [f64; 3],
+    max: [f64; 3],
+    area: f64,
+    density: f64,
+    z: ZStatsReport,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ZStatsReport {
+    p1: Option<f64>,
+    p50: Option<f64>,
+    p99: Option<f64>,
+    /// The bin size of the histogram, may be larger than requested for tiles with a large Z range
+    bin_size: f64,
+    /// `(lower bound, count)` of each non-empty bin
+    histogram: Vec<(f64, u64)>,
+}
+
+/// Write the statistics of all tiles as a JSON report
+pub fn write_report(path: &Path, units: &Units, tiles: &[TileSummary]) -> Result<()> {
+    let report = StatsReport {
+        units: units.names(),
+        tiles: tiles
+            .iter()
+            .map(|tile| TileStatsReport {
+                name: &tile.name,
+                tile_index: (tile.tile_index.x, tile.tile_index.y),
+                z_index: tile.tile_index.z,
+                quadtree_path: &tile.quadtree_path,
+                points: tile.stats.points,
+                min: tile.stats.min,
+                max: tile.stats.max,
+                area: units.area(tile.area()),
+                density: units.density(tile.stats.points, tile.area()),
+                z: ZStatsReport {
+                    p1: tile.stats.z_percentile(1.0),
+                    p50: tile.stats.z_percentile(50.0),
+                    p99: tile.stats.z_percentile(99.0),
+                    bin_size: tile.stats.z_histogram.bin_size(),
+                    histogram: tile.stats.z_histogram.bins(),
+                },
+            })
+            .collect(),
+    };
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create stats report: {}", path.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &Versioned::new(&report))
+        .with_context(|| format!("write stats report: {}", path.display()))
+}
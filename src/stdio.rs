@@ -0,0 +1,112 @@
+//! Reading a LAS/LAZ stream from stdin and writing the output to stdout, for using lasretile
+//! inside Unix pipelines. Both are selected with a path of `-`.
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+
+/// The file the stream on stdin was copied to, once it was first opened
+static STDIN: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Whether the path stands for stdin or stdout
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// A reader for the LAS/LAZ stream on stdin. LAS readers need to seek, so the whole stream is
+/// copied to a file in the system temporary directory the first time, and each reader opens it.
+pub fn open_stdin() -> Result<File> {
+    let mut stdin = STDIN.lock().expect("not poisoned");
+    let path = match &*stdin {
+        Some(path) => path.clone(),
+        None => {
+            let path = spool_path("stdin")?;
+            let mut file =
+                File::create(&path).with_context(|| format!("create {}", path.display()))?;
+            std::io::copy(&mut std::io::stdin().lock(), &mut file)
+                .context("read LAS/LAZ stream from stdin")?;
+            stdin.insert(path).clone()
+        }
+    };
+    File::open(&path).with_context(|| format!("open {}", path.display()))
+}
+
+/// The path of a file named `name` in the folder where streams from stdin and to stdout are
+/// kept while they are read or assembled
+pub fn spool_path(name: &str) -> Result<PathBuf> {
+    let dir = spool_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("create folder: {}", dir.display()))?;
+    Ok(dir.join(name))
+}
+
+/// Write the complete file at `path` to stdout
+pub fn copy_to_stdout(path: &Path) -> Result<()> {
+    let mut file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut stdout = std::io::stdout().lock();
+    std::io::copy(&mut file, &mut stdout)
+        .and_then(|_| stdout.flush())
+        .context("write to stdout")?;
+    Ok(())
+}
+
+/// The folder streams are kept in, in the system temporary directory
+fn spool_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("lasretile-{}-stdio", std::process::id()))
+}
+
+/// Remove the copy of stdin and any stream assembled for stdout
+pub fn cleanup() {
+    STDIN.lock().expect("not poisoned").take();
+    let dir = spool_dir();
+    if !dir.exists() {
+        return;
+    }
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        tracing::warn!("could not remove {}: {e}", dir.display());
+    }
+}
+
+/// A tar archive written to stdout, to which the tiles are added as they are finished
+pub struct TarStream {
+    builder: Mutex<tar::Builder<std::io::Stdout>>,
+}
+
+impl std::fmt::Debug for TarStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TarStream")
+    }
+}
+
+impl TarStream {
+    pub fn new() -> Self {
+        let mut builder = tar::Builder::new(std::io::stdout());
+        // the modification times of the staged files tell nothing about the tiles
+        builder.mode(tar::HeaderMode::Deterministic);
+        Self {
+            builder: Mutex::new(builder),
+        }
+    }
+
+    /// Add a file to the archive under the given name
+    pub fn append(&self, path: &Path, name: &str) -> Result<()> {
+        self.builder
+            .lock()
+            .expect("not poisoned")
+            .append_path_with_name(path, name)
+            .with_context(|| format!("write {name} to stdout"))
+    }
+
+    /// Write the end of the archive
+    pub fn finish(&self) -> Result<()> {
+        self.builder
+            .lock()
+            .expect("not poisoned")
+            .finish()
+            .context("finish tar archive on stdout")
+    }
+}
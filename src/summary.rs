@@ -0,0 +1,179 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    input::InputFile,
+    output::Output,
+    postprocess::open_reader,
+    schema::{Document, Versioned},
+};
+
+/// Name of the summary file written to the output folder
+pub const SUMMARY_FILE_NAME: &str = "summary.json";
+
+/// What a run did, for orchestration systems to validate and catalog its results
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Summary {
+    /// Why the run was stopped early, if it was (see `checkpoint.json`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+
+    /// The input files that were read, in processing order
+    pub inputs: Vec<InputSummary>,
+
+    /// Total number of points read from the input files
+    pub points_read: u64,
+
+    /// Total number of points in the written tiles
+    pub points_written: u64,
+
+    /// Points that were read but not written, by reason
+    pub dropped_points: DroppedPoints,
+
+    /// All written tiles, ordered by name
+    pub tiles: Vec<SummaryTile>,
+
+    pub timings: Timings,
+
+    /// Points read per second of wall-clock time
+    pub points_per_second: f64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InputSummary {
+    pub path: InputFile,
+    /// Number of points read from this file, less than in its header if the run stopped early
+    pub points: u64,
+    /// Number of points outside the bounds in the header of this file
+    #[serde(skip_serializing_if = "is_zero")]
+    pub points_out_of_bounds: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct DroppedPoints {
+    /// Removed by `--ground-only`, `--wasm-filter` or `--script`
+    pub filtered: u64,
+    /// Outside the `--clip` polygons or `--bounds`
+    pub clipped: u64,
+    /// Outside all tiles of `--tiles-from`
+    pub outside_tiles: u64,
+    /// In tiles skipped by `--invalid-extent skip`
+    pub invalid_extent: u64,
+    /// In tiles that already existed with `--if-exists skip`
+    pub existing_tiles: u64,
+    /// Removed by `--overlap-prefer`
+    pub overlap: u64,
+    /// Removed by `--dedupe`
+    pub duplicates: u64,
+    /// In tiles of other shards with `--shard`
+    pub other_shards: u64,
+    /// In tiles with fewer than `--min-tile-points` points
+    pub small_tiles: u64,
+    /// Outside the header bounds of their input with `--out-of-bounds clip`, or for a tile that
+    /// was already written with `--out-of-bounds warn`
+    pub out_of_bounds: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SummaryTile {
+    pub name: String,
+    pub points: u64,
+    /// `[min x, min y, min z]` from the tile header
+    pub min: [f64; 3],
+    /// `[max x, max y, max z]` from the tile header
+    pub max: [f64; 3],
+}
+
+/// Wall-clock durations of the phases of a run, in seconds
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Timings {
+    /// Collecting the input files and reading their headers
+    pub scan_seconds: f64,
+    /// Reading, routing and writing the points, including post-processing of the tiles
+    pub retile_seconds: f64,
+    pub total_seconds: f64,
+}
+
+impl Document for Summary {
+    const SCHEMA_VERSION: &'static str = "1.4";
+}
+
+impl SummaryTile {
+    /// Describe a written tile file from its header
+    pub fn new(path: &Path, name: &str) -> Result<Self> {
+        let header = open_reader(path)?.header().clone();
+        let bounds = header.bounds();
+        Ok(Self {
+            name: name.to_owned(),
+            points: header.number_of_points(),
+            min: [bounds.min.x, bounds.min.y, bounds.min.z],
+            max: [bounds.max.x, bounds.max.y, bounds.max.z],
+        })
+    }
+}
+
+impl Timings {
+    pub fn new(scan: Duration, total: Duration) -> Self {
+        Self {
+            scan_seconds: scan.as_secs_f64(),
+            retile_seconds: total.saturating_sub(scan).as_secs_f64(),
+            total_seconds: total.as_secs_f64(),
+        }
+    }
+}
+
+impl Summary {
+    /// Summarize a run that read `input_points[i]` points from each input file, of which
+    /// `out_of_bounds[i]` were outside its header bounds
+    pub fn new(
+        stop_reason: Option<String>,
+        headers: &[(InputFile, las::Header)],
+        input_points: &[u64],
+        out_of_bounds: &[u64],
+        dropped_points: DroppedPoints,
+        mut tiles: Vec<SummaryTile>,
+        timings: Timings,
+    ) -> Self {
+        tiles.sort_by(|a, b| a.name.cmp(&b.name));
+        let inputs: Vec<InputSummary> = headers
+            .iter()
+            .zip(input_points.iter().zip(out_of_bounds))
+            .filter(|(_, (points, _))| **points > 0)
+            .map(
+                |((path, _), (&points, &points_out_of_bounds))| InputSummary {
+                    path: path.clone(),
+                    points,
+                    points_out_of_bounds,
+                },
+            )
+            .collect();
+        let points_read = inputs.iter().map(|i| i.points).sum();
+        let points_written = tiles.iter().map(|t| t.points).sum();
+        Self {
+            stop_reason,
+            inputs,
+            points_read,
+            points_written,
+            dropped_points,
+            tiles,
+            points_per_second: points_read as f64 / timings.total_seconds.max(f64::EPSILON),
+            timings,
+        }
+    }
+
+    pub fn write(&self, output: &Output) -> Result<()> {
+        let path = output.path(SUMMARY_FILE_NAME);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("create summary: {}", path.display()))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &Versioned::new(self))
+            .with_context(|| format!("write summary: {}", path.display()))?;
+        output.publish(SUMMARY_FILE_NAME)
+    }
+}
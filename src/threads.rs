@@ -0,0 +1,33 @@
+//! `--threads`: one limit for all parallel work, so that the tool can be confined to part of a
+//! shared server. It bounds the threads decompressing and compressing LAZ chunks, the tile writer
+//! threads, the concurrent header reads and the runtime of remote inputs.
+
+use std::{num::NonZeroUsize, sync::OnceLock};
+
+use anyhow::{Context, Result};
+
+static THREADS: OnceLock<NonZeroUsize> = OnceLock::new();
+
+/// Limit all parallel work to `threads` threads. Must be called before any LAZ file is read.
+pub fn configure(threads: NonZeroUsize) -> Result<()> {
+    // set once at startup, later calls keep the first value
+    if THREADS.set(threads).is_err() {
+        return Ok(());
+    }
+    // the parallel LAZ decompression and compression of las runs on the global rayon pool
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.get())
+        .build_global()
+        .context("configure the LAZ thread pool")
+}
+
+/// The limit given with `--threads`, if any
+pub fn limit() -> Option<NonZeroUsize> {
+    THREADS.get().copied()
+}
+
+/// The number of threads to use when not specified otherwise: the limit of `--threads`, or one
+/// per available core
+pub fn default_threads() -> NonZeroUsize {
+    limit().unwrap_or_else(|| std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN))
+}
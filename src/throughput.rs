@@ -0,0 +1,124 @@
+//! Live I/O counters for the progress display, so that operators can tell whether a run is
+//! limited by reading, compressing or writing. Unlike `--bench`, they are always counted.
+
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Bytes read from the inputs
+static READ: AtomicU64 = AtomicU64::new(0);
+/// Bytes written to the tile files
+static WRITTEN: AtomicU64 = AtomicU64::new(0);
+/// Uncompressed size of the points written to the tiles
+static ENCODED: AtomicU64 = AtomicU64::new(0);
+static OPEN_WRITERS: AtomicI64 = AtomicI64::new(0);
+
+/// A reader or writer that counts the bytes passing through it
+#[derive(Debug)]
+pub struct Counting<T> {
+    inner: T,
+    counter: &'static AtomicU64,
+}
+
+impl<R: Read> Counting<R> {
+    /// Count the bytes read from an input
+    pub fn input(inner: R) -> Self {
+        Self {
+            inner,
+            counter: &READ,
+        }
+    }
+}
+
+impl<W: Write> Counting<W> {
+    /// Count the bytes written to a tile
+    pub fn tile(inner: W) -> Self {
+        Self {
+            inner,
+            counter: &WRITTEN,
+        }
+    }
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for Counting<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Record that `bytes` bytes of an input were read without a counting reader, e.g. from a
+/// memory mapping
+pub fn read(bytes: usize) {
+    READ.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Record that `points` points of `record_length` bytes each were handed to a tile writer
+pub fn encoded(points: usize, record_length: u16) {
+    ENCODED.fetch_add(points as u64 * u64::from(record_length), Ordering::Relaxed);
+}
+
+/// Record that a tile writer was opened (`1`) or closed (`-1`)
+pub fn writers_opened(n: i64) {
+    OPEN_WRITERS.fetch_add(n, Ordering::Relaxed);
+}
+
+/// The counters at one moment
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    /// Uncompressed size of the written points per byte written, above 1 for LAZ tiles
+    pub compression_ratio: Option<f64>,
+    pub open_writers: u64,
+}
+
+pub fn snapshot() -> Snapshot {
+    let written_bytes = WRITTEN.load(Ordering::Relaxed);
+    Snapshot {
+        read_bytes: READ.load(Ordering::Relaxed),
+        written_bytes,
+        compression_ratio: (written_bytes > 0)
+            .then(|| ENCODED.load(Ordering::Relaxed) as f64 / written_bytes as f64),
+        open_writers: OPEN_WRITERS.load(Ordering::Relaxed).max(0) as u64,
+    }
+}
+
+impl Snapshot {
+    /// A line for the progress bar, with the rates averaged over `elapsed`
+    pub fn line(&self, elapsed: Duration) -> String {
+        let rate = |bytes: u64| match elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => bytes as f64 / secs / 1e6,
+        };
+        format!(
+            "read {:.1} MB/s, written {:.1} MB/s, compression {}, {} open tiles",
+            rate(self.read_bytes),
+            rate(self.written_bytes),
+            self.compression_ratio
+                .map_or("-".to_owned(), |ratio| format!("{ratio:.1}x")),
+            self.open_writers
+        )
+    }
+}
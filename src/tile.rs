@@ -0,0 +1,805 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, hash_map::Entry},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::BufWriter,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc},
+    thread::JoinHandle,
+};
+
+use anyhow::{Context, Result};
+use lru::LruCache;
+
+use crate::{
+    bench::{self, Stage},
+    compression, passthrough, point_format,
+    pool::BufferPool,
+    postprocess::{create_writer, open_reader, temp_path},
+    queued_io::QueuedFile,
+    raw::RecordWriter,
+    stats,
+    throughput::{self, Counting},
+};
+
+/// The grid index of an output tile, with a Z index if tiles are also partitioned along
+/// elevation (`--tile-size-z`). With `--tiles-from`, `x` is the position of the tile in the tile
+/// index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TileKey {
+    pub x: i32,
+    pub y: i32,
+    pub z: Option<i32>,
+    /// The part of the tile with `--split-by`, `None` for the tile itself
+    pub part: Option<u32>,
+}
+
+impl TileKey {
+    /// The key of the whole tile this part belongs to
+    pub fn tile(self) -> TileKey {
+        TileKey { part: None, ..self }
+    }
+}
+
+type PointWriter = las::Writer<BufWriter<Counting<QueuedFile>>>;
+
+/// The open file of a tile, written by las from points, or from point records as they are
+enum TileWriter {
+    Points(PointWriter),
+    Records(Box<RecordWriter>),
+}
+
+impl TileWriter {
+    fn write_point(&mut self, point: las::Point) -> Result<()> {
+        match self {
+            TileWriter::Points(writer) => Ok(writer.write_point(point)?),
+            TileWriter::Records(writer) => writer.write_point(point),
+        }
+    }
+
+    /// Write point records in the format of the tile
+    fn write_records(&mut self, records: &[u8]) -> Result<()> {
+        match self {
+            TileWriter::Points(writer) => {
+                let format = *writer.header().point_format();
+                let transforms = *writer.header().transforms();
+                for record in records.chunks_exact(usize::from(format.len())) {
+                    let raw = las::raw::Point::read_from(record, &format)?;
+                    writer.write_point(las::Point::new(raw, &transforms))?;
+                }
+                Ok(())
+            }
+            TileWriter::Records(writer) => writer.write_records(records),
+        }
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            TileWriter::Points(mut writer) => Ok(writer.close()?),
+            TileWriter::Records(writer) => writer.close(),
+        }
+    }
+}
+
+/// Number of points read at a time when merging the parts of a tile
+const MERGE_BUFFER_SIZE: u64 = 64 * 1024;
+
+/// The file name of a grid tile in the output folder
+pub fn file_name(key: TileKey) -> String {
+    match key.z {
+        None => format!("tile_{}_{}.laz", key.x, key.y),
+        Some(z) => format!("tile_{}_{}_{z}.laz", key.x, key.y),
+    }
+}
+
+pub struct OutTile {
+    /// the index of this tile
+    pub tile_index: TileKey,
+
+    /// The file name of this tile in the output folder
+    pub name: String,
+
+    /// `[min x, min y, max x, max y]` of the area covered by this tile
+    pub bounds: [f64; 4],
+
+    /// The input files that contribute to this tile
+    pub input_files: HashSet<usize>,
+
+    /// The input files that wrote points to this tile so far
+    pub sources: BTreeSet<usize>,
+
+    /// Statistics of the points written to this tile, if requested
+    pub stats: Option<stats::TileStats>,
+
+    /// Whether the tile crosses the boundary of the `--clip` polygons, so that each point needs
+    /// to be checked
+    pub clipped: bool,
+
+    /// The File Source ID of the tile with `--file-source-id`, otherwise the one of the first
+    /// input written to it is kept
+    pub file_source_id: Option<u16>,
+}
+
+/// The files written so far for a tile
+struct WrittenTile {
+    /// The file name of the tile
+    name: String,
+
+    /// The header all parts of this tile are written with
+    header: las::Header,
+
+    /// Number of points written to all parts so far
+    points: u64,
+
+    /// The part files of this tile, the first one is the final tile file
+    parts: Vec<PathBuf>,
+
+    /// Compressed chunks of the inputs to add to the tile when it is closed
+    chunks: Vec<passthrough::Chunk>,
+}
+
+/// Most batches that are queued for each writer thread before the caller blocks
+const QUEUE_DEPTH: usize = 16;
+
+/// Points for a tile, decoded or as point records in the format of the tile
+enum Data {
+    Points(Vec<las::Point>),
+    Records(Vec<u8>),
+}
+
+/// The buffers of the batches, handed back by the writer threads once written
+struct Pools {
+    points: BufferPool<las::Point>,
+    records: BufferPool<u8>,
+}
+
+impl Pools {
+    fn give(&self, data: Data) {
+        match data {
+            Data::Points(points) => self.points.give(points),
+            Data::Records(records) => self.records.give(records),
+        }
+    }
+}
+
+enum Command {
+    Write {
+        key: TileKey,
+        name: String,
+        file_source_id: Option<u16>,
+        header: Arc<las::Header>,
+        data: Data,
+    },
+    Copy {
+        key: TileKey,
+        name: String,
+        file_source_id: Option<u16>,
+        header: Arc<las::Header>,
+        chunk: passthrough::Chunk,
+    },
+    Close {
+        key: TileKey,
+        done: mpsc::Sender<Result<bool>>,
+    },
+    Discard {
+        key: TileKey,
+        done: mpsc::Sender<Result<()>>,
+    },
+    /// Reply once all earlier commands have been handled
+    Sync { done: mpsc::Sender<()> },
+}
+
+/// Collected points of a tile that have not been sent to its writer thread yet, either decoded
+/// or as records, so that they are written in the order they came in
+struct Batch {
+    name: String,
+    file_source_id: Option<u16>,
+    header: Arc<las::Header>,
+    points: Vec<las::Point>,
+    records: Vec<u8>,
+}
+
+/// Writes the output tiles using a pool of writer threads, so that multiple tiles are
+/// compressed concurrently.
+///
+/// Each tile is always handled by the same thread. Points are collected per tile and sent to
+/// that thread in batches, so the caller only blocks when the queue of a thread is full.
+pub struct TileWriters {
+    senders: Vec<mpsc::SyncSender<Command>>,
+    threads: Vec<JoinHandle<u64>>,
+    pending: HashMap<TileKey, Batch>,
+    batch_points: usize,
+    pools: Arc<Pools>,
+}
+
+impl TileWriters {
+    /// Start `threads` writer threads, that together keep at most `max_open` files open.
+    /// Points are sent to the threads in batches of `batch_points` per tile, with at most
+    /// `queued_batches` batches waiting for all threads together.
+    pub fn new(
+        dir: &Path,
+        max_open: Option<NonZeroUsize>,
+        write_buffer_size: usize,
+        batch_points: usize,
+        queued_batches: usize,
+        threads: NonZeroUsize,
+    ) -> Self {
+        // every thread needs to be able to keep at least one file open
+        let threads = max_open.map_or(threads, |max| threads.min(max));
+        let max_open_per_thread = max_open.map(|max| {
+            NonZeroUsize::new(max.get() / threads.get()).expect("at least one file per thread")
+        });
+
+        let queue_depth = (queued_batches / threads.get()).clamp(1, QUEUE_DEPTH);
+        // enough buffers for the batches queued for all threads, the pending batches of the
+        // tiles are already part of the memory budget
+        let pools = Arc::new(Pools {
+            points: BufferPool::new(threads.get() * queue_depth),
+            records: BufferPool::new(threads.get() * queue_depth),
+        });
+        let (senders, threads) = (0..threads.get())
+            .map(|_| {
+                let (sender, receiver) = mpsc::sync_channel(queue_depth);
+                let mut worker =
+                    Worker::new(dir, max_open_per_thread, write_buffer_size, pools.clone());
+                let thread = std::thread::spawn(move || {
+                    worker.run(receiver);
+                    worker.reopened
+                });
+                (sender, thread)
+            })
+            .unzip();
+
+        Self {
+            senders,
+            threads,
+            pending: HashMap::new(),
+            batch_points,
+            pools,
+        }
+    }
+
+    /// Queue points to be written to a tile. `header` is used as a template for the tile header
+    /// when the tile is written to for the first time.
+    pub fn write(
+        &mut self,
+        key: TileKey,
+        tile: &OutTile,
+        header: &Arc<las::Header>,
+        points: &[las::Point],
+    ) -> Result<()> {
+        if self
+            .pending
+            .get(&key)
+            .is_some_and(|batch| !batch.records.is_empty())
+        {
+            self.flush(key)?;
+        }
+        let batch = self.pending.entry(key).or_insert_with(|| Batch {
+            name: tile.name.clone(),
+            file_source_id: tile.file_source_id,
+            header: header.clone(),
+            points: self.pools.points.take(self.batch_points),
+            records: Vec::new(),
+        });
+        batch.points.extend_from_slice(points);
+        if batch.points.len() >= self.batch_points {
+            self.flush(key)?;
+        }
+        Ok(())
+    }
+
+    /// Queue point records in the format and with the scales and offsets of the tile to be
+    /// written to it as they are, see [`TileWriters::write`]
+    pub fn write_records(
+        &mut self,
+        key: TileKey,
+        tile: &OutTile,
+        header: &Arc<las::Header>,
+        records: &[u8],
+    ) -> Result<()> {
+        if self
+            .pending
+            .get(&key)
+            .is_some_and(|batch| !batch.points.is_empty())
+        {
+            self.flush(key)?;
+        }
+        let batch_bytes = self.batch_points * usize::from(header.point_format().len());
+        let batch = self.pending.entry(key).or_insert_with(|| Batch {
+            name: tile.name.clone(),
+            file_source_id: tile.file_source_id,
+            header: header.clone(),
+            points: Vec::new(),
+            records: self.pools.records.take(batch_bytes),
+        });
+        batch.records.extend_from_slice(records);
+        if batch.records.len() >= batch_bytes {
+            self.flush(key)?;
+        }
+        Ok(())
+    }
+
+    /// Queue a compressed chunk of an input to be copied into a tile as it is, see
+    /// [`passthrough`]. `header` is used as for [`TileWriters::write`].
+    pub fn copy(
+        &mut self,
+        key: TileKey,
+        tile: &OutTile,
+        header: &Arc<las::Header>,
+        chunk: passthrough::Chunk,
+    ) -> Result<()> {
+        // the points collected before go first, they may be the first ones of the tile
+        self.flush(key)?;
+        self.send(
+            key,
+            Command::Copy {
+                key,
+                name: tile.name.clone(),
+                file_source_id: tile.file_source_id,
+                header: header.clone(),
+                chunk,
+            },
+        )
+    }
+
+    /// Send the collected points of a tile to its writer thread
+    fn flush(&mut self, key: TileKey) -> Result<()> {
+        let Some(batch) = self.pending.remove(&key) else {
+            return Ok(());
+        };
+        self.send(
+            key,
+            Command::Write {
+                key,
+                name: batch.name,
+                file_source_id: batch.file_source_id,
+                header: batch.header,
+                data: match batch.records.is_empty() {
+                    true => Data::Points(batch.points),
+                    false => Data::Records(batch.records),
+                },
+            },
+        )
+    }
+
+    fn send(&self, key: TileKey, command: Command) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let thread = (hasher.finish() % self.senders.len() as u64) as usize;
+        self.senders[thread]
+            .send(command)
+            .ok()
+            .context("tile writer thread stopped unexpectedly")
+    }
+
+    /// Send all collected points to the writer threads and wait until they have been written
+    pub fn drain(&mut self) -> Result<()> {
+        let keys: Vec<_> = self.pending.keys().copied().collect();
+        for key in keys {
+            self.flush(key)?;
+        }
+
+        let mut replies = Vec::with_capacity(self.senders.len());
+        for sender in &self.senders {
+            let (done, reply) = mpsc::channel();
+            sender
+                .send(Command::Sync { done })
+                .ok()
+                .context("tile writer thread stopped unexpectedly")?;
+            replies.push(reply);
+        }
+        for reply in replies {
+            reply
+                .recv()
+                .context("tile writer thread stopped unexpectedly")?;
+        }
+        Ok(())
+    }
+
+    /// Close the tiles, merging their parts if they were reopened. Returns for each tile whether
+    /// any file was written.
+    pub fn close(&mut self, keys: &[TileKey]) -> Result<Vec<bool>> {
+        // send all requests first, so that the tiles are closed concurrently
+        let mut replies = Vec::with_capacity(keys.len());
+        for &key in keys {
+            self.flush(key)?;
+            let (done, reply) = mpsc::channel();
+            self.send(key, Command::Close { key, done })?;
+            replies.push(reply);
+        }
+
+        replies
+            .into_iter()
+            .map(|reply| {
+                reply
+                    .recv()
+                    .context("tile writer thread stopped unexpectedly")?
+            })
+            .collect()
+    }
+
+    /// Drop the tiles without giving them their final names, removing the files written for them
+    /// so far
+    pub fn discard(&mut self, keys: &[TileKey]) -> Result<()> {
+        let mut replies = Vec::with_capacity(keys.len());
+        for &key in keys {
+            if let Some(batch) = self.pending.remove(&key) {
+                self.pools.points.give(batch.points);
+                self.pools.records.give(batch.records);
+            }
+            let (done, reply) = mpsc::channel();
+            self.send(key, Command::Discard { key, done })?;
+            replies.push(reply);
+        }
+        for reply in replies {
+            reply
+                .recv()
+                .context("tile writer thread stopped unexpectedly")??;
+        }
+        Ok(())
+    }
+
+    /// Wait for all writer threads to finish, all tiles should have been closed. Returns the
+    /// number of times a writer had to be opened again after being closed to stay within the
+    /// limit of open files.
+    pub fn join(self) -> Result<u64> {
+        drop(self.senders);
+        let mut reopened = 0;
+        for thread in self.threads {
+            reopened += thread
+                .join()
+                .map_err(|_| anyhow::anyhow!("tile writer thread panicked"))?;
+        }
+        Ok(reopened)
+    }
+}
+
+/// The tile writers of a single writer thread, keeping at most a fixed number of them open.
+///
+/// When the limit is reached, the least recently used writer is closed. If more points arrive
+/// for that tile later, they are written to a new part file (LAS/LAZ files can not be appended
+/// to) and all parts are merged into the final tile file when the tile is closed.
+struct Worker {
+    dir: PathBuf,
+    write_buffer_size: usize,
+    open: LruCache<TileKey, TileWriter>,
+    written: HashMap<TileKey, WrittenTile>,
+    /// The first error that happened for a tile, reported when the tile is closed
+    failed: HashMap<TileKey, anyhow::Error>,
+    reopened: u64,
+    pools: Arc<Pools>,
+}
+
+impl Worker {
+    fn new(
+        dir: &Path,
+        max_open: Option<NonZeroUsize>,
+        write_buffer_size: usize,
+        pools: Arc<Pools>,
+    ) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            write_buffer_size,
+            open: match max_open {
+                Some(max) => LruCache::new(max),
+                None => LruCache::unbounded(),
+            },
+            written: HashMap::new(),
+            failed: HashMap::new(),
+            reopened: 0,
+            pools,
+        }
+    }
+
+    fn run(&mut self, receiver: mpsc::Receiver<Command>) {
+        for command in receiver {
+            match command {
+                Command::Write {
+                    key,
+                    name,
+                    file_source_id,
+                    header,
+                    data,
+                } => {
+                    if self.failed.contains_key(&key) {
+                        self.pools.give(data);
+                        continue;
+                    }
+                    if let Err(e) = self.write(key, &name, file_source_id, &header, data) {
+                        self.failed.insert(key, e);
+                    }
+                }
+                Command::Copy {
+                    key,
+                    name,
+                    file_source_id,
+                    header,
+                    chunk,
+                } => {
+                    if self.failed.contains_key(&key) {
+                        continue;
+                    }
+                    if let Err(e) = self.copy(key, &name, file_source_id, &header, chunk) {
+                        self.failed.insert(key, e);
+                    }
+                }
+                Command::Close { key, done } => {
+                    let result = match self.failed.remove(&key) {
+                        Some(e) => {
+                            if self.open.pop(&key).is_some() {
+                                throughput::writers_opened(-1);
+                            }
+                            self.written.remove(&key);
+                            Err(e)
+                        }
+                        None => self.close(key),
+                    };
+                    // the caller may have given up waiting
+                    let _ = done.send(result);
+                }
+                Command::Discard { key, done } => {
+                    self.failed.remove(&key);
+                    let _ = done.send(self.discard(key));
+                }
+                Command::Sync { done } => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    fn write(
+        &mut self,
+        key: TileKey,
+        name: &str,
+        file_source_id: Option<u16>,
+        header: &las::Header,
+        data: Data,
+    ) -> Result<()> {
+        let started = bench::start();
+        let (count, records) = match &data {
+            Data::Points(points) => (points.len(), false),
+            Data::Records(records) => (
+                records.len() / usize::from(header.point_format().len()),
+                true,
+            ),
+        };
+        let writer = self
+            .get(key, name, file_source_id, header, records)
+            .context("Could not get writer")?;
+        let written = match data {
+            Data::Points(mut points) => {
+                for point in points.drain(..) {
+                    writer.write_point(point).context("Could not write point")?;
+                }
+                Data::Points(points)
+            }
+            Data::Records(records) => {
+                writer
+                    .write_records(&records)
+                    .context("Could not write point records")?;
+                Data::Records(records)
+            }
+        };
+        self.pools.give(written);
+        bench::record(Stage::Encode, started, count);
+
+        let tile = self.written.get_mut(&key).expect("tile was written");
+        throughput::encoded(count, tile.header.point_format().len());
+        tile.add_points(count as u64)
+    }
+
+    /// Keep a compressed chunk of an input for adding it to the tile when it is closed
+    fn copy(
+        &mut self,
+        key: TileKey,
+        name: &str,
+        file_source_id: Option<u16>,
+        header: &las::Header,
+        chunk: passthrough::Chunk,
+    ) -> Result<()> {
+        let tile = self.tile(key, name, file_source_id, header)?;
+        tile.add_points(chunk.len())?;
+        tile.chunks.push(chunk);
+        Ok(())
+    }
+
+    /// The files written so far for a tile, starting it if it was not written to yet
+    fn tile(
+        &mut self,
+        key: TileKey,
+        name: &str,
+        file_source_id: Option<u16>,
+        header: &las::Header,
+    ) -> Result<&mut WrittenTile> {
+        Ok(match self.written.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut builder = las::Builder::from(header.clone());
+                if let Some(id) = file_source_id {
+                    builder.file_source_id = id;
+                }
+                let mut new_header = builder.into_header()?;
+                new_header.clear();
+                entry.insert(WrittenTile {
+                    name: name.to_owned(),
+                    header: new_header,
+                    points: 0,
+                    parts: Vec::new(),
+                    chunks: Vec::new(),
+                })
+            }
+        })
+    }
+
+    /// Get the writer for a tile, opening it if needed. `name`, `file_source_id` and `header`
+    /// are used for the tile file when the tile is written to for the first time. A new file is
+    /// written from point `records` as they are if they come first.
+    fn get(
+        &mut self,
+        key: TileKey,
+        name: &str,
+        file_source_id: Option<u16>,
+        header: &las::Header,
+        records: bool,
+    ) -> Result<&mut TileWriter> {
+        if self.open.contains(&key) {
+            return Ok(self.open.get_mut(&key).expect("writer is open"));
+        }
+
+        let (dir, write_buffer_size) = (self.dir.clone(), self.write_buffer_size);
+        let tile = self.tile(key, name, file_source_id, header)?;
+        // the first part is renamed to the tile once the tile is closed
+        let path = temp_path(&dir.join(&tile.name), &format!("part{}", tile.parts.len()));
+        let writer = create_tile_writer(&path, &tile.header, write_buffer_size, records)
+            .with_context(|| format!("create writer: {}", path.display()))?;
+        tile.parts.push(path);
+        if tile.parts.len() > 1 {
+            self.reopened += 1;
+        }
+
+        throughput::writers_opened(1);
+        if let Some((evicted, writer)) = self.open.push(key, writer) {
+            throughput::writers_opened(-1);
+            writer
+                .close()
+                .with_context(|| format!("close tile {}", self.written[&evicted].name))?;
+        }
+
+        Ok(self.open.get_mut(&key).expect("writer was just opened"))
+    }
+
+    /// Close the tile, merging its parts if it was reopened. Returns true if any file was written.
+    ///
+    /// Tiles are written under a temporary name and only renamed to their final name here, so
+    /// that a run that is killed leaves no truncated tile that a later `--if-exists skip` would
+    /// take as done.
+    fn close(&mut self, key: TileKey) -> Result<bool> {
+        let Some(tile) = self.written.remove(&key) else {
+            return Ok(false);
+        };
+        if let Some(writer) = self.open.pop(&key) {
+            throughput::writers_opened(-1);
+            writer
+                .close()
+                .with_context(|| format!("close tile {}", tile.name))?;
+        }
+        if tile.parts.len() > 1 {
+            merge_parts(&tile.parts, &tile.header)
+                .with_context(|| format!("merge parts of tile {}", tile.name))?;
+        }
+        let final_path = self.dir.join(&tile.name);
+        let path = match tile.parts.first() {
+            Some(path) => path.clone(),
+            None => temp_path(&final_path, "part0"),
+        };
+        if !tile.chunks.is_empty() {
+            if tile.parts.is_empty() {
+                // a tile without any points to write still needs its header and VLRs
+                create_tile_writer(&path, &tile.header, self.write_buffer_size, false)
+                    .and_then(TileWriter::close)
+                    .with_context(|| format!("create tile {}", tile.name))?;
+            }
+            passthrough::assemble(&path, &tile.chunks)
+                .with_context(|| format!("copy chunks into tile {}", tile.name))?;
+        }
+        if !tile.parts.is_empty() || !tile.chunks.is_empty() {
+            std::fs::rename(&path, &final_path)
+                .with_context(|| format!("move {} to {}", path.display(), final_path.display()))?;
+        }
+        Ok(true)
+    }
+
+    /// Drop the tile, removing its parts
+    fn discard(&mut self, key: TileKey) -> Result<()> {
+        if let Some(writer) = self.open.pop(&key) {
+            throughput::writers_opened(-1);
+            // the file is removed anyway
+            let _ = writer.close();
+        }
+        let Some(tile) = self.written.remove(&key) else {
+            return Ok(());
+        };
+        for part in &tile.parts {
+            std::fs::remove_file(part).with_context(|| format!("remove {}", part.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl WrittenTile {
+    /// Count points added to the tile, failing before writing a header that can not count them
+    fn add_points(&mut self, points: u64) -> Result<()> {
+        self.points += points;
+        anyhow::ensure!(
+            !point_format::needs_extended_counts(&self.header, self.points),
+            "tile {} has more than {} points, which LAS {} can not count, use --las-version 1.4 \
+             or smaller tiles",
+            self.name,
+            u32::MAX,
+            self.header.version()
+        );
+        Ok(())
+    }
+}
+
+/// Create a writer with a write buffer of the given size, compressing the output if the path
+/// has a `.laz` extension. With `records`, the points are mostly written as point records.
+fn create_tile_writer(
+    path: &Path,
+    header: &las::Header,
+    buffer_size: usize,
+    records: bool,
+) -> Result<TileWriter> {
+    let mut builder = las::Builder::from(header.clone());
+    builder.point_format.is_compressed = path.extension().is_some_and(|ext| ext == "laz");
+    compression::apply(&mut builder)?;
+    let header = builder.into_header()?;
+
+    // tiles may be in subfolders with --output-layout
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    if records {
+        return Ok(TileWriter::Records(Box::new(RecordWriter::create(
+            path,
+            header,
+            buffer_size,
+        )?)));
+    }
+    let file = QueuedFile::create(path)?;
+    Ok(TileWriter::Points(las::Writer::new(
+        BufWriter::with_capacity(buffer_size, Counting::tile(file)),
+        header,
+    )?))
+}
+
+/// Concatenate all parts into the first one, removing the other parts
+fn merge_parts(parts: &[PathBuf], header: &las::Header) -> Result<()> {
+    let final_path = &parts[0];
+    let merged_path = temp_path(final_path, "merged");
+
+    let mut writer = create_writer(&merged_path, header)?;
+    let mut points = Vec::new();
+    for part in parts {
+        let mut reader = open_reader(part)?;
+        loop {
+            points.clear();
+            if reader.read_points_into(MERGE_BUFFER_SIZE, &mut points)? == 0 {
+                break;
+            }
+            for point in points.drain(..) {
+                writer.write_point(point)?;
+            }
+        }
+    }
+    writer.close()?;
+
+    std::fs::rename(&merged_path, final_path)
+        .with_context(|| format!("replace {}", final_path.display()))?;
+    for part in &parts[1..] {
+        std::fs::remove_file(part).with_context(|| format!("remove {}", part.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::{stats::TileSummary, units::Units};
+
+/// Write a GeoJSON tile index with one polygon per tile, with the tile statistics as attributes
+pub fn write(path: &Path, units: &Units, tiles: &[TileSummary]) -> Result<()> {
+    let names = units.names();
+    let features: Vec<_> = tiles
+        .iter()
+        .map(|tile| {
+            let [x0, y0, x1, y1] = tile.bounds;
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[x0, y0], [x1, y0], [x1, y1], [x0, y1], [x0, y0]]],
+                },
+                "properties": {
+                    "name": tile.name,
+                    "z_index": tile.tile_index.z,
+                    "quadtree_path": tile.quadtree_path,
+                    "points": tile.stats.points,
+                    "area": units.area(tile.area()),
+                    "area_unit": names.area,
+                    "density": units.density(tile.stats.points, tile.area()),
+                    "density_unit": names.density,
+                    "z_min": tile.stats.min[2],
+                    "z_max": tile.stats.max[2],
+                    "z_p1": tile.stats.z_percentile(1.0),
+                    "z_p50": tile.stats.z_percentile(50.0),
+                    "z_p99": tile.stats.z_percentile(99.0),
+                },
+            })
+        })
+        .collect();
+
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("create tile index: {}", path.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &collection)
+        .with_context(|| format!("write tile index: {}", path.display()))
+}
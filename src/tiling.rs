@@ -0,0 +1,768 @@
+//! How points are assigned to output tiles: a regular grid, the tiles of an existing tile index or
+//! a single tile for all points
+
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::{
+    cli::TileNames,
+    clip::{self, Polygon},
+    extent,
+    grid_scheme::GridScheme,
+    tile::{self, TileKey},
+};
+
+pub enum Tiling {
+    /// Tiles of the given size along X and Y, optionally with layers along Z. The tile with
+    /// index 0 starts at `origin`, which is the origin of the coordinates unless the extent is
+    /// divided with `--grid`.
+    Grid {
+        size: [f64; 2],
+        origin: [f64; 2],
+        size_z: Option<f64>,
+        names: GridNames,
+    },
+    /// The tiles of an existing tile index
+    Index(TileIndex),
+    /// A single tile with all points, `[min x, min y, max x, max y]`, to only partition them
+    /// with `--split-by`
+    Whole { bounds: [f64; 4] },
+}
+
+/// `--grid`: the extent of the inputs divided into a fixed number of columns and rows of tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridDivision {
+    pub columns: NonZeroU32,
+    pub rows: NonZeroU32,
+}
+
+impl GridDivision {
+    /// The tile size and origin of the grid dividing the extent from `min` to `max`
+    pub fn cells(&self, min: &las::Vector<f64>, max: &las::Vector<f64>) -> Result<[[f64; 2]; 2]> {
+        let extent = [max.x - min.x, max.y - min.y];
+        anyhow::ensure!(
+            extent.iter().all(|e| *e > 0.0),
+            "the inputs have no area to divide into a {self} grid"
+        );
+        // a tiny bit wider, so that points on the upper edges of the extent fall into the last
+        // column and row instead of one more
+        let size = [
+            extent[0] / f64::from(self.columns.get()) * (1.0 + 1e-12),
+            extent[1] / f64::from(self.rows.get()) * (1.0 + 1e-12),
+        ];
+        Ok([size, [min.x, min.y]])
+    }
+}
+
+impl FromStr for GridDivision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once(['x', 'X'])
+            .and_then(|(columns, rows)| Some((columns.parse().ok()?, rows.parse().ok()?)));
+        match parsed {
+            Some((columns, rows)) => Ok(Self { columns, rows }),
+            None => Err(format!(
+                "expected <columns>x<rows> with at least one of each, e.g. 4x4, got {s:?}"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for GridDivision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.columns, self.rows)
+    }
+}
+
+/// How the tiles of a grid are named
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridNames {
+    /// `--tile-names`
+    Tiles(TileNames),
+    /// The names of a standard grid
+    Scheme(GridScheme),
+}
+
+impl Default for GridNames {
+    fn default() -> Self {
+        GridNames::Tiles(TileNames::Index)
+    }
+}
+
+/// The file name of the single tile of [`Tiling::Whole`]
+const WHOLE_TILE_NAME: &str = "points.laz";
+
+/// The key of the single tile of [`Tiling::Whole`]
+const WHOLE_TILE: TileKey = TileKey {
+    x: 0,
+    y: 0,
+    z: None,
+    part: None,
+};
+
+impl Tiling {
+    /// The tile the point falls into, if any. `previous` is the tile of the previous point, which
+    /// is checked first for tile indices.
+    pub fn key_of(&self, point: &las::Point, previous: Option<TileKey>) -> Option<TileKey> {
+        self.key_of_xyz([point.x, point.y, point.z], previous)
+    }
+
+    /// Like [`Tiling::key_of`], for the coordinates of a point
+    pub fn key_of_xyz(&self, [x, y, z]: [f64; 3], previous: Option<TileKey>) -> Option<TileKey> {
+        match self {
+            Tiling::Grid {
+                size,
+                origin,
+                size_z,
+                ..
+            } => Some(TileKey {
+                x: ((x - origin[0]) / size[0]).floor() as i32,
+                y: ((y - origin[1]) / size[1]).floor() as i32,
+                z: size_z.map(|size| (z / size).floor() as i32),
+                part: None,
+            }),
+            Tiling::Index(index) => index.key_of(x, y, previous),
+            Tiling::Whole { .. } => Some(WHOLE_TILE),
+        }
+    }
+
+    /// Split the points pushed to `runs` into runs of consecutive points of the same tile. On a
+    /// grid, the tile indices of all points are computed first in straight loops without
+    /// branches, which the compiler vectorizes, and only the comparison with the previous point
+    /// remains per point.
+    pub fn split_runs(&self, runs: &mut TileRuns) {
+        runs.runs.clear();
+        let Tiling::Grid {
+            size,
+            origin,
+            size_z,
+            ..
+        } = self
+        else {
+            let mut previous = None;
+            for i in 0..runs.x.len() {
+                let key = self.key_of_xyz([runs.x[i], runs.y[i], runs.z[i]], previous.flatten());
+                runs.push(key);
+                previous = Some(key);
+            }
+            return;
+        };
+        let [ix, iy, iz] = &mut runs.indices;
+        for (index, (values, axis)) in [ix, iy, iz].into_iter().zip([
+            (&runs.x, Some((origin[0], size[0]))),
+            (&runs.y, Some((origin[1], size[1]))),
+            (&runs.z, size_z.map(|size| (0.0, size))),
+        ]) {
+            index.clear();
+            if let Some((origin, size)) = axis {
+                index.extend(values.iter().map(|v| ((v - origin) / size).floor() as i32));
+            }
+        }
+        for i in 0..runs.x.len() {
+            let key = TileKey {
+                x: runs.indices[0][i],
+                y: runs.indices[1][i],
+                z: size_z.map(|_| runs.indices[2][i]),
+                part: None,
+            };
+            runs.push(Some(key));
+        }
+    }
+
+    /// All tiles that points within the given bounds may fall into
+    pub fn tiles_in(&self, bounds: &las::Bounds) -> Result<Vec<TileKey>> {
+        match self {
+            Tiling::Grid {
+                size,
+                origin,
+                size_z,
+                ..
+            } => {
+                anyhow::ensure!(
+                    extent::check_bounds(bounds, *size, *size_z),
+                    "bounds are too large for tile size {}",
+                    grid_size_name(*size)
+                );
+                // the same keys as the points at the corners of the bounds get
+                let index = |v: f64, origin: f64, size: f64| ((v - origin) / size).floor() as i32;
+                let layers: Vec<Option<i32>> = match size_z {
+                    Some(size) => (index(bounds.min.z, 0.0, *size)
+                        ..=index(bounds.max.z, 0.0, *size))
+                        .map(Some)
+                        .collect(),
+                    None => vec![None],
+                };
+                let mut keys = Vec::new();
+                let ([size_x, size_y], [x0, y0]) = (*size, *origin);
+                for x in index(bounds.min.x, x0, size_x)..=index(bounds.max.x, x0, size_x) {
+                    for y in index(bounds.min.y, y0, size_y)..=index(bounds.max.y, y0, size_y) {
+                        keys.extend(layers.iter().map(|&z| TileKey {
+                            x,
+                            y,
+                            z,
+                            part: None,
+                        }));
+                    }
+                }
+                Ok(keys)
+            }
+            Tiling::Index(index) => Ok(index.tiles_in(bounds)),
+            Tiling::Whole { .. } => Ok(vec![WHOLE_TILE]),
+        }
+    }
+
+    /// The file name of a tile
+    pub fn name(&self, key: TileKey) -> String {
+        match self {
+            Tiling::Grid {
+                names: GridNames::Tiles(TileNames::Index),
+                ..
+            } => tile::file_name(key),
+            Tiling::Grid {
+                names: GridNames::Scheme(scheme),
+                ..
+            } => scheme.name(key),
+            Tiling::Grid {
+                size,
+                origin,
+                names: GridNames::Tiles(names),
+                ..
+            } => {
+                // the coordinates of the lower left corner, without decimals for whole numbers
+                let unit = if *names == TileNames::Km { 1000.0 } else { 1.0 };
+                let x = (origin[0] + key.x as f64 * size[0]) / unit;
+                let y = (origin[1] + key.y as f64 * size[1]) / unit;
+                match key.z {
+                    None => format!("{x}_{y}.laz"),
+                    Some(z) => format!("{x}_{y}_{z}.laz"),
+                }
+            }
+            Tiling::Index(index) => index.tiles[key.x as usize].name.clone(),
+            Tiling::Whole { .. } => WHOLE_TILE_NAME.to_owned(),
+        }
+    }
+
+    /// The grid tile a file is named after by [`Tiling::name`] with `--tile-names`, if any, also
+    /// followed by the suffixes of `--split-by` parts or quadtree leaves, which lie within the
+    /// tile
+    pub fn key_of_name(&self, name: &str) -> Option<TileKey> {
+        let Tiling::Grid {
+            size,
+            origin,
+            size_z,
+            names: GridNames::Tiles(names),
+        } = self
+        else {
+            return None;
+        };
+        // coordinates may have decimals, only the extension is removed
+        let stem = Path::new(name).file_stem()?.to_str()?;
+        let stem = stem.strip_suffix(".copc").unwrap_or(stem);
+        let mut parts = match names {
+            TileNames::Index => stem.strip_prefix("tile_")?,
+            TileNames::Coords | TileNames::Km => stem,
+        }
+        .split('_');
+        let (x, y) = (parts.next()?, parts.next()?);
+        let (x, y) = match names {
+            TileNames::Index => (x.parse().ok()?, y.parse().ok()?),
+            TileNames::Coords | TileNames::Km => {
+                let unit = if *names == TileNames::Km { 1000.0 } else { 1.0 };
+                let x: f64 = x.parse().ok()?;
+                let y: f64 = y.parse().ok()?;
+                (
+                    ((x * unit - origin[0]) / size[0]).round() as i32,
+                    ((y * unit - origin[1]) / size[1]).round() as i32,
+                )
+            }
+        };
+        let z = match size_z {
+            Some(_) => Some(parts.next()?.parse().ok()?),
+            None => None,
+        };
+        Some(TileKey {
+            x,
+            y,
+            z,
+            part: None,
+        })
+    }
+
+    /// `[min x, min y, max x, max y]` of the area covered by a tile
+    pub fn bounds(&self, key: TileKey) -> [f64; 4] {
+        match self {
+            Tiling::Grid {
+                size: [size_x, size_y],
+                origin: [x0, y0],
+                ..
+            } => {
+                let (x, y) = (x0 + key.x as f64 * size_x, y0 + key.y as f64 * size_y);
+                [x, y, x + size_x, y + size_y]
+            }
+            Tiling::Index(index) => index.tiles[key.x as usize].bbox,
+            Tiling::Whole { bounds } => *bounds,
+        }
+    }
+
+    /// `[min z, max z]` of the layer of a tile with `--tile-size-z`
+    pub fn z_bounds(&self, key: TileKey) -> Option<[f64; 2]> {
+        match (self, key.z) {
+            (
+                Tiling::Grid {
+                    size_z: Some(size), ..
+                },
+                Some(z),
+            ) => Some([z as f64 * size, (z + 1) as f64 * size]),
+            _ => None,
+        }
+    }
+
+    /// The outline of a tile as WKT, if it is not the rectangle of its bounds
+    pub fn wkt(&self, key: TileKey) -> Option<String> {
+        match self {
+            Tiling::Index(index) => index.tiles[key.x as usize]
+                .polygons
+                .as_deref()
+                .map(clip::multipolygon_wkt),
+            Tiling::Grid { .. } | Tiling::Whole { .. } => None,
+        }
+    }
+}
+
+/// The coordinates of a batch of points and the runs of consecutive points of the same tile they
+/// are split into by [`Tiling::split_runs`], kept to be reused for the next batch
+#[derive(Default)]
+pub struct TileRuns {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    z: Vec<f64>,
+    /// The grid indices of the points along each axis
+    indices: [Vec<i32>; 3],
+    /// The tile of each run, `None` if outside all tiles, and its number of points
+    runs: Vec<(Option<TileKey>, usize)>,
+}
+
+impl TileRuns {
+    pub fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.z.clear();
+        self.runs.clear();
+    }
+
+    pub fn runs(&self) -> &[(Option<TileKey>, usize)] {
+        &self.runs
+    }
+
+    /// Add the next point to its run
+    fn push(&mut self, key: Option<TileKey>) {
+        match self.runs.last_mut() {
+            Some((current, n)) if *current == key => *n += 1,
+            _ => self.runs.push((key, 1)),
+        }
+    }
+
+    /// Split the runs further by the `--split-by` part of each point
+    pub fn split_parts(&mut self, mut part_of: impl FnMut(usize) -> u32) {
+        let runs = std::mem::take(&mut self.runs);
+        let mut start = 0;
+        for (key, n) in runs {
+            match key {
+                Some(key) => {
+                    for i in start..start + n {
+                        self.push(Some(TileKey {
+                            part: Some(part_of(i)),
+                            ..key
+                        }));
+                    }
+                }
+                None => self.runs.push((None, n)),
+            }
+            start += n;
+        }
+    }
+}
+
+impl Extend<[f64; 3]> for TileRuns {
+    fn extend<T: IntoIterator<Item = [f64; 3]>>(&mut self, points: T) {
+        for [x, y, z] in points {
+            self.x.push(x);
+            self.y.push(y);
+            self.z.push(z);
+        }
+    }
+}
+
+/// A grid tile size for messages, `<size>` for square tiles and `<x>x<y>` otherwise
+pub fn grid_size_name([x, y]: [f64; 2]) -> String {
+    if x == y {
+        x.to_string()
+    } else {
+        format!("{x}x{y}")
+    }
+}
+
+/// A tile of a tile index
+struct IndexTile {
+    name: String,
+    /// `[min x, min y, max x, max y]`
+    bbox: [f64; 4],
+    /// The polygons of the tile, or `None` if it is an axis-aligned rectangle that is fully
+    /// described by its bounding box
+    polygons: Option<Vec<Polygon>>,
+}
+
+impl IndexTile {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let [x0, y0, x1, y1] = self.bbox;
+        if x < x0 || x >= x1 || y < y0 || y >= y1 {
+            return false;
+        }
+        match &self.polygons {
+            None => true,
+            Some(polygons) => polygons.iter().any(|p| p.contains(x, y)),
+        }
+    }
+}
+
+/// Output tiles read from the polygon features of a GeoJSON tile index. The tiles are identified
+/// by their position in the file (`TileKey::x`).
+pub struct TileIndex {
+    tiles: Vec<IndexTile>,
+    /// Size of the cells of the lookup grid
+    cell_size: f64,
+    /// The tiles whose bounding box overlaps each cell of the lookup grid
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl TileIndex {
+    /// Read the tiles from a GeoJSON file. Each (multi)polygon feature becomes a tile named after
+    /// its `name` property (or its id), with a `.laz` extension added if it has none.
+    pub fn load(path: &Path) -> Result<Self> {
+        crate::cli::parse_geojson_path(&path.to_string_lossy()).map_err(anyhow::Error::msg)?;
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("open tile index: {}", path.display()))?;
+        let json: Value = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("read tile index: {}", path.display()))?;
+
+        let features = json["features"]
+            .as_array()
+            .with_context(|| format!("{} is not a GeoJSON FeatureCollection", path.display()))?;
+        let mut tiles = Vec::new();
+        let mut names = HashSet::new();
+        for (i, feature) in features.iter().enumerate() {
+            let mut polygons = Vec::new();
+            clip::collect_polygons(&feature["geometry"], &mut polygons)
+                .with_context(|| format!("read polygons of feature {i} of {}", path.display()))?;
+            if polygons.is_empty() {
+                continue;
+            }
+
+            let name = match (&feature["properties"]["name"], &feature["id"]) {
+                (Value::String(name), _) | (_, Value::String(name)) => name.clone(),
+                (Value::Number(name), _) | (_, Value::Number(name)) => name.to_string(),
+                _ => format!("tile_{i}"),
+            };
+            let name = if name.ends_with(".laz") || name.ends_with(".las") {
+                name
+            } else {
+                format!("{name}.laz")
+            };
+            anyhow::ensure!(
+                names.insert(name.clone()),
+                "duplicate tile name {name} in {}",
+                path.display()
+            );
+
+            let bbox = polygons
+                .iter()
+                .map(Polygon::bbox)
+                .reduce(|a, b| {
+                    [
+                        a[0].min(b[0]),
+                        a[1].min(b[1]),
+                        a[2].max(b[2]),
+                        a[3].max(b[3]),
+                    ]
+                })
+                .expect("at least one polygon");
+            let rectangle = match polygons.as_slice() {
+                [polygon] => polygon.as_rectangle(),
+                _ => None,
+            };
+            tiles.push(IndexTile {
+                name,
+                bbox,
+                polygons: rectangle.is_none().then_some(polygons),
+            });
+        }
+        anyhow::ensure!(!tiles.is_empty(), "no tiles found in {}", path.display());
+
+        // size the lookup grid after the tiles, so each tile overlaps only a few cells
+        let cell_size = tiles
+            .iter()
+            .map(|t| (t.bbox[2] - t.bbox[0]).max(t.bbox[3] - t.bbox[1]))
+            .sum::<f64>()
+            / tiles.len() as f64;
+        anyhow::ensure!(
+            cell_size > 0.0,
+            "the tiles of {} have no area",
+            path.display()
+        );
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, tile) in tiles.iter().enumerate() {
+            let [x0, y0, x1, y1] = tile.bbox.map(|v| (v / cell_size).floor() as i64);
+            for cx in x0..=x1 {
+                for cy in y0..=y1 {
+                    cells.entry((cx, cy)).or_default().push(i);
+                }
+            }
+        }
+
+        Ok(Self {
+            tiles,
+            cell_size,
+            cells,
+        })
+    }
+
+    fn key(i: usize) -> TileKey {
+        TileKey {
+            x: i as i32,
+            y: 0,
+            z: None,
+            part: None,
+        }
+    }
+
+    /// The first tile containing the point
+    fn key_of(&self, x: f64, y: f64, previous: Option<TileKey>) -> Option<TileKey> {
+        if let Some(previous) = previous
+            && self.tiles[previous.x as usize].contains(x, y)
+        {
+            return Some(previous);
+        }
+        let cell = (
+            (x / self.cell_size).floor() as i64,
+            (y / self.cell_size).floor() as i64,
+        );
+        self.cells
+            .get(&cell)?
+            .iter()
+            .find(|&&i| self.tiles[i].contains(x, y))
+            .map(|&i| Self::key(i))
+    }
+
+    /// All tiles whose bounding box overlaps the bounds
+    fn tiles_in(&self, bounds: &las::Bounds) -> Vec<TileKey> {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| {
+                t.bbox[0] <= bounds.max.x
+                    && t.bbox[2] >= bounds.min.x
+                    && t.bbox[1] <= bounds.max.y
+                    && t.bbox[3] >= bounds.min.y
+            })
+            .map(|(i, _)| Self::key(i))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(size: f64, size_z: Option<f64>) -> Tiling {
+        Tiling::Grid {
+            size: [size; 2],
+            origin: [0.0; 2],
+            size_z,
+            names: GridNames::default(),
+        }
+    }
+
+    #[test]
+    fn points_fall_into_the_tile_whose_bounds_contain_them() {
+        let tiling = grid(10.0, None);
+        for [x, y] in [
+            [-5.0, -5.0],
+            [-10.0, 3.0],
+            [-0.001, -9.999],
+            [5.0, -15.0],
+            [0.0, 0.0],
+            [25.0, 10.0],
+        ] {
+            let key = tiling.key_of_xyz([x, y, 0.0], None).unwrap();
+            let [x0, y0, x1, y1] = tiling.bounds(key);
+            assert!(
+                (x0..x1).contains(&x) && (y0..y1).contains(&y),
+                "({x}, {y}) is not in {key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn z_layers_below_zero_have_the_layer_height() {
+        let tiling = grid(10.0, Some(5.0));
+        for z in [-12.0, -5.0, -0.01, 0.0, 4.99, 7.5] {
+            let key = tiling.key_of_xyz([1.0, 1.0, z], None).unwrap();
+            let [z0, z1] = tiling.z_bounds(key).unwrap();
+            assert!((z0..z1).contains(&z), "{z} is not in {key:?}");
+            assert_eq!(z1 - z0, 5.0);
+        }
+    }
+
+    #[test]
+    fn split_runs_agrees_with_key_of() {
+        let tiling = grid(10.0, Some(5.0));
+        let points = [
+            [-5.0, -5.0, -1.0],
+            [-5.0, -4.0, -2.0],
+            [5.0, -5.0, 1.0],
+            [-15.0, 0.0, -6.0],
+        ];
+        let mut runs = TileRuns::default();
+        runs.extend(points);
+        tiling.split_runs(&mut runs);
+        let keys: Vec<_> = points.iter().map(|&p| tiling.key_of_xyz(p, None)).collect();
+        assert_eq!(
+            runs.runs(),
+            [(keys[0], 2), (keys[2], 1), (keys[3], 1)].as_slice()
+        );
+    }
+
+    #[test]
+    fn coordinate_names_are_the_lower_left_corner() {
+        for (names, point, name) in [
+            (TileNames::Coords, [-5.0, -15.0], "-10_-20.laz"),
+            (TileNames::Coords, [5.0, 15.0], "0_10.laz"),
+            (TileNames::Coords, [-0.5, 0.5], "-10_0.laz"),
+            (TileNames::Km, [-2500.0, 1500.0], "-3_1.laz"),
+        ] {
+            let size = if names == TileNames::Km { 1000.0 } else { 10.0 };
+            let tiling = Tiling::Grid {
+                size: [size; 2],
+                origin: [0.0; 2],
+                size_z: None,
+                names: GridNames::Tiles(names),
+            };
+            let key = tiling.key_of_xyz([point[0], point[1], 0.0], None).unwrap();
+            assert_eq!(tiling.name(key), name, "{point:?}");
+            assert_eq!(tiling.key_of_name(name), Some(key));
+        }
+    }
+
+    #[test]
+    fn tiles_in_covers_negative_bounds() {
+        let tiling = grid(10.0, None);
+        let bounds = las::Bounds {
+            min: las::Vector {
+                x: -15.0,
+                y: -5.0,
+                z: 0.0,
+            },
+            max: las::Vector {
+                x: -5.0,
+                y: 5.0,
+                z: 0.0,
+            },
+        };
+        let keys = tiling.tiles_in(&bounds).unwrap();
+        for corner in [[-15.0, -5.0], [-5.0, 5.0], [-15.0, 5.0], [-5.0, -5.0]] {
+            let key = tiling
+                .key_of_xyz([corner[0], corner[1], 0.0], None)
+                .unwrap();
+            assert!(
+                keys.contains(&key),
+                "{corner:?} is in {key:?}, not in {keys:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tiles_with_negative_coordinates_are_clipped_by_their_own_bounds() {
+        let path = std::env::temp_dir().join(format!(
+            "lasretile-test-negative-clip-{}.geojson",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"{"type":"Polygon","coordinates":[[[-8,-8],[-2,-8],[-2,-2],[-8,-2],[-8,-8]]]}"#,
+        )
+        .unwrap();
+        let clip = clip::Clip::new(Some(&path), None).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let tiling = grid(10.0, None);
+        let inside = tiling.key_of_xyz([-5.0, -5.0, 0.0], None).unwrap();
+        assert!(clip.contains(-5.0, -5.0));
+        assert_eq!(
+            clip.coverage(tiling.bounds(inside)),
+            clip::Coverage::Partial
+        );
+        let outside = tiling.key_of_xyz([5.0, 5.0, 0.0], None).unwrap();
+        assert_ne!(inside, outside);
+        assert_eq!(
+            clip.coverage(tiling.bounds(outside)),
+            clip::Coverage::Outside
+        );
+    }
+
+    #[test]
+    fn grid_division_parses_columns_and_rows() {
+        let division: GridDivision = "4x3".parse().unwrap();
+        assert_eq!((division.columns.get(), division.rows.get()), (4, 3));
+        assert_eq!("2X5".parse::<GridDivision>().unwrap().to_string(), "2x5");
+        for invalid in ["0x3", "4X", "x4", "4", "4x-1", ""] {
+            assert!(invalid.parse::<GridDivision>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn points_on_the_upper_edges_fall_into_the_last_cells() {
+        let division: GridDivision = "4x3".parse().unwrap();
+        let min = las::Vector {
+            x: -100.0,
+            y: 10.0,
+            z: 0.0,
+        };
+        let max = las::Vector {
+            x: 300.0,
+            y: 40.0,
+            z: 0.0,
+        };
+        let [size, origin] = division.cells(&min, &max).unwrap();
+        let tiling = Tiling::Grid {
+            size,
+            origin,
+            size_z: None,
+            names: GridNames::default(),
+        };
+        let key = |x, y| tiling.key_of_xyz([x, y, 0.0], None).unwrap().tile();
+        assert_eq!(key(min.x, min.y), key_at(0, 0));
+        assert_eq!(key(max.x, max.y), key_at(3, 2));
+        assert_eq!(key(max.x, min.y), key_at(3, 0));
+
+        let flat = las::Vector { y: 10.0, ..max };
+        assert!(division.cells(&min, &flat).is_err());
+    }
+
+    fn key_at(x: i32, y: i32) -> TileKey {
+        TileKey {
+            x,
+            y,
+            z: None,
+            part: None,
+        }
+    }
+}
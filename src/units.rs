@@ -0,0 +1,142 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+const METERS_PER_FOOT: f64 = 0.3048;
+const METERS_PER_US_SURVEY_FOOT: f64 = 1200.0 / 3937.0;
+const SQUARE_METERS_PER_HECTARE: f64 = 10_000.0;
+const SQUARE_METERS_PER_ACRE: f64 = 4_046.856_422_4;
+
+/// The linear unit of the coordinates in the input files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LinearUnit {
+    Meter,
+    /// International foot (0.3048 m)
+    Foot,
+    /// US survey foot (1200/3937 m)
+    UsFoot,
+}
+
+impl LinearUnit {
+    fn meters(self) -> f64 {
+        match self {
+            LinearUnit::Meter => 1.0,
+            LinearUnit::Foot => METERS_PER_FOOT,
+            LinearUnit::UsFoot => METERS_PER_US_SURVEY_FOOT,
+        }
+    }
+}
+
+/// The unit system lengths, areas and densities are reported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UnitSystem {
+    /// Meters, hectares and points per square meter
+    Metric,
+    /// Feet, acres and points per square foot
+    Imperial,
+}
+
+/// The names of the units used in a report
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct UnitNames {
+    pub length: &'static str,
+    pub area: &'static str,
+    pub density: &'static str,
+}
+
+/// Converts lengths and areas in coordinate units to the units they are reported in
+#[derive(Debug, Clone, Copy)]
+pub struct Units {
+    pub coordinates: LinearUnit,
+    pub system: UnitSystem,
+}
+
+impl Units {
+    pub fn names(&self) -> UnitNames {
+        match self.system {
+            UnitSystem::Metric => UnitNames {
+                length: "m",
+                area: "ha",
+                density: "pts/m²",
+            },
+            UnitSystem::Imperial => UnitNames {
+                length: "ft",
+                area: "acres",
+                density: "pts/ft²",
+            },
+        }
+    }
+
+    /// Convert a length in coordinate units
+    pub fn length(&self, length: f64) -> f64 {
+        let meters = length * self.coordinates.meters();
+        match self.system {
+            UnitSystem::Metric => meters,
+            UnitSystem::Imperial => meters / METERS_PER_FOOT,
+        }
+    }
+
+    /// Convert an area in square coordinate units
+    pub fn area(&self, area: f64) -> f64 {
+        let square_meters = area * self.coordinates.meters().powi(2);
+        match self.system {
+            UnitSystem::Metric => square_meters / SQUARE_METERS_PER_HECTARE,
+            UnitSystem::Imperial => square_meters / SQUARE_METERS_PER_ACRE,
+        }
+    }
+
+    /// The point density of `points` spread over an area in square coordinate units
+    pub fn density(&self, points: u64, area: f64) -> f64 {
+        // the density is per square length unit, not per area unit
+        points as f64 / self.length(area.sqrt()).powi(2)
+    }
+
+    pub fn format_length(&self, length: f64) -> String {
+        format!(
+            "{} {}",
+            format_number(self.length(length), 2),
+            self.names().length
+        )
+    }
+
+    pub fn format_area(&self, area: f64) -> String {
+        format!(
+            "{} {}",
+            format_number(self.area(area), 2),
+            self.names().area
+        )
+    }
+
+    pub fn format_density(&self, points: u64, area: f64) -> String {
+        format!(
+            "{} {}",
+            format_number(self.density(points, area), 2),
+            self.names().density
+        )
+    }
+}
+
+/// Format a number with the given number of decimals and thousands separators
+pub fn format_number(value: f64, decimals: usize) -> String {
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = match formatted.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut out = String::new();
+    if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        out.push('-');
+    }
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        out.push('.');
+        out.push_str(fraction);
+    }
+    out
+}
@@ -0,0 +1,108 @@
+//! Updating the tiles of an earlier run (`lasretile update`) after some of its inputs were added,
+//! modified or removed. The inputs are compared against the `manifest.json` of the earlier run,
+//! and only the tiles of the changed inputs are written again, from all of their inputs.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    cli::Args,
+    input::{self, InputFile},
+    manifest::{MANIFEST_FILE_NAME, Manifest},
+    output::Output,
+};
+
+/// What to write again when only some of the inputs changed since an earlier run
+#[derive(Debug, Default)]
+pub struct Rebuild {
+    /// New or modified inputs, the tiles they touch are written again from all of their inputs
+    pub changed: HashSet<InputFile>,
+    /// Files of the earlier run with points of modified or removed inputs. Their tiles are
+    /// written again, and the files that are not written again are removed.
+    pub stale: HashSet<String>,
+    /// The manifest of the earlier run, updated with the tiles written again
+    pub previous: Option<Manifest>,
+}
+
+pub fn run(mut args: Args) -> Result<()> {
+    anyhow::ensure!(
+        args.max_points.is_none() && args.max_runtime.is_none() && args.shard.is_none(),
+        "update writes all tiles of the changed inputs, --max-points, --max-runtime and --shard are not supported"
+    );
+    anyhow::ensure!(!args.watch, "update can not --watch the inputs");
+    crate::interrupt::install()?;
+    let output_arg = args.output.as_deref().expect("required by clap");
+    let output = Output::new(output_arg, args.staging_dir.as_deref())?;
+    anyhow::ensure!(!output.is_stdout(), "update needs an output folder");
+    let previous = Manifest::load(&output)?.with_context(|| {
+        format!(
+            "no {MANIFEST_FILE_NAME} in {}, retile with --manifest first",
+            output_arg.display()
+        )
+    })?;
+    anyhow::ensure!(
+        !previous.inputs.is_empty(),
+        "the {MANIFEST_FILE_NAME} in {} does not list its inputs, retile with --manifest again to start updating",
+        output_arg.display()
+    );
+
+    let versions: HashMap<&str, &str> = previous
+        .inputs
+        .iter()
+        .map(|input| (input.path.as_str(), input.version.as_str()))
+        .collect();
+    let mut changed = HashSet::new();
+    let mut present = HashSet::new();
+    let mut modified = HashSet::new();
+    for file in input::collect_inputs(&args.input, &mut input::SkipReport::default())? {
+        let path = file.to_string();
+        match versions.get(path.as_str()) {
+            None => {
+                changed.insert(file);
+            }
+            Some(&version) if version != file.version()? => {
+                changed.insert(file);
+                modified.insert(path.clone());
+            }
+            Some(_) => {}
+        }
+        present.insert(path);
+    }
+    let removed: HashSet<&str> = versions
+        .keys()
+        .copied()
+        .filter(|path| !present.contains(*path))
+        .collect();
+    tracing::info!(
+        "{} new, {} modified and {} removed inputs since the last run",
+        changed.len() - modified.len(),
+        modified.len(),
+        removed.len()
+    );
+
+    let stale: HashSet<String> = previous
+        .tiles
+        .iter()
+        .filter(|tile| {
+            tile.sources
+                .iter()
+                .any(|source| modified.contains(source) || removed.contains(source.as_str()))
+        })
+        .map(|tile| tile.name.clone())
+        .collect();
+    if changed.is_empty() && stale.is_empty() {
+        tracing::info!("All {} tiles are up to date", previous.tiles.len());
+        return Ok(());
+    }
+
+    args.manifest = true;
+    crate::retile_inputs(
+        &args,
+        Some(&Rebuild {
+            changed,
+            stale,
+            previous: Some(previous),
+        }),
+    )
+}
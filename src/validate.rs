@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    cli::ValidateArgs,
+    extent,
+    input::{self, InputFile},
+    progress_bar,
+    schema::{Document, Versioned},
+};
+
+/// Number of points read at a time
+const BUFFER_SIZE: u64 = 64 * 1024;
+
+/// The result of checking the inputs before retiling them, or the tiles written by a run
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ValidationReport {
+    pub files: Vec<FileReport>,
+    /// Number of problems that make retiling fail or produce wrong results
+    pub errors: usize,
+    /// Number of problems worth knowing about that retiling can handle
+    pub warnings: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FileReport {
+    pub path: InputFile,
+    /// Number of points according to the header, if it could be read
+    pub header_points: Option<u64>,
+    /// Number of points that could actually be read
+    pub points_read: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub point_format: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crs: Option<String>,
+    pub problems: Vec<Problem>,
+    /// The header of the file, if it could be read
+    #[serde(skip)]
+    pub header: Option<las::Header>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Problem {
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "problem", rename_all = "kebab-case")]
+pub enum ProblemKind {
+    /// The header could not be read
+    Unreadable { message: String },
+    /// Reading the points failed part way, e.g. a truncated LAZ stream
+    Truncated { message: String },
+    /// The header declares a different number of points than could be read
+    PointCount { header: u64, actual: u64 },
+    /// Points lie outside the bounds declared in the header, which retiling relies on
+    BoundsExceeded {
+        header_min: [f64; 3],
+        header_max: [f64; 3],
+        actual_min: [f64; 3],
+        actual_max: [f64; 3],
+    },
+    /// The bounds declared in the header are larger than the extent of the points
+    BoundsOverstated {
+        header_min: [f64; 3],
+        header_max: [f64; 3],
+        actual_min: [f64; 3],
+        actual_max: [f64; 3],
+    },
+    /// The CRS differs from the one used by most inputs
+    CrsMismatch { expected: Option<String> },
+    /// The point format differs from the one used by most inputs
+    PointFormatMismatch { expected: u8 },
+    /// A tile has points outside the grid cell its name refers to (`verify` only)
+    OutsideTile {
+        tile_min: [f64; 3],
+        tile_max: [f64; 3],
+        header_min: [f64; 3],
+        header_max: [f64; 3],
+    },
+    /// A temporary file left behind by a run that was interrupted while rewriting a tile
+    /// (`verify` only)
+    TemporaryFile,
+}
+
+impl Document for ValidationReport {
+    const SCHEMA_VERSION: &'static str = "1.1";
+}
+
+impl Problem {
+    pub fn error(kind: ProblemKind) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind,
+        }
+    }
+
+    fn warning(kind: ProblemKind) -> Self {
+        Self {
+            severity: Severity::Warning,
+            kind,
+        }
+    }
+}
+
+/// Read every input completely, print a JSON report of the problems found and fail if any of
+/// them are errors
+pub fn run(args: &ValidateArgs) -> Result<()> {
+    let mut skipped = input::SkipReport::default();
+    let input_files = input::collect_inputs(&args.input, &mut skipped)?;
+    skipped.print_summary();
+    anyhow::ensure!(!input_files.is_empty(), "no input files to validate");
+
+    let mut files = check_files(input_files);
+    check_consistency(&mut files);
+    print_report(files, "the inputs")
+}
+
+/// Read all points of each file and compare them against its header
+pub fn check_files(input_files: Vec<InputFile>) -> Vec<FileReport> {
+    let headers: Vec<_> = input_files
+        .iter()
+        .map(|path| {
+            path.reader(las::ReaderOptions::default())
+                .map(|reader| reader.header().clone())
+        })
+        .collect();
+    let total_points = headers
+        .iter()
+        .flatten()
+        .map(las::Header::number_of_points)
+        .sum();
+
+    let file_count = input_files.len();
+    let pb = progress_bar(total_points);
+    let options = las::ReaderOptions::default().with_laz_parallelism(las::LazParallelism::Yes);
+    let mut files = Vec::with_capacity(file_count);
+    for (i, (path, header)) in input_files.into_iter().zip(headers).enumerate() {
+        pb.set_message(format!("{}/{file_count}", i + 1));
+        let header = match header {
+            Ok(header) => header,
+            Err(e) => {
+                files.push(FileReport {
+                    path,
+                    header_points: None,
+                    points_read: 0,
+                    version: None,
+                    point_format: None,
+                    crs: None,
+                    problems: vec![Problem::error(ProblemKind::Unreadable {
+                        message: format!("{e:#}"),
+                    })],
+                    header: None,
+                });
+                continue;
+            }
+        };
+        files.push(check_points(path, &header, options, &pb));
+    }
+    pb.finish_with_message("Done");
+    files
+}
+
+/// Print the JSON report of the problems found in `what` and fail if any of them are errors
+pub fn print_report(files: Vec<FileReport>, what: &str) -> Result<()> {
+    let errors = count(&files, Severity::Error);
+    let warnings = count(&files, Severity::Warning);
+    let report = ValidationReport {
+        files,
+        errors,
+        warnings,
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Versioned::new(&report))?
+    );
+    anyhow::ensure!(
+        errors == 0,
+        "found {errors} errors (and {warnings} warnings) in {what}"
+    );
+    tracing::info!("No errors found ({warnings} warnings)");
+    Ok(())
+}
+
+/// Read all points of a file and compare them against its header
+fn check_points(
+    path: InputFile,
+    header: &las::Header,
+    options: las::ReaderOptions,
+    pb: &indicatif::ProgressBar,
+) -> FileReport {
+    let mut problems = Vec::new();
+    let mut points_read = 0;
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    match path.reader(options) {
+        Ok(mut reader) => {
+            let mut points = Vec::new();
+            loop {
+                points.clear();
+                match reader.read_points_into(BUFFER_SIZE, &mut points) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        points_read += n;
+                        pb.inc(n);
+                    }
+                    Err(e) => {
+                        problems.push(Problem::error(ProblemKind::Truncated {
+                            message: e.to_string(),
+                        }));
+                        break;
+                    }
+                }
+                for p in &points {
+                    for (axis, value) in [p.x, p.y, p.z].into_iter().enumerate() {
+                        min[axis] = min[axis].min(value);
+                        max[axis] = max[axis].max(value);
+                    }
+                }
+            }
+        }
+        Err(e) => problems.push(Problem::error(ProblemKind::Truncated {
+            message: format!("{e:#}"),
+        })),
+    }
+
+    if points_read != header.number_of_points() {
+        problems.push(Problem::error(ProblemKind::PointCount {
+            header: header.number_of_points(),
+            actual: points_read,
+        }));
+    }
+
+    if points_read > 0 {
+        let bounds = header.bounds();
+        let header_min = [bounds.min.x, bounds.min.y, bounds.min.z];
+        let header_max = [bounds.max.x, bounds.max.y, bounds.max.z];
+        let tolerance = input::bounds_tolerance(header);
+        let exceeded = (0..3).any(|axis| {
+            min[axis] < header_min[axis] - tolerance[axis]
+                || max[axis] > header_max[axis] + tolerance[axis]
+        });
+        let overstated = (0..3).any(|axis| {
+            min[axis] > header_min[axis] + tolerance[axis]
+                || max[axis] < header_max[axis] - tolerance[axis]
+        });
+        if exceeded {
+            problems.push(Problem::error(ProblemKind::BoundsExceeded {
+                header_min,
+                header_max,
+                actual_min: min,
+                actual_max: max,
+            }));
+        } else if overstated {
+            problems.push(Problem::warning(ProblemKind::BoundsOverstated {
+                header_min,
+                header_max,
+                actual_min: min,
+                actual_max: max,
+            }));
+        }
+    }
+
+    FileReport {
+        path,
+        header_points: Some(header.number_of_points()),
+        points_read,
+        version: Some(header.version().to_string()),
+        point_format: header.point_format().to_u8().ok(),
+        crs: extent::crs_description(header),
+        problems,
+        header: Some(header.clone()),
+    }
+}
+
+/// Flag files whose CRS or point format differs from the one used by most inputs
+fn check_consistency(files: &mut [FileReport]) {
+    let readable = || files.iter().filter(|f| f.header_points.is_some());
+    let expected_crs = most_common(readable().map(|f| f.crs.clone()));
+    let expected_format = most_common(readable().filter_map(|f| f.point_format));
+
+    for file in files.iter_mut().filter(|f| f.header_points.is_some()) {
+        if let Some(expected) = &expected_crs
+            && file.crs != *expected
+        {
+            file.problems.push(Problem::error(ProblemKind::CrsMismatch {
+                expected: expected.clone(),
+            }));
+        }
+        if let (Some(expected), Some(format)) = (expected_format, file.point_format)
+            && format != expected
+        {
+            file.problems
+                .push(Problem::warning(ProblemKind::PointFormatMismatch {
+                    expected,
+                }));
+        }
+    }
+}
+
+/// The most common value, the first one seen in case of a tie
+fn most_common<T: Eq + std::hash::Hash + Clone>(values: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts: HashMap<T, (usize, usize)> = HashMap::new();
+    for (i, value) in values.enumerate() {
+        counts.entry(value).or_insert((0, i)).0 += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(_, (count, first))| (count, std::cmp::Reverse(first)))
+        .map(|(value, _)| value)
+}
+
+fn count(files: &[FileReport], severity: Severity) -> usize {
+    files
+        .iter()
+        .flat_map(|f| &f.problems)
+        .filter(|p| p.severity == severity)
+        .count()
+}
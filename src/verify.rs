@@ -0,0 +1,88 @@
+//! Checking the tiles written by a run, to catch partial files left by crashes or a full disk
+
+use anyhow::Result;
+
+use crate::{
+    cli::VerifyArgs,
+    input::{self, InputFile},
+    postprocess,
+    tiling::{GridNames, Tiling},
+    validate::{self, FileReport, Problem, ProblemKind},
+};
+
+/// Read every tile completely, print a JSON report of the problems found and fail if any of them
+/// are errors
+pub fn run(args: &VerifyArgs) -> Result<()> {
+    let mut skipped = input::SkipReport::default();
+    let files = input::collect_inputs(&args.input, &mut skipped)?;
+    skipped.print_summary();
+    anyhow::ensure!(!files.is_empty(), "no tiles to verify");
+
+    // temporary files are not tiles, they are only reported
+    let (temporary, tiles): (Vec<InputFile>, Vec<InputFile>) = files
+        .into_iter()
+        .partition(|file| postprocess::is_temp_name(&file.file_name()));
+
+    let mut reports = validate::check_files(tiles);
+    if let Some(size) = args.grid_size() {
+        for report in &mut reports {
+            check_grid(
+                report,
+                &Tiling::Grid {
+                    size,
+                    origin: [0.0; 2],
+                    size_z: args.tile_size_z,
+                    names: GridNames::Tiles(args.tile_names),
+                },
+            );
+        }
+    }
+    reports.extend(temporary.into_iter().map(|path| FileReport {
+        path,
+        header_points: None,
+        points_read: 0,
+        version: None,
+        point_format: None,
+        crs: None,
+        problems: vec![Problem::error(ProblemKind::TemporaryFile)],
+        header: None,
+    }));
+    validate::print_report(reports, "the tiles")
+}
+
+/// Flag a tile whose header bounds extend beyond the grid cell it is named after. Tiles that are
+/// not named after a grid cell (e.g. from `--tiles-from`) are not checked.
+fn check_grid(report: &mut FileReport, tiling: &Tiling) {
+    let Some(header) = &report.header else {
+        return;
+    };
+    let Some(key) = tiling.key_of_name(&report.path.file_name()) else {
+        return;
+    };
+
+    let [min_x, min_y, max_x, max_y] = tiling.bounds(key);
+    let [min_z, max_z] = tiling
+        .z_bounds(key)
+        .unwrap_or([f64::NEG_INFINITY, f64::INFINITY]);
+    let tile_min = [min_x, min_y, min_z];
+    let tile_max = [max_x, max_y, max_z];
+
+    let bounds = header.bounds();
+    let header_min = [bounds.min.x, bounds.min.y, bounds.min.z];
+    let header_max = [bounds.max.x, bounds.max.y, bounds.max.z];
+    let tolerance = input::bounds_tolerance(header);
+    let outside = (0..3).any(|axis| {
+        header_min[axis] < tile_min[axis] - tolerance[axis]
+            || header_max[axis] > tile_max[axis] + tolerance[axis]
+    });
+    if outside {
+        report
+            .problems
+            .push(Problem::error(ProblemKind::OutsideTile {
+                tile_min,
+                tile_max,
+                header_min,
+                header_max,
+            }));
+    }
+}
@@ -0,0 +1,111 @@
+//! Watching the input folders for new files (`--watch`), to retile data as it comes off an
+//! acquisition system. Each batch of new files rebuilds the tiles they touch from all of their
+//! inputs, so the tiles end up the same as if all files had been retiled at once.
+
+use std::{collections::HashSet, path::PathBuf, sync::mpsc, time::Duration};
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+
+use crate::{
+    cli::Args,
+    input::{self, InputFile},
+    interrupt, reader, stdio,
+    update::Rebuild,
+};
+
+/// How often to check for Ctrl-C while waiting for new files
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Retile the inputs, then keep watching the input folders and retile the new files whenever
+/// some arrive. Returns on errors, or once interrupted with Ctrl-C: while waiting for new files
+/// that ends watching, while retiling it fails like an interrupted run without `--watch`.
+pub fn run(args: &Args) -> Result<()> {
+    let output = args.output.as_deref().expect("required by clap");
+    anyhow::ensure!(
+        !stdio::is_stdio(output),
+        "--watch can not write the tiles to stdout"
+    );
+    let folders: Vec<&PathBuf> = args.input.inputs.iter().filter(|p| p.is_dir()).collect();
+    anyhow::ensure!(
+        !folders.is_empty(),
+        "--watch needs an input folder to watch"
+    );
+    let output = std::path::absolute(output)?;
+    // files in watched folders may be replaced or truncated while they are read
+    reader::disable_mapping();
+    for folder in &folders {
+        anyhow::ensure!(
+            !output.starts_with(std::path::absolute(folder)?),
+            "the output folder is inside the watched folder {}, its tiles would be read as new inputs",
+            folder.display()
+        );
+    }
+
+    // watch before the first run, so that no file arriving during it is missed
+    let (events, received) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events).context("start watching")?;
+    let mode = match args.input.recursive {
+        true => RecursiveMode::Recursive,
+        false => RecursiveMode::NonRecursive,
+    };
+    for folder in &folders {
+        watcher
+            .watch(folder, mode)
+            .with_context(|| format!("watch {}", folder.display()))?;
+    }
+
+    // files arriving between listing them here and in the run are retiled again, which
+    // rebuilds the same tiles
+    let mut seen: HashSet<InputFile> =
+        input::collect_inputs(&args.input, &mut input::SkipReport::default())?
+            .into_iter()
+            .collect();
+    crate::retile_inputs(args, None)?;
+
+    let settle = Duration::from_secs_f64(args.watch_settle);
+    loop {
+        tracing::info!(
+            "Watching {} for new files",
+            folders
+                .iter()
+                .map(|folder| folder.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        // wait for a change, then until the files have been written completely
+        loop {
+            if interrupt::requested() {
+                tracing::info!("Stopped watching");
+                return Ok(());
+            }
+            match received.recv_timeout(INTERRUPT_POLL_INTERVAL) {
+                Ok(Ok(_)) => break,
+                Ok(Err(e)) => tracing::warn!("watching the input folders: {e}"),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => anyhow::bail!("file watcher stopped"),
+            }
+        }
+        while received.recv_timeout(settle).is_ok() {}
+
+        let rebuild = Rebuild {
+            changed: input::collect_inputs(&args.input, &mut input::SkipReport::default())?
+                .into_iter()
+                .filter(|file| !seen.contains(file))
+                .collect(),
+            ..Default::default()
+        };
+        if rebuild.changed.is_empty() {
+            continue;
+        }
+        tracing::info!("Retiling {} new input files", rebuild.changed.len());
+        match crate::retile_inputs(args, Some(&rebuild)) {
+            Ok(()) => seen.extend(rebuild.changed),
+            // the rebuild stopped with its checkpoint written, retrying would only stop again
+            Err(e) if interrupt::requested() => return Err(e),
+            Err(e) => tracing::error!(
+                "Retiling the new input files failed, trying again after the next change: {e:#}"
+            ),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Vertical corrections from a raster (`--z-adjust`), e.g. geoid heights to convert ellipsoidal
+//! heights to orthometric ones while retiling. The raster is a single band GeoTIFF in the CRS of
+//! the (transformed) points, sampled with bilinear interpolation at the XY of each point.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tiff::{
+    ColorType,
+    decoder::{Decoder, DecodingResult},
+};
+
+use crate::{
+    geotiff::{self, Georeference},
+    input,
+};
+
+#[derive(Debug)]
+pub struct ZAdjust {
+    georeference: Georeference,
+    /// The values row by row from the top, `NaN` for no data
+    values: Vec<f64>,
+}
+
+impl ZAdjust {
+    /// Load the raster from a GeoTIFF with a pixel scale and tie point
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let load = || -> Result<Self> {
+            let mut decoder = Decoder::new(std::io::BufReader::new(file))?;
+            anyhow::ensure!(
+                matches!(decoder.colortype()?, ColorType::Gray(_)),
+                "only single band rasters are supported"
+            );
+            let georeference = Georeference::read(&mut decoder)?;
+            let no_data = geotiff::no_data(&mut decoder)?;
+
+            let mut values: Vec<f64> = match decoder.read_image()? {
+                DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+                DecodingResult::F64(v) => v,
+                _ => anyhow::bail!("unsupported sample format"),
+            };
+            if let Some(no_data) = no_data {
+                for value in values.iter_mut().filter(|v| **v == no_data) {
+                    *value = f64::NAN;
+                }
+            }
+            anyhow::ensure!(
+                values.len() == georeference.width * georeference.height,
+                "unexpected number of values"
+            );
+            Ok(Self {
+                georeference,
+                values,
+            })
+        };
+        load().with_context(|| format!("read --z-adjust raster {}", path.display()))
+    }
+
+    /// `[min x, min y, max x, max y]` of the area covered by the pixels
+    pub fn extent(&self) -> [f64; 4] {
+        self.georeference.extent()
+    }
+
+    /// The smallest and largest correction
+    pub fn range(&self) -> [f64; 2] {
+        self.values
+            .iter()
+            .filter(|v| !v.is_nan())
+            .fold([f64::INFINITY, f64::NEG_INFINITY], |[min, max], &v| {
+                [min.min(v), max.max(v)]
+            })
+    }
+
+    /// The correction at a position, interpolated between the centers of the four nearest
+    /// pixels, or the nearest pixel if one of them has no data. `None` outside the raster or on
+    /// no data.
+    pub fn sample(&self, x: f64, y: f64) -> Option<f64> {
+        let [col, row] = self.georeference.position(x, y)?;
+        let value = |c: f64, r: f64| self.values[self.georeference.index(c, r)];
+        // clamped to the pixel centers at the edges
+        let (c0, r0) = (col.floor().max(0.0), row.floor().max(0.0));
+        let (fc, fr) = ((col - c0).clamp(0.0, 1.0), (row - r0).clamp(0.0, 1.0));
+        let top = value(c0, r0) * (1.0 - fc) + value(c0 + 1.0, r0) * fc;
+        let bottom = value(c0, r0 + 1.0) * (1.0 - fc) + value(c0 + 1.0, r0 + 1.0) * fc;
+        let interpolated = top * (1.0 - fr) + bottom * fr;
+        if !interpolated.is_nan() {
+            return Some(interpolated);
+        }
+        let nearest = self.values[self.georeference.nearest(x, y)?];
+        (!nearest.is_nan()).then_some(nearest)
+    }
+
+    /// The input header with the Z bounds widened by the range of the corrections
+    pub fn header(&self, header: &las::Header) -> Result<las::Header> {
+        let [min, max] = self.range();
+        input::modify_header(header, |raw| {
+            raw.min_z += min;
+            raw.max_z += max;
+        })
+    }
+
+    /// Add the correction to the Z of the points
+    pub fn apply(&self, points: &mut [las::Point]) -> Result<()> {
+        for p in points {
+            let correction = self.sample(p.x, p.y).with_context(|| {
+                format!(
+                    "no --z-adjust correction for the point at ({}, {})",
+                    p.x, p.y
+                )
+            })?;
+            p.z += correction;
+        }
+        Ok(())
+    }
+}